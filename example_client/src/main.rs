@@ -6,6 +6,7 @@ use std::net::TcpStream;
 use std::thread::spawn;
 use std::sync::mpsc::channel;
 use std::sync::mpsc::Sender;
+use std::time::Duration;
 
 use profix::*;
 
@@ -34,8 +35,6 @@ enum HandlerFeedback {
 }
 
 struct ExampleHandler {
-    is_logged : bool,
-
     tx : Sender<HandlerFeedback>,
 }
 
@@ -43,7 +42,6 @@ impl profix::FixHandler<ExampleSessionMessage, ExampleAppMessage, Action> for Ex
     fn handle_session(&mut self, _client: &mut FixClient, msg: ExampleSessionMessage) -> Result<(), HandleErr> {
         match msg {
             ExampleSessionMessage::LogonResp(_) => {
-                self.is_logged = true;
                 self.tx.send(HandlerFeedback::LoggedIn);
             },
         }
@@ -61,7 +59,7 @@ impl profix::FixHandler<ExampleSessionMessage, ExampleAppMessage, Action> for Ex
             ExampleAppMessage::MassQuoteAck(mqa) => {
 //                println!("got mqa!");
 //                let mq = MassQuote {
-//                    sending_time : Timestamp::now(),
+//                    sending_time : client.sending_time(),
 //                    seq : client.get_next_send_seq(),
 //                    sender : client.comp_ids().sender.clone(),
 //                    target : client.comp_ids().target.clone(),
@@ -74,25 +72,38 @@ impl profix::FixHandler<ExampleSessionMessage, ExampleAppMessage, Action> for Ex
         Ok(())
     }
 
-    fn handle_action(&mut self, client: &mut FixClient, action: Action) {
+    fn handle_action(&mut self, client: &mut FixClient, action: Action) -> Result<(), HandleErr> {
         match action {
             Action::SendMassQuote => {
                 for i in 0..1000 {
                     let mq = MassQuote {
-                        sending_time: Timestamp::now(),
+                        sending_time: client.sending_time(),
                         seq: client.get_next_send_seq(),
                         sender: client.comp_ids().sender.clone(),
                         target: client.comp_ids().target.clone(),
                     };
 
-                    client.send(&mq);
+                    client.send(&mq)?;
                 };
             }
         }
+
+        Ok(())
     }
 
-    fn is_logged(&self) -> bool {
-        self.is_logged
+    // sent from here rather than the factory so it passes through `to_admin`, e.g. to add a password.
+    fn on_connect(&mut self, client: &mut FixClient) {
+        let logon = LogonReq {
+            target : client.comp_ids().target.clone(),
+            sender : client.comp_ids().sender.clone(),
+            seq : client.get_next_send_seq(),
+            sending_time : client.sending_time(),
+        };
+
+        match client.send(&logon) {
+            Ok(()) => println!("Logon sent."),
+            Err(err) => eprintln!("failed to send the logon: {:?}", err),
+        }
     }
 }
 
@@ -102,25 +113,25 @@ struct Factory {
 
 impl profix::FixFactory<ExampleHandler> for Factory {
     fn connection_factory(&self) -> Result<FixClient, ConnectionFailure> {
+        let stream = PlainStreamWrapper::new(TcpStream::connect("127.0.0.1:3213").expect("server not found."));
+        let source = stream.event_source().map_err(ConnectionFailure::TcpStreamError)?;
         let mut client = FixClient::new(CompIds { sender : "client".to_string(), target : "server".to_string() },
-                          Box::new(PlainStreamWrapper::new(TcpStream::connect("127.0.0.1:3213").expect("server not found."))));
-
-        let logon = LogonReq {
-            target : client.comp_ids().target.clone(),
-            sender : client.comp_ids().sender.clone(),
-            seq : client.get_next_send_seq(),
-            sending_time : Timestamp::now(),
-        };
+                          Box::new(stream));
+        client.set_event_source(source);
 
-        client.send(&logon);
-        println!("Logon sent.");
+        // keeps the bursts of MassQuotes within the rate limit of the venue.
+        let mut throttle = Throttle::new(OverflowAction::Queue);
+        throttle
+            .add_limit(RateLimit::for_msg_type("i", 100, Duration::from_secs(1)))
+            .expect("valid rate limit");
+        client.set_throttle(throttle);
+        client.set_message_log(Box::new(LogMessageLog::pretty(PrettyPrinter::default())));
 
         Ok(client)
     }
 
     fn handler_factory(&self) -> ExampleHandler {
         ExampleHandler {
-            is_logged : false,
             tx : self.tx.clone(),
         }
     }
@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+/// Prices and quantities are kept in units of 10^-8, so matching never suffers from rounding.
+pub type Units = i64;
+
+const UNITS_PER_ONE: f64 = 100_000_000.0;
+
+pub fn to_units(value: f64) -> Units {
+    (value * UNITS_PER_ONE).round() as Units
+}
+
+pub fn from_units(units: Units) -> f64 {
+    units as f64 / UNITS_PER_ONE
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Order {
+    pub id: u64,
+    // TargetCompID of the session owning the order.
+    pub owner: String,
+    pub cl_ord_id: String,
+    pub side: Side,
+    // None for market orders, which never rest.
+    pub price: Option<Units>,
+    pub qty: Units,
+    pub cum_qty: Units,
+    // sum of price * qty of the fills, in units of 10^-16.
+    pub notional: i128,
+}
+
+impl Order {
+    pub fn leaves_qty(&self) -> Units {
+        self.qty - self.cum_qty
+    }
+
+    pub fn avg_px(&self) -> Units {
+        if self.cum_qty == 0 {
+            0
+        } else {
+            (self.notional / self.cum_qty as i128) as Units
+        }
+    }
+
+    fn fill(&mut self, price: Units, qty: Units) {
+        self.cum_qty += qty;
+        self.notional += price as i128 * qty as i128;
+    }
+}
+
+/// A trade between a resting order and an incoming one, at the price of the resting one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub price: Units,
+    pub qty: Units,
+    // the resting order after the fill, its leaves quantity is 0 once it left the book.
+    pub maker: Order,
+}
+
+/// Price-time priority order book of one symbol.
+#[derive(Debug, Default)]
+pub struct OrderBook {
+    bids: BTreeMap<Units, VecDeque<Order>>,
+    asks: BTreeMap<Units, VecDeque<Order>>,
+    // side and price of every resting order.
+    resting: HashMap<u64, (Side, Units)>,
+}
+
+impl OrderBook {
+    /// Matches the order against the other side, then rests what is left of a limit order
+    /// unless `rest` is false. Returns the fills and the order as it is afterwards.
+    pub fn submit(&mut self, mut order: Order, rest: bool) -> (Vec<Fill>, Order) {
+        let mut fills = Vec::new();
+        while order.leaves_qty() > 0 {
+            let best = match order.side {
+                Side::Buy => self.asks.keys().next().cloned(),
+                Side::Sell => self.bids.keys().next_back().cloned(),
+            };
+            let best = match best {
+                Some(best) if crosses(&order, best) => best,
+                _ => break,
+            };
+
+            let (level, empty) = {
+                let levels = match order.side {
+                    Side::Buy => &mut self.asks,
+                    Side::Sell => &mut self.bids,
+                };
+                let level = levels.get_mut(&best).unwrap();
+                let qty = level[0].leaves_qty().min(order.leaves_qty());
+                level[0].fill(best, qty);
+                order.fill(best, qty);
+                fills.push(Fill {
+                    price: best,
+                    qty,
+                    maker: level[0].clone(),
+                });
+                if level[0].leaves_qty() == 0 {
+                    let maker = level.pop_front().unwrap();
+                    self.resting.remove(&maker.id);
+                }
+                (best, level.is_empty())
+            };
+            if empty {
+                match order.side {
+                    Side::Buy => self.asks.remove(&level),
+                    Side::Sell => self.bids.remove(&level),
+                };
+            }
+        }
+
+        if rest && order.leaves_qty() > 0 {
+            if let Some(price) = order.price {
+                self.resting.insert(order.id, (order.side, price));
+                self.side_mut(order.side)
+                    .entry(price)
+                    .or_default()
+                    .push_back(order.clone());
+            }
+        }
+        (fills, order)
+    }
+
+    pub fn cancel(&mut self, id: u64) -> Option<Order> {
+        let (side, price) = self.resting.remove(&id)?;
+        let levels = self.side_mut(side);
+        let order = {
+            let level = levels.get_mut(&price)?;
+            let index = level.iter().position(|order| order.id == id)?;
+            level.remove(index)
+        };
+        if levels.get(&price).is_some_and(|level| level.is_empty()) {
+            levels.remove(&price);
+        }
+        order
+    }
+
+    /// Changes price and quantity of a resting order. It keeps its priority when only its
+    /// quantity went down, otherwise it goes to the back and may trade right away.
+    pub fn replace(&mut self, id: u64, cl_ord_id: &str, price: Units, qty: Units) -> Option<(Vec<Fill>, Order)> {
+        let (side, old_price) = *self.resting.get(&id)?;
+        if price == old_price {
+            let level = self.side_mut(side).get_mut(&price)?;
+            let order = level.iter_mut().find(|order| order.id == id)?;
+            if qty <= order.qty {
+                order.qty = qty;
+                order.cl_ord_id = cl_ord_id.to_string();
+                return Some((Vec::new(), order.clone()));
+            }
+        }
+
+        let mut order = self.cancel(id)?;
+        order.cl_ord_id = cl_ord_id.to_string();
+        order.price = Some(price);
+        order.qty = qty;
+        Some(self.submit(order, true))
+    }
+
+    pub fn order(&self, id: u64) -> Option<&Order> {
+        let &(side, price) = self.resting.get(&id)?;
+        let levels = match side {
+            Side::Buy => &self.bids,
+            Side::Sell => &self.asks,
+        };
+        levels.get(&price)?.iter().find(|order| order.id == id)
+    }
+
+    /// Price and total quantity of the `depth` best levels, all of them for 0.
+    pub fn levels(&self, side: Side, depth: usize) -> Vec<(Units, Units)> {
+        let total = |level: &VecDeque<Order>| level.iter().map(Order::leaves_qty).sum();
+        let depth = if depth == 0 { usize::MAX } else { depth };
+        match side {
+            Side::Buy => self
+                .bids
+                .iter()
+                .rev()
+                .take(depth)
+                .map(|(&price, level)| (price, total(level)))
+                .collect(),
+            Side::Sell => self
+                .asks
+                .iter()
+                .take(depth)
+                .map(|(&price, level)| (price, total(level)))
+                .collect(),
+        }
+    }
+
+    fn side_mut(&mut self, side: Side) -> &mut BTreeMap<Units, VecDeque<Order>> {
+        match side {
+            Side::Buy => &mut self.bids,
+            Side::Sell => &mut self.asks,
+        }
+    }
+}
+
+fn crosses(order: &Order, best: Units) -> bool {
+    match (order.side, order.price) {
+        (_, None) => true,
+        (Side::Buy, Some(price)) => price >= best,
+        (Side::Sell, Some(price)) => price <= best,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn order(id: u64, side: Side, price: Option<f64>, qty: f64) -> Order {
+        Order {
+            id,
+            owner: "client".to_string(),
+            cl_ord_id: id.to_string(),
+            side,
+            price: price.map(to_units),
+            qty: to_units(qty),
+            cum_qty: 0,
+            notional: 0,
+        }
+    }
+
+    #[test]
+    fn test_price_time_priority() {
+        let mut book = OrderBook::default();
+        book.submit(order(1, Side::Sell, Some(10.1), 1.0), true);
+        book.submit(order(2, Side::Sell, Some(10.0), 1.0), true);
+        book.submit(order(3, Side::Sell, Some(10.0), 2.0), true);
+        book.submit(order(4, Side::Buy, Some(9.9), 5.0), true);
+
+        let (fills, taker) = book.submit(order(5, Side::Buy, Some(10.1), 3.5), true);
+        let trades: Vec<_> = fills
+            .iter()
+            .map(|fill| (fill.maker.id, from_units(fill.price), from_units(fill.qty)))
+            .collect();
+        assert_eq!(trades, vec![(2, 10.0, 1.0), (3, 10.0, 2.0), (1, 10.1, 0.5)]);
+        assert_eq!(taker.leaves_qty(), 0);
+        // 35.05 / 3.5
+        assert_eq!(taker.avg_px(), 1_001_428_571);
+        assert_eq!(book.levels(Side::Sell, 0), vec![(to_units(10.1), to_units(0.5))]);
+        assert_eq!(book.levels(Side::Buy, 0), vec![(to_units(9.9), to_units(5.0))]);
+        assert_eq!(book.order(1).unwrap().cum_qty, to_units(0.5));
+        assert!(book.order(2).is_none());
+    }
+
+    #[test]
+    fn test_market_order_does_not_rest() {
+        let mut book = OrderBook::default();
+        book.submit(order(1, Side::Buy, Some(10.0), 1.0), true);
+        let (fills, taker) = book.submit(order(2, Side::Sell, None, 3.0), true);
+        assert_eq!(fills.len(), 1);
+        assert_eq!(taker.leaves_qty(), to_units(2.0));
+        assert!(book.levels(Side::Buy, 0).is_empty());
+        assert!(book.levels(Side::Sell, 0).is_empty());
+    }
+
+    #[test]
+    fn test_cancel_and_replace() {
+        let mut book = OrderBook::default();
+        book.submit(order(1, Side::Buy, Some(10.0), 1.0), true);
+        book.submit(order(2, Side::Buy, Some(10.0), 1.0), true);
+        book.submit(order(3, Side::Sell, Some(10.5), 1.0), true);
+
+        // a smaller quantity keeps the place in the queue.
+        let (_, replaced) = book.replace(1, "1b", to_units(10.0), to_units(0.5)).unwrap();
+        assert_eq!(replaced.cl_ord_id, "1b");
+        let (fills, _) = book.submit(order(4, Side::Sell, Some(10.0), 0.5), true);
+        assert_eq!(fills[0].maker.id, 1);
+
+        // a new price trades when it crosses.
+        let (fills, replaced) = book.replace(2, "2b", to_units(10.5), to_units(2.0)).unwrap();
+        assert_eq!(fills[0].maker.id, 3);
+        assert_eq!(replaced.leaves_qty(), to_units(1.0));
+        assert_eq!(book.levels(Side::Buy, 1), vec![(to_units(10.5), to_units(1.0))]);
+
+        assert_eq!(book.cancel(2).unwrap().cl_ord_id, "2b");
+        assert!(book.cancel(2).is_none());
+        assert!(book.levels(Side::Buy, 0).is_empty());
+    }
+}
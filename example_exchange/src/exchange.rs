@@ -0,0 +1,722 @@
+use std::collections::HashMap;
+
+use profix::Timestamp;
+
+use book::{self, from_units, to_units, Fill, Order, OrderBook, Units};
+use messages::*;
+
+/// A message for the session of a counterparty, its header is filled in when it is sent.
+#[derive(Debug)]
+pub enum Outbound {
+    Exec(ExecutionReport),
+    CancelReject(OrderCancelReject),
+    Snapshot(MarketDataSnapshotFullRefresh),
+    Incremental(MarketDataIncrementalRefresh),
+    MarketDataReject(MarketDataRequestReject),
+}
+
+// (TargetCompID of the session, message).
+pub type Outbox = Vec<(String, Outbound)>;
+
+#[derive(Debug, Clone, PartialEq)]
+struct Subscription {
+    session: String,
+    md_req_id: String,
+    symbol: String,
+    depth: usize,
+}
+
+// both sides of a book, bids first.
+type Levels = (Vec<(Units, Units)>, Vec<(Units, Units)>);
+
+/// Order books of all symbols and the market data subscriptions, shared by every session.
+///
+/// Orders stay in the book when their session disconnects. Market orders and IOC orders
+/// never rest, what they do not fill right away is canceled.
+#[derive(Debug, Default)]
+pub struct Exchange {
+    books: HashMap<String, OrderBook>,
+    // (owner, ClOrdID) of the resting orders to their id and symbol.
+    live: HashMap<(String, String), (u64, String)>,
+    subscriptions: Vec<Subscription>,
+    next_order_id: u64,
+    next_exec_id: u64,
+}
+
+fn header() -> (u64, String, String, Timestamp) {
+    (0, String::new(), String::new(), Timestamp::now())
+}
+
+fn status(order: &Order) -> OrdStatus {
+    if order.leaves_qty() == 0 {
+        OrdStatus::Filled
+    } else if order.cum_qty > 0 {
+        OrdStatus::PartiallyFilled
+    } else {
+        OrdStatus::New
+    }
+}
+
+fn side_of(side: Side) -> book::Side {
+    match side {
+        Side::Buy => book::Side::Buy,
+        Side::Sell => book::Side::Sell,
+    }
+}
+
+fn side_to_fix(side: book::Side) -> Side {
+    match side {
+        book::Side::Buy => Side::Buy,
+        book::Side::Sell => Side::Sell,
+    }
+}
+
+impl Exchange {
+    pub fn new() -> Exchange {
+        Exchange {
+            next_order_id: 1,
+            next_exec_id: 1,
+            ..Exchange::default()
+        }
+    }
+
+    pub fn new_order(&mut self, session: &str, req: &NewOrderSingle, now: Timestamp) -> Outbox {
+        let mut outbox = Vec::new();
+        let reject = if self.live.contains_key(&(session.to_string(), req.cl_ord_id.clone())) {
+            Some((6, "duplicate ClOrdID"))
+        } else if to_units(req.order_qty) <= 0 {
+            Some((13, "OrderQty has to be positive"))
+        } else {
+            match (req.ord_type, req.price) {
+                (OrdType::Limit, None) => Some((99, "Price is missing")),
+                (OrdType::Limit, Some(price)) if to_units(price) <= 0 => Some((99, "Price has to be positive")),
+                _ => None,
+            }
+        };
+
+        let order = Order {
+            id: self.next_order_id,
+            owner: session.to_string(),
+            cl_ord_id: req.cl_ord_id.clone(),
+            side: side_of(req.side),
+            price: match req.ord_type {
+                OrdType::Limit => req.price.map(to_units),
+                OrdType::Market => None,
+            },
+            qty: to_units(req.order_qty),
+            cum_qty: 0,
+            notional: 0,
+        };
+        if let Some((reason, text)) = reject {
+            let mut report = self.report(&order, &req.symbol, ExecType::Rejected, OrdStatus::Rejected, now);
+            report.order_id = "NONE".to_string();
+            report.ord_rej_reason = Some(reason);
+            report.text = Some(text.to_string());
+            outbox.push((session.to_string(), Outbound::Exec(report)));
+            return outbox;
+        }
+        self.next_order_id += 1;
+
+        let before = self.levels_if_watched(&req.symbol);
+        let report = self.report(&order, &req.symbol, ExecType::New, OrdStatus::New, now);
+        outbox.push((session.to_string(), Outbound::Exec(report)));
+
+        let rest = order.price.is_some() && req.time_in_force != Some(TimeInForce::ImmediateOrCancel);
+        let (fills, order) = self
+            .books
+            .entry(req.symbol.clone())
+            .or_default()
+            .submit(order, rest);
+        self.fill_reports(&req.symbol, &order, &fills, now, &mut outbox);
+
+        if order.leaves_qty() > 0 {
+            if rest {
+                self.live.insert(
+                    (order.owner.clone(), order.cl_ord_id.clone()),
+                    (order.id, req.symbol.clone()),
+                );
+            } else {
+                let report = self.report(&order, &req.symbol, ExecType::Canceled, OrdStatus::Canceled, now);
+                outbox.push((session.to_string(), Outbound::Exec(report)));
+            }
+        }
+        self.publish(&req.symbol, before, &fills, &mut outbox);
+        outbox
+    }
+
+    pub fn cancel(&mut self, session: &str, req: &OrderCancelRequest, now: Timestamp) -> Outbox {
+        let mut outbox = Vec::new();
+        let key = (session.to_string(), req.orig_cl_ord_id.clone());
+        let id = match self.live.get(&key) {
+            Some(&(id, ref symbol)) if *symbol == req.symbol => id,
+            _ => {
+                let reject = self.cancel_reject(None, &req.cl_ord_id, &req.orig_cl_ord_id, 1, "unknown order");
+                outbox.push((session.to_string(), Outbound::CancelReject(reject)));
+                return outbox;
+            }
+        };
+
+        let before = self.levels_if_watched(&req.symbol);
+        let mut order = self
+            .books
+            .get_mut(&req.symbol)
+            .and_then(|book| book.cancel(id))
+            .unwrap();
+        self.live.remove(&key);
+        order.cl_ord_id = req.cl_ord_id.clone();
+        let mut report = self.report(&order, &req.symbol, ExecType::Canceled, OrdStatus::Canceled, now);
+        report.orig_cl_ord_id = Some(req.orig_cl_ord_id.clone());
+        outbox.push((session.to_string(), Outbound::Exec(report)));
+        self.publish(&req.symbol, before, &[], &mut outbox);
+        outbox
+    }
+
+    pub fn replace(&mut self, session: &str, req: &OrderCancelReplaceRequest, now: Timestamp) -> Outbox {
+        let mut outbox = Vec::new();
+        let key = (session.to_string(), req.orig_cl_ord_id.clone());
+        let existing = match self.live.get(&key) {
+            Some(&(id, ref symbol)) if *symbol == req.symbol => self.books[symbol].order(id).cloned(),
+            _ => None,
+        };
+        let existing = match existing {
+            Some(existing) => existing,
+            None => {
+                let reject = self.cancel_reject(None, &req.cl_ord_id, &req.orig_cl_ord_id, 2, "unknown order");
+                outbox.push((session.to_string(), Outbound::CancelReject(reject)));
+                return outbox;
+            }
+        };
+        let qty = to_units(req.order_qty);
+        let reject = if req.ord_type != OrdType::Limit || req.price.is_none_or(|price| to_units(price) <= 0) {
+            Some("only limit orders with a price can be replaced")
+        } else if qty <= existing.cum_qty {
+            Some("OrderQty has to exceed the filled quantity")
+        } else if self.live.contains_key(&(session.to_string(), req.cl_ord_id.clone())) {
+            Some("duplicate ClOrdID")
+        } else {
+            None
+        };
+        if let Some(text) = reject {
+            let reject = self.cancel_reject(Some(&existing), &req.cl_ord_id, &req.orig_cl_ord_id, 2, text);
+            outbox.push((session.to_string(), Outbound::CancelReject(reject)));
+            return outbox;
+        }
+
+        let before = self.levels_if_watched(&req.symbol);
+        let price = to_units(req.price.unwrap());
+        let (fills, order) = self
+            .books
+            .get_mut(&req.symbol)
+            .and_then(|book| book.replace(existing.id, &req.cl_ord_id, price, qty))
+            .unwrap();
+        self.live.remove(&key);
+
+        // the order as replaced, before it traded.
+        let replaced = Order {
+            cum_qty: existing.cum_qty,
+            notional: existing.notional,
+            ..order.clone()
+        };
+        let mut report = self.report(&replaced, &req.symbol, ExecType::Replaced, status(&replaced), now);
+        report.orig_cl_ord_id = Some(req.orig_cl_ord_id.clone());
+        outbox.push((session.to_string(), Outbound::Exec(report)));
+        self.fill_reports(&req.symbol, &order, &fills, now, &mut outbox);
+        if order.leaves_qty() > 0 {
+            self.live.insert(
+                (order.owner.clone(), order.cl_ord_id.clone()),
+                (order.id, req.symbol.clone()),
+            );
+        }
+        self.publish(&req.symbol, before, &fills, &mut outbox);
+        outbox
+    }
+
+    pub fn market_data_request(&mut self, session: &str, req: &MarketDataRequest) -> Outbox {
+        let mut outbox = Vec::new();
+        let reject = |reason: &str, text: &str| {
+            let (seq, sender, target, sending_time) = header();
+            let reject = MarketDataRequestReject {
+                seq,
+                sender,
+                target,
+                sending_time,
+                md_req_id: req.md_req_id.clone(),
+                md_req_rej_reason: Some(reason.to_string()),
+                text: Some(text.to_string()),
+            };
+            vec![(session.to_string(), Outbound::MarketDataReject(reject))]
+        };
+
+        match req.subscription_request_type {
+            0 | 1 if req.related_sym.is_empty() => return reject("0", "no symbols requested"),
+            0 | 1 => {}
+            2 => {
+                self.subscriptions
+                    .retain(|subscription| subscription.session != session || subscription.md_req_id != req.md_req_id);
+                return outbox;
+            }
+            _ => return reject("4", "unsupported SubscriptionRequestType"),
+        }
+        if req.subscription_request_type == 1
+            && self
+                .subscriptions
+                .iter()
+                .any(|subscription| subscription.session == session && subscription.md_req_id == req.md_req_id)
+        {
+            return reject("1", "duplicate MDReqID");
+        }
+
+        for related in &req.related_sym {
+            let depth = req.market_depth as usize;
+            let (bids, offers) = match self.books.get(&related.symbol) {
+                Some(book) => (
+                    book.levels(book::Side::Buy, depth),
+                    book.levels(book::Side::Sell, depth),
+                ),
+                None => (Vec::new(), Vec::new()),
+            };
+            let entries = bids
+                .iter()
+                .map(|&level| entry(MDEntryType::Bid, level))
+                .chain(offers.iter().map(|&level| entry(MDEntryType::Offer, level)))
+                .collect();
+            let (seq, sender, target, sending_time) = header();
+            let snapshot = MarketDataSnapshotFullRefresh {
+                seq,
+                sender,
+                target,
+                sending_time,
+                md_req_id: req.md_req_id.clone(),
+                symbol: related.symbol.clone(),
+                entries,
+            };
+            outbox.push((session.to_string(), Outbound::Snapshot(snapshot)));
+
+            if req.subscription_request_type == 1 {
+                self.subscriptions.push(Subscription {
+                    session: session.to_string(),
+                    md_req_id: req.md_req_id.clone(),
+                    symbol: related.symbol.clone(),
+                    depth,
+                });
+            }
+        }
+        outbox
+    }
+
+    // drops the market data subscriptions of the session, its orders stay.
+    pub fn session_ended(&mut self, session: &str) {
+        self.subscriptions
+            .retain(|subscription| subscription.session != session);
+    }
+
+    fn report(
+        &mut self,
+        order: &Order,
+        symbol: &str,
+        exec_type: ExecType,
+        ord_status: OrdStatus,
+        now: Timestamp,
+    ) -> ExecutionReport {
+        let (seq, sender, target, sending_time) = header();
+        let exec_id = self.next_exec_id;
+        self.next_exec_id += 1;
+        let leaves_qty = match ord_status {
+            OrdStatus::Canceled | OrdStatus::Rejected => 0,
+            _ => order.leaves_qty(),
+        };
+        ExecutionReport {
+            seq,
+            sender,
+            target,
+            sending_time,
+            order_id: order.id.to_string(),
+            cl_ord_id: order.cl_ord_id.clone(),
+            orig_cl_ord_id: None,
+            exec_id: exec_id.to_string(),
+            exec_type,
+            ord_status,
+            symbol: symbol.to_string(),
+            side: side_to_fix(order.side),
+            order_qty: from_units(order.qty),
+            price: order.price.map(from_units),
+            last_qty: None,
+            last_px: None,
+            leaves_qty: from_units(leaves_qty),
+            cum_qty: from_units(order.cum_qty),
+            avg_px: from_units(order.avg_px()),
+            transact_time: now,
+            ord_rej_reason: None,
+            text: None,
+        }
+    }
+
+    // a Trade report for both sides of every fill, `taker` is the incoming order after all of them.
+    fn fill_reports(&mut self, symbol: &str, taker: &Order, fills: &[Fill], now: Timestamp, outbox: &mut Outbox) {
+        let mut progress = Order {
+            cum_qty: taker.cum_qty - fills.iter().map(|fill| fill.qty).sum::<Units>(),
+            notional: taker.notional
+                - fills
+                    .iter()
+                    .map(|fill| fill.price as i128 * fill.qty as i128)
+                    .sum::<i128>(),
+            ..taker.clone()
+        };
+        for fill in fills {
+            if fill.maker.leaves_qty() == 0 {
+                self.live
+                    .remove(&(fill.maker.owner.clone(), fill.maker.cl_ord_id.clone()));
+            }
+            let mut report = self.report(&fill.maker, symbol, ExecType::Trade, status(&fill.maker), now);
+            report.last_qty = Some(from_units(fill.qty));
+            report.last_px = Some(from_units(fill.price));
+            outbox.push((fill.maker.owner.clone(), Outbound::Exec(report)));
+
+            progress.cum_qty += fill.qty;
+            progress.notional += fill.price as i128 * fill.qty as i128;
+            let mut report = self.report(&progress, symbol, ExecType::Trade, status(&progress), now);
+            report.last_qty = Some(from_units(fill.qty));
+            report.last_px = Some(from_units(fill.price));
+            outbox.push((taker.owner.clone(), Outbound::Exec(report)));
+        }
+    }
+
+    fn cancel_reject(
+        &self,
+        order: Option<&Order>,
+        cl_ord_id: &str,
+        orig_cl_ord_id: &str,
+        response_to: u64,
+        text: &str,
+    ) -> OrderCancelReject {
+        let (seq, sender, target, sending_time) = header();
+        OrderCancelReject {
+            seq,
+            sender,
+            target,
+            sending_time,
+            order_id: order.map_or("NONE".to_string(), |order| order.id.to_string()),
+            cl_ord_id: cl_ord_id.to_string(),
+            orig_cl_ord_id: orig_cl_ord_id.to_string(),
+            ord_status: order.map_or(OrdStatus::Rejected, status),
+            cxl_rej_response_to: response_to,
+            // 1 unknown order, 99 other.
+            cxl_rej_reason: Some(if order.is_some() { 99 } else { 1 }),
+            text: Some(text.to_string()),
+        }
+    }
+
+    fn levels_if_watched(&self, symbol: &str) -> Option<Levels> {
+        if !self
+            .subscriptions
+            .iter()
+            .any(|subscription| subscription.symbol == symbol)
+        {
+            return None;
+        }
+        Some(self.levels(symbol))
+    }
+
+    fn levels(&self, symbol: &str) -> Levels {
+        match self.books.get(symbol) {
+            Some(book) => (book.levels(book::Side::Buy, 0), book.levels(book::Side::Sell, 0)),
+            None => (Vec::new(), Vec::new()),
+        }
+    }
+
+    // sends the subscribers of the symbol the levels which changed within their depth and the trades.
+    fn publish(&self, symbol: &str, before: Option<Levels>, fills: &[Fill], outbox: &mut Outbox) {
+        let before = match before {
+            Some(before) => before,
+            None => return,
+        };
+        let after = self.levels(symbol);
+        let trades: Vec<MDIncrement> = fills
+            .iter()
+            .map(|fill| MDIncrement {
+                action: MDUpdateAction::New,
+                symbol: symbol.to_string(),
+                entry: entry(MDEntryType::Trade, (fill.price, fill.qty)),
+            })
+            .collect();
+
+        for subscription in self
+            .subscriptions
+            .iter()
+            .filter(|subscription| subscription.symbol == symbol)
+        {
+            let mut increments = Vec::new();
+            increments.extend(diff(symbol, MDEntryType::Bid, &before.0, &after.0, subscription.depth));
+            increments.extend(diff(
+                symbol,
+                MDEntryType::Offer,
+                &before.1,
+                &after.1,
+                subscription.depth,
+            ));
+            increments.extend(trades.iter().cloned());
+            if increments.is_empty() {
+                continue;
+            }
+            let (seq, sender, target, sending_time) = header();
+            let incremental = MarketDataIncrementalRefresh {
+                seq,
+                sender,
+                target,
+                sending_time,
+                md_req_id: subscription.md_req_id.clone(),
+                increments,
+            };
+            outbox.push((subscription.session.clone(), Outbound::Incremental(incremental)));
+        }
+    }
+}
+
+fn entry(entry_type: MDEntryType, (price, size): (Units, Units)) -> MDEntry {
+    MDEntry {
+        entry_type,
+        price: from_units(price),
+        size: from_units(size),
+    }
+}
+
+// the changes from `before` to `after` of the `depth` best levels, deletes first.
+fn diff(
+    symbol: &str,
+    entry_type: MDEntryType,
+    before: &[(Units, Units)],
+    after: &[(Units, Units)],
+    depth: usize,
+) -> Vec<MDIncrement> {
+    let depth = if depth == 0 { usize::MAX } else { depth };
+    let before = &before[..before.len().min(depth)];
+    let after = &after[..after.len().min(depth)];
+    let increment = |action, level| MDIncrement {
+        action,
+        symbol: symbol.to_string(),
+        entry: entry(entry_type, level),
+    };
+
+    let mut increments: Vec<MDIncrement> = before
+        .iter()
+        .filter(|&&(price, _)| !after.iter().any(|&(after_price, _)| after_price == price))
+        .map(|&level| increment(MDUpdateAction::Delete, level))
+        .collect();
+    for &(price, size) in after {
+        match before.iter().find(|&&(before_price, _)| before_price == price) {
+            Some(&(_, before_size)) if before_size == size => {}
+            Some(_) => increments.push(increment(MDUpdateAction::Change, (price, size))),
+            None => increments.push(increment(MDUpdateAction::New, (price, size))),
+        }
+    }
+    increments
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn order(cl_ord_id: &str, side: Side, qty: f64, price: Option<f64>) -> NewOrderSingle {
+        NewOrderSingle {
+            seq: 1,
+            sender: "client".to_string(),
+            target: "exchange".to_string(),
+            sending_time: Timestamp::now(),
+            cl_ord_id: cl_ord_id.to_string(),
+            symbol: "EURUSD".to_string(),
+            side,
+            order_qty: qty,
+            ord_type: if price.is_some() {
+                OrdType::Limit
+            } else {
+                OrdType::Market
+            },
+            price,
+            time_in_force: None,
+        }
+    }
+
+    // (session, ExecType, OrdStatus, ClOrdID, LastQty, LeavesQty) of the execution reports.
+    type Report<'a> = (&'a str, ExecType, OrdStatus, &'a str, Option<f64>, f64);
+
+    fn reports(outbox: &Outbox) -> Vec<Report<'_>> {
+        outbox
+            .iter()
+            .filter_map(|(session, msg)| match *msg {
+                Outbound::Exec(ref report) => Some((
+                    session.as_str(),
+                    report.exec_type,
+                    report.ord_status,
+                    report.cl_ord_id.as_str(),
+                    report.last_qty,
+                    report.leaves_qty,
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_orders_fill_partially_and_completely() {
+        let mut exchange = Exchange::new();
+        exchange.new_order("maker", &order("s1", Side::Sell, 5.0, Some(1.1)), Timestamp::now());
+        let outbox = exchange.new_order("taker", &order("b1", Side::Buy, 8.0, Some(1.2)), Timestamp::now());
+        assert_eq!(
+            reports(&outbox),
+            vec![
+                ("taker", ExecType::New, OrdStatus::New, "b1", None, 8.0),
+                ("maker", ExecType::Trade, OrdStatus::Filled, "s1", Some(5.0), 0.0),
+                (
+                    "taker",
+                    ExecType::Trade,
+                    OrdStatus::PartiallyFilled,
+                    "b1",
+                    Some(5.0),
+                    3.0
+                ),
+            ]
+        );
+
+        // the rest of the buy order rests, a market order takes it and is canceled for the rest.
+        let outbox = exchange.new_order("maker", &order("s2", Side::Sell, 4.0, None), Timestamp::now());
+        assert_eq!(
+            reports(&outbox),
+            vec![
+                ("maker", ExecType::New, OrdStatus::New, "s2", None, 4.0),
+                ("taker", ExecType::Trade, OrdStatus::Filled, "b1", Some(3.0), 0.0),
+                (
+                    "maker",
+                    ExecType::Trade,
+                    OrdStatus::PartiallyFilled,
+                    "s2",
+                    Some(3.0),
+                    1.0
+                ),
+                ("maker", ExecType::Canceled, OrdStatus::Canceled, "s2", None, 0.0),
+            ]
+        );
+
+        let outbox = exchange.new_order("maker", &order("s3", Side::Sell, 0.0, Some(1.0)), Timestamp::now());
+        assert_eq!(
+            reports(&outbox),
+            vec![("maker", ExecType::Rejected, OrdStatus::Rejected, "s3", None, 0.0)]
+        );
+    }
+
+    #[test]
+    fn test_cancel_and_replace() {
+        let mut exchange = Exchange::new();
+        exchange.new_order("client", &order("b1", Side::Buy, 2.0, Some(1.0)), Timestamp::now());
+        exchange.new_order("other", &order("s1", Side::Sell, 1.0, Some(1.5)), Timestamp::now());
+
+        let replace = OrderCancelReplaceRequest {
+            seq: 2,
+            sender: "client".to_string(),
+            target: "exchange".to_string(),
+            sending_time: Timestamp::now(),
+            orig_cl_ord_id: "b1".to_string(),
+            cl_ord_id: "b2".to_string(),
+            symbol: "EURUSD".to_string(),
+            side: Side::Buy,
+            order_qty: 3.0,
+            ord_type: OrdType::Limit,
+            price: Some(1.5),
+        };
+        let outbox = exchange.replace("client", &replace, Timestamp::now());
+        assert_eq!(
+            reports(&outbox),
+            vec![
+                ("client", ExecType::Replaced, OrdStatus::New, "b2", None, 3.0),
+                ("other", ExecType::Trade, OrdStatus::Filled, "s1", Some(1.0), 0.0),
+                (
+                    "client",
+                    ExecType::Trade,
+                    OrdStatus::PartiallyFilled,
+                    "b2",
+                    Some(1.0),
+                    2.0
+                ),
+            ]
+        );
+
+        let cancel = |orig: &str| OrderCancelRequest {
+            seq: 3,
+            sender: "client".to_string(),
+            target: "exchange".to_string(),
+            sending_time: Timestamp::now(),
+            orig_cl_ord_id: orig.to_string(),
+            cl_ord_id: "b3".to_string(),
+            symbol: "EURUSD".to_string(),
+            side: Side::Buy,
+        };
+        let outbox = exchange.cancel("client", &cancel("b1"), Timestamp::now());
+        match outbox[..] {
+            [(_, Outbound::CancelReject(ref reject))] => assert_eq!(reject.cxl_rej_reason, Some(1)),
+            ref outbox => panic!("unexpected {:?}", outbox),
+        }
+        let outbox = exchange.cancel("client", &cancel("b2"), Timestamp::now());
+        assert_eq!(
+            reports(&outbox),
+            vec![("client", ExecType::Canceled, OrdStatus::Canceled, "b3", None, 0.0)]
+        );
+    }
+
+    #[test]
+    fn test_market_data() {
+        let mut exchange = Exchange::new();
+        exchange.new_order("maker", &order("b1", Side::Buy, 1.0, Some(1.0)), Timestamp::now());
+        exchange.new_order("maker", &order("b2", Side::Buy, 1.0, Some(0.9)), Timestamp::now());
+
+        let request = MarketDataRequest {
+            seq: 2,
+            sender: "watcher".to_string(),
+            target: "exchange".to_string(),
+            sending_time: Timestamp::now(),
+            md_req_id: "md-1".to_string(),
+            subscription_request_type: 1,
+            market_depth: 1,
+            related_sym: vec![RelatedSym {
+                symbol: "EURUSD".to_string(),
+            }],
+        };
+        let outbox = exchange.market_data_request("watcher", &request);
+        match outbox[..] {
+            [(_, Outbound::Snapshot(ref snapshot))] => assert_eq!(
+                snapshot.entries,
+                vec![MDEntry {
+                    entry_type: MDEntryType::Bid,
+                    price: 1.0,
+                    size: 1.0,
+                }]
+            ),
+            ref outbox => panic!("unexpected {:?}", outbox),
+        }
+
+        // the best bid is taken, the second one comes into view.
+        let outbox = exchange.new_order("taker", &order("s1", Side::Sell, 1.0, None), Timestamp::now());
+        let increments = outbox
+            .iter()
+            .filter_map(|(_, msg)| match *msg {
+                Outbound::Incremental(ref incremental) => Some(incremental.increments.clone()),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        let increments: Vec<_> = increments
+            .iter()
+            .map(|increment| (increment.action, increment.entry.entry_type, increment.entry.price))
+            .collect();
+        assert_eq!(
+            increments,
+            vec![
+                (MDUpdateAction::Delete, MDEntryType::Bid, 1.0),
+                (MDUpdateAction::New, MDEntryType::Bid, 0.9),
+                (MDUpdateAction::New, MDEntryType::Trade, 1.0),
+            ]
+        );
+
+        exchange.session_ended("watcher");
+        let outbox = exchange.new_order("maker", &order("b3", Side::Buy, 1.0, Some(1.0)), Timestamp::now());
+        assert_eq!(outbox.len(), 1);
+    }
+}
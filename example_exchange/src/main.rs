@@ -0,0 +1,235 @@
+//! A local exchange to trade against: a price-time order book per symbol behind a FIX acceptor.
+//!
+//! Usage: example_exchange [ADDR] [COUNTERPARTY...]
+//!
+//! Listens on ADDR, 0.0.0.0:3214 by default, as SenderCompID `exchange` for the given
+//! counterparties, `client` by default. Takes NewOrderSingle, OrderCancelRequest,
+//! OrderCancelReplaceRequest and MarketDataRequest, answers with ExecutionReports,
+//! OrderCancelRejects and market data snapshots and incrementals.
+
+#[macro_use]
+extern crate log;
+
+extern crate env_logger;
+
+extern crate profix;
+
+mod book;
+mod exchange;
+mod messages;
+
+use std::collections::HashMap;
+use std::env;
+use std::net::TcpListener;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use profix::admin::{Heartbeat, Logout, ResendRequest, TestRequest};
+use profix::*;
+
+use exchange::{Exchange, Outbound, Outbox};
+use messages::*;
+
+#[derive(Debug, PartialEq, FixDeserialize)]
+enum SessionMessage {
+    Logon(Logon),
+    Heartbeat(Heartbeat),
+    TestRequest(TestRequest),
+    ResendRequest(ResendRequest),
+    Logout(Logout),
+}
+
+#[derive(Debug, PartialEq, FixDeserialize)]
+enum AppMessage {
+    NewOrderSingle(NewOrderSingle),
+    OrderCancelRequest(OrderCancelRequest),
+    OrderCancelReplaceRequest(OrderCancelReplaceRequest),
+    MarketDataRequest(MarketDataRequest),
+}
+
+// the senders of the sessions logged on, by their TargetCompID.
+type Sessions = Arc<Mutex<HashMap<String, Sender<Outbound>>>>;
+
+macro_rules! send {
+    ($client:expr, $msg:expr) => {{
+        let mut msg = $msg;
+        msg.seq = $client.get_next_send_seq();
+        msg.sender = $client.comp_ids().sender.clone();
+        msg.target = $client.comp_ids().target.clone();
+        msg.sending_time = $client.sending_time();
+        $client.send(&msg)
+    }};
+}
+
+fn send(client: &mut FixClient, outbound: Outbound) -> Result<(), SendError> {
+    match outbound {
+        Outbound::Exec(msg) => send!(client, msg),
+        Outbound::CancelReject(msg) => send!(client, msg),
+        Outbound::Snapshot(msg) => send!(client, msg),
+        Outbound::Incremental(msg) => send!(client, msg),
+        Outbound::MarketDataReject(msg) => send!(client, msg),
+    }
+}
+
+struct ExchangeHandler {
+    // TargetCompID of our session.
+    counterparty: String,
+
+    exchange: Arc<Mutex<Exchange>>,
+    sessions: Sessions,
+}
+
+impl ExchangeHandler {
+    // runs `trade` on the exchange and delivers its messages, those for other sessions
+    // while still holding the exchange so they arrive in order.
+    fn trade<F: FnOnce(&mut Exchange, &str) -> Outbox>(
+        &mut self,
+        client: &mut FixClient,
+        trade: F,
+    ) -> Result<(), HandleErr> {
+        let mut ours = Vec::new();
+        {
+            let mut exchange = self.exchange.lock().unwrap();
+            let sessions = self.sessions.lock().unwrap();
+            for (session, msg) in trade(&mut exchange, &self.counterparty) {
+                if session == self.counterparty {
+                    ours.push(msg);
+                } else if let Some(tx) = sessions.get(&session) {
+                    // a session ending right now misses the message, as would a disconnected one.
+                    let _ = tx.send(msg);
+                }
+            }
+        }
+        for msg in ours {
+            send(client, msg)?;
+        }
+        Ok(())
+    }
+}
+
+impl FixHandler<SessionMessage, AppMessage, Outbound> for ExchangeHandler {
+    fn handle_session(&mut self, client: &mut FixClient, msg: SessionMessage) -> Result<(), HandleErr> {
+        match msg {
+            SessionMessage::Logon(logon) => {
+                client.validate_msg(&logon)?;
+                info!("{} logged on", self.counterparty);
+                let resp = Logon {
+                    seq: client.get_next_send_seq(),
+                    sender: client.comp_ids().sender.clone(),
+                    target: client.comp_ids().target.clone(),
+                    sending_time: client.sending_time(),
+                    encrypt_method: 0,
+                    heart_bt_int: logon.heart_bt_int,
+                };
+                client.send(&resp)?;
+            }
+            SessionMessage::Heartbeat(heartbeat) => client.validate_msg(&heartbeat)?,
+            SessionMessage::TestRequest(request) => {
+                client.validate_msg(&request)?;
+                let resp = Heartbeat {
+                    seq: client.get_next_send_seq(),
+                    sender: client.comp_ids().sender.clone(),
+                    target: client.comp_ids().target.clone(),
+                    sending_time: client.sending_time(),
+                    test_req_id: Some(request.test_req_id),
+                };
+                client.send(&resp)?;
+            }
+            // the session answers it, gap filling everything as nothing is stored.
+            SessionMessage::ResendRequest(request) => client.validate_msg(&request)?,
+            SessionMessage::Logout(logout) => {
+                client.validate_msg(&logout)?;
+                info!("{} logged out", self.counterparty);
+                if client.is_logged_on() {
+                    client.send_logout(None)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle_app(&mut self, client: &mut FixClient, msg: AppMessage) -> Result<(), HandleErr> {
+        let now = client.sending_time();
+        match msg {
+            AppMessage::NewOrderSingle(order) => {
+                client.validate_msg(&order)?;
+                self.trade(client, |exchange, session| exchange.new_order(session, &order, now))
+            }
+            AppMessage::OrderCancelRequest(cancel) => {
+                client.validate_msg(&cancel)?;
+                self.trade(client, |exchange, session| exchange.cancel(session, &cancel, now))
+            }
+            AppMessage::OrderCancelReplaceRequest(replace) => {
+                client.validate_msg(&replace)?;
+                self.trade(client, |exchange, session| exchange.replace(session, &replace, now))
+            }
+            AppMessage::MarketDataRequest(request) => {
+                client.validate_msg(&request)?;
+                self.trade(client, |exchange, session| {
+                    exchange.market_data_request(session, &request)
+                })
+            }
+        }
+    }
+
+    // fills of our resting orders and market data caused by other sessions.
+    fn handle_action(&mut self, client: &mut FixClient, action: Outbound) -> Result<(), HandleErr> {
+        send(client, action)?;
+        Ok(())
+    }
+}
+
+struct Factory {
+    exchange: Arc<Mutex<Exchange>>,
+    sessions: Sessions,
+}
+
+impl AcceptorFactory<ExchangeHandler, Outbound> for Factory {
+    fn handler_factory(&self, comp_ids: &CompIds) -> ExchangeHandler {
+        ExchangeHandler {
+            counterparty: comp_ids.target.clone(),
+            exchange: self.exchange.clone(),
+            sessions: self.sessions.clone(),
+        }
+    }
+
+    fn session_started(&self, comp_ids: &CompIds, actions: Sender<Outbound>) {
+        self.sessions.lock().unwrap().insert(comp_ids.target.clone(), actions);
+    }
+
+    fn session_ended(&self, comp_ids: &CompIds) {
+        self.sessions.lock().unwrap().remove(&comp_ids.target);
+        self.exchange.lock().unwrap().session_ended(&comp_ids.target);
+    }
+}
+
+fn main() {
+    env_logger::init();
+
+    let mut args = env::args().skip(1);
+    let addr = args.next().unwrap_or_else(|| "0.0.0.0:3214".to_string());
+    let mut counterparties: Vec<String> = args.collect();
+    if counterparties.is_empty() {
+        counterparties.push("client".to_string());
+    }
+
+    let listener = TcpListener::bind(&addr).expect("couldnt create server");
+    info!("exchange listening on {} for {:?}", addr, counterparties);
+
+    let factory = Factory {
+        exchange: Arc::new(Mutex::new(Exchange::new())),
+        sessions: Arc::new(Mutex::new(HashMap::new())),
+    };
+    let mut acceptor = Acceptor::new(listener, factory);
+    for counterparty in counterparties {
+        let comp_ids = CompIds {
+            sender: "exchange".to_string(),
+            target: counterparty,
+        };
+        acceptor.add_session(comp_ids, SessionConfig::default());
+    }
+    if let Err(e) = acceptor.run::<SessionMessage, AppMessage, ExchangeHandler, Outbound>() {
+        error!("acceptor failed: {:?}", e);
+    }
+}
@@ -0,0 +1,452 @@
+use std::fmt;
+use std::fmt::Write;
+
+use profix::detail::FixSerializable;
+use profix::*;
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "A"]
+pub struct Logon {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "98"]
+    pub encrypt_method: u64,
+    #[id = "108"]
+    pub heart_bt_int: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum Side {
+    #[fix_value = "1"]
+    Buy,
+    #[fix_value = "2"]
+    Sell,
+}
+
+//todo remove me after enum serialization is done.
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Side::Buy => write!(f, "1"),
+            Side::Sell => write!(f, "2"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum OrdType {
+    #[fix_value = "1"]
+    Market,
+    #[fix_value = "2"]
+    Limit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum TimeInForce {
+    #[fix_value = "0"]
+    Day,
+    #[fix_value = "1"]
+    GoodTillCancel,
+    #[fix_value = "3"]
+    ImmediateOrCancel,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum ExecType {
+    #[fix_value = "0"]
+    New,
+    #[fix_value = "4"]
+    Canceled,
+    #[fix_value = "5"]
+    Replaced,
+    #[fix_value = "8"]
+    Rejected,
+    #[fix_value = "F"]
+    Trade,
+}
+
+//todo remove me after enum serialization is done.
+impl fmt::Display for ExecType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ExecType::New => write!(f, "0"),
+            ExecType::Canceled => write!(f, "4"),
+            ExecType::Replaced => write!(f, "5"),
+            ExecType::Rejected => write!(f, "8"),
+            ExecType::Trade => write!(f, "F"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum OrdStatus {
+    #[fix_value = "0"]
+    New,
+    #[fix_value = "1"]
+    PartiallyFilled,
+    #[fix_value = "2"]
+    Filled,
+    #[fix_value = "4"]
+    Canceled,
+    #[fix_value = "8"]
+    Rejected,
+}
+
+//todo remove me after enum serialization is done.
+impl fmt::Display for OrdStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OrdStatus::New => write!(f, "0"),
+            OrdStatus::PartiallyFilled => write!(f, "1"),
+            OrdStatus::Filled => write!(f, "2"),
+            OrdStatus::Canceled => write!(f, "4"),
+            OrdStatus::Rejected => write!(f, "8"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize)]
+#[msg_type = "D"]
+pub struct NewOrderSingle {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "11"]
+    pub cl_ord_id: String,
+    #[id = "55"]
+    pub symbol: String,
+    #[id = "54"]
+    pub side: Side,
+    #[id = "38"]
+    pub order_qty: f64,
+    #[id = "40"]
+    pub ord_type: OrdType,
+    #[id = "44"]
+    pub price: Option<f64>,
+    #[id = "59"]
+    pub time_in_force: Option<TimeInForce>,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize)]
+#[msg_type = "F"]
+pub struct OrderCancelRequest {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "41"]
+    pub orig_cl_ord_id: String,
+    #[id = "11"]
+    pub cl_ord_id: String,
+    #[id = "55"]
+    pub symbol: String,
+    #[id = "54"]
+    pub side: Side,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize)]
+#[msg_type = "G"]
+pub struct OrderCancelReplaceRequest {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "41"]
+    pub orig_cl_ord_id: String,
+    #[id = "11"]
+    pub cl_ord_id: String,
+    #[id = "55"]
+    pub symbol: String,
+    #[id = "54"]
+    pub side: Side,
+    #[id = "38"]
+    pub order_qty: f64,
+    #[id = "40"]
+    pub ord_type: OrdType,
+    #[id = "44"]
+    pub price: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "8"]
+pub struct ExecutionReport {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "37"]
+    pub order_id: String,
+    #[id = "11"]
+    pub cl_ord_id: String,
+    #[id = "41"]
+    pub orig_cl_ord_id: Option<String>,
+    #[id = "17"]
+    pub exec_id: String,
+    #[id = "150"]
+    pub exec_type: ExecType,
+    #[id = "39"]
+    pub ord_status: OrdStatus,
+    #[id = "55"]
+    pub symbol: String,
+    #[id = "54"]
+    pub side: Side,
+    #[id = "38"]
+    pub order_qty: f64,
+    #[id = "44"]
+    pub price: Option<f64>,
+    #[id = "32"]
+    pub last_qty: Option<f64>,
+    #[id = "31"]
+    pub last_px: Option<f64>,
+    #[id = "151"]
+    pub leaves_qty: f64,
+    #[id = "14"]
+    pub cum_qty: f64,
+    #[id = "6"]
+    pub avg_px: f64,
+    #[id = "60"]
+    pub transact_time: Timestamp,
+    #[id = "103"]
+    pub ord_rej_reason: Option<u64>,
+    #[id = "58"]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "9"]
+pub struct OrderCancelReject {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    // NONE for unknown orders.
+    #[id = "37"]
+    pub order_id: String,
+    #[id = "11"]
+    pub cl_ord_id: String,
+    #[id = "41"]
+    pub orig_cl_ord_id: String,
+    #[id = "39"]
+    pub ord_status: OrdStatus,
+    // 1 for an OrderCancelRequest, 2 for an OrderCancelReplaceRequest.
+    #[id = "434"]
+    pub cxl_rej_response_to: u64,
+    #[id = "102"]
+    pub cxl_rej_reason: Option<u64>,
+    #[id = "58"]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FixDeserializeGroup)]
+pub struct RelatedSym {
+    #[id = "55"]
+    pub symbol: String,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize)]
+#[msg_type = "V"]
+pub struct MarketDataRequest {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "262"]
+    pub md_req_id: String,
+    // 0 snapshot, 1 snapshot and updates, 2 unsubscribe.
+    #[id = "263"]
+    pub subscription_request_type: u64,
+    // 0 for the full book.
+    #[id = "264"]
+    pub market_depth: u64,
+    #[id = "146"]
+    pub related_sym: Vec<RelatedSym>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MDEntryType {
+    Bid,
+    Offer,
+    Trade,
+}
+
+//todo remove me after enum serialization is done.
+impl fmt::Display for MDEntryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MDEntryType::Bid => write!(f, "0"),
+            MDEntryType::Offer => write!(f, "1"),
+            MDEntryType::Trade => write!(f, "2"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MDUpdateAction {
+    New,
+    Change,
+    Delete,
+}
+
+//todo remove me after enum serialization is done.
+impl fmt::Display for MDUpdateAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MDUpdateAction::New => write!(f, "0"),
+            MDUpdateAction::Change => write!(f, "1"),
+            MDUpdateAction::Delete => write!(f, "2"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MDEntry {
+    pub entry_type: MDEntryType,
+    pub price: f64,
+    pub size: f64,
+}
+
+// repeating groups can not be derived for serialization yet, hence the hand written bodies below.
+#[derive(Debug, PartialEq)]
+pub struct MarketDataSnapshotFullRefresh {
+    pub seq: u64,
+    pub sender: String,
+    pub target: String,
+    pub sending_time: Timestamp,
+
+    pub md_req_id: String,
+    pub symbol: String,
+    pub entries: Vec<MDEntry>,
+}
+
+impl FixSerializable for MarketDataSnapshotFullRefresh {
+    fn serialize_body_to_fix(&self) -> String {
+        let mut out = String::from("35=W\x01");
+        write!(
+            out,
+            "34={}\x0149={}\x0156={}\x0152={}\x01262={}\x0155={}\x01268={}\x01",
+            self.seq,
+            self.sender,
+            self.target,
+            self.sending_time,
+            self.md_req_id,
+            self.symbol,
+            self.entries.len()
+        )
+        .unwrap();
+        for entry in &self.entries {
+            write!(
+                out,
+                "269={}\x01270={}\x01271={}\x01",
+                entry.entry_type, entry.price, entry.size
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MDIncrement {
+    pub action: MDUpdateAction,
+    pub symbol: String,
+    pub entry: MDEntry,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MarketDataIncrementalRefresh {
+    pub seq: u64,
+    pub sender: String,
+    pub target: String,
+    pub sending_time: Timestamp,
+
+    pub md_req_id: String,
+    pub increments: Vec<MDIncrement>,
+}
+
+impl FixSerializable for MarketDataIncrementalRefresh {
+    fn serialize_body_to_fix(&self) -> String {
+        let mut out = String::from("35=X\x01");
+        write!(
+            out,
+            "34={}\x0149={}\x0156={}\x0152={}\x01262={}\x01268={}\x01",
+            self.seq,
+            self.sender,
+            self.target,
+            self.sending_time,
+            self.md_req_id,
+            self.increments.len()
+        )
+        .unwrap();
+        for increment in &self.increments {
+            write!(
+                out,
+                "279={}\x01269={}\x0155={}\x01270={}\x01271={}\x01",
+                increment.action,
+                increment.entry.entry_type,
+                increment.symbol,
+                increment.entry.price,
+                increment.entry.size
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "Y"]
+pub struct MarketDataRequestReject {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "262"]
+    pub md_req_id: String,
+    #[id = "281"]
+    pub md_req_rej_reason: Option<String>,
+    #[id = "58"]
+    pub text: Option<String>,
+}
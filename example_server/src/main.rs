@@ -7,7 +7,6 @@ extern crate profix;
 
 mod messages;
 
-use std::net::TcpStream;
 use std::net::TcpListener;
 
 use std::time::Duration;
@@ -34,7 +33,8 @@ enum ExampleAppMessage {
 
 #[derive(Debug)]
 enum Action {
-    SendMarketOrder
+    // the ExecutionReports of the order are handed to the PendingRequest sent back.
+    SendMarketOrder(Sender<PendingRequest>),
 }
 
 #[derive(Debug)]
@@ -43,29 +43,31 @@ enum HandlerFeedback {
 }
 
 struct ExampleHandler {
-    is_logged : bool,
-
     tx : Sender<HandlerFeedback>,
+    correlator : Correlator,
 
     messages_this_second : i32,
     this_second : Instant,
 }
 
 impl profix::FixHandler<ExampleSessionMessage, ExampleAppMessage, Action> for ExampleHandler {
+    fn on_connect(&mut self, client: &mut FixClient) {
+        client.add_interceptor(Box::new(self.correlator.clone()));
+    }
+
     fn handle_session(&mut self, client: &mut FixClient, msg: ExampleSessionMessage) -> Result<(), HandleErr> {
         match msg {
             ExampleSessionMessage::LogonReq(_) => {
-                self.is_logged = true;
                 println!("Client has logged in.");
 
                 let resp = LogonResp {
-                    sending_time : Timestamp::now(),
+                    sending_time : client.sending_time(),
                     seq : client.get_next_send_seq(),
                     sender : client.comp_ids().sender.clone(),
                     target : client.comp_ids().target.clone(),
                 };
 
-                client.send(&resp);
+                client.send(&resp)?;
             },
         }
 
@@ -91,13 +93,13 @@ impl profix::FixHandler<ExampleSessionMessage, ExampleAppMessage, Action> for Ex
                 }
 //                println!("got mq!");
                 let mqa = MassQuoteAck {
-                    sending_time : Timestamp::now(),
+                    sending_time : client.sending_time(),
                     seq : client.get_next_send_seq(),
                     sender : client.comp_ids().sender.clone(),
                     target : client.comp_ids().target.clone(),
                 };
 
-                client.send(&mqa);
+                client.send(&mqa)?;
                 self.messages_this_second += 1;
 
             }
@@ -106,14 +108,14 @@ impl profix::FixHandler<ExampleSessionMessage, ExampleAppMessage, Action> for Ex
         Ok(())
     }
 
-    fn handle_action(&mut self, client: &mut FixClient, action: Action) {
+    fn handle_action(&mut self, client: &mut FixClient, action: Action) -> Result<(), HandleErr> {
         match action {
-            Action::SendMarketOrder => {
+            Action::SendMarketOrder(reply) => {
                 let req = NewMarketOrder {
                     seq : client.get_next_send_seq(),
                     sender: client.comp_ids().sender.clone(),
                     target: client.comp_ids().target.clone(),
-                    sending_time: Timestamp::now(),
+                    sending_time: client.sending_time(),
                     our_order_id: "1".to_string(),
                     symbol: "BTCUSD".to_string(),
                     side: Side::Buy,
@@ -121,33 +123,26 @@ impl profix::FixHandler<ExampleSessionMessage, ExampleAppMessage, Action> for Ex
                     order_type: OrderType::Market,
                 };
 
-                client.send(&req);
+                let pending = self.correlator.send(client, &req)?;
+                if let Err(e) = reply.send(pending) {
+                    eprintln!("order sent for no one: {:?}", e);
+                }
             }
         }
-    }
 
-    fn is_logged(&self) -> bool {
-        self.is_logged
+        Ok(())
     }
 }
 
 struct Factory {
     tx : Sender<HandlerFeedback>,
-
-    listener : TcpListener,
 }
 
-impl profix::FixFactory<ExampleHandler> for Factory {
-    fn connection_factory(&self) -> Result<FixClient, ConnectionFailure> {
-        Ok(FixClient::new(CompIds { sender : "server".to_string(), target : "client".to_string() },
-                          Box::new(PlainStreamWrapper::new(self.listener.incoming().next().expect("failed to finish establshin connection").expect("2") )),
-        ))
-    }
-
-    fn handler_factory(&self) -> ExampleHandler {
+impl profix::AcceptorFactory<ExampleHandler, Action> for Factory {
+    fn handler_factory(&self, _comp_ids: &CompIds) -> ExampleHandler {
         ExampleHandler {
-            is_logged : false,
             tx : self.tx.clone(),
+            correlator : Correlator::new(Duration::from_secs(10)),
 
             this_second : Instant::now(),
             messages_this_second : 0,
@@ -162,15 +157,17 @@ fn main() {
     info!("Starting server");
     let listener = TcpListener::bind("0.0.0.0:3213").expect("couldnt create server");
 
-    let (action_tx, action_rx) = channel();
     let (feedback_tx, feedback_rx) = channel();
 
     let factory = Factory {
         tx : feedback_tx.clone(),
-        listener,
     };
 
-    profix::fix_loop(factory, action_rx);
+    let mut acceptor = Acceptor::new(listener, factory);
+    acceptor.add_session(CompIds { sender : "server".to_string(), target : "client".to_string() }, SessionConfig::default());
+    if let Err(e) = acceptor.run::<ExampleSessionMessage, ExampleAppMessage, ExampleHandler, Action>() {
+        error!("acceptor failed: {:?}", e);
+    }
 
     println!("Hello, world!");
 }
@@ -0,0 +1,367 @@
+extern crate profix;
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+use std::sync::Arc;
+
+use profix::detail::{begin_string, parse_fix_field, validate_fix_message};
+use profix::{Dictionary, PrettyPrinter};
+
+const USAGE: &str = "\
+usage: fixdecode [OPTIONS] [FILE...]
+
+Decodes the FIX messages found in FILEs, or stdin, one or more per line. Lines may carry a
+prefix such as the timestamp of a log, fields are delimited by SOH or `|`.
+
+options:
+  -t, --msg-type TYPE      only messages of MsgType TYPE, may be repeated
+  -f, --field TAG=VALUE    only messages with a field TAG, by number or name, of VALUE, may be repeated
+  -s, --seq FIRST-LAST     only messages with a MsgSeqNum in the range, either end may be left out
+  -d, --dictionary FILE    QuickFIX data dictionary to use instead of the built-in FIX 4.4 one
+  -m, --multiline          one field per line, repeating groups indented
+  -j, --json               one JSON object per message
+  -h, --help               this text
+
+Messages failing the BodyLength or CheckSum validation are still printed, the error goes to
+stderr and the exit status is 1.";
+
+#[derive(Debug, Default)]
+struct Options {
+    files: Vec<String>,
+    msg_types: Vec<String>,
+    fields: Vec<(String, String)>,
+    seqs: Option<(u64, u64)>,
+    dictionary: Option<String>,
+    multiline: bool,
+    json: bool,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("{} needs a value", name));
+        match arg.as_str() {
+            "-t" | "--msg-type" => options.msg_types.push(value(&arg)?),
+            "-f" | "--field" => {
+                let field = value(&arg)?;
+                let eq = field.find('=').ok_or_else(|| format!("{} is not TAG=VALUE", field))?;
+                options
+                    .fields
+                    .push((field[..eq].to_string(), field[eq + 1..].to_string()));
+            }
+            "-s" | "--seq" => {
+                let range = value(&arg)?;
+                options.seqs = Some(parse_range(&range).ok_or_else(|| format!("{} is not FIRST-LAST", range))?);
+            }
+            "-d" | "--dictionary" => options.dictionary = Some(value(&arg)?),
+            "-m" | "--multiline" => options.multiline = true,
+            "-j" | "--json" => options.json = true,
+            "-h" | "--help" => return Err(String::new()),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option {}", arg)),
+            _ => options.files.push(arg),
+        }
+    }
+    Ok(options)
+}
+
+fn parse_range(range: &str) -> Option<(u64, u64)> {
+    let (first, last) = match range.find('-') {
+        Some(dash) => (&range[..dash], &range[dash + 1..]),
+        None => (range, range),
+    };
+    let first = if first.is_empty() { 0 } else { first.parse().ok()? };
+    let last = if last.is_empty() { u64::MAX } else { last.parse().ok()? };
+    Some((first, last))
+}
+
+// the FIX messages within a line, from each `8=FIX` to the end of its CheckSum field.
+fn messages(line: &str) -> Vec<Vec<u8>> {
+    let line = line.trim_end();
+    let line = if line.contains('\x01') {
+        line.to_string()
+    } else {
+        line.replace('|', "\x01")
+    };
+
+    let mut messages = Vec::new();
+    let mut rest = line.as_str();
+    while let Some(start) = begin_string(rest) {
+        rest = &rest[start..];
+        let end = match rest.find("\x0110=") {
+            Some(trailer) => rest[trailer + 1..]
+                .find('\x01')
+                .map_or(rest.len(), |soh| trailer + 1 + soh + 1),
+            None => rest.len(),
+        };
+        let mut msg = rest.as_bytes()[..end].to_vec();
+        if msg.last() != Some(&b'\x01') {
+            msg.push(b'\x01');
+        }
+        messages.push(msg);
+        rest = &rest[end..];
+    }
+    messages
+}
+
+fn fields(msg: &[u8]) -> Vec<(u64, String)> {
+    let mut fields = Vec::new();
+    let mut rest = msg;
+    while !rest.is_empty() {
+        match parse_fix_field(rest) {
+            Ok(field) => {
+                fields.push((field.id, String::from_utf8_lossy(field.value).into_owned()));
+                rest = &rest[field.length..];
+            }
+            Err(_) => break,
+        }
+    }
+    fields
+}
+
+struct Filter {
+    msg_types: Vec<String>,
+    fields: Vec<(u64, String)>,
+    seqs: Option<(u64, u64)>,
+}
+
+impl Filter {
+    fn new(options: &Options, dictionary: &Dictionary) -> Result<Filter, String> {
+        let mut fields = Vec::new();
+        for (tag, value) in &options.fields {
+            let tag = match tag.parse() {
+                Ok(tag) => tag,
+                Err(_) => dictionary.tag_of(tag).ok_or_else(|| format!("unknown field {}", tag))?,
+            };
+            fields.push((tag, value.clone()));
+        }
+        Ok(Filter {
+            msg_types: options.msg_types.clone(),
+            fields,
+            seqs: options.seqs,
+        })
+    }
+
+    fn matches(&self, fields: &[(u64, String)]) -> bool {
+        let value = |tag| fields.iter().find(|field| field.0 == tag).map(|field| field.1.as_str());
+        if !self.msg_types.is_empty() {
+            match value(35) {
+                Some(msg_type) if self.msg_types.iter().any(|wanted| wanted == msg_type) => (),
+                _ => return false,
+            }
+        }
+        if !self.fields.iter().all(|wanted| fields.contains(wanted)) {
+            return false;
+        }
+        if let Some((first, last)) = self.seqs {
+            match value(34).and_then(|seq| seq.parse::<u64>().ok()) {
+                Some(seq) if seq >= first && seq <= last => (),
+                _ => return false,
+            }
+        }
+        true
+    }
+}
+
+fn to_json(
+    source: &str,
+    line: usize,
+    fields: &[(u64, String)],
+    error: Option<&str>,
+    dictionary: &Dictionary,
+) -> String {
+    let mut json = format!(
+        "{{\"source\":{},\"line\":{},\"valid\":{},\"error\":{},\"fields\":[",
+        json_string(source),
+        line,
+        error.is_none(),
+        error.map_or("null".to_string(), json_string)
+    );
+    for (i, &(tag, ref value)) in fields.iter().enumerate() {
+        if i > 0 {
+            json.push(',');
+        }
+        json.push_str(&format!(
+            "{{\"tag\":{},\"name\":{},\"value\":{}",
+            tag,
+            dictionary.field_name(tag).map_or("null".to_string(), json_string),
+            json_string(value)
+        ));
+        if let Some(name) = dictionary.value_name(tag, value) {
+            json.push_str(&format!(",\"enum\":{}", json_string(name)));
+        }
+        json.push('}');
+    }
+    json.push_str("]}");
+    json
+}
+
+fn json_string(text: &str) -> String {
+    let mut json = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if (c as u32) < 0x20 => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// returns how many messages failed validation.
+fn decode<R: BufRead, W: Write>(
+    source: &str,
+    input: R,
+    out: &mut W,
+    options: &Options,
+    filter: &Filter,
+    printer: &PrettyPrinter,
+) -> io::Result<usize> {
+    let mut invalid = 0;
+    for (number, line) in input.split(b'\n').enumerate() {
+        let line = line?;
+        for msg in messages(&String::from_utf8_lossy(&line)) {
+            let fields = fields(&msg);
+            if !filter.matches(&fields) {
+                continue;
+            }
+            let error = validate_fix_message(&msg).err();
+            if let Some(error) = error {
+                invalid += 1;
+                eprintln!("fixdecode: {}:{}: {}", source, number + 1, error);
+            }
+
+            if options.json {
+                writeln!(
+                    out,
+                    "{}",
+                    to_json(source, number + 1, &fields, error, printer.dictionary())
+                )?;
+            } else {
+                // multiline messages end in a newline, so they are apart.
+                writeln!(out, "{}", printer.format(&msg))?;
+            }
+        }
+    }
+    Ok(invalid)
+}
+
+fn run(options: &Options) -> Result<usize, String> {
+    let dictionary = match options.dictionary {
+        Some(ref path) => Dictionary::load(path).map_err(|err| format!("{}: {}", path, err))?,
+        None => Dictionary::fix44(),
+    };
+    let filter = Filter::new(options, &dictionary)?;
+    let mut printer = PrettyPrinter::new(Arc::new(dictionary));
+    printer.set_multiline(options.multiline);
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut invalid = 0;
+    if options.files.is_empty() {
+        let stdin = io::stdin();
+        invalid += decode("-", stdin.lock(), &mut out, options, &filter, &printer).map_err(|err| err.to_string())?;
+    }
+    for path in &options.files {
+        let result = if path == "-" {
+            let stdin = io::stdin();
+            decode("-", stdin.lock(), &mut out, options, &filter, &printer)
+        } else {
+            let file = File::open(path).map_err(|err| format!("{}: {}", path, err))?;
+            decode(path, BufReader::new(file), &mut out, options, &filter, &printer)
+        };
+        invalid += result.map_err(|err| format!("{}: {}", path, err))?;
+    }
+    Ok(invalid)
+}
+
+fn main() {
+    let options = match parse_args(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            if !err.is_empty() {
+                eprintln!("fixdecode: {}", err);
+            }
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+
+    match run(&options) {
+        Ok(0) => (),
+        Ok(_) => process::exit(1),
+        Err(err) => {
+            eprintln!("fixdecode: {}", err);
+            process::exit(2);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn args(args: &str) -> Options {
+        parse_args(args.split_whitespace().map(|arg| arg.to_string())).unwrap()
+    }
+
+    #[test]
+    fn test_messages_in_log_lines() {
+        let line = "20200101-00:00:00.000 : 8=FIX.4.4|9=5|35=0|10=163|8=FIX.4.4|9=5|35=0|58=8=FIX|10=163";
+        let found = messages(line);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0], b"8=FIX.4.4\x019=5\x0135=0\x0110=163\x01".to_vec());
+        assert_eq!(found[1], b"8=FIX.4.4\x019=5\x0135=0\x0158=8=FIX\x0110=163\x01".to_vec());
+        assert!(messages("no fix here").is_empty());
+    }
+
+    #[test]
+    fn test_filters() {
+        let fields = fields(b"8=FIX.4.4\x019=5\x0135=D\x0134=7\x0155=EURUSD\x0110=000\x01");
+        let dictionary = Dictionary::fix44();
+        let matches = |args_: &str| Filter::new(&args(args_), &dictionary).unwrap().matches(&fields);
+        assert!(matches(""));
+        assert!(matches("-t 8 -t D"));
+        assert!(!matches("-t 8"));
+        assert!(matches("-f Symbol=EURUSD -f 34=7"));
+        assert!(!matches("-f 55=GBPUSD"));
+        assert!(matches("-s 5-"));
+        assert!(matches("-s -7"));
+        assert!(!matches("-s 8-10"));
+        assert!(Filter::new(&args("-f Unknown=1"), &dictionary).is_err());
+    }
+
+    #[test]
+    fn test_json() {
+        let msg = b"8=FIX.4.4\x019=5\x0135=0\x0110=163\x01";
+        let input = format!("{}\n", String::from_utf8_lossy(msg));
+        let options = args("-j");
+        let filter = Filter::new(&options, &Dictionary::fix44()).unwrap();
+        let mut out = Vec::new();
+        let invalid = decode(
+            "a.log",
+            input.as_bytes(),
+            &mut out,
+            &options,
+            &filter,
+            &PrettyPrinter::default(),
+        )
+        .unwrap();
+        assert_eq!(invalid, 0);
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "{\"source\":\"a.log\",\"line\":1,\"valid\":true,\"error\":null,\"fields\":[\
+             {\"tag\":8,\"name\":\"BeginString\",\"value\":\"FIX.4.4\"},\
+             {\"tag\":9,\"name\":\"BodyLength\",\"value\":\"5\"},\
+             {\"tag\":35,\"name\":\"MsgType\",\"value\":\"0\",\"enum\":\"Heartbeat\"},\
+             {\"tag\":10,\"name\":\"CheckSum\",\"value\":\"163\"}]}\n"
+        );
+        assert_eq!(json_string("a\"b\\\x01"), "\"a\\\"b\\\\\\u0001\"");
+    }
+}
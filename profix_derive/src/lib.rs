@@ -7,7 +7,7 @@ extern crate syn;
 
 use proc_macro::TokenStream;
 
-#[proc_macro_derive(FixSerialize, attributes(msg_type, id))]
+#[proc_macro_derive(FixSerialize, attributes(msg_type, id, precision))]
 pub fn fix_serialize(input: TokenStream) -> TokenStream {
     let s = input.to_string();
     let ast = syn::parse_derive_input(&s).unwrap();
@@ -15,7 +15,7 @@ pub fn fix_serialize(input: TokenStream) -> TokenStream {
     gen.parse().unwrap()
 }
 
-#[proc_macro_derive(FixDeserialize, attributes(msg_type, id))]
+#[proc_macro_derive(FixDeserialize, attributes(msg_type, id, precision))]
 pub fn fix_deserialize(input: TokenStream) -> TokenStream {
     let s = input.to_string();
     let ast = syn::parse_derive_input(&s).unwrap();
@@ -27,7 +27,7 @@ pub fn fix_deserialize(input: TokenStream) -> TokenStream {
     }
 }
 
-#[proc_macro_derive(FixDeserializeGroup, attributes(id))]
+#[proc_macro_derive(FixDeserializeGroup, attributes(id, precision))]
 pub fn fix_deserialize_group(input: TokenStream) -> TokenStream {
     let s = input.to_string();
     let ast = syn::parse_derive_input(&s).unwrap();
@@ -50,7 +50,7 @@ pub fn fix_parse(input: TokenStream) -> TokenStream {
     }
 }
 
-#[proc_macro_derive(FixHeader)]
+#[proc_macro_derive(FixHeader, attributes(id))]
 pub fn fix_header(input: TokenStream) -> TokenStream {
     let s = input.to_string();
     let ast = syn::parse_derive_input(&s).unwrap();
@@ -68,17 +68,40 @@ fn impl_fix_serialize(ast: syn::DeriveInput) -> quote::Tokens {
     if let syn::Body::Struct(syn::VariantData::Struct(fields)) = ast.body {
         let fields = find_fix_fields(&fields);
 
-        let ids = fields.iter().map(|f| f.id.to_string());
-        let idents = fields.iter().map(|f| f.ident.clone());
+        let writes = fields.iter().map(|f| {
+            let ident = &f.ident;
+            let id = f.id.to_string();
+            let value = match f.precision {
+                Some(ref precision) => quote! { _value.with_precision(::profix::TimestampPrecision::#precision) },
+                None => quote! { _value },
+            };
+            if is_option(&f.ty) {
+                quote! {
+                    if let Some(ref _value) = self.#ident {
+                        write!(_out, concat!(#id, "={}\x01"), #value).unwrap();
+                    }
+                }
+            } else {
+                quote! {
+                    {
+                        let _value = &self.#ident;
+                        write!(_out, concat!(#id, "={}\x01"), #value).unwrap();
+                    }
+                }
+            }
+        });
         let dummy_const = syn::Ident::new(format!("_IMPL_FIX_SERIALIZE_FOR_{}", name));
 
         quote! {
             #[allow(non_upper_case_globals)]
             const #dummy_const: () = {
-                extern crate profix;
-                impl profix::detail::FixSerializable for #name {
+                impl ::profix::detail::FixSerializable for #name {
                     fn serialize_body_to_fix(&self) -> String {
-                        format!(concat!("35=", #msg_type, "\x01", concat!(#(#ids, "={}\x01"),* )), #(self.#idents),*)
+                        use std::fmt::Write;
+
+                        let mut _out = String::from(concat!("35=", #msg_type, "\x01"));
+                        #( #writes )*
+                        _out
                     }
                 }
             };
@@ -135,9 +158,8 @@ fn impl_fix_parse_enum(name: syn::Ident, variants: Vec<syn::Variant>) -> quote::
     let tokens = quote! {
         #[allow(non_upper_case_globals)]
         const #dummy_const: () = {
-            extern crate profix;
-            impl profix::FixParse for #name {
-                fn parse(value: &[u8]) -> Result<Self, profix::ParseError> {
+            impl ::profix::FixParse for #name {
+                fn parse(value: &[u8]) -> Result<Self, ::profix::ParseError> {
                     #(
                         if value == #values {
                             return Ok(#names::#idents);
@@ -155,22 +177,76 @@ fn impl_fix_parse_enum(name: syn::Ident, variants: Vec<syn::Variant>) -> quote::
 fn impl_fix_header(ast: syn::DeriveInput) -> quote::Tokens {
     let name = &ast.ident;
 
+    let fields = match ast.body {
+        syn::Body::Struct(syn::VariantData::Struct(ref fields)) => find_fix_fields(fields),
+        _ => panic!("#[derive(FixHeader)] is only defined for structs"),
+    };
+    // header fields are found by their id, a field of another type than the header needs is a compile error.
+    let header_field = |id: u64, ty: &str| {
+        fields.iter().find(|f| f.id == id).map(|f| {
+            let found = type_name(&f.ty);
+            if found != ty {
+                panic!("#[derive(FixHeader)] {}.{} with id {} must be {}, not {}", name, f.ident, id, ty, found);
+            }
+            f.ident.clone()
+        })
+    };
+    let required_field = |id: u64, ty: &str| match header_field(id, ty) {
+        Some(ident) => ident,
+        None => panic!("#[derive(FixHeader)] {} has no field with id {}", name, id),
+    };
+
+    let seq = required_field(34, "u64");
+    let sender = required_field(49, "String");
+    let target = required_field(56, "String");
+    // optional header fields are implemented when the struct declares them.
+    let sending_time = match header_field(52, "Timestamp") {
+        Some(ident) => quote! {
+            fn sending_time(&self) -> Option<::profix::Timestamp> {
+                Some(self.#ident)
+            }
+        },
+        None => quote! {},
+    };
+    let poss_dup = match header_field(43, "Option<Flag>") {
+        Some(ident) => quote! {
+            fn poss_dup(&self) -> bool {
+                self.#ident == Some(::profix::admin::Flag::Yes)
+            }
+        },
+        None => quote! {},
+    };
+    // a PossDup message is rejected without OrigSendingTime, so a struct which can be one has to carry it.
+    let orig_sending_time = match header_field(122, "Option<Timestamp>") {
+        Some(ident) => quote! {
+            fn orig_sending_time(&self) -> Option<::profix::Timestamp> {
+                self.#ident
+            }
+        },
+        None if poss_dup.as_str().is_empty() => quote! {},
+        None => panic!("#[derive(FixHeader)] {} has PossDupFlag (43) but no OrigSendingTime (122)", name),
+    };
+
     let dummy_const = syn::Ident::new(format!("_IMPL_SMART_ENUM_DERIVE_FOR_{}", name));
     quote! {
     #[allow(non_upper_case_globals)]
             const #dummy_const: () = {
             impl ::profix::FixHeader for #name {
                 fn seq(&self) -> u64 {
-                    self.seq
+                    self.#seq
                 }
 
                 fn sender(&self) -> &str {
-                    &self.sender
+                    &self.#sender
                 }
 
                 fn target(&self) -> &str {
-                    &self.target
+                    &self.#target
                 }
+
+                #sending_time
+                #poss_dup
+                #orig_sending_time
             }
     };
         }
@@ -199,9 +275,9 @@ fn generate_parser_internals(name: &syn::Ident, fields: &Vec<FixField>) -> Parse
                 });
                 parses.push(quote! {
                     #id => {
-                        use profix::detail::FixDeserializableGroup as _FDG;
+                        use ::profix::detail::FixDeserializableGroup as _FDG;
 
-                        let _len: usize = profix::FixParse::parse(_field.value)?;
+                        let _len: usize = ::profix::FixParse::parse(_field.value)?;
                         if _input.len() <= _field.length {
                             return Err(#err_input_end_before_checksum);
                         }
@@ -231,7 +307,7 @@ fn generate_parser_internals(name: &syn::Ident, fields: &Vec<FixField>) -> Parse
                 parses.push(quote! {
                     #id => {
                         if #out.is_none() {
-                            #out = Some(profix::FixParse::parse(_field.value)?);
+                            #out = Some(::profix::FixParse::parse(_field.value)?);
                         } else {
                             return Err(#err_multiple);
                         }
@@ -249,7 +325,7 @@ fn generate_parser_internals(name: &syn::Ident, fields: &Vec<FixField>) -> Parse
                 parses.push(quote! {
                     #id => {
                         if #out.is_err() {
-                            #out = Ok(profix::FixParse::parse(_field.value)?);
+                            #out = Ok(::profix::FixParse::parse(_field.value)?);
                         } else {
                             return Err(#err_multiple);
                         }
@@ -274,53 +350,59 @@ fn impl_fix_deserialize_group_struct(
     let ParserInternals { intros, parses, conses } = generate_parser_internals(&name, &fields);
     let parses_head = &parses[0];
     let parses_tail = &parses[1..];
+    let head_id = fields[0].id;
 
     let dummy_const = syn::Ident::new(format!("_IMPL_FIX_DESERIALIZE_GROUP_FOR_{}", name));
 
     let err_input_end_before_checksum = format!("{} input ended before checksum", name);
+    let err_count = format!("{} entries do not match the group count", name);
+
+    // a field no entry declares belongs to the entry before the last, the group continues with the
+    // first field of the next entry. After the last entry it is the next field of the message.
+    let parse_tail_loop = quote! {
+        loop {
+            match _field.id {
+                #( #parses_tail )*
+                _ if _field.id != #head_id && _field.id != 10 && _out.len() + 1 < _expected_length => {}
+                _ => break
+            }
 
-    let parse_tail_loop = if parses_tail.is_empty() {
-        quote! {}
-    } else {
-        quote! {
-            loop {
-                match _field.id {
-                    #( #parses_tail )*
-                    _ => break
-                }
-
-                if _input.len() <= _field.length {
-                    return Err(#err_input_end_before_checksum);
-                }
-                _input = &_input[_field.length..];
-                _checksum += _field.checksum;
-                _field = profix::detail::parse_fix_field(_input)?;
+            if _input.len() <= _field.length {
+                return Err(#err_input_end_before_checksum);
             }
+            _input = &_input[_field.length..];
+            _checksum += _field.checksum;
+            _field = ::profix::detail::parse_fix_field(_input)?;
         }
     };
 
     let tokens = quote! {
         #[allow(non_upper_case_globals)]
         const #dummy_const: () = {
-            extern crate profix;
 
-            impl profix::detail::FixDeserializableGroup for #name {
+            impl ::profix::detail::FixDeserializableGroup for #name {
                 fn deserialize_group_from_fix(_expected_length: usize, _input_arg: &[u8])
-                    -> Result<(Vec<Self>, profix::detail::ParserContinuation), profix::ParseError>
+                    -> Result<(Vec<Self>, ::profix::detail::ParserContinuation), ::profix::ParseError>
                 {
                     let mut _input = _input_arg;
                     let mut _checksum = ::std::num::Wrapping(0u8);
                     let mut _out = Vec::new();
                     _out.reserve(_expected_length);
 
-                    let mut _field = profix::detail::parse_fix_field(_input)?;
+                    let mut _field = ::profix::detail::parse_fix_field(_input)?;
                     loop {
                         #( #intros )*
 
+                        if _field.id == #head_id && _out.len() == _expected_length {
+                            return Err(#err_count);
+                        }
                         match _field.id {
                             #parses_head
                             _ => {
-                                let cont = profix::detail::ParserContinuation {
+                                if _out.len() != _expected_length {
+                                    return Err(#err_count);
+                                }
+                                let cont = ::profix::detail::ParserContinuation {
                                     checksum: _checksum,
                                     next_input: _input,
                                     next_field: _field,
@@ -334,7 +416,7 @@ fn impl_fix_deserialize_group_struct(
                         }
                         _input = &_input[_field.length..];
                         _checksum += _field.checksum;
-                        _field = profix::detail::parse_fix_field(_input)?;
+                        _field = ::profix::detail::parse_fix_field(_input)?;
 
                         #parse_tail_loop
 
@@ -373,20 +455,19 @@ fn impl_fix_deserialize_struct(
     let tokens = quote! {
         #[allow(non_upper_case_globals)]
         const #dummy_const: () = {
-            extern crate profix;
             use std::num::Wrapping;
 
-            impl profix::detail::FixMessageType for #name {
+            impl ::profix::detail::FixMessageType for #name {
                 const MSG_TYPE: &'static [u8] = &#msg_type_bytes;
             }
 
-            impl profix::detail::FixDeserializable for #name {
-                fn deserialize_from_fix(_msg: profix::detail::FixMessage) -> Result<Self, profix::ParseError> {
+            impl ::profix::detail::FixDeserializable for #name {
+                fn deserialize_from_fix(_msg: ::profix::detail::FixMessage) -> Result<Self, ::profix::ParseError> {
                     #( #intros )*
 
                     let mut _input = _msg.body;
                     let mut _checksum = _msg.header_checksum;
-                    let mut _field = profix::detail::parse_fix_field(_input)?;
+                    let mut _field = ::profix::detail::parse_fix_field(_input)?;
                     loop {
                         match _field.id {
                             #( #parses )*
@@ -397,7 +478,7 @@ fn impl_fix_deserialize_struct(
                                     return Err(#err_input_after_checksum);
                                 }
                             */
-                                let _parsed_checksum: u8 = profix::FixParse::parse(_field.value)?;
+                                let _parsed_checksum: u8 = ::profix::FixParse::parse(_field.value)?;
                                 if Wrapping(_parsed_checksum) != _checksum {
                                     return Err(#err_invalid_checksum);
                                 }
@@ -414,7 +495,7 @@ fn impl_fix_deserialize_struct(
                         }
                         _checksum += _field.checksum;
                         _input = &_input[_field.length..];
-                        _field = profix::detail::parse_fix_field(_input)?;
+                        _field = ::profix::detail::parse_fix_field(_input)?;
                     }
                 }
             }
@@ -450,14 +531,13 @@ fn impl_fix_deserialize_enum(name: syn::Ident, variants: Vec<syn::Variant>) -> q
     let tokens = quote! {
         #[allow(non_upper_case_globals)]
         const #dummy_const: () = {
-            extern crate profix;
-            use profix::detail::FixMessageType;
+            use ::profix::detail::FixMessageType;
 
-            impl profix::detail::FixDeserializable for #name {
-                fn deserialize_from_fix(msg: profix::detail::FixMessage) -> Result<Self, profix::ParseError> {
+            impl ::profix::detail::FixDeserializable for #name {
+                fn deserialize_from_fix(msg: ::profix::detail::FixMessage) -> Result<Self, ::profix::ParseError> {
                     #(
                         if msg.msg_type == #tys::MSG_TYPE {
-                            return Ok(#names::#cases(profix::detail::FixDeserializable::deserialize_from_fix(msg)?));
+                            return Ok(#names::#cases(::profix::detail::FixDeserializable::deserialize_from_fix(msg)?));
                         }
                     )*
 
@@ -469,10 +549,35 @@ fn impl_fix_deserialize_enum(name: syn::Ident, variants: Vec<syn::Variant>) -> q
     tokens
 }
 
+fn is_option(ty: &syn::Ty) -> bool {
+    match *ty {
+        syn::Ty::Path(_, ref path) => path.segments.last().unwrap().ident == "Option",
+        _ => false,
+    }
+}
+
+// a type by the last segment of its path and those of its type arguments, e.g. `Option<Timestamp>`.
+fn type_name(ty: &syn::Ty) -> String {
+    match *ty {
+        syn::Ty::Path(_, ref path) => {
+            let segment = path.segments.last().unwrap();
+            match segment.parameters {
+                syn::PathParameters::AngleBracketed(ref data) if !data.types.is_empty() => {
+                    let args: Vec<_> = data.types.iter().map(type_name).collect();
+                    format!("{}<{}>", segment.ident, args.join(", "))
+                }
+                _ => segment.ident.to_string(),
+            }
+        }
+        _ => quote!(#ty).to_string(),
+    }
+}
+
 struct FixField {
     id: u64,
     ident: syn::Ident,
     ty: syn::Ty,
+    precision: Option<syn::Ident>,
 }
 
 fn find_fix_fields(fields: &[syn::Field]) -> Vec<FixField> {
@@ -484,16 +589,31 @@ fn find_fix_fields(fields: &[syn::Field]) -> Vec<FixField> {
                 Ok(id) => id,
                 Err(e) => panic!("Could not parse ID as u64: {} {}", id, e),
             };
+            let precision = find_optional_attr("precision", &field.attrs).map(|p| match p.as_str() {
+                "seconds" => syn::Ident::new("Seconds"),
+                "millis" => syn::Ident::new("Millis"),
+                "micros" => syn::Ident::new("Micros"),
+                "nanos" => syn::Ident::new("Nanos"),
+                _ => panic!("precision must be one of seconds, millis, micros, nanos: {}", p),
+            });
             FixField {
                 id,
                 ident: field.ident.clone().unwrap(),
                 ty: field.ty.clone(),
+                precision,
             }
         })
         .collect()
 }
 
 fn find_attr(name: &str, attrs: &[syn::Attribute]) -> String {
+    match find_optional_attr(name, attrs) {
+        Some(x) => x,
+        None => panic!("{} not found", name),
+    }
+}
+
+fn find_optional_attr(name: &str, attrs: &[syn::Attribute]) -> Option<String> {
     let mut result = None;
     for attr in attrs {
         if let syn::AttrStyle::Outer = attr.style {
@@ -513,8 +633,5 @@ fn find_attr(name: &str, attrs: &[syn::Attribute]) -> String {
         }
     }
 
-    match result {
-        Some(x) => x,
-        None => panic!("{} not found", name),
-    }
+    result
 }
@@ -0,0 +1,490 @@
+use std::collections::HashSet;
+use std::fmt::Debug;
+use std::io::{Error, ErrorKind};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use mio;
+use mio::{Events, Interest, Poll, Token};
+
+use client::tcp_event_source;
+use detail::{find_field, parse_fix_message, FixDeserializable};
+use event_loop::update_interest;
+use runner::{logout, StopHandle, STOP_CHECK_INTERVAL};
+use session::{frame_length, Drive, Session, SessionError, LOGON_MSG_TYPE};
+use {CompIds, FixClient, FixHandler, PlainStreamWrapper, SessionConfig, Stream, TlsConfig};
+
+const SENDER_COMP_ID: u64 = 49;
+const TARGET_COMP_ID: u64 = 56;
+const LISTENER: Token = Token(0);
+const TRANSPORT: Token = Token(0);
+
+pub trait AcceptorFactory<Handler, Action> {
+    //called for every session that logged on, before its Logon is handled.
+    fn handler_factory(&self, comp_ids: &CompIds) -> Handler;
+    //actions sent through `actions` are handled by the session's handler.
+    fn session_started(&self, _comp_ids: &CompIds, _actions: Sender<Action>) {}
+    fn session_ended(&self, _comp_ids: &CompIds) {}
+}
+
+/// Accepts connections and serves every configured session on its own thread.
+///
+/// The first message of a connection has to be a Logon, its TargetCompID and SenderCompID
+/// pick the session added with `add_session`. Unknown and already logged on sessions are
+/// logged out right away. Once stopped the acceptor no longer accepts and every session logs out.
+/// With `set_tls` connections have to complete a TLS handshake before sending their Logon.
+pub struct Acceptor<Factory> {
+    listener: TcpListener,
+    factory: Arc<Factory>,
+    sessions: Vec<(CompIds, SessionConfig)>,
+    tls: Option<TlsConfig>,
+    // (sender, target) of the sessions logged on.
+    active: Arc<Mutex<HashSet<(String, String)>>>,
+    stop: StopHandle,
+
+    pub logon_timeout: Duration,
+    pub logout_timeout: Duration,
+}
+
+// everything a connection thread needs.
+struct Connection<Factory> {
+    factory: Arc<Factory>,
+    sessions: Vec<(CompIds, SessionConfig)>,
+    tls: Option<TlsConfig>,
+    active: Arc<Mutex<HashSet<(String, String)>>>,
+    stop: StopHandle,
+    logon_timeout: Duration,
+    logout_timeout: Duration,
+}
+
+impl<Factory> Acceptor<Factory> {
+    pub fn new(listener: TcpListener, factory: Factory) -> Acceptor<Factory> {
+        Acceptor {
+            listener,
+            factory: Arc::new(factory),
+            sessions: Vec::new(),
+            tls: None,
+            active: Arc::new(Mutex::new(HashSet::new())),
+            stop: StopHandle::new(),
+
+            logon_timeout: Duration::from_secs(10),
+            logout_timeout: Duration::from_secs(10),
+        }
+    }
+
+    // comp_ids are ours, i.e. sender is the TargetCompID of the counterparty's Logon.
+    pub fn add_session(&mut self, comp_ids: CompIds, config: SessionConfig) {
+        self.sessions.push((comp_ids, config));
+    }
+
+    // needs an identity, the certificate presented to the counterparties.
+    pub fn set_tls(&mut self, tls: TlsConfig) {
+        self.tls = Some(tls);
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.listener.local_addr()
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    // accepts until stopped, sessions then end on their own threads.
+    pub fn run<Sess, App, H, Action>(&self) -> Result<(), Error>
+    where
+        Sess: FixDeserializable + Debug + 'static,
+        App: FixDeserializable + Debug + 'static,
+        H: FixHandler<Sess, App, Action> + 'static,
+        Factory: AcceptorFactory<H, Action> + Send + Sync + 'static,
+        Action: Debug + Send + 'static,
+    {
+        self.listener.set_nonblocking(true)?;
+        let mut source = mio::net::TcpListener::from_std(self.listener.try_clone()?);
+        let mut poll = Poll::new()?;
+        poll.registry().register(&mut source, LISTENER, Interest::READABLE)?;
+        let mut events = Events::with_capacity(16);
+
+        while !self.stop.is_stopped() {
+            if let Err(err) = poll.poll(&mut events, Some(STOP_CHECK_INTERVAL)) {
+                if err.kind() != ErrorKind::Interrupted {
+                    return Err(err);
+                }
+            }
+
+            loop {
+                match self.listener.accept() {
+                    Ok((stream, addr)) => {
+                        info!("accepted connection from {}", addr);
+                        self.spawn_connection::<Sess, App, H, Action>(stream);
+                    }
+                    Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                    Err(err) => {
+                        error!("accept failed: {:?}", err);
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn spawn_connection<Sess, App, H, Action>(&self, stream: TcpStream)
+    where
+        Sess: FixDeserializable + Debug + 'static,
+        App: FixDeserializable + Debug + 'static,
+        H: FixHandler<Sess, App, Action> + 'static,
+        Factory: AcceptorFactory<H, Action> + Send + Sync + 'static,
+        Action: Debug + Send + 'static,
+    {
+        let connection = Connection {
+            factory: self.factory.clone(),
+            sessions: self.sessions.clone(),
+            tls: self.tls.clone(),
+            active: self.active.clone(),
+            stop: self.stop.clone(),
+            logon_timeout: self.logon_timeout,
+            logout_timeout: self.logout_timeout,
+        };
+        thread::spawn(move || {
+            if let Err(err) = connection.serve::<Sess, App, H, Action>(stream) {
+                error!("session ended with {:?}", err);
+            }
+        });
+    }
+}
+
+impl<Factory> Connection<Factory> {
+    fn serve<Sess, App, H, Action>(self, stream: TcpStream) -> Result<(), SessionError>
+    where
+        Sess: FixDeserializable + Debug,
+        App: FixDeserializable + Debug,
+        H: FixHandler<Sess, App, Action>,
+        Factory: AcceptorFactory<H, Action>,
+        Action: Debug,
+    {
+        stream.set_nonblocking(false)?;
+        // the handshake and the Logon have to complete within the logon timeout.
+        stream.set_read_timeout(Some(self.logon_timeout))?;
+        // the transport may own the stream, the control clone is used for timeouts and readiness.
+        let control = stream.try_clone()?;
+        let mut transport: Box<Stream> = match self.tls {
+            Some(ref tls) => Box::new(tls.accept(stream)?),
+            None => Box::new(PlainStreamWrapper::new(stream)),
+        };
+        let (received, comp_ids) = self.read_logon(&mut *transport)?;
+        control.set_read_timeout(None)?;
+
+        let config = match self.sessions.iter().find(|(ids, _)| same_session(ids, &comp_ids)) {
+            Some((_, config)) => config.clone(),
+            None => {
+                reject(transport, comp_ids, "unknown session");
+                return Ok(());
+            }
+        };
+
+        let key = (comp_ids.sender.clone(), comp_ids.target.clone());
+        if !self.active.lock().unwrap().insert(key.clone()) {
+            reject(transport, comp_ids, "session already logged on");
+            return Ok(());
+        }
+
+        let result = self.run_session::<Sess, App, H, Action>(transport, &control, comp_ids.clone(), config, &received);
+
+        self.active.lock().unwrap().remove(&key);
+        self.factory.session_ended(&comp_ids);
+        result
+    }
+
+    // reads until the first message is complete, returns everything read and the comp ids from our side.
+    fn read_logon(&self, stream: &mut Stream) -> Result<(Vec<u8>, CompIds), SessionError> {
+        let mut received = Vec::new();
+        let mut buf = [0u8; 4096];
+        let length = loop {
+            if let Some(length) = frame_length(&received) {
+                break length;
+            }
+            let size = stream.read(&mut buf)?;
+            if size == 0 {
+                return Err(SessionError::Io(Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "connection closed before Logon",
+                )));
+            }
+            received.extend_from_slice(&buf[..size]);
+        };
+
+        let comp_ids = {
+            let logon = parse_fix_message(&received[..length]).map_err(SessionError::Deserialize)?;
+            if logon.msg_type != LOGON_MSG_TYPE {
+                return Err(SessionError::Deserialize("first message is not a Logon"));
+            }
+            let field = |id| -> Result<String, SessionError> {
+                match find_field(logon.body, id).map_err(SessionError::Deserialize)? {
+                    Some(value) => str::from_utf8(value)
+                        .map(|v| v.to_string())
+                        .map_err(|_| SessionError::Deserialize("comp id is not UTF8")),
+                    None => Err(SessionError::Deserialize("Logon without comp ids")),
+                }
+            };
+            CompIds {
+                sender: field(TARGET_COMP_ID)?,
+                target: field(SENDER_COMP_ID)?,
+            }
+        };
+
+        Ok((received, comp_ids))
+    }
+
+    fn run_session<Sess, App, H, Action>(
+        &self,
+        transport: Box<Stream>,
+        control: &TcpStream,
+        comp_ids: CompIds,
+        config: SessionConfig,
+        received: &[u8],
+    ) -> Result<(), SessionError>
+    where
+        Sess: FixDeserializable + Debug,
+        App: FixDeserializable + Debug,
+        H: FixHandler<Sess, App, Action>,
+        Factory: AcceptorFactory<H, Action>,
+        Action: Debug,
+    {
+        info!("session {} -> {} logging on", comp_ids.target, comp_ids.sender);
+        let mut source = tcp_event_source(control)?;
+        let mut poll = Poll::new()?;
+        let mut interest = Interest::READABLE;
+        poll.registry().register(&mut source, TRANSPORT, interest)?;
+        let mut events = Events::with_capacity(16);
+
+        let client = FixClient::with_config(comp_ids.clone(), config, transport);
+        let handler = self.factory.handler_factory(&comp_ids);
+        let (action_tx, action_rx) = channel();
+        self.factory.session_started(&comp_ids, action_tx);
+
+        let mut session = Session::new(client, handler, &action_rx);
+        session.push_received(received)?;
+
+        loop {
+            if self.stop.is_stopped() {
+                if let Err(err) = session.handle_actions() {
+                    error!("failed to handle the remaining actions. {:?}", err);
+                }
+                logout(&mut session, self.logout_timeout, Some((&mut poll, &mut events)));
+                return Ok(());
+            }
+            if session.logout_received() {
+                info!("session {} -> {} logged out", comp_ids.target, comp_ids.sender);
+                // confirm unless the handler already answered it.
+                if !session.client().logout_sent() {
+                    session.send_logout(None)?;
+                }
+                session.client().flush()?;
+                return Ok(());
+            }
+
+            if !session.step()? {
+                // the client buffers what the socket does not take, see `FixClient::write_buffered`.
+                let wanted = session.interest();
+                update_interest(poll.registry(), &mut source, TRANSPORT, &mut interest, wanted)?;
+                let timeout = session
+                    .next_deadline()
+                    .map_or(STOP_CHECK_INTERVAL, |deadline| deadline.min(STOP_CHECK_INTERVAL));
+                if let Err(err) = poll.poll(&mut events, Some(timeout)) {
+                    if err.kind() != ErrorKind::Interrupted {
+                        return Err(SessionError::Io(err));
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn same_session(a: &CompIds, b: &CompIds) -> bool {
+    a.sender == b.sender && a.target == b.target
+}
+
+fn reject(transport: Box<Stream>, comp_ids: CompIds, text: &str) {
+    warn!("rejecting Logon of {} -> {}: {}", comp_ids.target, comp_ids.sender, text);
+    let mut client = FixClient::new(comp_ids, transport);
+    if let Err(err) = client.send_logout(Some(text.to_string())) {
+        error!("failed to reject Logon: {:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::thread::spawn;
+
+    use super::*;
+    use admin::Logout;
+    use testing::{AppMsg, Logon, NewOrder, SessionMsg, TestHandler};
+    use {serialize, HandleErr, Timestamp};
+
+    // answers the Logon with a Logon followed by `burst` orders of 10k each and the Logout with a Logout.
+    struct Handler {
+        burst: usize,
+    }
+
+    impl TestHandler for Handler {
+        type Action = ();
+
+        fn handle_session(&mut self, client: &mut FixClient, msg: SessionMsg) -> Result<(), HandleErr> {
+            match msg {
+                SessionMsg::Logon(_) => {
+                    let logon = Logon::next(client);
+                    client.send(&logon)?;
+                    for i in 0..self.burst {
+                        let order = NewOrder {
+                            account: Some("x".repeat(10_000)),
+                            ..NewOrder::next(client, &i.to_string())
+                        };
+                        client.send(&order)?;
+                    }
+                }
+                SessionMsg::Logout(_) => client.send_logout(None)?,
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    struct Factory {
+        burst: usize,
+    }
+
+    impl AcceptorFactory<Handler, ()> for Factory {
+        fn handler_factory(&self, _comp_ids: &CompIds) -> Handler {
+            Handler { burst: self.burst }
+        }
+    }
+
+    // returns the connection still open and the answer to its Logon.
+    fn connect(addr: SocketAddr, sender: &str) -> (TcpStream, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let logon = Logon::new(1, sender, "server");
+        stream.write_all(serialize(&logon).as_bytes()).unwrap();
+
+        let mut buf = [0u8; 1024];
+        let size = stream.read(&mut buf).unwrap();
+        (stream, String::from_utf8_lossy(&buf[..size]).into_owned())
+    }
+
+    fn logon(addr: SocketAddr, sender: &str) -> String {
+        connect(addr, sender).1
+    }
+
+    fn serve(clients: &[&str]) -> (SocketAddr, StopHandle, thread::JoinHandle<Result<(), Error>>) {
+        serve_burst(clients, 0)
+    }
+
+    fn serve_burst(clients: &[&str], burst: usize) -> (SocketAddr, StopHandle, thread::JoinHandle<Result<(), Error>>) {
+        let mut acceptor = Acceptor::new(TcpListener::bind("127.0.0.1:0").unwrap(), Factory { burst });
+        for client in clients {
+            let comp_ids = CompIds {
+                sender: "server".to_string(),
+                target: client.to_string(),
+            };
+            acceptor.add_session(comp_ids, SessionConfig::default());
+        }
+        let addr = acceptor.local_addr().unwrap();
+        let stop = acceptor.stop_handle();
+        let running = spawn(move || acceptor.run::<SessionMsg, AppMsg, Handler, ()>());
+        (addr, stop, running)
+    }
+
+    #[test]
+    fn test_accepts_configured_sessions_and_rejects_unknown_ones() {
+        let (addr, stop, running) = serve(&["alice", "bob"]);
+
+        let alice = spawn(move || logon(addr, "alice"));
+        let bob = spawn(move || logon(addr, "bob"));
+        for answer in &[alice.join().unwrap(), bob.join().unwrap()] {
+            assert!(answer.contains("\x0135=A\x01"));
+            assert!(answer.contains("\x0149=server\x01"));
+        }
+
+        let answer = logon(addr, "mallory");
+        assert!(answer.contains("\x0135=5\x01"));
+        assert!(answer.contains("\x0158=unknown session\x01"));
+
+        stop.stop();
+        assert!(running.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_rejects_a_session_already_logged_on() {
+        let (addr, stop, running) = serve(&["alice"]);
+
+        let (_alice, answer) = connect(addr, "alice");
+        assert!(answer.contains("\x0135=A\x01"));
+
+        let answer = logon(addr, "alice");
+        assert!(answer.contains("\x0135=5\x01"));
+        assert!(answer.contains("\x0158=session already logged on\x01"));
+
+        stop.stop();
+        assert!(running.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_logout_answered_by_the_handler_is_not_confirmed_again() {
+        let (addr, stop, running) = serve(&["alice"]);
+
+        let (mut alice, answer) = connect(addr, "alice");
+        assert!(answer.contains("\x0135=A\x01"));
+        let logout = Logout {
+            seq: 2,
+            sender: "alice".to_string(),
+            target: "server".to_string(),
+            sending_time: Timestamp::now(),
+            text: None,
+        };
+        alice.write_all(serialize(&logout).as_bytes()).unwrap();
+
+        // the acceptor closes the connection once the session logged out.
+        let mut answer = String::new();
+        alice.read_to_string(&mut answer).unwrap();
+        assert_eq!(answer.matches("\x0135=5\x01").count(), 1);
+
+        stop.stop();
+        assert!(running.join().unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_burst_larger_than_the_socket_buffers_is_delivered() {
+        let burst = 5_000;
+        let (addr, stop, running) = serve_burst(&["alice"], burst);
+
+        let mut alice = TcpStream::connect(addr).unwrap();
+        let logon = Logon::new(1, "alice", "server");
+        alice.write_all(serialize(&logon).as_bytes()).unwrap();
+        // not reading for a while, the socket buffers fill up and the session buffers the rest.
+        thread::sleep(Duration::from_millis(200));
+
+        let mut orders = 0;
+        let mut received = Vec::new();
+        let mut buf = [0u8; 65536];
+        while orders < burst {
+            let size = alice.read(&mut buf).unwrap();
+            assert!(size > 0, "connection closed after {} orders", orders);
+            received.extend_from_slice(&buf[..size]);
+            while let Some(length) = frame_length(&received) {
+                if parse_fix_message(&received[..length]).unwrap().msg_type == b"D" {
+                    orders += 1;
+                }
+                received.drain(..length);
+            }
+        }
+
+        stop.stop();
+        assert!(running.join().unwrap().is_ok());
+    }
+}
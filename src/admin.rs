@@ -0,0 +1,184 @@
+//! Standard FIX session level messages the library itself needs to send or recognise.
+//! Venue specific messages, like Logon, are still defined by the user.
+
+use std::fmt;
+
+use Timestamp;
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum Flag {
+    #[fix_value = "Y"]
+    Yes,
+    #[fix_value = "N"]
+    No,
+}
+
+impl fmt::Display for Flag {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Flag::Yes => write!(f, "Y"),
+            Flag::No => write!(f, "N"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum SessionRejectReason {
+    #[fix_value = "0"]
+    InvalidTagNumber,
+    #[fix_value = "1"]
+    RequiredTagMissing,
+    #[fix_value = "2"]
+    TagNotDefinedForMessageType,
+    #[fix_value = "3"]
+    UndefinedTag,
+    #[fix_value = "4"]
+    TagSpecifiedWithoutValue,
+    #[fix_value = "5"]
+    ValueIsIncorrect,
+    #[fix_value = "6"]
+    IncorrectDataFormat,
+    #[fix_value = "9"]
+    CompIdProblem,
+    #[fix_value = "10"]
+    SendingTimeAccuracyProblem,
+    #[fix_value = "11"]
+    InvalidMsgType,
+    #[fix_value = "13"]
+    TagAppearsMoreThanOnce,
+    #[fix_value = "99"]
+    Other,
+}
+
+impl fmt::Display for SessionRejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SessionRejectReason::InvalidTagNumber => write!(f, "0"),
+            SessionRejectReason::RequiredTagMissing => write!(f, "1"),
+            SessionRejectReason::TagNotDefinedForMessageType => write!(f, "2"),
+            SessionRejectReason::UndefinedTag => write!(f, "3"),
+            SessionRejectReason::TagSpecifiedWithoutValue => write!(f, "4"),
+            SessionRejectReason::ValueIsIncorrect => write!(f, "5"),
+            SessionRejectReason::IncorrectDataFormat => write!(f, "6"),
+            SessionRejectReason::CompIdProblem => write!(f, "9"),
+            SessionRejectReason::SendingTimeAccuracyProblem => write!(f, "10"),
+            SessionRejectReason::InvalidMsgType => write!(f, "11"),
+            SessionRejectReason::TagAppearsMoreThanOnce => write!(f, "13"),
+            SessionRejectReason::Other => write!(f, "99"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "3"]
+pub struct Reject {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "45"]
+    pub ref_seq_num: u64,
+    #[id = "371"]
+    pub ref_tag_id: Option<u64>,
+    #[id = "372"]
+    pub ref_msg_type: Option<String>,
+    #[id = "373"]
+    pub reason: Option<SessionRejectReason>,
+    #[id = "58"]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "5"]
+pub struct Logout {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "58"]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "0"]
+pub struct Heartbeat {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    // set when answering a TestRequest.
+    #[id = "112"]
+    pub test_req_id: Option<String>,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "1"]
+pub struct TestRequest {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "112"]
+    pub test_req_id: String,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "2"]
+pub struct ResendRequest {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "7"]
+    pub begin_seq_no: u64,
+    // 0 means infinity.
+    #[id = "16"]
+    pub end_seq_no: u64,
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "4"]
+pub struct SequenceReset {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+    #[id = "43"]
+    pub poss_dup: Option<Flag>,
+    #[id = "122"]
+    pub orig_sending_time: Option<Timestamp>,
+
+    #[id = "123"]
+    pub gap_fill: Option<Flag>,
+    #[id = "36"]
+    pub new_seq_no: u64,
+}
@@ -0,0 +1,1026 @@
+//! Tokio based sessions, enabled by the `async` feature.
+//! The crate is still on the 2015 edition, so the futures here are written by hand; they are awaited as usual.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Debug;
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use bytes::BytesMut;
+use futures_core::Stream as AsyncStream;
+use futures_sink::Sink;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, Instant, Sleep};
+use tokio_util::codec::{Decoder, Encoder, Framed};
+use tokio_util::sync::PollSender;
+
+use admin::{Flag, Heartbeat, Logout, ResendRequest, SequenceReset, TestRequest};
+use clock::{Clock, SystemClock};
+use detail::{find_field, parse_fix_message, FixDeserializable, FixMessageType, FixSerializable};
+use session::{frame_length, SessionError};
+use message_log::{LogMessageLog, MessageLog};
+use parsing::seq_of;
+use store::{resend_plan, MessageStore, Resend};
+use timers::SessionTimers;
+use {deserialize, serialize, CompIds, FixHeader, FixParse, ParseError, SessionConfig, Timestamp};
+
+// messages waiting to be written, `send` waits while the queue is full.
+const OUTBOUND_QUEUE_LENGTH: usize = 1024;
+// messages waiting for the `MessageStream`, reading stops while the queue is full.
+const INBOUND_QUEUE_LENGTH: usize = 1024;
+
+/// Splits a byte stream into FIX messages on their checksum field.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FixCodec;
+
+impl Decoder for FixCodec {
+    type Item = Vec<u8>;
+    type Error = Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, Error> {
+        Ok(frame_length(src).map(|length| src.split_to(length).to_vec()))
+    }
+}
+
+impl Encoder<String> for FixCodec {
+    type Error = Error;
+
+    fn encode(&mut self, msg: String, dst: &mut BytesMut) -> Result<(), Error> {
+        dst.extend_from_slice(msg.as_bytes());
+        Ok(())
+    }
+}
+
+// state shared by the session task and the client handle.
+struct Shared {
+    comp_ids: CompIds,
+    config: SessionConfig,
+    clock: Box<Clock + Send>,
+    timers: SessionTimers,
+    message_log: Box<MessageLog + Send>,
+    store: Option<Box<MessageStore + Send>>,
+
+    next_send_seq: u64,
+    // highest MsgSeqNum handed to the outbound queue. The task queues its own messages only when
+    // it caught up with next_send_seq, so they never overtake a message the user is about to send.
+    last_queued_seq: u64,
+}
+
+impl Shared {
+    fn get_next_send_seq(&mut self) -> u64 {
+        let seq = self.next_send_seq;
+        self.next_send_seq += 1;
+        seq
+    }
+
+    fn sending_time(&self) -> Timestamp {
+        Timestamp::from_clock(&*self.clock).with_precision(self.config.timestamp_precision)
+    }
+
+    fn nothing_outstanding(&self) -> bool {
+        self.last_queued_seq + 1 >= self.next_send_seq
+    }
+
+    fn store_sent(&mut self, seq: u64, msg: &str) {
+        if let Some(ref mut store) = self.store {
+            store.store(seq, msg);
+        }
+    }
+}
+
+fn closed() -> SessionError {
+    SessionError::Io(Error::new(ErrorKind::BrokenPipe, "session task ended"))
+}
+
+/// Sends messages through a session started by `AsyncSession::spawn`.
+///
+/// Like with `FixClient` the messages carry a MsgSeqNum taken from `get_next_send_seq`.
+/// Every number taken has to be sent, the session holds back heartbeats until it was.
+pub struct AsyncFixClient {
+    shared: Arc<Mutex<Shared>>,
+    tx: PollSender<String>,
+}
+
+impl AsyncFixClient {
+    pub fn comp_ids(&self) -> CompIds {
+        self.shared.lock().unwrap().comp_ids.clone()
+    }
+
+    pub fn get_next_send_seq(&self) -> u64 {
+        self.shared.lock().unwrap().get_next_send_seq()
+    }
+
+    // current time in the precision configured for this session, meant for SendingTime.
+    pub fn sending_time(&self) -> Timestamp {
+        self.shared.lock().unwrap().sending_time()
+    }
+
+    // resolves once the message is queued, waiting while the queue is full.
+    pub fn send<Msg: FixSerializable + FixHeader>(&mut self, msg: &Msg) -> SendFuture<'_> {
+        SendFuture {
+            client: self,
+            msg: Some(serialize(msg)),
+            seq: msg.seq(),
+        }
+    }
+
+    // the session ends once the counterparty confirmed it.
+    pub fn send_logout(&mut self, text: Option<String>) -> SendFuture<'_> {
+        let logout = {
+            let mut shared = self.shared.lock().unwrap();
+            Logout {
+                seq: shared.get_next_send_seq(),
+                sender: shared.comp_ids.sender.clone(),
+                target: shared.comp_ids.target.clone(),
+                sending_time: shared.sending_time(),
+                text,
+            }
+        };
+        self.send(&logout)
+    }
+}
+
+/// Future returned by `AsyncFixClient::send`.
+pub struct SendFuture<'a> {
+    client: &'a mut AsyncFixClient,
+    msg: Option<String>,
+    seq: u64,
+}
+
+impl<'a> Future for SendFuture<'a> {
+    type Output = Result<(), SessionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        match this.client.tx.poll_reserve(cx) {
+            Poll::Pending => return Poll::Pending,
+            Poll::Ready(Err(_)) => return Poll::Ready(Err(closed())),
+            Poll::Ready(Ok(())) => {}
+        }
+
+        let msg = this.msg.take().expect("SendFuture polled after completion");
+        let mut shared = this.client.shared.lock().unwrap();
+        shared.store_sent(this.seq, &msg);
+        if this.client.tx.send_item(msg).is_err() {
+            return Poll::Ready(Err(closed()));
+        }
+        shared.last_queued_seq = shared.last_queued_seq.max(this.seq);
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a> Drop for SendFuture<'a> {
+    fn drop(&mut self) {
+        // a cancelled send leaves a gap the counterparty will ask to fill, but must not hold back heartbeats.
+        if self.msg.is_some() {
+            self.client.tx.abort_send();
+            let mut shared = self.client.shared.lock().unwrap();
+            shared.last_queued_seq = shared.last_queued_seq.max(self.seq);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Inbound<Sess, App> {
+    Session(Sess),
+    App(App),
+}
+
+/// Messages received by the session which it did not consume itself, in order.
+/// Ends when the session task does.
+pub struct MessageStream<Sess, App> {
+    rx: mpsc::Receiver<Vec<u8>>,
+    _messages: PhantomData<fn() -> (Sess, App)>,
+}
+
+impl<Sess, App> AsyncStream for MessageStream<Sess, App>
+where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+{
+    type Item = Result<Inbound<Sess, App>, ParseError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        match self.get_mut().rx.poll_recv(cx) {
+            Poll::Ready(Some(msg)) => Poll::Ready(Some(decode(&msg))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+fn decode<Sess, App>(msg: &[u8]) -> Result<Inbound<Sess, App>, ParseError>
+where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+{
+    if let Ok(resp) = deserialize::<Sess>(msg) {
+        info!("sess << {:?}", resp);
+        return Ok(Inbound::Session(resp));
+    }
+
+    let resp = deserialize::<App>(msg)?;
+    info!("app << {:?}", resp);
+    Ok(Inbound::App(resp))
+}
+
+/// A session over any tokio transport, e.g. a `TcpStream`, a TLS stream or `tokio::io::duplex`.
+///
+/// `spawn` moves the session layer into a background task. It sends Heartbeats and TestRequests
+/// when due, answers TestRequests, asks for resends on sequence gaps and holds back what follows
+/// a gap until it is filled, applies SequenceResets and fails once the counterparty times out.
+/// ResendRequests are answered like `FixClient::resend` does from the store given to `set_store`,
+/// without one everything asked for is gap filled. Everything else goes to the `MessageStream`.
+///
+/// The task ends after a Logout exchange, or once every `AsyncFixClient` is dropped and
+/// the queued messages are written.
+pub struct AsyncSession<T> {
+    io: T,
+    comp_ids: CompIds,
+    config: SessionConfig,
+    clock: Box<Clock + Send>,
+    message_log: Box<MessageLog + Send>,
+    store: Option<Box<MessageStore + Send>>,
+}
+
+impl<T> AsyncSession<T>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    pub fn new(comp_ids: CompIds, config: SessionConfig, io: T) -> AsyncSession<T> {
+        AsyncSession {
+            io,
+            comp_ids,
+            config,
+            clock: Box::new(SystemClock),
+            message_log: Box::new(LogMessageLog::new()),
+            store: None,
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Box<Clock + Send>) {
+        self.clock = clock;
+    }
+
+    pub fn set_message_log(&mut self, message_log: Box<MessageLog + Send>) {
+        self.message_log = message_log;
+    }
+
+    // continues the sequence numbers of the store and stores every message sent, see `FixClient::set_store`.
+    pub fn set_store(&mut self, store: Box<MessageStore + Send>) {
+        self.store = Some(store);
+    }
+
+    // has to be called within a tokio runtime.
+    pub fn spawn<Sess, App>(
+        self,
+    ) -> (AsyncFixClient, MessageStream<Sess, App>, JoinHandle<Result<(), SessionError>>)
+    where
+        Sess: FixDeserializable + Debug,
+        App: FixDeserializable + Debug,
+    {
+        let timers = SessionTimers::new(self.config.heartbeat_interval, self.clock.now());
+        let heartbeat_interval = self.config.heartbeat_interval;
+        let (next_send_seq, next_rcv_seq) = match self.store {
+            Some(ref store) => (store.next_sender_seq(), store.next_target_seq()),
+            None => (1, 1),
+        };
+        let shared = Arc::new(Mutex::new(Shared {
+            comp_ids: self.comp_ids,
+            config: self.config,
+            clock: self.clock,
+            timers,
+            message_log: self.message_log,
+            store: self.store,
+
+            next_send_seq,
+            last_queued_seq: next_send_seq - 1,
+        }));
+
+        let (outbound_tx, outbound_rx) = mpsc::channel(OUTBOUND_QUEUE_LENGTH);
+        let (inbound_tx, inbound_rx) = mpsc::channel(INBOUND_QUEUE_LENGTH);
+
+        let task = SessionTask {
+            framed: Framed::new(self.io, FixCodec),
+            shared: shared.clone(),
+            outbound_rx,
+            outbound_tx: outbound_tx.downgrade(),
+            inbound_tx: PollSender::new(inbound_tx),
+            inbound_pending: None,
+            admin: VecDeque::new(),
+            timer: Box::pin(sleep(heartbeat_interval)),
+
+            next_rcv_seq,
+            held_back: BTreeMap::new(),
+            resend_requested_to: 0,
+            heartbeat_pending: false,
+            test_request_pending: false,
+            logout_sent: false,
+            logout_received: false,
+            outbound_closed: false,
+        };
+
+        let client = AsyncFixClient {
+            shared,
+            tx: PollSender::new(outbound_tx),
+        };
+        let messages = MessageStream {
+            rx: inbound_rx,
+            _messages: PhantomData,
+        };
+        (client, messages, tokio::spawn(task))
+    }
+}
+
+// messages the session task sends on its own.
+#[derive(Debug)]
+enum AdminMsg {
+    Heartbeat(Option<String>),
+    TestRequest,
+    ResendRequest { begin: u64, end: u64 },
+    // answer to a ResendRequest, planned into the messages below once it is its turn.
+    Resend { begin: u64, end: u64 },
+    // a stored message resent as PossDup.
+    Resent(String),
+    GapFill { seq: u64, new_seq_no: u64 },
+    Logout,
+}
+
+impl AdminMsg {
+    // takes the place of a message sent before, instead of a new MsgSeqNum.
+    fn is_resend(&self) -> bool {
+        matches!(*self, AdminMsg::Resent(_) | AdminMsg::GapFill { .. })
+    }
+}
+
+struct SessionTask<T> {
+    framed: Framed<T, FixCodec>,
+    shared: Arc<Mutex<Shared>>,
+    outbound_rx: mpsc::Receiver<String>,
+    // the task's own messages take the same queue, keeping their place in the sequence.
+    // Weak, so the queue closes once every client is gone.
+    outbound_tx: mpsc::WeakSender<String>,
+    inbound_tx: PollSender<Vec<u8>>,
+    // message waiting for room in the inbound queue, nothing is read meanwhile.
+    inbound_pending: Option<Vec<u8>>,
+    admin: VecDeque<AdminMsg>,
+    timer: Pin<Box<Sleep>>,
+
+    next_rcv_seq: u64,
+    // messages received after a sequence gap, handled once the resent ones filled it.
+    // None for a ResendRequest, which is answered right away as the counterparty may wait for it.
+    held_back: BTreeMap<u64, Option<Vec<u8>>>,
+    // last MsgSeqNum a ResendRequest was sent for.
+    resend_requested_to: u64,
+    heartbeat_pending: bool,
+    test_request_pending: bool,
+    logout_sent: bool,
+    logout_received: bool,
+    outbound_closed: bool,
+}
+
+// MsgSeqNum (34) and PossDupFlag (43) of a message body.
+fn seq_and_poss_dup(body: &[u8]) -> Result<(Option<u64>, bool), ParseError> {
+    let seq = match find_field(body, 34)? {
+        Some(value) => Some(FixParse::parse(value)?),
+        None => None,
+    };
+    let poss_dup = find_field(body, 43)? == Some(&b"Y"[..]);
+    Ok((seq, poss_dup))
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> SessionTask<T> {
+    fn on_message(&mut self, msg: Vec<u8>) -> Result<(), SessionError> {
+        {
+            let mut shared = self.shared.lock().unwrap();
+            let now = shared.clock.now();
+            shared.message_log.on_incoming(now, &msg);
+            shared.timers.on_receive(now);
+        }
+        self.test_request_pending = false;
+
+        let (msg_type, seq, poss_dup) = {
+            let parsed = parse_fix_message(&msg).map_err(SessionError::Deserialize)?;
+            let (seq, poss_dup) = seq_and_poss_dup(parsed.body).map_err(SessionError::Deserialize)?;
+            (parsed.msg_type.to_vec(), seq, poss_dup)
+        };
+
+        // a SequenceReset in reset mode applies whatever its MsgSeqNum, a gap fill only in sequence.
+        if msg_type == SequenceReset::MSG_TYPE {
+            let reset = deserialize::<SequenceReset>(&msg).map_err(SessionError::Deserialize)?;
+            if reset.gap_fill != Some(Flag::Yes) {
+                let next_rcv_seq = self.next_rcv_seq.max(reset.new_seq_no);
+                self.set_next_rcv_seq(next_rcv_seq);
+                return Ok(());
+            }
+        }
+
+        match seq {
+            Some(seq) if seq > self.next_rcv_seq => self.hold_back(seq, msg, &msg_type),
+            Some(seq) if seq < self.next_rcv_seq => {
+                if poss_dup {
+                    return Ok(());
+                }
+                let text = format!("MsgSeqNum too low, expected {} got {}", self.next_rcv_seq, seq);
+                Err(SessionError::Io(Error::new(ErrorKind::InvalidData, text)))
+            }
+            Some(_) => {
+                let next_rcv_seq = self.next_rcv_seq + 1;
+                self.set_next_rcv_seq(next_rcv_seq);
+                self.handle(msg, &msg_type)
+            }
+            None => self.handle(msg, &msg_type),
+        }
+    }
+
+    fn set_next_rcv_seq(&mut self, seq: u64) {
+        self.next_rcv_seq = seq;
+        if let Some(ref mut store) = self.shared.lock().unwrap().store {
+            store.set_next_target_seq(seq);
+        }
+    }
+
+    // keeps a message following a sequence gap, asking for the missing ones unless that was done already.
+    fn hold_back(&mut self, seq: u64, msg: Vec<u8>, msg_type: &[u8]) -> Result<(), SessionError> {
+        let begin = self.next_rcv_seq.max(self.resend_requested_to + 1);
+        if begin < seq {
+            warn!("sequence gap, expected {} got {}", self.next_rcv_seq, seq);
+            self.admin.push_back(AdminMsg::ResendRequest { begin, end: seq - 1 });
+            self.resend_requested_to = seq - 1;
+        }
+        if msg_type == ResendRequest::MSG_TYPE {
+            self.handle(msg, msg_type)?;
+            self.held_back.insert(seq, None);
+        } else {
+            self.held_back.insert(seq, Some(msg));
+        }
+        Ok(())
+    }
+
+    // handles the held back message next in sequence, returns whether there was one.
+    fn release_held_back(&mut self) -> Result<bool, SessionError> {
+        // gap filled by a SequenceReset meanwhile.
+        self.held_back = self.held_back.split_off(&self.next_rcv_seq);
+        match self.held_back.remove(&self.next_rcv_seq) {
+            Some(held) => {
+                let next_rcv_seq = self.next_rcv_seq + 1;
+                self.set_next_rcv_seq(next_rcv_seq);
+                if let Some(msg) = held {
+                    let parsed = parse_fix_message(&msg).map_err(SessionError::Deserialize)?;
+                    let msg_type = parsed.msg_type.to_vec();
+                    self.handle(msg, &msg_type)?;
+                }
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    // a message in sequence.
+    fn handle(&mut self, msg: Vec<u8>, msg_type: &[u8]) -> Result<(), SessionError> {
+        if msg_type == Heartbeat::MSG_TYPE {
+            Ok(())
+        } else if msg_type == TestRequest::MSG_TYPE {
+            let request = deserialize::<TestRequest>(&msg).map_err(SessionError::Deserialize)?;
+            self.admin.push_back(AdminMsg::Heartbeat(Some(request.test_req_id)));
+            Ok(())
+        } else if msg_type == ResendRequest::MSG_TYPE {
+            let request = deserialize::<ResendRequest>(&msg).map_err(SessionError::Deserialize)?;
+            self.admin.push_back(AdminMsg::Resend {
+                begin: request.begin_seq_no,
+                end: request.end_seq_no,
+            });
+            Ok(())
+        } else if msg_type == SequenceReset::MSG_TYPE {
+            // a gap fill, the rest of the gap is taken.
+            let reset = deserialize::<SequenceReset>(&msg).map_err(SessionError::Deserialize)?;
+            let next_rcv_seq = self.next_rcv_seq.max(reset.new_seq_no);
+            self.set_next_rcv_seq(next_rcv_seq);
+            Ok(())
+        } else {
+            if msg_type == Logout::MSG_TYPE {
+                self.logout_received = true;
+                if !self.logout_sent {
+                    self.admin.push_back(AdminMsg::Logout);
+                }
+            }
+            self.inbound_pending = Some(msg);
+            Ok(())
+        }
+    }
+
+    fn check_timers(&mut self) -> Result<(), SessionError> {
+        let shared = self.shared.lock().unwrap();
+        let now = shared.clock.now();
+        if shared.timers.timed_out(now) {
+            return Err(SessionError::Io(Error::new(ErrorKind::TimedOut, "counterparty stopped responding")));
+        }
+        if shared.timers.test_request_due(now) && !self.test_request_pending {
+            self.test_request_pending = true;
+            self.admin.push_back(AdminMsg::TestRequest);
+        }
+        if shared.timers.heartbeat_due(now) && !self.heartbeat_pending {
+            self.heartbeat_pending = true;
+            self.admin.push_back(AdminMsg::Heartbeat(None));
+        }
+        Ok(())
+    }
+
+    // false when the message has to wait, either for room in the queue or for the user to send
+    // a MsgSeqNum taken already.
+    fn queue_admin(&mut self, admin: &AdminMsg) -> Result<bool, SessionError> {
+        let tx = match self.outbound_tx.upgrade() {
+            Some(tx) => tx,
+            // every client is gone and the session about to end.
+            None => return Ok(true),
+        };
+        let permit = match tx.try_reserve() {
+            Ok(permit) => permit,
+            Err(_) => return Ok(false),
+        };
+
+        let mut shared = self.shared.lock().unwrap();
+        if !shared.nothing_outstanding() {
+            return Ok(false);
+        }
+        if let AdminMsg::Resend { begin, end } = *admin {
+            // planned once every MsgSeqNum taken is queued, and so stored.
+            for resend in Self::plan_resend(&mut shared, begin, end)?.into_iter().rev() {
+                self.admin.push_front(resend);
+            }
+            return Ok(true);
+        }
+
+        let msg = Self::build_admin(&mut shared, admin);
+        if !admin.is_resend() {
+            if let Some(seq) = seq_of(msg.as_bytes()) {
+                shared.store_sent(seq, &msg);
+            }
+        }
+        permit.send(msg);
+        Ok(true)
+    }
+
+    // answers a ResendRequest like `FixClient::resend`, from the store if there is one.
+    fn plan_resend(shared: &mut Shared, begin: u64, end: u64) -> Result<Vec<AdminMsg>, SessionError> {
+        let last_sent = shared.next_send_seq - 1;
+        let end = if end == 0 { last_sent } else { end.min(last_sent) };
+        let now = shared.clock.now();
+        shared
+            .message_log
+            .on_event(now, &format!("Resending messages from {} to {}", begin, end));
+        if begin > end {
+            return Ok(Vec::new());
+        }
+
+        let stored = match shared.store {
+            Some(ref store) => store.get(begin, end),
+            None => Vec::new(),
+        };
+        let sending_time = shared.sending_time().to_string();
+        let plan = resend_plan(stored, begin, end, &sending_time).map_err(SessionError::Deserialize)?;
+        Ok(plan
+            .into_iter()
+            .map(|resend| match resend {
+                Resend::Message(msg) => AdminMsg::Resent(msg),
+                Resend::GapFill { seq, new_seq_no } => AdminMsg::GapFill { seq, new_seq_no },
+            })
+            .collect())
+    }
+
+    fn build_admin(shared: &mut Shared, admin: &AdminMsg) -> String {
+        let sender = shared.comp_ids.sender.clone();
+        let target = shared.comp_ids.target.clone();
+        let sending_time = shared.sending_time();
+
+        match *admin {
+            AdminMsg::Heartbeat(ref test_req_id) => serialize(&Heartbeat {
+                seq: Self::allocate_seq(shared),
+                sender,
+                target,
+                sending_time,
+                test_req_id: test_req_id.clone(),
+            }),
+            AdminMsg::TestRequest => {
+                let seq = Self::allocate_seq(shared);
+                serialize(&TestRequest {
+                    seq,
+                    sender,
+                    target,
+                    sending_time,
+                    test_req_id: format!("TEST{}", seq),
+                })
+            }
+            AdminMsg::ResendRequest { begin, end } => serialize(&ResendRequest {
+                seq: Self::allocate_seq(shared),
+                sender,
+                target,
+                sending_time,
+                begin_seq_no: begin,
+                end_seq_no: end,
+            }),
+            AdminMsg::Resend { .. } => unreachable!("a Resend is planned into messages by queue_admin"),
+            AdminMsg::Resent(ref msg) => msg.clone(),
+            AdminMsg::GapFill { seq, new_seq_no } => serialize(&SequenceReset {
+                seq,
+                sender,
+                target,
+                sending_time,
+                poss_dup: Some(Flag::Yes),
+                orig_sending_time: Some(sending_time),
+                gap_fill: Some(Flag::Yes),
+                new_seq_no,
+            }),
+            AdminMsg::Logout => serialize(&Logout {
+                seq: Self::allocate_seq(shared),
+                sender,
+                target,
+                sending_time,
+                text: None,
+            }),
+        }
+    }
+
+    fn allocate_seq(shared: &mut Shared) -> u64 {
+        let seq = shared.get_next_send_seq();
+        shared.last_queued_seq = seq;
+        seq
+    }
+
+    fn on_send(&mut self, msg: &str) {
+        {
+            let mut shared = self.shared.lock().unwrap();
+            let now = shared.clock.now();
+            shared.message_log.on_outgoing(now, msg);
+            shared.timers.on_send(now);
+        }
+        self.heartbeat_pending = false;
+
+        if let Ok(parsed) = parse_fix_message(msg.as_bytes()) {
+            if parsed.msg_type == Logout::MSG_TYPE {
+                self.logout_sent = true;
+            }
+        }
+    }
+
+    fn reset_timer(&mut self) {
+        let deadline = {
+            let shared = self.shared.lock().unwrap();
+            shared
+                .timers
+                .next_deadline(shared.clock.now())
+                .and_then(|d| d.to_std().ok())
+                .unwrap_or(shared.config.heartbeat_interval)
+        };
+        // rounds up, so the timers are due once it fires.
+        let deadline = deadline + Duration::from_millis(1);
+        self.timer.as_mut().reset(Instant::now() + deadline);
+    }
+
+    fn poll_session(&mut self, cx: &mut Context) -> Poll<Result<(), SessionError>> {
+        loop {
+            let mut progress = false;
+
+            if let Some(msg) = self.inbound_pending.take() {
+                match self.inbound_tx.poll_reserve(cx) {
+                    Poll::Pending => self.inbound_pending = Some(msg),
+                    Poll::Ready(Ok(())) => {
+                        let _ = self.inbound_tx.send_item(msg);
+                    }
+                    // nobody listens any more.
+                    Poll::Ready(Err(_)) => {}
+                }
+            }
+
+            while self.inbound_pending.is_none() {
+                if self.release_held_back()? {
+                    progress = true;
+                    continue;
+                }
+                match Pin::new(&mut self.framed).poll_next(cx) {
+                    Poll::Ready(Some(Ok(msg))) => {
+                        self.on_message(msg)?;
+                        progress = true;
+                    }
+                    Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(SessionError::Io(err))),
+                    Poll::Ready(None) => {
+                        return Poll::Ready(if self.logout_received {
+                            Ok(())
+                        } else {
+                            Err(SessionError::Io(Error::new(
+                                ErrorKind::UnexpectedEof,
+                                "connection closed by counterparty",
+                            )))
+                        })
+                    }
+                    Poll::Pending => break,
+                }
+            }
+
+            self.check_timers()?;
+            while let Some(admin) = self.admin.pop_front() {
+                if !self.queue_admin(&admin)? {
+                    self.admin.push_front(admin);
+                    break;
+                }
+            }
+
+            loop {
+                match Pin::new(&mut self.framed).poll_ready(cx) {
+                    Poll::Ready(Ok(())) => {}
+                    Poll::Ready(Err(err)) => return Poll::Ready(Err(SessionError::Io(err))),
+                    Poll::Pending => break,
+                }
+                match self.outbound_rx.poll_recv(cx) {
+                    Poll::Ready(Some(msg)) => {
+                        self.on_send(&msg);
+                        Pin::new(&mut self.framed).start_send(msg).map_err(SessionError::Io)?;
+                        progress = true;
+                    }
+                    Poll::Ready(None) => {
+                        self.outbound_closed = true;
+                        break;
+                    }
+                    Poll::Pending => break,
+                }
+            }
+
+            let flushed = match Pin::new(&mut self.framed).poll_flush(cx) {
+                Poll::Ready(Ok(())) => true,
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(SessionError::Io(err))),
+                Poll::Pending => false,
+            };
+            let done = self.outbound_closed || (self.logout_sent && self.logout_received);
+            if done && flushed && self.inbound_pending.is_none() {
+                return Poll::Ready(Ok(()));
+            }
+
+            self.reset_timer();
+            if self.timer.as_mut().poll(cx).is_ready() {
+                progress = true;
+            }
+            if !progress {
+                return Poll::Pending;
+            }
+        }
+    }
+}
+
+impl<T: AsyncRead + AsyncWrite + Unpin> Future for SessionTask<T> {
+    type Output = Result<(), SessionError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.get_mut().poll_session(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::future::poll_fn;
+
+    use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+    use tokio::runtime::{Builder, Runtime};
+
+    use super::*;
+    use store::MemoryStore;
+    use testing::{AppMsg, NewOrder, SessionMsg};
+
+    fn runtime() -> Runtime {
+        Builder::new_current_thread().enable_all().build().unwrap()
+    }
+
+    fn session(io: DuplexStream, sender: &str, target: &str) -> AsyncSession<DuplexStream> {
+        let comp_ids = CompIds {
+            sender: sender.to_string(),
+            target: target.to_string(),
+        };
+        let config = SessionConfig {
+            heartbeat_interval: Duration::from_millis(50),
+            ..SessionConfig::default()
+        };
+        AsyncSession::new(comp_ids, config, io)
+    }
+
+    fn order(client: &AsyncFixClient, cl_ord_id: &str) -> NewOrder {
+        let comp_ids = client.comp_ids();
+        NewOrder {
+            seq: client.get_next_send_seq(),
+            sender: comp_ids.sender,
+            target: comp_ids.target,
+            sending_time: client.sending_time(),
+            poss_dup: None,
+            orig_sending_time: None,
+            cl_ord_id: cl_ord_id.to_string(),
+            symbol: None,
+            side: None,
+            qty: None,
+            account: None,
+        }
+    }
+
+    fn next<S: AsyncStream + Unpin>(stream: &mut S) -> impl Future<Output = Option<S::Item>> + '_ {
+        poll_fn(move |cx| Pin::new(&mut *stream).poll_next(cx))
+    }
+
+    #[test]
+    fn test_messages_flow_between_sessions_and_logout_ends_both() {
+        let rt = runtime();
+        let _runtime = rt.enter();
+        let (ours, theirs) = duplex(4096);
+        let (mut initiator, _, initiator_task) = session(ours, "us", "them").spawn::<SessionMsg, AppMsg>();
+        let (_acceptor, mut messages, acceptor_task) = session(theirs, "them", "us").spawn::<SessionMsg, AppMsg>();
+
+        let order = order(&initiator, "1");
+        rt.block_on(initiator.send(&order)).unwrap();
+        match rt.block_on(next(&mut messages)) {
+            Some(Ok(Inbound::App(AppMsg::NewOrder(received)))) => {
+                assert_eq!(received.seq, order.seq);
+                assert_eq!(received.cl_ord_id, order.cl_ord_id);
+            }
+            other => panic!("expected the order, got {:?}", other),
+        }
+
+        rt.block_on(initiator.send_logout(None)).unwrap();
+        match rt.block_on(next(&mut messages)) {
+            Some(Ok(Inbound::Session(SessionMsg::Logout(_)))) => {}
+            other => panic!("expected a logout, got {:?}", other),
+        }
+
+        assert!(rt.block_on(initiator_task).unwrap().is_ok());
+        assert!(rt.block_on(acceptor_task).unwrap().is_ok());
+    }
+
+    #[test]
+    fn test_session_task_answers_test_request_and_sends_heartbeats() {
+        let rt = runtime();
+        let _runtime = rt.enter();
+        let (ours, mut theirs) = duplex(4096);
+        let (_client, _messages, _task) = session(ours, "us", "them").spawn::<SessionMsg, AppMsg>();
+
+        let request = TestRequest {
+            seq: 1,
+            sender: "them".to_string(),
+            target: "us".to_string(),
+            sending_time: Timestamp::now(),
+            test_req_id: "abc".to_string(),
+        };
+        rt.block_on(theirs.write_all(serialize(&request).as_bytes())).unwrap();
+
+        let mut received = String::new();
+        let mut buf = [0u8; 1024];
+        while !received.contains("\x0135=0\x01") || !received.contains("\x01112=abc\x01") {
+            let size = rt.block_on(theirs.read(&mut buf)).unwrap();
+            assert!(size > 0);
+            received.push_str(::std::str::from_utf8(&buf[..size]).unwrap());
+        }
+
+        // idle for a heartbeat interval.
+        let size = rt.block_on(theirs.read(&mut buf)).unwrap();
+        let heartbeat = ::std::str::from_utf8(&buf[..size]).unwrap();
+        assert!(heartbeat.contains("\x0135=0\x01"));
+        assert!(!heartbeat.contains("\x01112="));
+    }
+
+    #[test]
+    fn test_messages_after_a_gap_are_held_back_until_resent() {
+        let rt = runtime();
+        let _runtime = rt.enter();
+        let (ours, mut theirs) = duplex(4096);
+        let (_client, mut messages, _task) = session(ours, "us", "them").spawn::<SessionMsg, AppMsg>();
+
+        for seq in &[1, 3] {
+            let order = NewOrder::new(*seq, "them", "us", &seq.to_string());
+            rt.block_on(theirs.write_all(serialize(&order).as_bytes())).unwrap();
+        }
+        let mut received = String::new();
+        let mut buf = [0u8; 1024];
+        while !received.contains("\x0135=2\x01") {
+            let size = rt.block_on(theirs.read(&mut buf)).unwrap();
+            assert!(size > 0);
+            received.push_str(::std::str::from_utf8(&buf[..size]).unwrap());
+        }
+        assert!(received.contains("\x017=2\x0116=2\x01"));
+
+        let resent = NewOrder {
+            poss_dup: Some(Flag::Yes),
+            ..NewOrder::new(2, "them", "us", "2")
+        };
+        rt.block_on(theirs.write_all(serialize(&resent).as_bytes())).unwrap();
+        for expected in 1..4 {
+            match rt.block_on(next(&mut messages)) {
+                Some(Ok(Inbound::App(AppMsg::NewOrder(order)))) => assert_eq!(order.seq, expected),
+                other => panic!("expected order {}, got {:?}", expected, other),
+            }
+        }
+    }
+
+    // reads until `pattern` arrived, returns everything read.
+    fn read_until(rt: &Runtime, theirs: &mut DuplexStream, pattern: &str) -> String {
+        let mut received = String::new();
+        let mut buf = [0u8; 1024];
+        while !received.contains(pattern) {
+            let size = rt.block_on(theirs.read(&mut buf)).unwrap();
+            assert!(size > 0, "closed before {:?} arrived: {:?}", pattern, received);
+            received.push_str(::std::str::from_utf8(&buf[..size]).unwrap());
+        }
+        received
+    }
+
+    #[test]
+    fn test_resend_request_is_answered_from_the_store() {
+        let rt = runtime();
+        let _runtime = rt.enter();
+        let (ours, mut theirs) = duplex(4096);
+        let store = MemoryStore::new();
+        let mut session = session(ours, "us", "them");
+        session.set_store(Box::new(store.clone()));
+        let (mut client, _messages, _task) = session.spawn::<SessionMsg, AppMsg>();
+
+        // a Heartbeat answering a TestRequest, then an order.
+        let request = TestRequest {
+            seq: 1,
+            sender: "them".to_string(),
+            target: "us".to_string(),
+            sending_time: Timestamp::now(),
+            test_req_id: "abc".to_string(),
+        };
+        rt.block_on(theirs.write_all(serialize(&request).as_bytes())).unwrap();
+        read_until(&rt, &mut theirs, "\x01112=abc\x01");
+        let order = order(&client, "1");
+        rt.block_on(client.send(&order)).unwrap();
+        read_until(&rt, &mut theirs, "\x0111=1\x01");
+        assert_eq!(store.get(1, 0).len(), 2);
+        assert_eq!(store.next_target_seq(), 2);
+
+        let request = ResendRequest {
+            seq: 2,
+            sender: "them".to_string(),
+            target: "us".to_string(),
+            sending_time: Timestamp::now(),
+            begin_seq_no: 1,
+            end_seq_no: 0,
+        };
+        rt.block_on(theirs.write_all(serialize(&request).as_bytes())).unwrap();
+        let received = read_until(&rt, &mut theirs, "\x0111=1\x01");
+        let gap_fill = received.find("\x0135=4\x0134=1\x01").unwrap();
+        let resent = received.find("\x0135=D\x0134=2\x01").unwrap();
+        assert!(gap_fill < resent);
+        assert!(received[gap_fill..resent].contains("\x01123=Y\x0136=2\x01"));
+        assert!(received[resent..].contains("\x0143=Y\x01122="));
+        // resends are not stored again.
+        assert_eq!(store.get(1, 0).len(), 2);
+    }
+
+    #[test]
+    fn test_gap_fill_too_high_asks_for_a_resend_first() {
+        let rt = runtime();
+        let _runtime = rt.enter();
+        let (ours, mut theirs) = duplex(4096);
+        let (_client, mut messages, _task) = session(ours, "us", "them").spawn::<SessionMsg, AppMsg>();
+
+        let gap_fill = SequenceReset {
+            seq: 3,
+            sender: "them".to_string(),
+            target: "us".to_string(),
+            sending_time: Timestamp::now(),
+            poss_dup: Some(Flag::Yes),
+            orig_sending_time: Some(Timestamp::now()),
+            gap_fill: Some(Flag::Yes),
+            new_seq_no: 10,
+        };
+        rt.block_on(theirs.write_all(serialize(&gap_fill).as_bytes())).unwrap();
+        let received = read_until(&rt, &mut theirs, "\x0135=2\x01");
+        assert!(received.contains("\x017=1\x0116=2\x01"));
+
+        // the missing ones still count, the gap fill applies after them.
+        for seq in &[1, 2, 10] {
+            let order = NewOrder::new(*seq, "them", "us", &seq.to_string());
+            rt.block_on(theirs.write_all(serialize(&order).as_bytes())).unwrap();
+        }
+        for expected in &[1, 2, 10] {
+            match rt.block_on(next(&mut messages)) {
+                Some(Ok(Inbound::App(AppMsg::NewOrder(order)))) => assert_eq!(order.seq, *expected),
+                other => panic!("expected order {}, got {:?}", expected, other),
+            }
+        }
+    }
+
+    #[test]
+    fn test_codec_frames_messages() {
+        let mut codec = FixCodec;
+        let mut buf = BytesMut::from(&b"8=FIX.4.4\x019=5\x0135=0\x0110=123\x018=FIX.4.4\x019=5\x0135=0\x0110=1"[..]);
+        assert_eq!(
+            codec.decode(&mut buf).unwrap(),
+            Some(b"8=FIX.4.4\x019=5\x0135=0\x0110=123\x01".to_vec())
+        );
+        assert_eq!(codec.decode(&mut buf).unwrap(), None);
+        assert_eq!(&buf[..], &b"8=FIX.4.4\x019=5\x0135=0\x0110=1"[..]);
+    }
+}
@@ -0,0 +1,705 @@
+use std::collections::HashMap;
+
+use market_data::{
+    IncrementalEntry, MDEntryType, MDUpdateAction, MarketDataIncrementalRefresh, MarketDataSnapshotFullRefresh,
+};
+use ParseError;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BookSide {
+    Bid,
+    Offer,
+}
+
+impl BookSide {
+    fn of(entry_type: &MDEntryType) -> Option<BookSide> {
+        match *entry_type {
+            MDEntryType::Bid => Some(BookSide::Bid),
+            MDEntryType::Offer => Some(BookSide::Offer),
+            _ => None,
+        }
+    }
+
+    // `price` goes before `other` in the book.
+    fn better(self, price: f64, other: f64) -> bool {
+        match self {
+            BookSide::Bid => price > other,
+            BookSide::Offer => price < other,
+        }
+    }
+}
+
+/// Quantity at a price, see `L2Book`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Level {
+    pub price: f64,
+    pub size: f64,
+    // NumberOfOrders (346), or the orders of an `L3Book` at the price.
+    pub orders: Option<u64>,
+}
+
+/// Price level book, from the entries without an MDEntryID (278). Levels are keyed by price.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct L2Book {
+    // best first.
+    bids: Vec<Level>,
+    offers: Vec<Level>,
+}
+
+impl L2Book {
+    pub fn levels(&self, side: BookSide) -> &[Level] {
+        match side {
+            BookSide::Bid => &self.bids,
+            BookSide::Offer => &self.offers,
+        }
+    }
+
+    pub fn best(&self, side: BookSide) -> Option<&Level> {
+        self.levels(side).first()
+    }
+
+    fn side_mut(&mut self, side: BookSide) -> &mut Vec<Level> {
+        match side {
+            BookSide::Bid => &mut self.bids,
+            BookSide::Offer => &mut self.offers,
+        }
+    }
+
+    // sets the level at its price, adding it if there is none.
+    fn set(&mut self, side: BookSide, level: Level) {
+        let levels = self.side_mut(side);
+        match levels.iter().position(|other| !side.better(other.price, level.price)) {
+            Some(index) if levels[index].price == level.price => levels[index] = level,
+            Some(index) => levels.insert(index, level),
+            None => levels.push(level),
+        }
+    }
+
+    fn remove(&mut self, side: BookSide, price: f64) -> Option<Level> {
+        let levels = self.side_mut(side);
+        let index = levels.iter().position(|level| level.price == price)?;
+        Some(levels.remove(index))
+    }
+}
+
+/// A resting order of an `L3Book`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookOrder {
+    // MDEntryID (278).
+    pub id: String,
+    pub price: f64,
+    pub size: f64,
+}
+
+/// Order level book, from the entries with an MDEntryID (278). Orders are in price-time priority.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct L3Book {
+    bids: Vec<BookOrder>,
+    offers: Vec<BookOrder>,
+}
+
+impl L3Book {
+    pub fn orders(&self, side: BookSide) -> &[BookOrder] {
+        match side {
+            BookSide::Bid => &self.bids,
+            BookSide::Offer => &self.offers,
+        }
+    }
+
+    pub fn order(&self, id: &str) -> Option<(BookSide, &BookOrder)> {
+        self.find(id).map(|(side, index)| (side, &self.orders(side)[index]))
+    }
+
+    // the orders aggregated by price, best first.
+    pub fn levels(&self, side: BookSide) -> Vec<Level> {
+        let mut levels: Vec<Level> = Vec::new();
+        for order in self.orders(side) {
+            match levels.last_mut() {
+                Some(ref mut level) if level.price == order.price => {
+                    level.size += order.size;
+                    level.orders = level.orders.map(|orders| orders + 1);
+                }
+                _ => levels.push(Level {
+                    price: order.price,
+                    size: order.size,
+                    orders: Some(1),
+                }),
+            }
+        }
+        levels
+    }
+
+    fn side_mut(&mut self, side: BookSide) -> &mut Vec<BookOrder> {
+        match side {
+            BookSide::Bid => &mut self.bids,
+            BookSide::Offer => &mut self.offers,
+        }
+    }
+
+    fn find(&self, id: &str) -> Option<(BookSide, usize)> {
+        for &side in &[BookSide::Bid, BookSide::Offer] {
+            if let Some(index) = self.orders(side).iter().position(|order| order.id == id) {
+                return Some((side, index));
+            }
+        }
+        None
+    }
+
+    // behind the orders at the same price.
+    fn insert(&mut self, side: BookSide, order: BookOrder) {
+        let orders = self.side_mut(side);
+        match orders.iter().position(|other| side.better(order.price, other.price)) {
+            Some(index) => orders.insert(index, order),
+            None => orders.push(order),
+        }
+    }
+
+    fn remove(&mut self, id: &str) -> Option<(BookSide, BookOrder)> {
+        let (side, index) = self.find(id)?;
+        Some((side, self.side_mut(side).remove(index)))
+    }
+}
+
+/// The books of a symbol, see `MarketDataBooks`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Book {
+    l2: L2Book,
+    l3: L3Book,
+    // price and size.
+    last_trade: Option<(f64, f64)>,
+    rpt_seq: Option<u64>,
+    stale: bool,
+    // entries received while stale, applied after the snapshot.
+    pending: Vec<IncrementalEntry>,
+}
+
+impl Book {
+    pub fn l2(&self) -> &L2Book {
+        &self.l2
+    }
+
+    pub fn l3(&self) -> &L3Book {
+        &self.l3
+    }
+
+    pub fn last_trade(&self) -> Option<(f64, f64)> {
+        self.last_trade
+    }
+
+    // RptSeq (83) of the last entry applied.
+    pub fn rpt_seq(&self) -> Option<u64> {
+        self.rpt_seq
+    }
+
+    // updates were missed or did not fit, the book waits for a snapshot.
+    pub fn is_stale(&self) -> bool {
+        self.stale
+    }
+
+    // applies an entry in RptSeq order, returns false if the book went stale with it.
+    fn update(&mut self, symbol: &str, entry: &IncrementalEntry) -> bool {
+        if self.stale {
+            if entry.rpt_seq.is_some() {
+                self.pending.push(entry.clone());
+            }
+            return true;
+        }
+        if let Some(rpt_seq) = entry.rpt_seq {
+            match self.rpt_seq {
+                Some(last) if rpt_seq <= last => {
+                    debug!("{}: skipping RptSeq {}, at {} already", symbol, rpt_seq, last);
+                    return true;
+                }
+                Some(last) if rpt_seq > last + 1 => {
+                    warn!("{}: RptSeq {} after {}, waiting for a snapshot", symbol, rpt_seq, last);
+                    self.stale = true;
+                    self.pending.push(entry.clone());
+                    return false;
+                }
+                _ => {}
+            }
+        }
+        if let Err(reason) = self.apply(entry) {
+            warn!("{}: {}, waiting for a snapshot", symbol, reason);
+            self.stale = true;
+            // a snapshot older than the entry does not include it either.
+            if entry.rpt_seq.is_some() {
+                self.pending.push(entry.clone());
+            }
+            return false;
+        }
+        if entry.rpt_seq.is_some() {
+            self.rpt_seq = entry.rpt_seq;
+        }
+        true
+    }
+
+    fn apply(&mut self, entry: &IncrementalEntry) -> Result<(), String> {
+        let entry_type = entry.entry_type.as_ref();
+        if entry_type == Some(&MDEntryType::Trade) {
+            if let (MDUpdateAction::New, Some(price), Some(size)) = (entry.action, entry.price, entry.size) {
+                self.last_trade = Some((price, size));
+            }
+            return Ok(());
+        }
+        let side = entry_type.and_then(BookSide::of);
+        if entry_type.is_some() && side.is_none() {
+            return Ok(());
+        }
+
+        if let Some(ref id) = entry.entry_id {
+            return self.apply_order(side, id, entry);
+        }
+        let side = side.ok_or("price level entry without MDEntryType (269)")?;
+        let price = entry.price.ok_or("price level entry without MDEntryPx (270)")?;
+        match entry.action {
+            MDUpdateAction::New | MDUpdateAction::Change => {
+                if entry.action == MDUpdateAction::Change && !self.l2.levels(side).iter().any(|l| l.price == price) {
+                    return Err(format!("change of the missing level {}", price));
+                }
+                let size = entry.size.ok_or("price level entry without MDEntrySize (271)")?;
+                self.l2.set(
+                    side,
+                    Level {
+                        price,
+                        size,
+                        orders: entry.number_of_orders,
+                    },
+                );
+            }
+            MDUpdateAction::Delete => {
+                self.l2
+                    .remove(side, price)
+                    .ok_or_else(|| format!("delete of the missing level {}", price))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_order(&mut self, side: Option<BookSide>, id: &str, entry: &IncrementalEntry) -> Result<(), String> {
+        match entry.action {
+            MDUpdateAction::New => {
+                if self.l3.find(id).is_some() {
+                    return Err(format!("new order {} exists already", id));
+                }
+                let order = BookOrder {
+                    id: id.to_string(),
+                    price: entry.price.ok_or("order entry without MDEntryPx (270)")?,
+                    size: entry.size.ok_or("order entry without MDEntrySize (271)")?,
+                };
+                self.l3
+                    .insert(side.ok_or("order entry without MDEntryType (269)")?, order);
+            }
+            MDUpdateAction::Change => {
+                let previous = entry.entry_ref_id.as_ref().map_or(id, String::as_str);
+                let (old_side, index) = self
+                    .l3
+                    .find(previous)
+                    .ok_or_else(|| format!("change of the missing order {}", previous))?;
+                let price = entry.price.unwrap_or(self.l3.orders(old_side)[index].price);
+                let size = entry.size.unwrap_or(self.l3.orders(old_side)[index].size);
+                let side = side.unwrap_or(old_side);
+                // a reduced size keeps its place, anything else goes to the back.
+                let order = &mut self.l3.side_mut(old_side)[index];
+                if side == old_side && price == order.price && size <= order.size {
+                    order.id = id.to_string();
+                    order.size = size;
+                } else {
+                    self.l3.remove(previous);
+                    let order = BookOrder {
+                        id: id.to_string(),
+                        price,
+                        size,
+                    };
+                    self.l3.insert(side, order);
+                }
+            }
+            MDUpdateAction::Delete => {
+                self.l3
+                    .remove(id)
+                    .ok_or_else(|| format!("delete of the missing order {}", id))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self, snapshot: &MarketDataSnapshotFullRefresh) {
+        self.l2 = L2Book::default();
+        self.l3 = L3Book::default();
+        self.rpt_seq = snapshot.rpt_seq;
+        self.stale = false;
+        for entry in &snapshot.entries {
+            let (price, size) = match (entry.price, entry.size) {
+                (Some(price), Some(size)) => (price, size),
+                _ => continue,
+            };
+            if entry.entry_type == MDEntryType::Trade {
+                self.last_trade = Some((price, size));
+            }
+            let side = match BookSide::of(&entry.entry_type) {
+                Some(side) => side,
+                None => continue,
+            };
+            match entry.entry_id {
+                Some(ref id) => self.l3.insert(
+                    side,
+                    BookOrder {
+                        id: id.clone(),
+                        price,
+                        size,
+                    },
+                ),
+                None => self.l2.set(
+                    side,
+                    Level {
+                        price,
+                        size,
+                        orders: entry.number_of_orders,
+                    },
+                ),
+            }
+        }
+    }
+}
+
+/// Price level and order level books per symbol, kept from MarketDataSnapshotFullRefresh (W) and
+/// MarketDataIncrementalRefresh (X) messages.
+///
+/// Entries with an MDEntryID (278) go to the `L3Book` of their symbol, the others to its `L2Book`.
+/// Incremental entries with a RptSeq (83) are applied in its order: one skipping ahead, or one that does
+/// not fit the book, e.g. the change of a missing level, makes the book stale. A stale book keeps
+/// that entry and those which come after and waits for a snapshot, which replaces it and is followed by
+/// those entries newer than the RptSeq of the snapshot.
+#[derive(Debug, Clone, Default)]
+pub struct MarketDataBooks {
+    books: HashMap<String, Book>,
+    // symbols of the orders, for entries giving only the MDEntryID.
+    order_symbols: HashMap<String, String>,
+}
+
+impl MarketDataBooks {
+    pub fn new() -> MarketDataBooks {
+        MarketDataBooks::default()
+    }
+
+    pub fn book(&self, symbol: &str) -> Option<&Book> {
+        self.books.get(symbol)
+    }
+
+    // symbols whose books need a snapshot, e.g. a MarketDataRequest (V) for a Snapshot.
+    pub fn stale(&self) -> Vec<&str> {
+        let mut stale: Vec<&str> = self
+            .books
+            .iter()
+            .filter(|&(_, book)| book.stale)
+            .map(|(symbol, _)| symbol.as_str())
+            .collect();
+        stale.sort();
+        stale
+    }
+
+    // returns the symbols that went stale with it.
+    pub fn apply_snapshot(&mut self, snapshot: &MarketDataSnapshotFullRefresh) -> Vec<String> {
+        let book = self.books.entry(snapshot.symbol.clone()).or_default();
+        let pending = ::std::mem::take(&mut book.pending);
+        book.reset(snapshot);
+        // the orders of the symbol are those of the snapshot now, the others are gone.
+        self.order_symbols.retain(|_, symbol| *symbol != snapshot.symbol);
+        for entry in &snapshot.entries {
+            if let Some(ref id) = entry.entry_id {
+                self.order_symbols.insert(id.clone(), snapshot.symbol.clone());
+            }
+        }
+        if snapshot.rpt_seq.is_none() {
+            // nothing to tell the entries the snapshot includes already from the newer ones.
+            return Vec::new();
+        }
+        let mut stale = Vec::new();
+        for entry in pending {
+            self.update(&snapshot.symbol, &entry, &mut stale);
+        }
+        stale
+    }
+
+    // returns the symbols that went stale with it. Entries are applied up to one without a symbol,
+    // which fails the rest.
+    pub fn apply_incremental(&mut self, refresh: &MarketDataIncrementalRefresh) -> Result<Vec<String>, ParseError> {
+        let mut stale = Vec::new();
+        let mut previous: Option<String> = None;
+        for entry in &refresh.entries {
+            // an entry may leave out the symbol of its order or, in a message for one symbol, of the previous entry.
+            let symbol = entry
+                .symbol
+                .clone()
+                .or_else(|| {
+                    entry
+                        .entry_id
+                        .as_ref()
+                        .and_then(|id| self.order_symbols.get(id).cloned())
+                })
+                .or_else(|| previous.clone())
+                .ok_or("MDEntry without Symbol (55)")?;
+            self.update(&symbol, entry, &mut stale);
+            previous = Some(symbol);
+        }
+        Ok(stale)
+    }
+
+    fn update(&mut self, symbol: &str, entry: &IncrementalEntry, stale: &mut Vec<String>) {
+        let book = self.books.entry(symbol.to_string()).or_default();
+        if !book.update(symbol, entry) && !stale.iter().any(|s| s == symbol) {
+            stale.push(symbol.to_string());
+        }
+        if let Some(ref id) = entry.entry_id {
+            match entry.action {
+                MDUpdateAction::Delete => {
+                    self.order_symbols.remove(id);
+                }
+                _ => {
+                    if let Some(ref previous) = entry.entry_ref_id {
+                        self.order_symbols.remove(previous);
+                    }
+                    self.order_symbols.insert(id.clone(), symbol.to_string());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use market_data::SnapshotEntry;
+    use Timestamp;
+
+    fn snapshot(rpt_seq: Option<u64>, entries: Vec<SnapshotEntry>) -> MarketDataSnapshotFullRefresh {
+        MarketDataSnapshotFullRefresh {
+            seq: 1,
+            sender: "them".to_string(),
+            target: "us".to_string(),
+            sending_time: Timestamp::now(),
+            md_req_id: None,
+            symbol: "EURUSD".to_string(),
+            rpt_seq,
+            entries,
+        }
+    }
+
+    fn level(entry_type: MDEntryType, id: Option<&str>, price: f64, size: f64) -> SnapshotEntry {
+        SnapshotEntry {
+            entry_type,
+            price: Some(price),
+            currency: None,
+            size: Some(size),
+            date: None,
+            time: None,
+            market: None,
+            entry_id: id.map(str::to_string),
+            position_no: None,
+            number_of_orders: None,
+            price_level: None,
+            text: None,
+        }
+    }
+
+    fn refresh(entries: Vec<IncrementalEntry>) -> MarketDataIncrementalRefresh {
+        MarketDataIncrementalRefresh {
+            seq: 2,
+            sender: "them".to_string(),
+            target: "us".to_string(),
+            sending_time: Timestamp::now(),
+            md_req_id: None,
+            entries,
+        }
+    }
+
+    fn update(
+        action: MDUpdateAction,
+        entry_type: Option<MDEntryType>,
+        id: Option<&str>,
+        rpt_seq: Option<u64>,
+        price: Option<f64>,
+        size: Option<f64>,
+    ) -> IncrementalEntry {
+        IncrementalEntry {
+            action,
+            entry_type,
+            entry_id: id.map(str::to_string),
+            entry_ref_id: None,
+            // changes and deletes of orders go by their MDEntryID alone.
+            symbol: if id.is_some() && action != MDUpdateAction::New {
+                None
+            } else {
+                Some("EURUSD".to_string())
+            },
+            rpt_seq,
+            price,
+            currency: None,
+            size,
+            date: None,
+            time: None,
+            market: None,
+            position_no: None,
+            number_of_orders: None,
+            price_level: None,
+            text: None,
+        }
+    }
+
+    fn prices(levels: &[Level]) -> Vec<(f64, f64)> {
+        levels.iter().map(|level| (level.price, level.size)).collect()
+    }
+
+    #[test]
+    fn test_price_levels() {
+        use market_data::MDEntryType::*;
+        use market_data::MDUpdateAction::*;
+
+        let mut books = MarketDataBooks::new();
+        books.apply_snapshot(&snapshot(
+            None,
+            vec![
+                level(Bid, None, 1.0, 5.0),
+                level(Bid, None, 1.1, 3.0),
+                level(Offer, None, 1.3, 2.0),
+                level(Trade, None, 1.2, 1.0),
+            ],
+        ));
+        let stale = books
+            .apply_incremental(&refresh(vec![
+                update(New, Some(Bid), None, None, Some(1.15), Some(4.0)),
+                update(Change, Some(Bid), None, None, Some(1.0), Some(6.0)),
+                update(Delete, Some(Offer), None, None, Some(1.3), None),
+                update(New, Some(Offer), None, None, Some(1.25), Some(7.0)),
+                update(New, Some(Trade), None, None, Some(1.2), Some(2.0)),
+            ]))
+            .unwrap();
+        assert!(stale.is_empty());
+
+        let book = books.book("EURUSD").unwrap();
+        assert_eq!(
+            prices(book.l2().levels(BookSide::Bid)),
+            vec![(1.15, 4.0), (1.1, 3.0), (1.0, 6.0)]
+        );
+        assert_eq!(prices(book.l2().levels(BookSide::Offer)), vec![(1.25, 7.0)]);
+        assert_eq!(book.last_trade(), Some((1.2, 2.0)));
+
+        let stale = books
+            .apply_incremental(&refresh(vec![update(
+                Change,
+                Some(Offer),
+                None,
+                None,
+                Some(1.4),
+                Some(1.0),
+            )]))
+            .unwrap();
+        assert_eq!(stale, vec!["EURUSD".to_string()]);
+        assert_eq!(books.stale(), vec!["EURUSD"]);
+    }
+
+    #[test]
+    fn test_orders_in_price_time_priority() {
+        use market_data::MDEntryType::*;
+        use market_data::MDUpdateAction::*;
+
+        let mut books = MarketDataBooks::new();
+        books.apply_snapshot(&snapshot(
+            Some(10),
+            vec![level(Bid, Some("a"), 1.0, 5.0), level(Bid, Some("b"), 1.0, 3.0)],
+        ));
+        let stale = books
+            .apply_incremental(&refresh(vec![
+                update(New, Some(Bid), Some("c"), Some(11), Some(1.0), Some(2.0)),
+                // a smaller size keeps its place, a new price loses it.
+                update(Change, Some(Bid), Some("a"), Some(12), Some(1.0), Some(4.0)),
+                update(Change, Some(Bid), Some("b"), Some(13), Some(1.1), Some(3.0)),
+                update(Delete, None, Some("c"), Some(14), None, None),
+                update(New, Some(Offer), Some("d"), Some(15), Some(1.2), Some(1.0)),
+            ]))
+            .unwrap();
+        assert!(stale.is_empty());
+
+        let book = books.book("EURUSD").unwrap();
+        let ids: Vec<&str> = book.l3().orders(BookSide::Bid).iter().map(|o| o.id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "a"]);
+        assert_eq!(prices(&book.l3().levels(BookSide::Bid)), vec![(1.1, 3.0), (1.0, 4.0)]);
+        assert_eq!(book.l3().order("d").map(|(side, _)| side), Some(BookSide::Offer));
+        assert_eq!(book.rpt_seq(), Some(15));
+    }
+
+    #[test]
+    fn test_gap_recovered_from_snapshot() {
+        use market_data::MDEntryType::*;
+        use market_data::MDUpdateAction::*;
+
+        let mut books = MarketDataBooks::new();
+        books.apply_snapshot(&snapshot(Some(1), vec![level(Bid, None, 1.0, 5.0)]));
+        books
+            .apply_incremental(&refresh(vec![update(
+                New,
+                Some(Bid),
+                None,
+                Some(2),
+                Some(1.1),
+                Some(1.0),
+            )]))
+            .unwrap();
+
+        // 3 and 4 are missed, 5 and 6 wait for the snapshot.
+        let stale = books
+            .apply_incremental(&refresh(vec![
+                update(New, Some(Offer), None, Some(5), Some(1.3), Some(2.0)),
+                update(Change, Some(Offer), None, Some(6), Some(1.3), Some(3.0)),
+            ]))
+            .unwrap();
+        assert_eq!(stale, vec!["EURUSD".to_string()]);
+        let book = books.book("EURUSD").unwrap();
+        assert!(book.is_stale());
+        assert_eq!(book.l2().levels(BookSide::Offer), &[]);
+
+        // the snapshot includes up to 5, so only 6 is applied after it.
+        let stale = books.apply_snapshot(&snapshot(
+            Some(5),
+            vec![level(Bid, None, 1.05, 2.0), level(Offer, None, 1.3, 2.0)],
+        ));
+        assert!(stale.is_empty());
+        let book = books.book("EURUSD").unwrap();
+        assert!(!book.is_stale());
+        assert_eq!(book.rpt_seq(), Some(6));
+        assert_eq!(prices(book.l2().levels(BookSide::Bid)), vec![(1.05, 2.0)]);
+        assert_eq!(prices(book.l2().levels(BookSide::Offer)), vec![(1.3, 3.0)]);
+        assert!(books.stale().is_empty());
+    }
+
+    #[test]
+    fn test_entry_that_did_not_fit_is_applied_after_an_older_snapshot() {
+        use market_data::MDEntryType::*;
+        use market_data::MDUpdateAction::*;
+
+        let mut books = MarketDataBooks::new();
+        books.apply_snapshot(&snapshot(
+            Some(1),
+            vec![level(Bid, Some("a"), 1.0, 5.0), level(Bid, Some("b"), 1.0, 3.0)],
+        ));
+
+        // 2 adds the level, it is missed so the change 3 does not fit.
+        let change = update(Change, Some(Offer), None, Some(3), Some(1.1), Some(1.0));
+        let stale = books.apply_incremental(&refresh(vec![change])).unwrap();
+        assert_eq!(stale, vec!["EURUSD".to_string()]);
+        assert_eq!(books.book("EURUSD").unwrap().rpt_seq(), Some(1));
+
+        // the snapshot includes 2 but not 3, order b is gone with it.
+        let stale = books.apply_snapshot(&snapshot(
+            Some(2),
+            vec![level(Bid, Some("a"), 1.0, 5.0), level(Offer, None, 1.1, 2.0)],
+        ));
+        assert!(stale.is_empty());
+        let book = books.book("EURUSD").unwrap();
+        assert_eq!(book.rpt_seq(), Some(3));
+        assert_eq!(prices(book.l2().levels(BookSide::Offer)), vec![(1.1, 1.0)]);
+        assert!(!books.order_symbols.contains_key("b"));
+        let delete = update(Delete, None, Some("b"), Some(4), None, None);
+        assert!(books.apply_incremental(&refresh(vec![delete])).is_err());
+    }
+}
@@ -1,22 +1,42 @@
 use std;
 use std::fmt::Debug;
-use std::io::Error;
+use std::io::{Error, ErrorKind};
 use std::io::{Read, Write};
 use std::net::TcpStream;
+use std::str;
 
+use chrono;
+use mio;
+use mio::event::Source;
 use native_tls::TlsStream;
+#[cfg(feature = "rustls")]
+use rustls;
 
-use detail::FixSerializable;
+use admin::{Flag, Logout, Reject, SequenceReset, SessionRejectReason};
+use clock::{Clock, SystemClock};
+use detail::{find_field, parse_fix_message, FixMessageType, FixSerializable};
+use interceptor::{intercept, logged, received, written, Intercepted, Interceptor};
+use message_log::{LogMessageLog, MessageLog};
 use serialize;
+use parsing::seq_of;
+use store::{is_admin_msg_type, resend_plan, MessageStore, Resend};
+use throttle::{Admission, OverflowAction, Queued, Throttle, ThrottleUsage};
 use CompIds;
 use FixHeader;
+use ParseError;
+use SessionConfig;
+use timers::SessionTimers;
+use Timestamp;
 
-pub trait Stream {
-    fn get_mut(&mut self) -> &mut TcpStream;
+/// Transport of a FIX session, any `Read + Write` will do: tcp, tls, unix sockets, pipes, captures.
+///
+/// Reads should not block indefinitely if the session has to keep sending heartbeats and actions,
+/// so make the transport non blocking or give it a read timeout.
+/// `WouldBlock` and `TimedOut` errors just mean there is no data yet, or for writes that the transport
+/// does not take more yet. The client buffers the rest, see `FixClient::write_buffered`.
+pub trait Stream: Read + Write {}
 
-    fn read(&mut self, buf : &mut [u8]) -> Result<usize, Error>;
-    fn write_all(&mut self, buf : &[u8]) -> Result<(), Error>;
-}
+impl<T: Read + Write> Stream for T {}
 
 pub struct PlainStreamWrapper {
     stream: TcpStream,
@@ -26,46 +46,130 @@ impl PlainStreamWrapper {
     pub fn new(stream: TcpStream) -> PlainStreamWrapper {
         PlainStreamWrapper { stream }
     }
-}
 
-impl Stream for PlainStreamWrapper {
-    fn get_mut(&mut self) -> &mut TcpStream {
+    pub fn get_mut(&mut self) -> &mut TcpStream {
         &mut self.stream
     }
 
+    // see `FixClient::set_event_source`. Puts the socket into non blocking mode.
+    pub fn event_source(&self) -> Result<mio::net::TcpStream, Error> {
+        tcp_event_source(&self.stream)
+    }
+}
+
+pub(crate) fn tcp_event_source(stream: &TcpStream) -> Result<mio::net::TcpStream, Error> {
+    let clone = stream.try_clone()?;
+    clone.set_nonblocking(true)?;
+    Ok(mio::net::TcpStream::from_std(clone))
+}
+
+impl Read for PlainStreamWrapper {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
         self.stream.read(buf)
     }
+}
 
-    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
-        self.stream.write_all(buf)
+impl Write for PlainStreamWrapper {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.stream.write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.stream.flush()
     }
 }
 
+/// TLS transport, made by `TlsConfig` or from a native-tls stream.
 pub struct TlsStreamWrapper {
-    stream: TlsStream<TcpStream>,
+    stream: TlsInner,
+}
+
+enum TlsInner {
+    Native(TlsStream<TcpStream>),
+    #[cfg(feature = "rustls")]
+    RustlsClient(Box<rustls::StreamOwned<rustls::ClientConnection, TcpStream>>),
+    #[cfg(feature = "rustls")]
+    RustlsServer(Box<rustls::StreamOwned<rustls::ServerConnection, TcpStream>>),
 }
 
 impl TlsStreamWrapper {
     pub fn new(stream: TlsStream<TcpStream>) -> TlsStreamWrapper {
-        TlsStreamWrapper { stream }
+        TlsStreamWrapper {
+            stream: TlsInner::Native(stream),
+        }
     }
-}
 
-impl Stream for TlsStreamWrapper {
-    fn get_mut(&mut self) -> &mut TcpStream {
-        self.stream.get_mut()
+    #[cfg(feature = "rustls")]
+    pub(crate) fn rustls_client(stream: rustls::StreamOwned<rustls::ClientConnection, TcpStream>) -> TlsStreamWrapper {
+        TlsStreamWrapper {
+            stream: TlsInner::RustlsClient(Box::new(stream)),
+        }
     }
 
+    #[cfg(feature = "rustls")]
+    pub(crate) fn rustls_server(stream: rustls::StreamOwned<rustls::ServerConnection, TcpStream>) -> TlsStreamWrapper {
+        TlsStreamWrapper {
+            stream: TlsInner::RustlsServer(Box::new(stream)),
+        }
+    }
+
+    pub fn get_mut(&mut self) -> &mut TcpStream {
+        match self.stream {
+            TlsInner::Native(ref mut stream) => stream.get_mut(),
+            #[cfg(feature = "rustls")]
+            TlsInner::RustlsClient(ref mut stream) => &mut stream.sock,
+            #[cfg(feature = "rustls")]
+            TlsInner::RustlsServer(ref mut stream) => &mut stream.sock,
+        }
+    }
+
+    fn get_ref(&self) -> &TcpStream {
+        match self.stream {
+            TlsInner::Native(ref stream) => stream.get_ref(),
+            #[cfg(feature = "rustls")]
+            TlsInner::RustlsClient(ref stream) => &stream.sock,
+            #[cfg(feature = "rustls")]
+            TlsInner::RustlsServer(ref stream) => &stream.sock,
+        }
+    }
+
+    // see `FixClient::set_event_source`. Puts the socket into non blocking mode.
+    pub fn event_source(&self) -> Result<mio::net::TcpStream, Error> {
+        tcp_event_source(self.get_ref())
+    }
+
+    fn as_stream(&mut self) -> &mut Stream {
+        match self.stream {
+            TlsInner::Native(ref mut stream) => stream,
+            #[cfg(feature = "rustls")]
+            TlsInner::RustlsClient(ref mut stream) => &mut **stream,
+            #[cfg(feature = "rustls")]
+            TlsInner::RustlsServer(ref mut stream) => &mut **stream,
+        }
+    }
+}
+
+impl Read for TlsStreamWrapper {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
-        self.stream.read(buf)
+        self.as_stream().read(buf)
     }
+}
 
-    fn write_all(&mut self, buf: &[u8]) -> Result<(), Error> {
-        self.stream.write_all(buf)
+impl Write for TlsStreamWrapper {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.as_stream().write(buf)
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        self.as_stream().flush()
     }
 }
 
+const ORIG_SENDING_TIME: u64 = 122;
+const RESET_SEQ_NUM_FLAG: u64 = 141;
+// how long `FixClient::flush` sleeps while the transport does not take more.
+const FLUSH_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_millis(1);
+
 pub struct FixClient {
     stream: Box<Stream>, //TlsStream<TcpStream>,
 //    stream: TlsStream<TcpStream>,
@@ -73,6 +177,41 @@ pub struct FixClient {
     send_seq_num: u64,
     rcv_seq_num: u64,
     comp_ids: CompIds,
+    config: SessionConfig,
+
+    clock: Box<Clock>,
+    timers: SessionTimers,
+    last_latency: Option<chrono::Duration>,
+
+    event_source: Option<Box<Source>>,
+    store: Option<Box<MessageStore>>,
+    throttle: Option<Throttle>,
+    message_log: Box<MessageLog>,
+    interceptors: Vec<Box<Interceptor>>,
+
+    // bytes of sent messages the transport did not take yet, see `write_buffered`.
+    outbound: Vec<u8>,
+    // the transport still has to flush what it took, e.g. TLS records.
+    unflushed: bool,
+
+    logged_on: bool,
+    logout_sent: bool,
+    logout_received: bool,
+    // a Logon with ResetSeqNumFlag went out or came in and the other side did not answer it yet.
+    seq_reset_pending: bool,
+    // Some while the `Session` holds outbound messages back for the handler, see `Held`.
+    held: Option<Vec<Held>>,
+}
+
+// a message sent while the `Session` runs a handler callback, it goes out once the callback
+// returned and `FixHandler::to_admin` or `FixHandler::to_app` amended it.
+pub(crate) enum Held {
+    // given to `send`, still to be amended, stored and throttled.
+    Send(String),
+    // given to `send` and already counted by a rejecting throttle, still to be amended and stored.
+    Counted(String),
+    // a resend or gap fill, written as it is.
+    Write(String),
 }
 
 #[derive(Debug)]
@@ -80,23 +219,207 @@ pub enum MessageValidationErr {
     SeqNumOutOfOrder,
     SenderMismatch(String),
     TargetMismatch(String),
+    SendingTimeAccuracy(String),
+    // a tag the message needs is missing, e.g. OrigSendingTime (122) on a PossDup message.
+    RequiredTagMissing(u64),
+}
+
+#[derive(Debug)]
+pub enum SendError {
+    // the transport failed, the message is marked unsent in the store if there is one.
+    Io(Error),
+    // a stored message could not be parsed for a resend.
+    Store(ParseError),
+    // over a limit of the throttle, nothing was sent and the seq is given back if it was the last one.
+    Throttled { msg_type: String, retry_after: std::time::Duration },
+    // an interceptor refused the message, nothing was sent and the seq is given back if it was the last one.
+    Vetoed(String),
+}
+
+impl From<Error> for SendError {
+    fn from(io_error: Error) -> SendError {
+        SendError::Io(io_error)
+    }
 }
 
 impl FixClient {
     pub fn new(comp_ids: CompIds, stream: Box<Stream>) -> FixClient {
+        FixClient::with_config(comp_ids, SessionConfig::default(), stream)
+    }
+
+    pub fn with_config(comp_ids: CompIds, config: SessionConfig, stream: Box<Stream>) -> FixClient {
+        let clock = Box::new(SystemClock);
+        let timers = SessionTimers::new(config.heartbeat_interval, clock.now());
         FixClient {
             stream,
             send_seq_num: 1u64,
             rcv_seq_num: 1u64,
 
             comp_ids,
+            config,
+
+            clock,
+            timers,
+            last_latency: None,
+
+            event_source: None,
+            store: None,
+            throttle: None,
+            message_log: Box::new(LogMessageLog::new()),
+            interceptors: Vec::new(),
+
+            outbound: Vec::new(),
+            unflushed: false,
+
+            logged_on: false,
+            logout_sent: false,
+            logout_received: false,
+            seq_reset_pending: false,
+            held: None,
+        }
+    }
+
+    // replaces the clock used for SendingTime and the session timers, which are restarted.
+    pub fn set_clock(&mut self, clock: Box<Clock>) {
+        self.timers = SessionTimers::new(self.config.heartbeat_interval, clock.now());
+        self.clock = clock;
+    }
+
+    pub fn clock(&self) -> &Clock {
+        &*self.clock
+    }
+
+    // lets `EventLoop` and `SessionRunner` sleep until the transport is readable, or writable while
+    // sent bytes are buffered. The source has to become ready together with the stream, e.g. a clone
+    // of the same socket, and the stream has to be non blocking.
+    pub fn set_event_source<S: Source + 'static>(&mut self, source: S) {
+        self.event_source = Some(Box::new(source));
+    }
+
+    pub fn event_source(&mut self) -> Option<&mut Source> {
+        match self.event_source {
+            Some(ref mut source) => Some(&mut **source),
+            None => None,
+        }
+    }
+
+    // continues the sequence numbers of the store and stores every message sent from now on.
+    pub fn set_store(&mut self, store: Box<MessageStore>) {
+        self.send_seq_num = store.next_sender_seq();
+        self.rcv_seq_num = store.next_target_seq();
+        self.store = Some(store);
+    }
+
+    // continues a session from the given sequence numbers, e.g. to replay a recording from its middle.
+    pub fn set_next_seqs(&mut self, next_send: u64, next_rcv: u64) {
+        self.send_seq_num = next_send;
+        self.rcv_seq_num = next_rcv;
+        if let Some(ref mut store) = self.store {
+            store.set_next_target_seq(next_rcv);
+        }
+    }
+
+    // a Logon went out or came in. One with ResetSeqNumFlag (141) starts both sequences over, unless it
+    // answers one that did already, and the store forgets every message of the old ones.
+    pub(crate) fn on_logon(&mut self, reset: bool, next_send: u64) {
+        if reset && !self.seq_reset_pending {
+            self.send_seq_num = next_send;
+            self.rcv_seq_num = 1;
+            if let Some(ref mut store) = self.store {
+                store.reset();
+            }
+            self.seq_reset_pending = true;
+        } else {
+            self.seq_reset_pending = false;
+        }
+    }
+
+    pub fn store(&mut self) -> Option<&mut MessageStore> {
+        match self.store {
+            Some(ref mut store) => Some(&mut **store),
+            None => None,
         }
     }
 
+    // MsgSeqNum expected of the next incoming message.
+    pub fn next_rcv_seq(&self) -> u64 {
+        self.rcv_seq_num
+    }
+
+    // MsgSeqNum of the next outgoing message, without taking it like `get_next_send_seq`.
+    pub fn next_send_seq(&self) -> u64 {
+        self.send_seq_num
+    }
+
+    // set by the `Session` once a Logon was handled, until a Logout was or the connection is gone.
+    pub fn is_logged_on(&self) -> bool {
+        self.logged_on
+    }
+
+    pub(crate) fn set_logged_on(&mut self, logged_on: bool) {
+        self.logged_on = logged_on;
+    }
+
+    // a Logout was written, by the handler or on its behalf.
+    pub fn logout_sent(&self) -> bool {
+        self.logout_sent
+    }
+
+    // the counterparty sent a Logout which the interceptors let through.
+    pub fn logout_received(&self) -> bool {
+        self.logout_received
+    }
+
+    pub(crate) fn set_logout_received(&mut self) {
+        self.log_event("Received logout");
+        self.logout_received = true;
+    }
+
     pub fn comp_ids(&self) -> &CompIds {
         &self.comp_ids
     }
 
+    pub fn config(&self) -> &SessionConfig {
+        &self.config
+    }
+
+    // current time in the precision configured for this session, meant for SendingTime.
+    pub fn sending_time(&self) -> Timestamp {
+        Timestamp::from_clock(self.clock()).with_precision(self.config.timestamp_precision)
+    }
+
+    // nothing was sent for a whole heartbeat interval, a Heartbeat should go out.
+    pub fn heartbeat_due(&self) -> bool {
+        self.timers.heartbeat_due(self.clock.now())
+    }
+
+    // counterparty is silent for too long, a TestRequest should go out.
+    pub fn test_request_due(&self) -> bool {
+        self.timers.test_request_due(self.clock.now())
+    }
+
+    // counterparty did not answer even a TestRequest, the connection should be dropped.
+    pub fn peer_timed_out(&self) -> bool {
+        self.timers.timed_out(self.clock.now())
+    }
+
+    // time until one of the session timers above becomes due.
+    pub fn next_timer_deadline(&self) -> Option<std::time::Duration> {
+        let now = self.clock.now();
+        let timers = self.timers.next_deadline(now).and_then(|d| d.to_std().ok());
+        let queued = self.throttle.as_ref().and_then(|throttle| throttle.next_deadline(now));
+        match (timers, queued) {
+            (Some(timers), Some(queued)) => Some(timers.min(queued)),
+            (timers, queued) => timers.or(queued),
+        }
+    }
+
+    // our clock minus SendingTime of the last validated message, i.e. one way latency plus clock skew.
+    // PossDup messages are not measured.
+    pub fn last_latency(&self) -> Option<chrono::Duration> {
+        self.last_latency
+    }
+
     pub fn get_next_send_seq(&mut self) -> u64 {
         let seq = self.send_seq_num;
         self.send_seq_num += 1;
@@ -104,39 +427,441 @@ impl FixClient {
         seq
     }
 
-    pub fn send<Msg: FixSerializable + Debug>(&mut self, msg: &Msg) {
-        let fix_msg = serialize(msg);
-        Self::log_send(&fix_msg);
-        self.stream.write_all(fix_msg.as_bytes()).unwrap();
+    // a message the transport refused is kept in the store, if any, for `resend_unsent`.
+    // With a throttle set, a message over its limits is queued or rejected, see `Throttle`.
+    // While a `Session` runs a handler callback the message is only serialized, it is sent when the
+    // callback returned and failures are reported by the session instead. A rejecting throttle still
+    // refuses it right away, so the caller learns about it and the seq is given back.
+    pub fn send<Msg: FixSerializable + Debug>(&mut self, msg: &Msg) -> Result<(), SendError> {
+        let serialized = serialize(msg);
+        let fix_msg = match intercept(&mut self.interceptors, serialized.as_bytes(), true) {
+            Intercepted::Unchanged => serialized,
+            Intercepted::Amended(amended) => amended,
+            Intercepted::Dropped => {
+                debug!("interceptor dropped {:?}", msg);
+                self.give_back_seq(&serialized);
+                return Ok(());
+            }
+            Intercepted::Vetoed(reason) => {
+                self.log_event(&format!("Vetoed {}: {}", msg_type_of(&serialized), reason));
+                self.give_back_seq(&serialized);
+                return Err(SendError::Vetoed(reason));
+            }
+        };
+        if self.held.is_some() {
+            let held = if self.admit_held(&fix_msg)? {
+                Held::Counted(fix_msg)
+            } else {
+                Held::Send(fix_msg)
+            };
+            debug!("holding {:?}", msg);
+            if let Some(ref mut holding) = self.held {
+                holding.push(held);
+            }
+            return Ok(());
+        }
+        self.send_serialized(fix_msg)
+    }
+
+    // counts a message about to be held against a rejecting throttle, returns whether it was counted.
+    // Later messages of the same callback see its use, as if it had been sent already.
+    fn admit_held(&mut self, fix_msg: &str) -> Result<bool, SendError> {
+        let now = self.clock.now();
+        let msg_type = match self.throttle {
+            Some(ref throttle) if throttle.on_overflow() == OverflowAction::Reject => msg_type_of(fix_msg),
+            _ => return Ok(false),
+        };
+        let admin = is_admin_msg_type(msg_type.as_bytes());
+        let admission = match self.throttle {
+            Some(ref throttle) => throttle.admit(&msg_type, admin, now),
+            None => Admission::Now,
+        };
+        if let Admission::Reject(retry_after) = admission {
+            self.give_back_seq(fix_msg);
+            return Err(SendError::Throttled { msg_type, retry_after });
+        }
+        if let Some(ref mut throttle) = self.throttle {
+            throttle.record(&msg_type, now);
+        }
+        Ok(true)
+    }
+
+    pub(crate) fn send_serialized(&mut self, fix_msg: String) -> Result<(), SendError> {
+        let msg_type = match self.throttle {
+            Some(_) => msg_type_of(&fix_msg),
+            None => String::new(),
+        };
+        let admin = is_admin_msg_type(msg_type.as_bytes());
+        let admission = match self.throttle {
+            Some(ref throttle) => throttle.admit(&msg_type, admin, self.clock.now()),
+            None => Admission::Now,
+        };
+
+        match admission {
+            Admission::Now => {}
+            Admission::Queue => {
+                let seq = self.store_sent(&fix_msg);
+                if let Some(ref mut throttle) = self.throttle {
+                    debug!("throttled, queueing {}", fix_msg);
+                    throttle.enqueue(Queued {
+                        msg_type,
+                        admin,
+                        seq,
+                        msg: fix_msg,
+                    });
+                }
+                return Ok(());
+            }
+            Admission::Reject(retry_after) => {
+                self.give_back_seq(&fix_msg);
+                return Err(SendError::Throttled { msg_type, retry_after });
+            }
+        }
+
+        let seq = self.store_sent(&fix_msg);
+        self.write_counted(&msg_type, seq, &fix_msg)
+    }
+
+    // lets the caller send something else with the seq of a message not sent, if it was the last one.
+    fn give_back_seq(&mut self, fix_msg: &str) {
+        if let Some(seq) = seq_of(fix_msg.as_bytes()) {
+            if seq + 1 == self.send_seq_num {
+                self.send_seq_num = seq;
+            }
+        }
+    }
+
+    // adds an interceptor after those added before, see `Interceptor`.
+    pub fn add_interceptor(&mut self, interceptor: Box<Interceptor>) {
+        self.interceptors.push(interceptor);
+    }
+
+    pub(crate) fn intercept_inbound(&mut self, msg: &[u8]) -> Intercepted {
+        intercept(&mut self.interceptors, msg, false)
+    }
+
+    // tells the interceptors about an incoming message the handler gets next.
+    pub(crate) fn received_inbound(&mut self, msg: &[u8]) {
+        received(&mut self.interceptors, msg);
     }
 
-    pub fn poll(&mut self, mut buf: &mut [u8]) -> Result<usize, Error> {
-        {
-            let mut peek_buffer = [0; 1];
-            let underlying = self.stream.get_mut();
+    // takes the seq of an incoming message the handler does not get, e.g. dropped by an interceptor.
+    pub(crate) fn skip_incoming(&mut self, msg: &[u8]) {
+        if seq_of(msg) == Some(self.rcv_seq_num) {
+            self.rcv_seq_num += 1;
+            if let Some(ref mut store) = self.store {
+                store.set_next_target_seq(self.rcv_seq_num);
+            }
+        }
+    }
+
+    // answers an incoming message an interceptor vetoed with a Reject.
+    pub(crate) fn veto_incoming(&mut self, msg: &[u8], text: &str) -> Result<(), SendError> {
+        self.skip_incoming(msg);
+        let ref_seq_num = seq_of(msg).unwrap_or(0);
+        self.log_event(&format!("Rejecting message {}: {}", ref_seq_num, text));
+        let reject = Reject {
+            seq: self.get_next_send_seq(),
+            sender: self.comp_ids.sender.clone(),
+            target: self.comp_ids.target.clone(),
+            sending_time: self.sending_time(),
+            ref_seq_num,
+            ref_tag_id: None,
+            ref_msg_type: Some(msg_type_of(&String::from_utf8_lossy(msg))),
+            reason: Some(SessionRejectReason::Other),
+            text: Some(text.to_string()),
+        };
+        self.send(&reject)
+    }
 
-            underlying.peek(&mut peek_buffer)?;
+    pub(crate) fn hold(&mut self) {
+        if self.held.is_none() {
+            self.held = Some(Vec::new());
         }
+    }
 
-        self.stream.read(&mut buf)
+    // stops holding, returns what was held in the order it was sent.
+    pub(crate) fn take_held(&mut self) -> Vec<Held> {
+        self.held.take().unwrap_or_default()
     }
 
+    pub(crate) fn send_held(&mut self, held: Held) -> Result<(), SendError> {
+        match held {
+            Held::Send(fix_msg) => self.send_serialized(fix_msg),
+            Held::Counted(fix_msg) => {
+                let seq = self.store_sent(&fix_msg);
+                self.write_stored(seq, &fix_msg)
+            }
+            Held::Write(fix_msg) => Ok(self.write(&fix_msg)?),
+        }
+    }
+
+    // sends the messages queued by the throttle as far as its limits allow, returns how many were sent.
+    pub fn send_queued(&mut self) -> Result<usize, SendError> {
+        let mut sent = 0;
+        loop {
+            let now = self.clock.now();
+            let queued = match self.throttle {
+                Some(ref mut throttle) => throttle.pop_ready(now),
+                None => None,
+            };
+            match queued {
+                Some(queued) => {
+                    self.write_counted(&queued.msg_type, queued.seq, &queued.msg)?;
+                    sent += 1;
+                }
+                None => return Ok(sent),
+            }
+        }
+    }
+
+    // replaces the outbound rate limits. Messages queued by the previous throttle already have their
+    // seqs, so they stay queued in front and go out as the new limits allow.
+    pub fn set_throttle(&mut self, mut throttle: Throttle) {
+        if let Some(mut previous) = self.throttle.take() {
+            throttle.take_queue_of(&mut previous);
+        }
+        self.throttle = Some(throttle);
+    }
+
+    pub fn throttle(&self) -> Option<&Throttle> {
+        self.throttle.as_ref()
+    }
+
+    // usage of every limit of the throttle, now.
+    pub fn throttle_usage(&self) -> Vec<ThrottleUsage> {
+        match self.throttle {
+            Some(ref throttle) => throttle.usage(self.clock.now()),
+            None => Vec::new(),
+        }
+    }
+
+    // returns the seq of the stored message.
+    fn store_sent(&mut self, fix_msg: &str) -> Option<u64> {
+        // the Logon resetting the sequences is the first message of the new ones.
+        if let Some(reset) = logon_reset(fix_msg.as_bytes()) {
+            let next_send = seq_of(fix_msg.as_bytes()).map_or(self.send_seq_num, |seq| seq + 1);
+            self.on_logon(reset, next_send);
+        }
+        match self.store {
+            Some(ref mut store) => match seq_of(fix_msg.as_bytes()) {
+                Some(seq) => {
+                    store.store(seq, fix_msg);
+                    Some(seq)
+                }
+                None => None,
+            },
+            None => None,
+        }
+    }
+
+    fn write_counted(&mut self, msg_type: &str, stored_seq: Option<u64>, fix_msg: &str) -> Result<(), SendError> {
+        if let Some(ref mut throttle) = self.throttle {
+            throttle.record(msg_type, self.clock.now());
+        }
+        self.write_stored(stored_seq, fix_msg)
+    }
+
+    fn write_stored(&mut self, stored_seq: Option<u64>, fix_msg: &str) -> Result<(), SendError> {
+        match self.write(fix_msg) {
+            Ok(()) => Ok(()),
+            Err(err) => {
+                error!("failed to send {}: {:?}", fix_msg, err);
+                self.log_event(&format!("Send failed: {}", err));
+                if let (Some(seq), Some(store)) = (stored_seq, self.store.as_mut()) {
+                    store.mark_unsent(seq);
+                }
+                Err(SendError::Io(err))
+            }
+        }
+    }
+
+    // answers a ResendRequest from the store: application messages are sent again as PossDup,
+    // admin messages and those missing from the store, or all without one, are gap filled up to the
+    // next seq to send. An `end` of 0 asks for everything from `begin`. Returns how many were resent.
+    pub fn resend(&mut self, begin: u64, end: u64) -> Result<usize, SendError> {
+        let last_sent = self.send_seq_num - 1;
+        let end = if end == 0 { last_sent } else { end.min(last_sent) };
+        self.log_event(&format!("Resending messages from {} to {}", begin, end));
+        if begin > end {
+            return Ok(0);
+        }
+        let stored = match self.store {
+            Some(ref store) => store.get(begin, end),
+            None => Vec::new(),
+        };
+        let sending_time = self.sending_time();
+        let plan = resend_plan(stored, begin, end, &sending_time.to_string()).map_err(SendError::Store)?;
+
+        let mut resent = 0;
+        for resend in plan {
+            match resend {
+                Resend::Message(copy) => {
+                    self.write(&copy)?;
+                    resent += 1;
+                }
+                Resend::GapFill { seq, new_seq_no } => self.send_gap_fill(seq, new_seq_no, sending_time)?,
+            }
+        }
+        Ok(resent)
+    }
+
+    // resends the messages the transport refused before, e.g. once logged on again after a reconnect.
+    pub fn resend_unsent(&mut self) -> Result<usize, SendError> {
+        let unsent = match self.store {
+            Some(ref mut store) => store.take_unsent(),
+            None => return Ok(0),
+        };
+
+        let mut resent = 0;
+        for (i, &seq) in unsent.iter().enumerate() {
+            match self.resend(seq, seq) {
+                Ok(count) => resent += count,
+                Err(err) => {
+                    if let Some(ref mut store) = self.store {
+                        for &seq in &unsent[i..] {
+                            store.mark_unsent(seq);
+                        }
+                    }
+                    return Err(err);
+                }
+            }
+        }
+        Ok(resent)
+    }
+
+    fn send_gap_fill(&mut self, seq: u64, new_seq_no: u64, sending_time: Timestamp) -> Result<(), SendError> {
+        let gap_fill = SequenceReset {
+            seq,
+            sender: self.comp_ids.sender.clone(),
+            target: self.comp_ids.target.clone(),
+            sending_time,
+            poss_dup: Some(Flag::Yes),
+            orig_sending_time: Some(sending_time),
+            gap_fill: Some(Flag::Yes),
+            new_seq_no,
+        };
+        self.write(&serialize(&gap_fill))?;
+        Ok(())
+    }
+
+    fn write(&mut self, fix_msg: &str) -> Result<(), Error> {
+        if let Some(ref mut held) = self.held {
+            held.push(Held::Write(fix_msg.to_string()));
+            return Ok(());
+        }
+        let now = self.clock.now();
+        match logged(&mut self.interceptors, fix_msg.as_bytes()) {
+            Some(scrubbed) => self.message_log.on_outgoing(now, &scrubbed),
+            None => self.message_log.on_outgoing(now, fix_msg),
+        }
+        self.outbound.extend_from_slice(fix_msg.as_bytes());
+        self.write_buffered()?;
+        self.timers.on_send(now);
+        if !self.logout_sent {
+            self.logout_sent =
+                parse_fix_message(fix_msg.as_bytes()).is_ok_and(|msg| msg.msg_type == Logout::MSG_TYPE);
+        }
+        written(&mut self.interceptors, fix_msg.as_bytes());
+        Ok(())
+    }
+
+    // writes the bytes of sent messages a non blocking transport did not take before, as far as it
+    // takes them now. Returns whether anything was written.
+    pub fn write_buffered(&mut self) -> Result<bool, Error> {
+        let mut written = 0;
+        let mut result = Ok(());
+        while written < self.outbound.len() {
+            match self.stream.write(&self.outbound[written..]) {
+                Ok(0) => {
+                    result = Err(Error::new(ErrorKind::WriteZero, "transport takes no more bytes"));
+                    break;
+                }
+                Ok(size) => written += size,
+                Err(ref err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(ref err) if would_block(err) => break,
+                Err(err) => {
+                    result = Err(err);
+                    break;
+                }
+            }
+        }
+        self.outbound.drain(..written);
+        result?;
+
+        if written > 0 || self.unflushed {
+            self.unflushed = match self.stream.flush() {
+                Ok(()) => false,
+                Err(ref err) if would_block(err) => true,
+                Err(err) => return Err(err),
+            };
+        }
+        Ok(written > 0)
+    }
+
+    // sent bytes wait for the transport, `write_buffered` should be called once it is writable.
+    pub fn has_buffered(&self) -> bool {
+        !self.outbound.is_empty() || self.unflushed
+    }
+
+    // writes everything buffered, waiting for the transport for up to a heartbeat interval.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let deadline = std::time::Instant::now() + self.config.heartbeat_interval;
+        loop {
+            self.write_buffered()?;
+            if !self.has_buffered() {
+                return Ok(());
+            }
+            if std::time::Instant::now() >= deadline {
+                return Err(Error::new(ErrorKind::TimedOut, "transport did not take the sent messages"));
+            }
+            std::thread::sleep(FLUSH_RETRY_INTERVAL);
+        }
+    }
+
+    pub fn poll(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let size = self.stream.read(buf)?;
+        if size == 0 {
+            return Err(Error::new(ErrorKind::UnexpectedEof, "connection closed by counterparty"));
+        }
+
+        self.timers.on_receive(self.clock.now());
+        Ok(size)
+    }
+
+    // replaces where sent and received messages and session events are logged, `LogMessageLog` by default.
+    pub fn set_message_log(&mut self, message_log: Box<MessageLog>) {
+        self.message_log = message_log;
+    }
+
+    // a single complete message, called by the `Session` for everything it receives.
+    pub fn log_incoming(&mut self, msg: &[u8]) {
+        let now = self.clock.now();
+        match logged(&mut self.interceptors, msg) {
+            Some(scrubbed) => self.message_log.on_incoming(now, scrubbed.as_bytes()),
+            None => self.message_log.on_incoming(now, msg),
+        }
+    }
+
+    pub fn log_event(&mut self, text: &str) {
+        let now = self.clock.now();
+        self.message_log.on_event(now, text);
+    }
+
+    #[deprecated(note = "sent messages are logged by the `MessageLog`, see `set_message_log`")]
     pub fn log_send(serialized: &str) {
-        //        println!(">> {}", serialized);
-        info!(">> {}", serialized);
+        LogMessageLog::new().on_outgoing(SystemClock.now(), serialized);
     }
 
+    #[deprecated(note = "received messages are logged by the `MessageLog`, see `log_incoming`")]
     pub fn log_rcv(buff: &[u8], size: usize) {
-        if let Ok(as_str) = std::str::from_utf8(buff) {
-            //            println!("<< {}", &as_str);
-            info!("<< {}", &as_str);
-        } else {
-            error!("couldnt view rcv as utf8?");
-        }
+        LogMessageLog::new().on_incoming(SystemClock.now(), &buff[..size.min(buff.len())]);
     }
 
     pub fn validate_msg<T: FixHeader>(&mut self, m: &T) -> Result<(), MessageValidationErr> {
         if m.seq() != self.rcv_seq_num {
+            let text = format!("MsgSeqNum out of order, expected {} got {}", self.rcv_seq_num, m.seq());
+            self.log_event(&text);
             Err(MessageValidationErr::SeqNumOutOfOrder)
         } else if m.sender() != self.comp_ids.target {
             Err(MessageValidationErr::SenderMismatch(format!(
@@ -152,7 +877,319 @@ impl FixClient {
             )))
         } else {
             self.rcv_seq_num += 1;
+            if let Some(ref mut store) = self.store {
+                store.set_next_target_seq(self.rcv_seq_num);
+            }
+            self.check_sending_time(m)
+        }
+    }
+
+    fn check_sending_time<T: FixHeader>(&mut self, m: &T) -> Result<(), MessageValidationErr> {
+        // replays carry the time of the original transmission in OrigSendingTime, which can not be later.
+        if m.poss_dup() {
+            match (m.orig_sending_time(), m.sending_time()) {
+                (None, _) => {
+                    let text = "OrigSendingTime missing on PossDup message";
+                    let reason = SessionRejectReason::RequiredTagMissing;
+                    if let Err(err) = self.reject_incoming(m.seq(), Some(ORIG_SENDING_TIME), reason, text) {
+                        error!("failed to reject message {}: {:?}", m.seq(), err);
+                    }
+                    return Err(MessageValidationErr::RequiredTagMissing(ORIG_SENDING_TIME));
+                }
+                (Some(orig_sending_time), Some(sending_time)) if orig_sending_time.time > sending_time.time => {
+                    let text = format!(
+                        "OrigSendingTime {} is after SendingTime {}",
+                        orig_sending_time, sending_time
+                    );
+                    let reason = SessionRejectReason::SendingTimeAccuracyProblem;
+                    if let Err(err) = self.reject_and_logout(m.seq(), reason, &text) {
+                        error!("failed to reject message {}: {:?}", m.seq(), err);
+                    }
+                    return Err(MessageValidationErr::SendingTimeAccuracy(text));
+                }
+                _ => {}
+            }
+        }
+        let sending_time = match m.sending_time() {
+            Some(sending_time) => sending_time,
+            None => return Ok(()),
+        };
+
+        let latency = self.clock.now() - sending_time.time;
+        if !m.poss_dup() {
+            self.last_latency = Some(latency);
+            let micros = latency.num_microseconds().unwrap_or(i64::MAX);
+            debug!("seq {} latency {}us", m.seq(), micros);
+        }
+
+        // a tolerance too long for chrono allows any difference.
+        let tolerance = match self.config.sending_time_tolerance {
+            Some(tolerance) => chrono::Duration::from_std(tolerance).unwrap_or(chrono::Duration::MAX),
+            None => return Ok(()),
+        };
+
+        if latency.abs() > tolerance {
+            let text = format!(
+                "SendingTime {} differs by {}ms",
+                sending_time,
+                latency.num_milliseconds()
+            );
+            if let Err(err) = self.reject_and_logout(m.seq(), SessionRejectReason::SendingTimeAccuracyProblem, &text) {
+                error!("failed to reject message {}: {:?}", m.seq(), err);
+            }
+            Err(MessageValidationErr::SendingTimeAccuracy(text))
+        } else {
             Ok(())
         }
     }
-}
\ No newline at end of file
+
+    fn reject_and_logout(&mut self, ref_seq_num: u64, reason: SessionRejectReason, text: &str) -> Result<(), SendError> {
+        self.reject_incoming(ref_seq_num, None, reason, text)?;
+        self.send_logout(Some(text.to_string()))
+    }
+
+    fn reject_incoming(
+        &mut self,
+        ref_seq_num: u64,
+        ref_tag_id: Option<u64>,
+        reason: SessionRejectReason,
+        text: &str,
+    ) -> Result<(), SendError> {
+        self.log_event(&format!("Rejecting message {}: {}", ref_seq_num, text));
+        let reject = Reject {
+            seq: self.get_next_send_seq(),
+            sender: self.comp_ids.sender.clone(),
+            target: self.comp_ids.target.clone(),
+            sending_time: self.sending_time(),
+            ref_seq_num,
+            ref_tag_id,
+            ref_msg_type: None,
+            reason: Some(reason),
+            text: Some(text.to_string()),
+        };
+        self.send(&reject)
+    }
+
+    pub fn send_logout(&mut self, text: Option<String>) -> Result<(), SendError> {
+        let logout = Logout {
+            seq: self.get_next_send_seq(),
+            sender: self.comp_ids.sender.clone(),
+            target: self.comp_ids.target.clone(),
+            sending_time: self.sending_time(),
+            text,
+        };
+        self.send(&logout)
+    }
+}
+
+// the transport can not take more right now, like reads `WouldBlock` and `TimedOut` are no failures.
+fn would_block(err: &Error) -> bool {
+    err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut
+}
+
+// MsgType (35) of a serialized message, empty if it does not parse.
+// whether ResetSeqNumFlag (141) is set if `msg` is a Logon, None for any other message.
+pub(crate) fn logon_reset(msg: &[u8]) -> Option<bool> {
+    let parsed = parse_fix_message(msg).ok()?;
+    if parsed.msg_type != b"A" {
+        return None;
+    }
+    Some(find_field(parsed.body, RESET_SEQ_NUM_FLAG) == Ok(Some(&b"Y"[..])))
+}
+
+fn msg_type_of(fix_msg: &str) -> String {
+    match parse_fix_message(fix_msg.as_bytes()) {
+        Ok(parsed) => String::from_utf8_lossy(parsed.msg_type).into_owned(),
+        Err(_) => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::TcpListener;
+    use std::thread::spawn;
+    use std::time::Duration;
+
+    use chrono::{NaiveDate, NaiveDateTime};
+
+    use super::*;
+    use admin::Flag;
+    use loopback::{loopback, LoopbackStream};
+    use testing::{self, sent, NewOrder};
+    use TestClock;
+
+    fn start() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 1, 2)
+            .unwrap()
+            .and_hms_opt(10, 0, 0)
+            .unwrap()
+    }
+
+    // a client checking SendingTime within `tolerance`, and the other end.
+    fn checking(tolerance: Duration) -> (FixClient, LoopbackStream) {
+        let (ours, theirs) = loopback();
+        let mut client = testing::client("us", "them", ours);
+        client.config.sending_time_tolerance = Some(tolerance);
+        client.set_clock(Box::new(TestClock::new(start())));
+        (client, theirs)
+    }
+
+    // an order sent `age` before the clock of the client.
+    fn order(seq: u64, age: i64) -> NewOrder {
+        NewOrder {
+            sending_time: Timestamp::new(start() - chrono::Duration::seconds(age)),
+            ..NewOrder::new(seq, "them", "us", "1")
+        }
+    }
+
+    #[test]
+    fn test_sending_time_within_tolerance_sets_last_latency() {
+        let (mut client, mut theirs) = checking(Duration::from_secs(2));
+        assert_eq!(client.last_latency(), None);
+        client.validate_msg(&order(1, 1)).unwrap();
+        assert_eq!(client.last_latency(), Some(chrono::Duration::seconds(1)));
+        client.validate_msg(&order(2, -2)).unwrap();
+        assert_eq!(client.last_latency(), Some(chrono::Duration::seconds(-2)));
+        assert_eq!(sent(&mut theirs), "");
+
+        // too long for chrono, so anything goes.
+        let (mut client, _theirs) = checking(Duration::from_secs(u64::MAX));
+        client.validate_msg(&order(1, 86400 * 365)).unwrap();
+    }
+
+    #[test]
+    fn test_sending_time_outside_tolerance_is_rejected_with_logout() {
+        let (mut client, mut theirs) = checking(Duration::from_secs(2));
+        match client.validate_msg(&order(1, 5)) {
+            Err(MessageValidationErr::SendingTimeAccuracy(text)) => {
+                assert_eq!(text, "SendingTime 20240102-09:59:55.000 differs by 5000ms")
+            }
+            other => panic!("not rejected: {:?}", other),
+        }
+        assert_eq!(client.last_latency(), Some(chrono::Duration::seconds(5)));
+
+        let sent = sent(&mut theirs);
+        let reject = sent.find("\x0135=3\x01").unwrap();
+        let logout = sent.find("\x0135=5\x01").unwrap();
+        assert!(reject < logout);
+        assert!(sent[reject..logout].contains("\x0145=1\x01"));
+        assert!(sent[reject..logout].contains("\x01373=10\x01"));
+    }
+
+    // a resend `age` before the clock of the client, of an order sent `orig_age` before it.
+    fn resent(seq: u64, age: i64, orig_age: Option<i64>) -> NewOrder {
+        NewOrder {
+            poss_dup: Some(Flag::Yes),
+            orig_sending_time: orig_age.map(|age| Timestamp::new(start() - chrono::Duration::seconds(age))),
+            ..order(seq, age)
+        }
+    }
+
+    #[test]
+    fn test_poss_dup_is_checked_against_sending_time_not_orig_sending_time() {
+        let (mut client, mut theirs) = checking(Duration::from_secs(2));
+        client.validate_msg(&order(1, 1)).unwrap();
+
+        // an old order resent now is let through, dropping the duplicate is up to the handler.
+        client.validate_msg(&resent(2, 0, Some(3600))).unwrap();
+        assert_eq!(client.last_latency(), Some(chrono::Duration::seconds(1)));
+        assert_eq!(client.next_rcv_seq(), 3);
+        assert_eq!(sent(&mut theirs), "");
+
+        match client.validate_msg(&resent(3, 5, Some(3600))) {
+            Err(MessageValidationErr::SendingTimeAccuracy(text)) => {
+                assert_eq!(text, "SendingTime 20240102-09:59:55.000 differs by 5000ms")
+            }
+            other => panic!("not rejected: {:?}", other),
+        }
+        assert!(sent(&mut theirs).contains("\x01373=10\x01"));
+    }
+
+    #[test]
+    fn test_poss_dup_without_orig_sending_time_is_rejected() {
+        let (mut client, mut theirs) = checking(Duration::from_secs(2));
+        match client.validate_msg(&resent(1, 0, None)) {
+            Err(MessageValidationErr::RequiredTagMissing(122)) => {}
+            other => panic!("not rejected: {:?}", other),
+        }
+        assert_eq!(client.next_rcv_seq(), 2);
+
+        let sent = sent(&mut theirs);
+        assert!(sent.contains("\x0135=3\x01"));
+        assert!(sent.contains("\x0145=1\x01371=122\x01"));
+        assert!(sent.contains("\x01373=1\x01"));
+        assert!(!sent.contains("\x0135=5\x01"));
+    }
+
+    #[test]
+    fn test_orig_sending_time_after_sending_time_is_rejected_with_logout() {
+        // without a tolerance too.
+        let (ours, mut theirs) = loopback();
+        let mut client = testing::client("us", "them", ours);
+        client.set_clock(Box::new(TestClock::new(start())));
+        match client.validate_msg(&resent(1, 10, Some(5))) {
+            Err(MessageValidationErr::SendingTimeAccuracy(text)) => assert_eq!(
+                text,
+                "OrigSendingTime 20240102-09:59:55.000 is after SendingTime 20240102-09:59:50.000"
+            ),
+            other => panic!("not rejected: {:?}", other),
+        }
+
+        let sent = sent(&mut theirs);
+        let reject = sent.find("\x0135=3\x01").unwrap();
+        let logout = sent.find("\x0135=5\x01").unwrap();
+        assert!(reject < logout);
+        assert!(sent[reject..logout].contains("\x01373=10\x01"));
+    }
+
+    #[test]
+    fn test_sending_time_follows_the_clock() {
+        let (ours, mut theirs) = loopback();
+        let mut client = testing::client("us", "them", ours);
+        let clock = TestClock::new(start());
+        client.set_clock(Box::new(clock.clone()));
+
+        let order = NewOrder::next(&mut client, "1");
+        client.send(&order).unwrap();
+        assert!(sent(&mut theirs).contains("\x0152=20240102-10:00:00.000\x01"));
+
+        clock.advance(Duration::from_millis(1500));
+        client.send_logout(None).unwrap();
+        assert!(sent(&mut theirs).contains("\x0152=20240102-10:00:01.500\x01"));
+    }
+
+    #[test]
+    fn test_client_buffers_what_a_full_socket_does_not_take() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let ours = PlainStreamWrapper::new(TcpStream::connect(listener.local_addr().unwrap()).unwrap());
+        let (mut theirs, _) = listener.accept().unwrap();
+        let source = ours.event_source().unwrap();
+        let mut client = testing::client("us", "them", ours);
+        client.set_event_source(source);
+
+        // nobody reads, so the socket fills up and the rest stays with the client.
+        let mut sent = 0;
+        while !client.has_buffered() {
+            let order = NewOrder::next(&mut client, &sent.to_string());
+            client.send(&order).unwrap();
+            sent += 1;
+            assert!(sent < 1_000_000, "the socket never filled up");
+        }
+        let order = NewOrder::next(&mut client, &sent.to_string());
+        client.send(&order).unwrap();
+        sent += 1;
+
+        let reader = spawn(move || {
+            let mut received = Vec::new();
+            theirs.read_to_end(&mut received).unwrap();
+            received
+        });
+        client.flush().unwrap();
+        assert!(!client.has_buffered());
+        drop(client);
+
+        let received = String::from_utf8(reader.join().unwrap()).unwrap();
+        assert_eq!(received.matches("\x0135=D\x01").count(), sent);
+        assert!(received.contains(&format!("\x0111={}\x01", sent - 1)));
+    }
+}
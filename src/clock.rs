@@ -0,0 +1,51 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono;
+use chrono::prelude::Utc;
+use chrono::NaiveDateTime;
+
+/// Source of the current UTC time for timestamps and session timers.
+pub trait Clock {
+    fn now(&self) -> NaiveDateTime;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> NaiveDateTime {
+        Utc::now().naive_utc()
+    }
+}
+
+/// Clock which only moves when advanced manually.
+/// Clones share the same time, so a test can keep one and hand the other to a `FixClient`.
+#[derive(Debug, Clone)]
+pub struct TestClock {
+    now: Arc<Mutex<NaiveDateTime>>,
+}
+
+impl TestClock {
+    pub fn new(start: NaiveDateTime) -> TestClock {
+        TestClock {
+            now: Arc::new(Mutex::new(start)),
+        }
+    }
+
+    pub fn set(&self, time: NaiveDateTime) {
+        *self.now.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        let by = chrono::Duration::from_std(by).expect("duration out of range");
+        let mut now = self.now.lock().unwrap();
+        *now += by;
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> NaiveDateTime {
+        *self.now.lock().unwrap()
+    }
+}
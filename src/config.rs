@@ -0,0 +1,25 @@
+use std::time::Duration;
+
+use TimestampPrecision;
+
+/// Settings of a single FIX session.
+#[derive(Debug, Clone)]
+pub struct SessionConfig {
+    // precision of timestamps stamped by the client, e.g. SendingTime and TransactTime.
+    pub timestamp_precision: TimestampPrecision,
+    // HeartBtInt (108), drives the heartbeat and TestRequest timers.
+    pub heartbeat_interval: Duration,
+    // maximum allowed difference between SendingTime (52) of inbound messages and our clock.
+    // None disables the check.
+    pub sending_time_tolerance: Option<Duration>,
+}
+
+impl Default for SessionConfig {
+    fn default() -> SessionConfig {
+        SessionConfig {
+            timestamp_precision: TimestampPrecision::default(),
+            heartbeat_interval: Duration::from_secs(30),
+            sending_time_tolerance: None,
+        }
+    }
+}
@@ -0,0 +1,545 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono;
+use chrono::NaiveDateTime;
+
+use detail::FixSerializable;
+use interceptor::{Interceptor, Verdict};
+use {serialize, Clock, FixClient, RawMessage, SendError, SystemClock};
+
+const CL_ORD_ID: u64 = 11;
+const MSG_SEQ_NUM: u64 = 34;
+const ORD_STATUS: u64 = 39;
+const ORIG_CL_ORD_ID: u64 = 41;
+const REF_SEQ_NUM: u64 = 45;
+const EXEC_TYPE: u64 = 150;
+const MD_REQ_ID: u64 = 262;
+const SUBSCRIPTION_REQUEST_TYPE: u64 = 263;
+const SECURITY_REQ_ID: u64 = 320;
+const REF_MSG_TYPE: u64 = 372;
+const BUSINESS_REJECT_REF_ID: u64 = 379;
+const SECURITY_REQUEST_RESULT: u64 = 560;
+const LAST_FRAGMENT: u64 = 893;
+
+/// The id a request and its responses share.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RequestId {
+    // ClOrdID (11) of a NewOrderSingle (D), OrderCancelRequest (F) or OrderCancelReplaceRequest (G).
+    ClOrdId(String),
+    // MDReqID (262) of a MarketDataRequest (V).
+    MdReqId(String),
+    // SecurityReqID (320) of a SecurityListRequest (x).
+    SecurityReqId(String),
+}
+
+impl RequestId {
+    // the id of a request, None for other messages.
+    pub fn of(msg: &RawMessage) -> Option<RequestId> {
+        let id = match msg.msg_type() {
+            "D" | "F" | "G" => msg.get(CL_ORD_ID),
+            "V" => msg.get(MD_REQ_ID),
+            "x" => msg.get(SECURITY_REQ_ID),
+            _ => None,
+        };
+        id.and_then(|id| RequestId::for_msg_type(msg.msg_type(), id))
+    }
+
+    // the id `value` is for a request of `msg_type`, e.g. by the RefMsgType (372) of a reject.
+    fn for_msg_type(msg_type: &str, value: &str) -> Option<RequestId> {
+        match msg_type {
+            "D" | "F" | "G" => Some(RequestId::ClOrdId(value.to_string())),
+            "V" => Some(RequestId::MdReqId(value.to_string())),
+            "x" => Some(RequestId::SecurityReqId(value.to_string())),
+            _ => None,
+        }
+    }
+}
+
+/// What a `PendingRequest` receives.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Response {
+    // e.g. an ExecutionReport (8), a MarketDataSnapshotFullRefresh (W) or a SecurityList (y).
+    Message(RawMessage),
+    // the request was refused: an ExecutionReport with ExecType (150) Rejected, an OrderCancelReject (9),
+    // a MarketDataRequestReject (Y), a SecurityList with a SecurityRequestResult (560) other than valid,
+    // a session level Reject (3) or a BusinessMessageReject (j).
+    Rejected(RawMessage),
+    // nothing came within the timeout of the `Correlator`.
+    TimedOut,
+}
+
+/// The responses to a request, see `Correlator`.
+///
+/// Iterating blocks for each next response and ends once the request is complete: the order is done
+/// or replaced, a snapshot or the last fragment came, the request was rejected or timed out.
+#[derive(Debug)]
+pub struct PendingRequest {
+    id: RequestId,
+    responses: Receiver<Response>,
+}
+
+impl PendingRequest {
+    pub fn id(&self) -> &RequestId {
+        &self.id
+    }
+
+    // the next response, None if the request is complete.
+    pub fn recv(&self) -> Option<Response> {
+        self.responses.recv().ok()
+    }
+
+    // the next response if there is one already.
+    pub fn try_recv(&self) -> Option<Response> {
+        self.responses.try_recv().ok()
+    }
+
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Response> {
+        self.responses.recv_timeout(timeout).ok()
+    }
+}
+
+impl Iterator for PendingRequest {
+    type Item = Response;
+
+    fn next(&mut self) -> Option<Response> {
+        self.recv()
+    }
+}
+
+/// Hands the responses of orders and requests to whoever sent them, by ClOrdID, MDReqID or SecurityReqID.
+///
+/// Add a clone to the `FixClient` as an `Interceptor`, it sees the requests go out and the responses
+/// come in. `send` sends a request and returns its `PendingRequest`; when the request is sent by a
+/// `FixHandler` on behalf of another thread, that thread calls `expect` with the id first and pushes
+/// the action after. Clones share the pending requests.
+///
+/// Responses are taken once every interceptor let them through, one with a MsgSeqNum (34) the request
+/// got a response with already, e.g. resent after a gap, is not delivered again.
+///
+/// A request not answered within the timeout gets `Response::TimedOut`. Timeouts are checked with every
+/// message intercepted, so at least once per heartbeat interval of a live session, and on `expire`.
+#[derive(Clone)]
+pub struct Correlator {
+    state: Arc<Mutex<Correlation>>,
+}
+
+struct Correlation {
+    timeout: chrono::Duration,
+    clock: Box<Clock + Send>,
+    pending: HashMap<RequestId, Waiting>,
+    // MsgSeqNum (34) of the requests sent, for the rejects referring to them by RefSeqNum (45).
+    by_seq: HashMap<u64, RequestId>,
+}
+
+struct Waiting {
+    responses: Sender<Response>,
+    // None once a response came, or if the timeout does not end.
+    deadline: Option<NaiveDateTime>,
+    // MsgSeqNum (34) of the responses delivered.
+    seqs: HashSet<u64>,
+    // a MarketDataRequest for a snapshot only, complete with the first snapshot.
+    snapshot: bool,
+}
+
+impl Correlator {
+    // `timeout` is how long a request may go without a first response, one too long for a
+    // `chrono::Duration` never ends.
+    pub fn new(timeout: Duration) -> Correlator {
+        Correlator {
+            state: Arc::new(Mutex::new(Correlation {
+                timeout: chrono::Duration::from_std(timeout).unwrap_or(chrono::Duration::MAX),
+                clock: Box::new(SystemClock),
+                pending: HashMap::new(),
+                by_seq: HashMap::new(),
+            })),
+        }
+    }
+
+    pub fn set_clock(&self, clock: Box<Clock + Send>) {
+        self.state.lock().unwrap().clock = clock;
+    }
+
+    // waits for the responses to the request with `id`, which is sent after. Replaces an earlier
+    // request with the same id, whose `PendingRequest` ends.
+    pub fn expect(&self, id: RequestId) -> PendingRequest {
+        let (tx, rx) = channel();
+        let mut state = self.state.lock().unwrap();
+        let deadline = state.clock.now().checked_add_signed(state.timeout);
+        state.forget(&id);
+        state.pending.insert(
+            id.clone(),
+            Waiting {
+                responses: tx,
+                deadline,
+                seqs: HashSet::new(),
+                snapshot: false,
+            },
+        );
+        PendingRequest { id, responses: rx }
+    }
+
+    // sends a request and waits for its responses. A message without a request id, see `RequestId::of`,
+    // is not sent and fails with `SendError::Vetoed`.
+    pub fn send<Msg: FixSerializable + Debug>(
+        &self,
+        client: &mut FixClient,
+        msg: &Msg,
+    ) -> Result<PendingRequest, SendError> {
+        let id = RawMessage::parse(serialize(msg).as_bytes())
+            .ok()
+            .as_ref()
+            .and_then(RequestId::of)
+            .ok_or_else(|| SendError::Vetoed(format!("{:?} has no request id", msg)))?;
+        let pending = self.expect(id);
+        if let Err(err) = client.send(msg) {
+            self.state.lock().unwrap().forget(pending.id());
+            return Err(err);
+        }
+        Ok(pending)
+    }
+
+    // times out the requests past their deadline.
+    pub fn expire(&self) {
+        self.state.lock().unwrap().expire();
+    }
+
+    // requests not complete yet.
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+}
+
+impl Interceptor for Correlator {
+    fn outbound(&mut self, msg: &mut RawMessage) -> Verdict {
+        let mut guard = self.state.lock().unwrap();
+        let state = &mut *guard;
+        state.expire();
+        let id = match RequestId::of(msg) {
+            Some(id) => id,
+            None => return Verdict::Pass,
+        };
+        if let Some(waiting) = state.pending.get_mut(&id) {
+            waiting.snapshot = msg.get(SUBSCRIPTION_REQUEST_TYPE) == Some("0");
+            if let Ok(Some(seq)) = msg.parsed(MSG_SEQ_NUM) {
+                state.by_seq.insert(seq, id);
+            }
+        }
+        Verdict::Pass
+    }
+
+    fn inbound(&mut self, _msg: &mut RawMessage) -> Verdict {
+        self.state.lock().unwrap().expire();
+        Verdict::Pass
+    }
+
+    fn received(&mut self, msg: &RawMessage) {
+        self.state.lock().unwrap().on_response(msg);
+    }
+}
+
+impl Correlation {
+    fn on_response(&mut self, msg: &RawMessage) {
+        let cl_ord_id = |id: u64| msg.get(id).map(|id| RequestId::ClOrdId(id.to_string()));
+        match msg.msg_type() {
+            "8" => {
+                let rejected = msg.get(EXEC_TYPE) == Some("8");
+                let done = matches!(
+                    msg.get(ORD_STATUS),
+                    Some("2") | Some("3") | Some("4") | Some("8") | Some("C")
+                );
+                if let Some(id) = cl_ord_id(CL_ORD_ID) {
+                    self.deliver(&id, msg, rejected, done);
+                }
+                // the order a cancel or replace refers to hears of it as well, a replaced one is done
+                // as it goes on under the ClOrdID of the replace.
+                if let Some(orig) = cl_ord_id(ORIG_CL_ORD_ID) {
+                    let replaced = msg.get(EXEC_TYPE) == Some("5");
+                    self.deliver(&orig, msg, rejected, done || replaced);
+                }
+            }
+            "9" => {
+                if let Some(id) = cl_ord_id(CL_ORD_ID) {
+                    self.deliver(&id, msg, true, true);
+                }
+            }
+            "W" | "X" | "Y" => {
+                if let Some(id) = msg.get(MD_REQ_ID).map(|id| RequestId::MdReqId(id.to_string())) {
+                    let rejected = msg.msg_type() == "Y";
+                    let snapshot = msg.msg_type() == "W" && self.pending.get(&id).is_some_and(|w| w.snapshot);
+                    self.deliver(&id, msg, rejected, rejected || snapshot);
+                }
+            }
+            "y" => {
+                if let Some(id) = msg
+                    .get(SECURITY_REQ_ID)
+                    .map(|id| RequestId::SecurityReqId(id.to_string()))
+                {
+                    let rejected = msg.get(SECURITY_REQUEST_RESULT).is_some_and(|result| result != "0");
+                    let last = msg.get(LAST_FRAGMENT) != Some("N");
+                    self.deliver(&id, msg, rejected, rejected || last);
+                }
+            }
+            "3" | "j" => {
+                let by_seq = msg
+                    .parsed(REF_SEQ_NUM)
+                    .ok()
+                    .and_then(|seq: Option<u64>| seq)
+                    .and_then(|seq| self.by_seq.get(&seq).cloned());
+                let by_ref_id = || match (msg.get(REF_MSG_TYPE), msg.get(BUSINESS_REJECT_REF_ID)) {
+                    (Some(ref_msg_type), Some(ref_id)) => RequestId::for_msg_type(ref_msg_type, ref_id),
+                    _ => None,
+                };
+                if let Some(id) = by_seq.or_else(by_ref_id) {
+                    self.deliver(&id, msg, true, true);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn deliver(&mut self, id: &RequestId, msg: &RawMessage, rejected: bool, done: bool) {
+        let delivered = match self.pending.get_mut(id) {
+            Some(waiting) => {
+                if let Ok(Some(seq)) = msg.parsed(MSG_SEQ_NUM) {
+                    if !waiting.seqs.insert(seq) {
+                        debug!("request {:?} got the response {} already", id, seq);
+                        return;
+                    }
+                }
+                waiting.deadline = None;
+                let response = if rejected {
+                    Response::Rejected(msg.clone())
+                } else {
+                    Response::Message(msg.clone())
+                };
+                waiting.responses.send(response).is_ok()
+            }
+            None => return,
+        };
+        // a request whose `PendingRequest` was dropped is done as well.
+        if done || !delivered {
+            self.forget(id);
+        }
+    }
+
+    fn expire(&mut self) {
+        let now = self.clock.now();
+        let expired: Vec<RequestId> = self
+            .pending
+            .iter()
+            .filter(|&(_, waiting)| waiting.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(id, _)| id.clone())
+            .collect();
+        for id in expired {
+            debug!("request {:?} timed out", id);
+            if let Some(waiting) = self.pending.get(&id) {
+                let _ = waiting.responses.send(Response::TimedOut);
+            }
+            self.forget(&id);
+        }
+    }
+
+    fn forget(&mut self, id: &RequestId) {
+        self.pending.remove(id);
+        self.by_seq.retain(|_, request| request != id);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::channel;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use loopback::{loopback, LoopbackStream};
+    use testing::{self, message as response, AppMsg, TestHandler};
+    use {HandleErr, Session, TestClock, Timestamp};
+
+    // the other end has to be kept open.
+    fn client(correlator: &Correlator) -> (FixClient, LoopbackStream) {
+        let (ours, theirs) = loopback();
+        let mut client = testing::client("us", "them", ours);
+        client.add_interceptor(Box::new(correlator.clone()));
+        (client, theirs)
+    }
+
+    fn request(client: &mut FixClient, msg_type: &str, fields: &[(u64, &str)]) -> RawMessage {
+        let mut msg = RawMessage::new(msg_type);
+        msg.set(34, client.get_next_send_seq());
+        msg.set(49, "us");
+        msg.set(56, "them");
+        msg.set(52, client.sending_time());
+        for &(id, value) in fields {
+            msg.set(id, value);
+        }
+        msg
+    }
+
+    #[test]
+    fn test_order_reports_and_cancel_reject() {
+        let correlator = Correlator::new(Duration::from_secs(5));
+        let (mut client, _theirs) = client(&correlator);
+        let mut inbound = correlator.clone();
+
+        let order = request(&mut client, "D", &[(11, "1"), (55, "EURUSD")]);
+        let pending = correlator.send(&mut client, &order).unwrap();
+        assert_eq!(pending.id(), &RequestId::ClOrdId("1".to_string()));
+
+        let ack = response("8", &[(11, "1"), (150, "0"), (39, "0")]);
+        let other = response("8", &[(11, "2"), (150, "0"), (39, "0")]);
+        let fill = response("8", &[(11, "1"), (150, "F"), (39, "2")]);
+        inbound.received(&ack);
+        inbound.received(&other);
+        inbound.received(&fill);
+        assert_eq!(
+            pending.collect::<Vec<_>>(),
+            vec![Response::Message(ack), Response::Message(fill)]
+        );
+
+        let cancel = request(&mut client, "F", &[(11, "3"), (41, "1")]);
+        let pending = correlator.send(&mut client, &cancel).unwrap();
+        let reject = response("9", &[(11, "3"), (41, "1"), (102, "0")]);
+        inbound.received(&reject);
+        assert_eq!(pending.collect::<Vec<_>>(), vec![Response::Rejected(reject)]);
+        assert_eq!(correlator.pending(), 0);
+
+        let heartbeat = request(&mut client, "0", &[]);
+        match correlator.send(&mut client, &heartbeat) {
+            Err(SendError::Vetoed(_)) => {}
+            other => panic!("sent: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_market_data_security_list_and_timeout() {
+        let correlator = Correlator::new(Duration::from_secs(5));
+        let clock = TestClock::new(
+            NaiveDate::from_ymd_opt(2024, 1, 2)
+                .unwrap()
+                .and_hms_opt(10, 0, 0)
+                .unwrap(),
+        );
+        correlator.set_clock(Box::new(clock.clone()));
+        let (mut client, _theirs) = client(&correlator);
+        let mut inbound = correlator.clone();
+
+        let snapshot = request(&mut client, "V", &[(262, "md1"), (263, "0")]);
+        let snapshot = correlator.send(&mut client, &snapshot).unwrap();
+        let subscribe = request(&mut client, "V", &[(262, "md2"), (263, "1")]);
+        let subscribe = correlator.send(&mut client, &subscribe).unwrap();
+        let list = correlator.expect(RequestId::SecurityReqId("sec1".to_string()));
+        let list_request = request(&mut client, "x", &[(320, "sec1"), (559, "4")]);
+        client.send(&list_request).unwrap();
+
+        let full = response("W", &[(262, "md1"), (55, "EURUSD")]);
+        inbound.received(&full);
+        assert_eq!(snapshot.collect::<Vec<_>>(), vec![Response::Message(full)]);
+
+        // the subscription is refused by the session, referring to its seq.
+        let reject = response("3", &[(45, "2"), (58, "required tag missing")]);
+        inbound.received(&reject);
+        assert_eq!(subscribe.collect::<Vec<_>>(), vec![Response::Rejected(reject)]);
+
+        clock.advance(Duration::from_secs(4));
+        correlator.expire();
+        assert_eq!(list.try_recv(), None);
+        clock.advance(Duration::from_secs(1));
+        correlator.expire();
+        assert_eq!(list.collect::<Vec<_>>(), vec![Response::TimedOut]);
+        assert_eq!(correlator.pending(), 0);
+    }
+
+    #[test]
+    fn test_business_reject_by_ref_msg_type() {
+        // a timeout too long for chrono never ends.
+        let correlator = Correlator::new(Duration::from_secs(u64::MAX));
+        let (mut client, _theirs) = client(&correlator);
+        let mut inbound = correlator.clone();
+
+        let order = request(&mut client, "D", &[(11, "1"), (55, "EURUSD")]);
+        let order = correlator.send(&mut client, &order).unwrap();
+        let subscribe = request(&mut client, "V", &[(262, "1"), (263, "1")]);
+        let subscribe = correlator.send(&mut client, &subscribe).unwrap();
+
+        let reject = response("j", &[(372, "V"), (379, "1"), (380, "4")]);
+        inbound.received(&reject);
+        assert_eq!(subscribe.collect::<Vec<_>>(), vec![Response::Rejected(reject)]);
+        correlator.expire();
+        assert_eq!(order.try_recv(), None);
+        assert_eq!(correlator.pending(), 1);
+    }
+
+    // drops the reports marked as tests, after the correlator.
+    struct Desk;
+
+    impl Interceptor for Desk {
+        fn inbound(&mut self, msg: &mut RawMessage) -> Verdict {
+            match msg.get(58) {
+                Some("test") => Verdict::Drop,
+                _ => Verdict::Pass,
+            }
+        }
+    }
+
+    // leaves what is out of sequence to the session.
+    struct Handler;
+
+    impl TestHandler for Handler {
+        type Action = ();
+
+        fn handle_app(&mut self, client: &mut FixClient, msg: AppMsg) -> Result<(), HandleErr> {
+            if let AppMsg::ExecReport(report) = msg {
+                let _ = client.validate_msg(&report);
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_responses_dropped_or_received_again_are_not_delivered() {
+        let correlator = Correlator::new(Duration::from_secs(5));
+        let (mut client, _theirs) = client(&correlator);
+        client.add_interceptor(Box::new(Desk));
+        let order = request(&mut client, "D", &[(11, "1"), (55, "EURUSD")]);
+        let pending = correlator.send(&mut client, &order).unwrap();
+
+        let report = |seq: &str, ord_status: &str, extra: &[(u64, &str)]| {
+            let mut report = response("8", &[(34, seq), (49, "them"), (56, "us"), (11, "1"), (39, ord_status)]);
+            report.set(52, Timestamp::now());
+            report.set(150, if ord_status == "0" { "0" } else { "F" });
+            for &(id, value) in extra {
+                report.set(id, value);
+            }
+            report
+        };
+        let ack = report("1", "0", &[]);
+        let partial = report("3", "1", &[]);
+        let fill = report("4", "2", &[]);
+        let (_actions_tx, actions) = channel();
+        let mut session = Session::new(client, Handler, &actions);
+        for msg in &[
+            ack.clone(),
+            report("1", "0", &[(43, "Y")]),
+            // 2 is missed, 3 comes again after it.
+            partial.clone(),
+            report("2", "1", &[(43, "Y"), (58, "test")]),
+            report("3", "1", &[(43, "Y")]),
+            fill.clone(),
+        ] {
+            session.push_received(msg.to_fix().as_bytes()).unwrap();
+        }
+        assert_eq!(
+            pending.collect::<Vec<_>>(),
+            vec![
+                Response::Message(ack),
+                Response::Message(partial),
+                Response::Message(fill)
+            ]
+        );
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use fix44_tags;
+use ParseError;
+
+/// A field of a `Dictionary`: its name and the names of its enumerated values.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDef {
+    pub tag: u64,
+    pub name: String,
+    pub values: HashMap<String, String>,
+}
+
+/// Tag names, enumerated values and repeating groups, used by `PrettyPrinter`.
+///
+/// Either the built-in FIX 4.4 table, `Dictionary::fix44`, or a QuickFIX data dictionary
+/// loaded with `Dictionary::load`.
+#[derive(Debug, Clone, Default)]
+pub struct Dictionary {
+    fields: HashMap<u64, FieldDef>,
+    // NoXXX count tag to the tags an entry may hold.
+    groups: HashMap<u64, Vec<u64>>,
+}
+
+impl Dictionary {
+    pub fn new() -> Dictionary {
+        Dictionary::default()
+    }
+
+    pub fn fix44() -> Dictionary {
+        let mut dictionary = Dictionary::new();
+        for &(tag, name) in fix44_tags::FIELDS {
+            dictionary.add_field(tag, name);
+        }
+        for &(tag, values) in fix44_tags::VALUES {
+            for &(value, name) in values {
+                dictionary.add_value(tag, value, name);
+            }
+        }
+        for &(tag, members) in fix44_tags::GROUPS {
+            dictionary.add_group(tag, members);
+        }
+        dictionary
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Dictionary, Error> {
+        let xml = fs::read_to_string(path)?;
+        Dictionary::from_quickfix_xml(&xml).map_err(|err| Error::new(ErrorKind::InvalidData, err))
+    }
+
+    /// Reads the `fields`, `components`, `header`, `trailer` and `messages` of a QuickFIX
+    /// data dictionary, e.g. FIX44.xml. Message names become the values of MsgType (35).
+    pub fn from_quickfix_xml(xml: &str) -> Result<Dictionary, ParseError> {
+        quickfix::read(xml)
+    }
+
+    pub fn add_field(&mut self, tag: u64, name: &str) {
+        self.fields
+            .entry(tag)
+            .or_insert_with(|| FieldDef {
+                tag,
+                name: String::new(),
+                values: HashMap::new(),
+            })
+            .name = name.to_string();
+    }
+
+    // the field has to be added first.
+    pub fn add_value(&mut self, tag: u64, value: &str, name: &str) {
+        if let Some(field) = self.fields.get_mut(&tag) {
+            field.values.insert(value.to_string(), name.to_string());
+        }
+    }
+
+    // adding the same group again extends the tags its entries may hold.
+    pub fn add_group(&mut self, count_tag: u64, members: &[u64]) {
+        let known = self.groups.entry(count_tag).or_default();
+        for &member in members {
+            if !known.contains(&member) {
+                known.push(member);
+            }
+        }
+    }
+
+    pub fn field(&self, tag: u64) -> Option<&FieldDef> {
+        self.fields.get(&tag)
+    }
+
+    pub fn field_name(&self, tag: u64) -> Option<&str> {
+        self.fields.get(&tag).map(|field| field.name.as_str())
+    }
+
+    pub fn value_name(&self, tag: u64, value: &str) -> Option<&str> {
+        self.fields
+            .get(&tag)
+            .and_then(|field| field.values.get(value))
+            .map(|name| name.as_str())
+    }
+
+    pub fn msg_type_name(&self, msg_type: &str) -> Option<&str> {
+        self.value_name(35, msg_type)
+    }
+
+    pub fn tag_of(&self, name: &str) -> Option<u64> {
+        self.fields
+            .values()
+            .find(|field| field.name == name)
+            .map(|field| field.tag)
+    }
+
+    pub fn group(&self, count_tag: u64) -> Option<&[u64]> {
+        self.groups.get(&count_tag).map(|members| members.as_slice())
+    }
+}
+
+mod quickfix {
+    use std::collections::HashMap;
+
+    use super::Dictionary;
+    use ParseError;
+
+    // a field, group or component reference within a message, component, header or trailer.
+    enum Item {
+        Field(String),
+        Group(String, Vec<Item>),
+        Component(String),
+    }
+
+    struct Element<'a> {
+        name: &'a str,
+        attributes: Vec<(&'a str, String)>,
+        closing: bool,
+        empty: bool,
+    }
+
+    impl<'a> Element<'a> {
+        fn attribute(&self, name: &str) -> Result<&str, ParseError> {
+            self.attributes
+                .iter()
+                .find(|&&(key, _)| key == name)
+                .map(|(_, value)| value.as_str())
+                .ok_or("dictionary element misses an attribute")
+        }
+    }
+
+    pub(super) fn read(xml: &str) -> Result<Dictionary, ParseError> {
+        let mut dictionary = Dictionary::new();
+        let mut in_fields = false;
+        let mut current_field = None;
+        // messages, components, header and trailer being read, innermost group last.
+        let mut open: Vec<(&str, String, Vec<Item>)> = Vec::new();
+        let mut components = HashMap::new();
+        let mut bodies = Vec::new();
+        let mut msg_types = Vec::new();
+
+        for element in elements(xml) {
+            let element = element?;
+            if element.closing {
+                let closes_open = open.last().map(|&(kind, _, _)| kind) == Some(element.name);
+                match element.name {
+                    "fields" => in_fields = false,
+                    _ if !closes_open => (),
+                    "group" => {
+                        let (_, name, items) = open.pop().unwrap();
+                        let parent = open.last_mut().ok_or("dictionary group outside of a message")?;
+                        parent.2.push(Item::Group(name, items));
+                    }
+                    "component" => {
+                        let (_, name, items) = open.pop().unwrap();
+                        components.insert(name, items);
+                    }
+                    _ => bodies.push(open.pop().unwrap().2),
+                }
+                continue;
+            }
+
+            match element.name {
+                "fields" => in_fields = !element.empty,
+                "field" if in_fields => {
+                    let tag = element
+                        .attribute("number")?
+                        .parse()
+                        .map_err(|_| "dictionary field number is not a number")?;
+                    dictionary.add_field(tag, element.attribute("name")?);
+                    current_field = Some(tag);
+                }
+                "value" if in_fields => {
+                    let tag = current_field.ok_or("dictionary value outside of a field")?;
+                    dictionary.add_value(tag, element.attribute("enum")?, element.attribute("description")?);
+                }
+                "message" => {
+                    let name = element.attribute("name")?.to_string();
+                    msg_types.push((element.attribute("msgtype")?.to_string(), name));
+                    if !element.empty {
+                        open.push(("message", String::new(), Vec::new()));
+                    }
+                }
+                "header" | "trailer" if !element.empty => open.push((element.name, String::new(), Vec::new())),
+                // a definition, within a message it is a reference.
+                "component" if open.is_empty() && !element.empty => {
+                    open.push(("component", element.attribute("name")?.to_string(), Vec::new()));
+                }
+                "group" if !open.is_empty() => {
+                    let name = element.attribute("name")?.to_string();
+                    if element.empty {
+                        open.last_mut().unwrap().2.push(Item::Group(name, Vec::new()));
+                    } else {
+                        open.push(("group", name, Vec::new()));
+                    }
+                }
+                "field" | "component" if !open.is_empty() => {
+                    let name = element.attribute("name")?.to_string();
+                    let item = if element.name == "field" {
+                        Item::Field(name)
+                    } else {
+                        Item::Component(name)
+                    };
+                    open.last_mut().unwrap().2.push(item);
+                }
+                _ => (),
+            }
+        }
+
+        // QuickFIX names the messages where it defines them rather than in the MsgType field.
+        if !msg_types.is_empty() && dictionary.field(35).is_none() {
+            dictionary.add_field(35, "MsgType");
+        }
+        for (msg_type, name) in msg_types {
+            dictionary.add_value(35, &msg_type, &name);
+        }
+
+        let tags: HashMap<String, u64> = dictionary
+            .fields
+            .values()
+            .map(|field| (field.name.clone(), field.tag))
+            .collect();
+        let resolver = Resolver {
+            tags: &tags,
+            components: &components,
+        };
+        let mut groups = Vec::new();
+        for items in bodies.iter().chain(components.values()) {
+            resolver.groups(items, &mut groups, 0)?;
+        }
+        for (count_tag, members) in groups {
+            dictionary.add_group(count_tag, &members);
+        }
+        Ok(dictionary)
+    }
+
+    struct Resolver<'a> {
+        tags: &'a HashMap<String, u64>,
+        components: &'a HashMap<String, Vec<Item>>,
+    }
+
+    // components may nest, but not this deep unless they refer to themselves.
+    const MAX_DEPTH: usize = 32;
+
+    impl<'a> Resolver<'a> {
+        fn tag(&self, name: &str) -> Result<u64, ParseError> {
+            self.tags
+                .get(name)
+                .cloned()
+                .ok_or("dictionary refers to an unknown field")
+        }
+
+        fn component(&self, name: &str) -> Result<&'a [Item], ParseError> {
+            self.components
+                .get(name)
+                .map(|items| items.as_slice())
+                .ok_or("dictionary refers to an unknown component")
+        }
+
+        // tags of the items as they appear in a message, a nested group by its count tag.
+        fn members(&self, items: &[Item], members: &mut Vec<u64>, depth: usize) -> Result<(), ParseError> {
+            if depth > MAX_DEPTH {
+                return Err("dictionary components nest too deep");
+            }
+            for item in items {
+                match *item {
+                    Item::Field(ref name) | Item::Group(ref name, _) => members.push(self.tag(name)?),
+                    Item::Component(ref name) => self.members(self.component(name)?, members, depth + 1)?,
+                }
+            }
+            Ok(())
+        }
+
+        fn groups(&self, items: &[Item], groups: &mut Vec<(u64, Vec<u64>)>, depth: usize) -> Result<(), ParseError> {
+            if depth > MAX_DEPTH {
+                return Err("dictionary components nest too deep");
+            }
+            for item in items {
+                if let Item::Group(ref name, ref group_items) = *item {
+                    let mut members = Vec::new();
+                    self.members(group_items, &mut members, depth)?;
+                    groups.push((self.tag(name)?, members));
+                    self.groups(group_items, groups, depth + 1)?;
+                }
+            }
+            Ok(())
+        }
+    }
+
+    // the elements of the document, skipping the declaration and comments.
+    fn elements(xml: &str) -> impl Iterator<Item = Result<Element<'_>, ParseError>> {
+        let mut rest = xml;
+        ::std::iter::from_fn(move || loop {
+            let start = rest.find('<')?;
+            rest = &rest[start..];
+            if rest.starts_with("<!--") {
+                match rest.find("-->") {
+                    Some(end) => rest = &rest[end + 3..],
+                    None => return Some(Err("unterminated comment in dictionary")),
+                }
+                continue;
+            }
+            let end = match rest.find('>') {
+                Some(end) => end,
+                None => return Some(Err("unterminated element in dictionary")),
+            };
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+            if tag.starts_with('?') || tag.starts_with('!') {
+                continue;
+            }
+            return Some(element(tag));
+        })
+    }
+
+    fn element(tag: &str) -> Result<Element<'_>, ParseError> {
+        let (closing, tag) = match tag.strip_prefix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let (empty, tag) = match tag.strip_suffix('/') {
+            Some(tag) => (true, tag),
+            None => (false, tag),
+        };
+        let name_end = tag.find(char::is_whitespace).unwrap_or(tag.len());
+        let mut element = Element {
+            name: &tag[..name_end],
+            attributes: Vec::new(),
+            closing,
+            empty,
+        };
+
+        let mut rest = tag[name_end..].trim_start();
+        while !rest.is_empty() {
+            let eq = rest.find('=').ok_or("dictionary attribute without a value")?;
+            let key = rest[..eq].trim_end();
+            let value = rest[eq + 1..].trim_start();
+            let quote = match value.chars().next() {
+                Some(quote @ '"') | Some(quote @ '\'') => quote,
+                _ => return Err("dictionary attribute value is not quoted"),
+            };
+            let value = &value[1..];
+            let value_end = value.find(quote).ok_or("unterminated dictionary attribute value")?;
+            element.attributes.push((key, unescape(&value[..value_end])));
+            rest = value[value_end + 1..].trim_start();
+        }
+        Ok(element)
+    }
+
+    fn unescape(value: &str) -> String {
+        value
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DICTIONARY: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<fix type="FIX" major="4" minor="4">
+  <header>
+    <field name="BeginString" required="Y"/>
+    <field name="MsgType" required="Y"/>
+  </header>
+  <messages>
+    <message name="MarketDataIncrementalRefresh" msgtype="X" msgcat="app">
+      <field name="MDReqID" required="N"/>
+      <group name="NoMDEntries" required="Y">
+        <field name="MDUpdateAction" required="Y"/>
+        <component name="Instrument" required="N"/>
+        <field name="MDEntryPx" required="N"/>
+      </group>
+    </message>
+  </messages>
+  <components>
+    <!-- <component name="Unused"/> -->
+    <component name="Instrument">
+      <field name="Symbol" required="N"/>
+      <group name="NoSecurityAltID" required="N">
+        <field name="SecurityAltID" required="N"/>
+      </group>
+    </component>
+  </components>
+  <fields>
+    <field number="8" name="BeginString" type="STRING"/>
+    <field number="35" name="MsgType" type="STRING">
+      <value enum="0" description="HEARTBEAT"/>
+    </field>
+    <field number="55" name="Symbol" type="STRING"/>
+    <field number="262" name="MDReqID" type="STRING"/>
+    <field number="268" name="NoMDEntries" type="NUMINGROUP"/>
+    <field number="270" name="MDEntryPx" type="PRICE"/>
+    <field number="279" name="MDUpdateAction" type="CHAR">
+      <value enum="0" description="NEW"/>
+      <value enum="2" description="DELETE"/>
+    </field>
+    <field number="454" name="NoSecurityAltID" type="NUMINGROUP"/>
+    <field number="455" name="SecurityAltID" type="STRING"/>
+  </fields>
+</fix>
+"#;
+
+    #[test]
+    fn test_quickfix_dictionary() {
+        let dictionary = Dictionary::from_quickfix_xml(DICTIONARY).unwrap();
+        assert_eq!(dictionary.field_name(262), Some("MDReqID"));
+        assert_eq!(dictionary.tag_of("MDEntryPx"), Some(270));
+        assert_eq!(dictionary.value_name(279, "2"), Some("DELETE"));
+        assert_eq!(dictionary.msg_type_name("X"), Some("MarketDataIncrementalRefresh"));
+        assert_eq!(dictionary.msg_type_name("0"), Some("HEARTBEAT"));
+        assert_eq!(dictionary.group(268), Some(&[279, 55, 454, 270][..]));
+        assert_eq!(dictionary.group(454), Some(&[455][..]));
+        assert_eq!(dictionary.group(262), None);
+    }
+
+    #[test]
+    fn test_broken_dictionary() {
+        assert!(Dictionary::from_quickfix_xml("<fix><fields><field number=\"x\" name=\"A\"/>").is_err());
+        let unknown_field = DICTIONARY.replace("name=\"MDEntryPx\" required", "name=\"Missing\" required");
+        assert_eq!(
+            Dictionary::from_quickfix_xml(&unknown_field).unwrap_err(),
+            "dictionary refers to an unknown field"
+        );
+    }
+
+    #[test]
+    fn test_fix44() {
+        let dictionary = Dictionary::fix44();
+        assert_eq!(dictionary.field_name(11), Some("ClOrdID"));
+        assert_eq!(dictionary.msg_type_name("D"), Some("NewOrderSingle"));
+        assert_eq!(dictionary.value_name(54, "2"), Some("Sell"));
+        assert_eq!(dictionary.group(453), Some(&[448, 447, 452, 802][..]));
+        // every group tag has a name.
+        for &(tag, members) in fix44_tags::GROUPS {
+            for &tag in members.iter().chain(Some(&tag)) {
+                assert!(dictionary.field_name(tag).is_some(), "{}", tag);
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::io::{Error, ErrorKind};
+use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+use std::sync::Arc;
+use std::time::Duration;
+
+use mio::event::Source;
+use mio::{Events, Interest, Poll, Registry, Token, Waker};
+
+use session::{Drive, SessionError};
+
+const WAKER: Token = Token(usize::MAX);
+const EVENTS_CAPACITY: usize = 1024;
+// steps given to a single session in a row, so a chatty counterparty can not starve the others.
+const MAX_STEPS_IN_A_ROW: usize = 1000;
+
+/// Sends actions to a session and wakes up whoever drives it, see `EventLoop::action_channel`.
+pub struct ActionSender<Action> {
+    tx: Sender<Action>,
+    waker: Arc<Waker>,
+}
+
+impl<Action> Clone for ActionSender<Action> {
+    fn clone(&self) -> Self {
+        ActionSender {
+            tx: self.tx.clone(),
+            waker: self.waker.clone(),
+        }
+    }
+}
+
+impl<Action> ActionSender<Action> {
+    pub fn send(&self, action: Action) -> Result<(), SendError<Action>> {
+        self.tx.send(action)?;
+        if let Err(err) = self.waker.wake() {
+            error!("failed to wake up the event loop: {:?}", err);
+        }
+        Ok(())
+    }
+}
+
+// registers `source` again if it is registered for anything but `wanted`, see `Drive::interest`.
+pub(crate) fn update_interest(
+    registry: &Registry,
+    source: &mut Source,
+    token: Token,
+    registered: &mut Interest,
+    wanted: Interest,
+) -> Result<(), Error> {
+    if *registered != wanted {
+        registry.reregister(source, token, wanted)?;
+        *registered = wanted;
+    }
+    Ok(())
+}
+
+pub(crate) fn waking_channel<Action>(waker: &Arc<Waker>) -> (ActionSender<Action>, Receiver<Action>) {
+    let (tx, rx) = channel();
+    let sender = ActionSender {
+        tx,
+        waker: waker.clone(),
+    };
+    (sender, rx)
+}
+
+struct Entry<'a> {
+    session: Box<Drive + 'a>,
+    registered: bool,
+    interest: Interest,
+}
+
+impl<'a> Entry<'a> {
+    fn update_interest(&mut self, registry: &Registry, token: Token) -> Result<(), SessionError> {
+        let wanted = self.session.interest();
+        match self.session.event_source() {
+            Some(source) if self.registered => {
+                update_interest(registry, source, token, &mut self.interest, wanted).map_err(SessionError::Io)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Drives many sessions on a single thread.
+///
+/// It sleeps until a transport becomes readable, or writable while a session buffers sent bytes, an action is sent through an `ActionSender`
+/// of this loop, or the nearest session timer is due.
+/// Sessions without an event source, and actions sent through plain channels,
+/// are only looked at every `max_wait`.
+pub struct EventLoop<'a> {
+    poll: Poll,
+    events: Events,
+    waker: Arc<Waker>,
+    sessions: Vec<Option<Entry<'a>>>,
+
+    pub max_wait: Duration,
+}
+
+impl<'a> EventLoop<'a> {
+    pub fn new() -> Result<EventLoop<'a>, Error> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+        Ok(EventLoop {
+            poll,
+            events: Events::with_capacity(EVENTS_CAPACITY),
+            waker,
+            sessions: Vec::new(),
+
+            max_wait: Duration::from_millis(10),
+        })
+    }
+
+    pub fn action_channel<Action>(&self) -> (ActionSender<Action>, Receiver<Action>) {
+        waking_channel(&self.waker)
+    }
+
+    // returns id of the session, used to report its failure.
+    pub fn add<D: Drive + 'a>(&mut self, session: D) -> Result<usize, Error> {
+        let id = self.sessions.len();
+        let mut session = Box::new(session);
+        let interest = session.interest();
+        let registered = match session.event_source() {
+            Some(source) => {
+                self.poll.registry().register(source, Token(id), interest)?;
+                true
+            }
+            None => false,
+        };
+        self.sessions.push(Some(Entry {
+            session,
+            registered,
+            interest,
+        }));
+        Ok(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.iter().filter(|s| s.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // waits for something to happen and steps the concerned sessions until they are idle.
+    // Sessions which failed are dropped and returned with their id.
+    pub fn run_once(&mut self) -> Vec<(usize, SessionError)> {
+        let timeout = self
+            .sessions
+            .iter()
+            .filter_map(|entry| entry.as_ref().and_then(|e| e.session.next_deadline()))
+            .fold(self.max_wait, |timeout, deadline| timeout.min(deadline));
+
+        if let Err(err) = self.poll.poll(&mut self.events, Some(timeout)) {
+            if err.kind() != ErrorKind::Interrupted {
+                error!("event loop poll failed: {:?}", err);
+            }
+        }
+
+        // on timeouts and woken up by an action we do not know which session needs attention.
+        let mut step_all = self.events.is_empty();
+        let mut ready = vec![false; self.sessions.len()];
+        for event in self.events.iter() {
+            match event.token() {
+                WAKER => step_all = true,
+                Token(id) => {
+                    if id < ready.len() {
+                        ready[id] = true;
+                    }
+                }
+            }
+        }
+
+        let mut failed = Vec::new();
+        let registry = self.poll.registry();
+        for (id, slot) in self.sessions.iter_mut().enumerate() {
+            let result = match *slot {
+                Some(ref mut entry) if step_all || ready[id] || !entry.registered => {
+                    Self::step_until_idle(&mut *entry.session).and_then(|()| entry.update_interest(registry, Token(id)))
+                }
+                _ => continue,
+            };
+
+            if let Err(err) = result {
+                if let Some(mut entry) = slot.take() {
+                    if let Some(source) = entry.session.event_source() {
+                        let _ = registry.deregister(source);
+                    }
+                }
+                failed.push((id, err));
+            }
+        }
+
+        failed
+    }
+
+    // runs until every session failed, returns the failures.
+    pub fn run(&mut self) -> Vec<(usize, SessionError)> {
+        let mut failed = Vec::new();
+        while !self.is_empty() {
+            failed.extend(self.run_once());
+        }
+        failed
+    }
+
+    fn step_until_idle(session: &mut Drive) -> Result<(), SessionError> {
+        for _ in 0..MAX_STEPS_IN_A_ROW {
+            if !session.step()? {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::mpsc::Receiver;
+    use std::time::Instant;
+
+    use super::*;
+    use admin::Heartbeat;
+    use testing::{self, header, Logon, SessionMsg, TestHandler};
+    use {FixClient, HandleErr, PlainStreamWrapper, Session};
+
+    // logs the counterparty on if `logs_on`, sends a heartbeat per action, counts heartbeats received.
+    struct Handler {
+        logs_on: bool,
+        received: Arc<AtomicUsize>,
+    }
+
+    impl TestHandler for Handler {
+        type Action = ();
+
+        fn handle_session(&mut self, _client: &mut FixClient, msg: SessionMsg) -> Result<(), HandleErr> {
+            if let SessionMsg::Heartbeat(_) = msg {
+                self.received.fetch_add(1, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+
+        fn handle_action(&mut self, client: &mut FixClient, _action: ()) -> Result<(), HandleErr> {
+            let (seq, sender, target, sending_time) = header(client);
+            let heartbeat = Heartbeat {
+                seq,
+                sender,
+                target,
+                sending_time,
+                test_req_id: None,
+            };
+            client.send(&heartbeat)?;
+            Ok(())
+        }
+
+        fn on_connect(&mut self, client: &mut FixClient) {
+            if self.logs_on {
+                let logon = Logon::next(client);
+                client.send(&logon).unwrap();
+            }
+        }
+    }
+
+    fn client(stream: TcpStream) -> FixClient {
+        let wrapper = PlainStreamWrapper::new(stream);
+        let source = wrapper.event_source().unwrap();
+        let mut client = testing::client("a", "b", wrapper);
+        client.set_event_source(source);
+        client
+    }
+
+    #[test]
+    fn test_one_thread_drives_two_tcp_sessions() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let initiator = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (acceptor, _) = listener.accept().unwrap();
+
+        // sessions borrow the receivers, which therefore have to outlive the loop.
+        let initiator_rx: Receiver<()>;
+        let acceptor_rx: Receiver<()>;
+        let mut event_loop = EventLoop::new().unwrap();
+        event_loop.max_wait = Duration::from_secs(5);
+        let (initiator_tx, rx) = event_loop.action_channel();
+        initiator_rx = rx;
+        let (_acceptor_tx, rx) = event_loop.action_channel();
+        acceptor_rx = rx;
+
+        let received = Arc::new(AtomicUsize::new(0));
+        let initiator_handler = Handler {
+            logs_on: false,
+            received: Arc::new(AtomicUsize::new(0)),
+        };
+        let acceptor_handler = Handler {
+            logs_on: true,
+            received: received.clone(),
+        };
+        let initiator = Session::new(client(initiator), initiator_handler, &initiator_rx);
+        let acceptor = Session::new(client(acceptor), acceptor_handler, &acceptor_rx);
+        event_loop.add(initiator).unwrap();
+        event_loop.add(acceptor).unwrap();
+
+        let started = Instant::now();
+        initiator_tx.send(()).unwrap();
+        // woken by the action, then by the Logon and the heartbeat arriving, long before max_wait.
+        while received.load(Ordering::SeqCst) == 0 {
+            assert!(event_loop.run_once().is_empty());
+        }
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(event_loop.len(), 2);
+    }
+}
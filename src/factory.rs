@@ -9,6 +9,10 @@ pub enum ConnectionFailure {
     TlsError(native_tls::Error),
     TlsHandshakeError(native_tls::HandshakeError<std::net::TcpStream>),
     TcpStreamError(std::io::Error),
+    // the TlsConfig can not be used, e.g. a certificate does not parse.
+    TlsConfigError(String),
+    // the counterparty's certificate was refused, e.g. it is not the pinned one.
+    TlsVerificationError(String),
 }
 
 impl From<native_tls::Error> for ConnectionFailure {
@@ -37,7 +41,12 @@ pub struct CompIds {
 
 pub trait FixFactory<Handler> {
     //called everytime we need to establish connection.
+    //nothing should be sent here, it would bypass `FixHandler::to_admin`: send the Logon from `FixHandler::on_connect`.
     fn connection_factory(&self) -> Result<FixClient, ConnectionFailure>;
+    //called instead of connection_factory when the reconnect policy lists hosts.
+    fn connect_to(&self, _host: &str) -> Result<FixClient, ConnectionFailure> {
+        self.connection_factory()
+    }
     //called everytime we established connection.
     fn handler_factory(&self) -> Handler;
 }
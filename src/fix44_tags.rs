@@ -0,0 +1,832 @@
+// Built-in FIX 4.4 tag table behind `Dictionary::fix44`: the header, the session messages and
+// the commonly used order, quote and market data fields. Load a data dictionary for the rest.
+
+pub(crate) const FIELDS: &[(u64, &str)] = &[
+    (1, "Account"),
+    (2, "AdvId"),
+    (3, "AdvRefID"),
+    (4, "AdvSide"),
+    (5, "AdvTransType"),
+    (6, "AvgPx"),
+    (7, "BeginSeqNo"),
+    (8, "BeginString"),
+    (9, "BodyLength"),
+    (10, "CheckSum"),
+    (11, "ClOrdID"),
+    (12, "Commission"),
+    (13, "CommType"),
+    (14, "CumQty"),
+    (15, "Currency"),
+    (16, "EndSeqNo"),
+    (17, "ExecID"),
+    (18, "ExecInst"),
+    (19, "ExecRefID"),
+    (21, "HandlInst"),
+    (22, "SecurityIDSource"),
+    (23, "IOIID"),
+    (25, "IOIQltyInd"),
+    (26, "IOIRefID"),
+    (27, "IOIQty"),
+    (28, "IOITransType"),
+    (29, "LastCapacity"),
+    (30, "LastMkt"),
+    (31, "LastPx"),
+    (32, "LastQty"),
+    (33, "NoLinesOfText"),
+    (34, "MsgSeqNum"),
+    (35, "MsgType"),
+    (36, "NewSeqNo"),
+    (37, "OrderID"),
+    (38, "OrderQty"),
+    (39, "OrdStatus"),
+    (40, "OrdType"),
+    (41, "OrigClOrdID"),
+    (43, "PossDupFlag"),
+    (44, "Price"),
+    (45, "RefSeqNum"),
+    (48, "SecurityID"),
+    (49, "SenderCompID"),
+    (50, "SenderSubID"),
+    (52, "SendingTime"),
+    (53, "Quantity"),
+    (54, "Side"),
+    (55, "Symbol"),
+    (56, "TargetCompID"),
+    (57, "TargetSubID"),
+    (58, "Text"),
+    (59, "TimeInForce"),
+    (60, "TransactTime"),
+    (61, "Urgency"),
+    (62, "ValidUntilTime"),
+    (63, "SettlType"),
+    (64, "SettlDate"),
+    (65, "SymbolSfx"),
+    (66, "ListID"),
+    (67, "ListSeqNo"),
+    (68, "TotNoOrders"),
+    (69, "ListExecInst"),
+    (70, "AllocID"),
+    (71, "AllocTransType"),
+    (72, "RefAllocID"),
+    (73, "NoOrders"),
+    (74, "AvgPxPrecision"),
+    (75, "TradeDate"),
+    (77, "PositionEffect"),
+    (78, "NoAllocs"),
+    (79, "AllocAccount"),
+    (80, "AllocQty"),
+    (81, "ProcessCode"),
+    (82, "NoRpts"),
+    (83, "RptSeq"),
+    (84, "CxlQty"),
+    (87, "AllocStatus"),
+    (88, "AllocRejCode"),
+    (89, "Signature"),
+    (90, "SecureDataLen"),
+    (91, "SecureData"),
+    (93, "SignatureLength"),
+    (95, "RawDataLength"),
+    (96, "RawData"),
+    (97, "PossResend"),
+    (98, "EncryptMethod"),
+    (99, "StopPx"),
+    (100, "ExDestination"),
+    (102, "CxlRejReason"),
+    (103, "OrdRejReason"),
+    (104, "IOIQualifier"),
+    (106, "Issuer"),
+    (107, "SecurityDesc"),
+    (108, "HeartBtInt"),
+    (110, "MinQty"),
+    (111, "MaxFloor"),
+    (112, "TestReqID"),
+    (114, "LocateReqd"),
+    (115, "OnBehalfOfCompID"),
+    (116, "OnBehalfOfSubID"),
+    (117, "QuoteID"),
+    (118, "NetMoney"),
+    (119, "SettlCurrAmt"),
+    (120, "SettlCurrency"),
+    (122, "OrigSendingTime"),
+    (123, "GapFillFlag"),
+    (124, "NoExecs"),
+    (126, "ExpireTime"),
+    (127, "DKReason"),
+    (128, "DeliverToCompID"),
+    (129, "DeliverToSubID"),
+    (131, "QuoteReqID"),
+    (132, "BidPx"),
+    (133, "OfferPx"),
+    (134, "BidSize"),
+    (135, "OfferSize"),
+    (136, "NoMiscFees"),
+    (137, "MiscFeeAmt"),
+    (138, "MiscFeeCurr"),
+    (139, "MiscFeeType"),
+    (140, "PrevClosePx"),
+    (141, "ResetSeqNumFlag"),
+    (142, "SenderLocationID"),
+    (143, "TargetLocationID"),
+    (144, "OnBehalfOfLocationID"),
+    (145, "DeliverToLocationID"),
+    (146, "NoRelatedSym"),
+    (147, "Subject"),
+    (148, "Headline"),
+    (149, "URLLink"),
+    (150, "ExecType"),
+    (151, "LeavesQty"),
+    (152, "CashOrderQty"),
+    (153, "AllocAvgPx"),
+    (154, "AllocNetMoney"),
+    (155, "SettlCurrFxRate"),
+    (156, "SettlCurrFxRateCalc"),
+    (157, "NumDaysInterest"),
+    (158, "AccruedInterestRate"),
+    (159, "AccruedInterestAmt"),
+    (167, "SecurityType"),
+    (168, "EffectiveTime"),
+    (188, "BidSpotRate"),
+    (189, "BidForwardPoints"),
+    (190, "OfferSpotRate"),
+    (191, "OfferForwardPoints"),
+    (192, "OrderQty2"),
+    (193, "SettlDate2"),
+    (198, "SecondaryOrderID"),
+    (200, "MaturityMonthYear"),
+    (202, "StrikePrice"),
+    (206, "OptAttribute"),
+    (207, "SecurityExchange"),
+    (211, "PegOffsetValue"),
+    (223, "CouponRate"),
+    (231, "ContractMultiplier"),
+    (262, "MDReqID"),
+    (263, "SubscriptionRequestType"),
+    (264, "MarketDepth"),
+    (265, "MDUpdateType"),
+    (266, "AggregatedBook"),
+    (267, "NoMDEntryTypes"),
+    (268, "NoMDEntries"),
+    (269, "MDEntryType"),
+    (270, "MDEntryPx"),
+    (271, "MDEntrySize"),
+    (272, "MDEntryDate"),
+    (273, "MDEntryTime"),
+    (274, "TickDirection"),
+    (275, "MDMkt"),
+    (276, "QuoteCondition"),
+    (277, "TradeCondition"),
+    (278, "MDEntryID"),
+    (279, "MDUpdateAction"),
+    (280, "MDEntryRefID"),
+    (281, "MDReqRejReason"),
+    (282, "MDEntryOriginator"),
+    (283, "LocationID"),
+    (284, "DeskID"),
+    (285, "DeleteReason"),
+    (286, "OpenCloseSettlFlag"),
+    (287, "SellerDays"),
+    (288, "MDEntryBuyer"),
+    (289, "MDEntrySeller"),
+    (290, "MDEntryPositionNo"),
+    (295, "NoQuoteEntries"),
+    (296, "NoQuoteSets"),
+    (297, "QuoteStatus"),
+    (298, "QuoteCancelType"),
+    (299, "QuoteEntryID"),
+    (300, "QuoteRejectReason"),
+    (301, "QuoteResponseLevel"),
+    (302, "QuoteSetID"),
+    (303, "QuoteRequestType"),
+    (304, "TotNoQuoteEntries"),
+    (311, "UnderlyingSymbol"),
+    (320, "SecurityReqID"),
+    (321, "SecurityRequestType"),
+    (322, "SecurityResponseID"),
+    (323, "SecurityResponseType"),
+    (324, "SecurityStatusReqID"),
+    (325, "UnsolicitedIndicator"),
+    (326, "SecurityTradingStatus"),
+    (327, "HaltReason"),
+    (332, "HighPx"),
+    (333, "LowPx"),
+    (336, "TradingSessionID"),
+    (337, "ContraTrader"),
+    (346, "NumberOfOrders"),
+    (354, "EncodedTextLen"),
+    (355, "EncodedText"),
+    (368, "QuoteEntryRejectReason"),
+    (369, "LastMsgSeqNumProcessed"),
+    (371, "RefTagID"),
+    (372, "RefMsgType"),
+    (373, "SessionRejectReason"),
+    (375, "ContraBroker"),
+    (377, "SolicitedFlag"),
+    (378, "ExecRestatementReason"),
+    (379, "BusinessRejectRefID"),
+    (380, "BusinessRejectReason"),
+    (382, "NoContraBrokers"),
+    (383, "MaxMessageSize"),
+    (384, "NoMsgTypes"),
+    (385, "MsgDirection"),
+    (386, "NoTradingSessions"),
+    (423, "PriceType"),
+    (432, "ExpireDate"),
+    (434, "CxlRejResponseTo"),
+    (437, "ContraTradeQty"),
+    (438, "ContraTradeTime"),
+    (447, "PartyIDSource"),
+    (448, "PartyID"),
+    (452, "PartyRole"),
+    (453, "NoPartyIDs"),
+    (454, "NoSecurityAltID"),
+    (455, "SecurityAltID"),
+    (456, "SecurityAltIDSource"),
+    (460, "Product"),
+    (461, "CFICode"),
+    (464, "TestMessageIndicator"),
+    (467, "IndividualAllocID"),
+    (523, "PartySubID"),
+    (526, "SecondaryClOrdID"),
+    (527, "SecondaryExecID"),
+    (528, "OrderCapacity"),
+    (529, "OrderRestrictions"),
+    (541, "MaturityDate"),
+    (553, "Username"),
+    (554, "Password"),
+    (555, "NoLegs"),
+    (559, "SecurityListRequestType"),
+    (560, "SecurityRequestResult"),
+    (564, "LegPositionEffect"),
+    (566, "LegPrice"),
+    (581, "AccountType"),
+    (587, "LegSettlType"),
+    (588, "LegSettlDate"),
+    (600, "LegSymbol"),
+    (602, "LegSecurityID"),
+    (603, "LegSecurityIDSource"),
+    (608, "LegCFICode"),
+    (609, "LegSecurityType"),
+    (611, "LegMaturityDate"),
+    (612, "LegStrikePrice"),
+    (623, "LegRatioQty"),
+    (624, "LegSide"),
+    (625, "TradingSessionSubID"),
+    (631, "MidPx"),
+    (632, "BidYield"),
+    (633, "MidYield"),
+    (634, "OfferYield"),
+    (636, "WorkingIndicator"),
+    (637, "LegLastPx"),
+    (654, "LegRefID"),
+    (655, "ContraLegRefID"),
+    (661, "AllocAcctIDSource"),
+    (687, "LegQty"),
+    (736, "AllocSettlCurrency"),
+    (789, "NextExpectedMsgSeqNum"),
+    (802, "NoPartySubIDs"),
+    (803, "PartySubIDType"),
+    (891, "MiscFeeBasis"),
+    (893, "LastFragment"),
+];
+
+const YES_NO: &[(&str, &str)] = &[("Y", "Yes"), ("N", "No")];
+
+const SECURITY_ID_SOURCE: &[(&str, &str)] = &[
+    ("1", "CUSIP"),
+    ("2", "SEDOL"),
+    ("3", "QUIK"),
+    ("4", "ISIN"),
+    ("5", "RIC"),
+    ("6", "ISOCurrencyCode"),
+    ("7", "ISOCountryCode"),
+    ("8", "ExchangeSymbol"),
+    ("9", "ConsolidatedTapeAssociation"),
+    ("A", "BloombergSymbol"),
+    ("B", "Wertpapier"),
+    ("C", "Dutch"),
+    ("D", "Valoren"),
+    ("E", "Sicovam"),
+    ("F", "Belgian"),
+    ("G", "Common"),
+    ("H", "ClearingHouse"),
+    ("I", "ISDAFpMLSpecification"),
+    ("J", "OptionPriceReportingAuthority"),
+];
+
+pub(crate) const VALUES: &[(u64, &[(&str, &str)])] = &[
+    (13, &[("1", "PerUnit"), ("2", "Percent"), ("3", "Absolute")]),
+    (
+        21,
+        &[
+            ("1", "AutomatedExecutionNoIntervention"),
+            ("2", "AutomatedExecutionInterventionOK"),
+            ("3", "ManualOrder"),
+        ],
+    ),
+    (22, SECURITY_ID_SOURCE),
+    (
+        35,
+        &[
+            ("0", "Heartbeat"),
+            ("1", "TestRequest"),
+            ("2", "ResendRequest"),
+            ("3", "Reject"),
+            ("4", "SequenceReset"),
+            ("5", "Logout"),
+            ("6", "IOI"),
+            ("7", "Advertisement"),
+            ("8", "ExecutionReport"),
+            ("9", "OrderCancelReject"),
+            ("A", "Logon"),
+            ("B", "News"),
+            ("C", "Email"),
+            ("D", "NewOrderSingle"),
+            ("E", "NewOrderList"),
+            ("F", "OrderCancelRequest"),
+            ("G", "OrderCancelReplaceRequest"),
+            ("H", "OrderStatusRequest"),
+            ("J", "AllocationInstruction"),
+            ("K", "ListCancelRequest"),
+            ("L", "ListExecute"),
+            ("M", "ListStatusRequest"),
+            ("N", "ListStatus"),
+            ("P", "AllocationInstructionAck"),
+            ("Q", "DontKnowTrade"),
+            ("R", "QuoteRequest"),
+            ("S", "Quote"),
+            ("T", "SettlementInstructions"),
+            ("V", "MarketDataRequest"),
+            ("W", "MarketDataSnapshotFullRefresh"),
+            ("X", "MarketDataIncrementalRefresh"),
+            ("Y", "MarketDataRequestReject"),
+            ("Z", "QuoteCancel"),
+            ("a", "QuoteStatusRequest"),
+            ("b", "MassQuoteAcknowledgement"),
+            ("c", "SecurityDefinitionRequest"),
+            ("d", "SecurityDefinition"),
+            ("e", "SecurityStatusRequest"),
+            ("f", "SecurityStatus"),
+            ("g", "TradingSessionStatusRequest"),
+            ("h", "TradingSessionStatus"),
+            ("i", "MassQuote"),
+            ("j", "BusinessMessageReject"),
+            ("k", "BidRequest"),
+            ("l", "BidResponse"),
+            ("m", "ListStrikePrice"),
+            ("q", "OrderMassCancelRequest"),
+            ("r", "OrderMassCancelReport"),
+            ("s", "NewOrderCross"),
+            ("x", "SecurityListRequest"),
+            ("y", "SecurityList"),
+            ("AD", "TradeCaptureReportRequest"),
+            ("AE", "TradeCaptureReport"),
+            ("AF", "OrderMassStatusRequest"),
+            ("AI", "QuoteStatusReport"),
+            ("AJ", "QuoteResponse"),
+            ("AR", "TradeCaptureReportAck"),
+            ("BE", "UserRequest"),
+            ("BF", "UserResponse"),
+        ],
+    ),
+    (
+        39,
+        &[
+            ("0", "New"),
+            ("1", "PartiallyFilled"),
+            ("2", "Filled"),
+            ("3", "DoneForDay"),
+            ("4", "Canceled"),
+            ("6", "PendingCancel"),
+            ("7", "Stopped"),
+            ("8", "Rejected"),
+            ("9", "Suspended"),
+            ("A", "PendingNew"),
+            ("B", "Calculated"),
+            ("C", "Expired"),
+            ("D", "AcceptedForBidding"),
+            ("E", "PendingReplace"),
+        ],
+    ),
+    (
+        40,
+        &[
+            ("1", "Market"),
+            ("2", "Limit"),
+            ("3", "Stop"),
+            ("4", "StopLimit"),
+            ("6", "WithOrWithout"),
+            ("7", "LimitOrBetter"),
+            ("8", "LimitWithOrWithout"),
+            ("9", "OnBasis"),
+            ("D", "PreviouslyQuoted"),
+            ("E", "PreviouslyIndicated"),
+            ("G", "ForexSwap"),
+            ("I", "Funari"),
+            ("J", "MarketIfTouched"),
+            ("K", "MarketWithLeftOverAsLimit"),
+            ("P", "Pegged"),
+        ],
+    ),
+    (43, YES_NO),
+    (
+        54,
+        &[
+            ("1", "Buy"),
+            ("2", "Sell"),
+            ("3", "BuyMinus"),
+            ("4", "SellPlus"),
+            ("5", "SellShort"),
+            ("6", "SellShortExempt"),
+            ("7", "Undisclosed"),
+            ("8", "Cross"),
+            ("9", "CrossShort"),
+            ("B", "AsDefined"),
+            ("C", "Opposite"),
+        ],
+    ),
+    (
+        59,
+        &[
+            ("0", "Day"),
+            ("1", "GoodTillCancel"),
+            ("2", "AtTheOpening"),
+            ("3", "ImmediateOrCancel"),
+            ("4", "FillOrKill"),
+            ("5", "GoodTillCrossing"),
+            ("6", "GoodTillDate"),
+            ("7", "AtTheClose"),
+        ],
+    ),
+    (
+        63,
+        &[
+            ("0", "Regular"),
+            ("1", "Cash"),
+            ("2", "NextDay"),
+            ("3", "TPlus2"),
+            ("4", "TPlus3"),
+            ("5", "TPlus4"),
+            ("6", "Future"),
+            ("7", "WhenAndIfIssued"),
+            ("8", "SellersOption"),
+            ("9", "TPlus5"),
+        ],
+    ),
+    (77, &[("O", "Open"), ("C", "Close"), ("R", "Rolled"), ("F", "FIFO")]),
+    (97, YES_NO),
+    (98, &[("0", "None")]),
+    (
+        102,
+        &[
+            ("0", "TooLateToCancel"),
+            ("1", "UnknownOrder"),
+            ("2", "BrokerOption"),
+            ("3", "OrderAlreadyInPendingStatus"),
+            ("4", "UnableToProcessOrderMassCancelRequest"),
+            ("5", "OrigOrdModTime"),
+            ("6", "DuplicateClOrdID"),
+            ("99", "Other"),
+        ],
+    ),
+    (
+        103,
+        &[
+            ("0", "BrokerOption"),
+            ("1", "UnknownSymbol"),
+            ("2", "ExchangeClosed"),
+            ("3", "OrderExceedsLimit"),
+            ("4", "TooLateToEnter"),
+            ("5", "UnknownOrder"),
+            ("6", "DuplicateOrder"),
+            ("7", "DuplicateOfAVerballyCommunicatedOrder"),
+            ("8", "StaleOrder"),
+            ("9", "TradeAlongRequired"),
+            ("10", "InvalidInvestorID"),
+            ("11", "UnsupportedOrderCharacteristic"),
+            ("12", "SurveillenceOption"),
+            ("13", "IncorrectQuantity"),
+            ("14", "IncorrectAllocatedQuantity"),
+            ("15", "UnknownAccount"),
+            ("99", "Other"),
+        ],
+    ),
+    (123, YES_NO),
+    (
+        127,
+        &[
+            ("A", "UnknownSymbol"),
+            ("B", "WrongSide"),
+            ("C", "QuantityExceedsOrder"),
+            ("D", "NoMatchingOrder"),
+            ("E", "PriceExceedsLimit"),
+            ("F", "CalculationDifference"),
+            ("Z", "Other"),
+        ],
+    ),
+    (141, YES_NO),
+    (
+        150,
+        &[
+            ("0", "New"),
+            ("3", "DoneForDay"),
+            ("4", "Canceled"),
+            ("5", "Replaced"),
+            ("6", "PendingCancel"),
+            ("7", "Stopped"),
+            ("8", "Rejected"),
+            ("9", "Suspended"),
+            ("A", "PendingNew"),
+            ("B", "Calculated"),
+            ("C", "Expired"),
+            ("D", "Restated"),
+            ("E", "PendingReplace"),
+            ("F", "Trade"),
+            ("G", "TradeCorrect"),
+            ("H", "TradeCancel"),
+            ("I", "OrderStatus"),
+        ],
+    ),
+    (
+        167,
+        &[
+            ("CS", "CommonStock"),
+            ("PS", "PreferredStock"),
+            ("FUT", "Future"),
+            ("OPT", "Option"),
+            ("FOR", "ForeignExchangeContract"),
+            ("MLEG", "MultiLegInstrument"),
+            ("CORP", "CorporateBond"),
+            ("TBOND", "TreasuryBond"),
+            ("NONE", "NoSecurityType"),
+        ],
+    ),
+    (
+        263,
+        &[
+            ("0", "Snapshot"),
+            ("1", "SnapshotPlusUpdates"),
+            ("2", "DisablePreviousSnapshotPlusUpdateRequest"),
+        ],
+    ),
+    (265, &[("0", "FullRefresh"), ("1", "IncrementalRefresh")]),
+    (266, YES_NO),
+    (
+        269,
+        &[
+            ("0", "Bid"),
+            ("1", "Offer"),
+            ("2", "Trade"),
+            ("3", "IndexValue"),
+            ("4", "OpeningPrice"),
+            ("5", "ClosingPrice"),
+            ("6", "SettlementPrice"),
+            ("7", "TradingSessionHighPrice"),
+            ("8", "TradingSessionLowPrice"),
+            ("9", "TradingSessionVWAPPrice"),
+            ("A", "Imbalance"),
+            ("B", "TradeVolume"),
+            ("C", "OpenInterest"),
+        ],
+    ),
+    (
+        274,
+        &[
+            ("0", "PlusTick"),
+            ("1", "ZeroPlusTick"),
+            ("2", "MinusTick"),
+            ("3", "ZeroMinusTick"),
+        ],
+    ),
+    (279, &[("0", "New"), ("1", "Change"), ("2", "Delete")]),
+    (
+        281,
+        &[
+            ("0", "UnknownSymbol"),
+            ("1", "DuplicateMDReqID"),
+            ("2", "InsufficientBandwidth"),
+            ("3", "InsufficientPermissions"),
+            ("4", "UnsupportedSubscriptionRequestType"),
+            ("5", "UnsupportedMarketDepth"),
+            ("6", "UnsupportedMDUpdateType"),
+            ("7", "UnsupportedAggregatedBook"),
+            ("8", "UnsupportedMDEntryType"),
+            ("9", "UnsupportedTradingSessionID"),
+            ("A", "UnsupportedScope"),
+            ("B", "UnsupportedOpenCloseSettlFlag"),
+            ("C", "UnsupportedMDImplicitDelete"),
+        ],
+    ),
+    (
+        297,
+        &[
+            ("0", "Accepted"),
+            ("1", "CanceledForSymbol"),
+            ("2", "CanceledForSecurityType"),
+            ("3", "CanceledForUnderlying"),
+            ("4", "CanceledAll"),
+            ("5", "Rejected"),
+            ("6", "RemovedFromMarket"),
+            ("7", "Expired"),
+            ("8", "Query"),
+            ("9", "QuoteNotFound"),
+            ("10", "Pending"),
+            ("11", "Pass"),
+            ("12", "LockedMarketWarning"),
+            ("13", "CrossMarketWarning"),
+            ("14", "CanceledDueToLockMarket"),
+            ("15", "CanceledDueToCrossMarket"),
+        ],
+    ),
+    (
+        298,
+        &[
+            ("1", "CancelForSymbol"),
+            ("2", "CancelForSecurityType"),
+            ("3", "CancelForUnderlyingSymbol"),
+            ("4", "CancelAllQuotes"),
+        ],
+    ),
+    (
+        300,
+        &[
+            ("1", "UnknownSymbol"),
+            ("2", "Exchange"),
+            ("3", "QuoteRequestExceedsLimit"),
+            ("4", "TooLateToEnter"),
+            ("5", "UnknownQuote"),
+            ("6", "DuplicateQuote"),
+            ("7", "InvalidBidAskSpread"),
+            ("8", "InvalidPrice"),
+            ("9", "NotAuthorizedToQuoteSecurity"),
+            ("99", "Other"),
+        ],
+    ),
+    (
+        301,
+        &[
+            ("0", "NoAcknowledgement"),
+            ("1", "AcknowledgeOnlyNegativeOrErroneousQuotes"),
+            ("2", "AcknowledgeEachQuoteMessage"),
+        ],
+    ),
+    (325, YES_NO),
+    (
+        326,
+        &[
+            ("1", "OpeningDelay"),
+            ("2", "TradingHalt"),
+            ("3", "Resume"),
+            ("4", "NoOpenNoResume"),
+            ("5", "PriceIndication"),
+            ("17", "ReadyToTrade"),
+            ("18", "NotAvailableForTrading"),
+            ("19", "NotTradedOnThisMarket"),
+            ("20", "UnknownOrInvalid"),
+            ("21", "PreOpen"),
+        ],
+    ),
+    (
+        373,
+        &[
+            ("0", "InvalidTagNumber"),
+            ("1", "RequiredTagMissing"),
+            ("2", "TagNotDefinedForThisMessageType"),
+            ("3", "UndefinedTag"),
+            ("4", "TagSpecifiedWithoutAValue"),
+            ("5", "ValueIsIncorrect"),
+            ("6", "IncorrectDataFormatForValue"),
+            ("7", "DecryptionProblem"),
+            ("8", "SignatureProblem"),
+            ("9", "CompIDProblem"),
+            ("10", "SendingTimeAccuracyProblem"),
+            ("11", "InvalidMsgType"),
+            ("12", "XMLValidationError"),
+            ("13", "TagAppearsMoreThanOnce"),
+            ("14", "TagSpecifiedOutOfRequiredOrder"),
+            ("15", "RepeatingGroupFieldsOutOfOrder"),
+            ("16", "IncorrectNumInGroupCountForRepeatingGroup"),
+            ("17", "NonDataValueIncludesFieldDelimiter"),
+            ("99", "Other"),
+        ],
+    ),
+    (
+        380,
+        &[
+            ("0", "Other"),
+            ("1", "UnknownID"),
+            ("2", "UnknownSecurity"),
+            ("3", "UnsupportedMessageType"),
+            ("4", "ApplicationNotAvailable"),
+            ("5", "ConditionallyRequiredFieldMissing"),
+            ("6", "NotAuthorized"),
+            ("7", "DeliverToFirmNotAvailableAtThisTime"),
+        ],
+    ),
+    (385, &[("S", "Send"), ("R", "Receive")]),
+    (434, &[("1", "OrderCancelRequest"), ("2", "OrderCancelReplaceRequest")]),
+    (
+        447,
+        &[
+            ("B", "BIC"),
+            ("C", "GenerallyAcceptedMarketParticipantIdentifier"),
+            ("D", "ProprietaryCustomCode"),
+            ("E", "ISOCountryCode"),
+            ("F", "SettlementEntityLocation"),
+            ("G", "MIC"),
+            ("H", "CSDParticipantMemberCode"),
+        ],
+    ),
+    (
+        452,
+        &[
+            ("1", "ExecutingFirm"),
+            ("3", "ClientID"),
+            ("4", "ClearingFirm"),
+            ("7", "EnteringFirm"),
+            ("11", "OrderOriginationTrader"),
+            ("12", "ExecutingTrader"),
+            ("13", "OrderOriginationFirm"),
+            ("17", "ContraFirm"),
+            ("24", "CustomerAccount"),
+            ("36", "EnteringTrader"),
+        ],
+    ),
+    (456, SECURITY_ID_SOURCE),
+    (464, YES_NO),
+    (
+        528,
+        &[
+            ("A", "Agency"),
+            ("G", "Proprietary"),
+            ("I", "Individual"),
+            ("P", "Principal"),
+            ("R", "RisklessPrincipal"),
+            ("W", "AgentForOtherMember"),
+        ],
+    ),
+    (
+        559,
+        &[
+            ("0", "Symbol"),
+            ("1", "SecurityTypeAndOrCFICode"),
+            ("2", "Product"),
+            ("3", "TradingSessionID"),
+            ("4", "AllSecurities"),
+        ],
+    ),
+    (
+        560,
+        &[
+            ("0", "ValidRequest"),
+            ("1", "InvalidOrUnsupportedRequest"),
+            ("2", "NoInstrumentsFound"),
+            ("3", "NotAuthorizedToRetrieveInstrumentData"),
+            ("4", "InstrumentDataTemporarilyUnavailable"),
+            ("5", "RequestForInstrumentDataNotSupported"),
+        ],
+    ),
+    (603, SECURITY_ID_SOURCE),
+    (636, YES_NO),
+    (893, YES_NO),
+];
+
+// NoXXX count tag and the tags an entry of the group may hold, the usual first one first.
+pub(crate) const GROUPS: &[(u64, &[u64])] = &[
+    (78, &[79, 661, 736, 467, 80]),
+    (136, &[137, 138, 139, 891]),
+    (
+        146,
+        &[
+            55, 65, 48, 22, 454, 460, 461, 167, 200, 541, 202, 206, 207, 231, 223, 106, 107, 15, 555, 38, 54, 64, 40,
+            126, 62, 336, 625,
+        ],
+    ),
+    (267, &[269]),
+    (
+        268,
+        &[
+            269, 279, 285, 278, 280, 55, 65, 48, 22, 454, 167, 83, 270, 15, 271, 272, 273, 274, 275, 336, 625, 276,
+            277, 282, 283, 284, 286, 59, 432, 126, 110, 18, 287, 37, 198, 299, 288, 289, 346, 290, 58, 354, 355,
+        ],
+    ),
+    (
+        295,
+        &[
+            299, 55, 65, 48, 22, 454, 167, 132, 133, 134, 135, 62, 188, 189, 190, 191, 631, 632, 633, 634, 60, 336,
+            625, 64, 40, 192, 193, 15, 368,
+        ],
+    ),
+    (296, &[302, 311, 304, 893, 295]),
+    (382, &[375, 337, 437, 438, 655]),
+    (384, &[372, 385]),
+    (386, &[336, 625]),
+    (453, &[448, 447, 452, 802]),
+    (454, &[455, 456]),
+    (
+        555,
+        &[
+            600, 602, 603, 608, 609, 611, 612, 623, 624, 687, 654, 566, 587, 588, 564, 637,
+        ],
+    ),
+    (802, &[523, 803]),
+];
@@ -1,12 +1,19 @@
 use std::convert::From;
 
-use client::MessageValidationErr;
+use admin::Reject;
+use client::{MessageValidationErr, SendError};
 use detail::FixDeserializable;
+use session::SessionError;
 use FixClient;
+use RawMessage;
 
 #[derive(Debug)]
 pub enum HandleErr {
     MessageValidationErr(MessageValidationErr),
+    // the session can not continue and reconnecting will not help, e.g. credentials were refused.
+    Fatal(String),
+    // writing to the transport failed, the runner reconnects.
+    Send(SendError),
     Unknown,
 }
 
@@ -16,13 +23,51 @@ impl From<MessageValidationErr> for HandleErr {
     }
 }
 
+impl From<SendError> for HandleErr {
+    fn from(e: SendError) -> Self {
+        HandleErr::Send(e)
+    }
+}
+
+/// Serves one connection of a FIX session, driven by a `Session`.
+///
+/// The `on_*` hooks are called by the session around the messages it hands over, whether the
+/// counterparty is logged on is kept by the library, see `FixClient::is_logged_on`.
+///
+/// Messages sent from any of these callbacks go out once the callback returned, after passing
+/// through `to_admin` or `to_app`.
 pub trait FixHandler<SessionMsg: FixDeserializable, AppMsg: FixDeserializable, Action> {
     fn handle_session(&mut self, client: &mut FixClient, msg: SessionMsg) -> Result<(), HandleErr>;
     fn handle_app(&mut self, client: &mut FixClient, msg: AppMsg) -> Result<(), HandleErr>;
 
-    fn handle_action(&mut self, client: &mut FixClient, action: Action);
+    // only called once logged on.
+    fn handle_action(&mut self, client: &mut FixClient, action: Action) -> Result<(), HandleErr>;
 
     fn poll(&mut self, client: &mut FixClient) {}
 
-    fn is_logged(&self) -> bool;
+    // the transport is up, e.g. the place for an initiator to send its Logon.
+    fn on_connect(&mut self, _client: &mut FixClient) {}
+
+    // a Logon of the counterparty was handled.
+    fn on_logon(&mut self, _client: &mut FixClient) {}
+
+    // a Logout of the counterparty was handled, `reason` is its Text (58).
+    fn on_logout(&mut self, _client: &mut FixClient, _reason: Option<&str>) {}
+
+    // the connection is gone. `cause` is None when it was closed on purpose, e.g. after a Logout
+    // or a `HandleErr::Fatal` of the handler.
+    fn on_disconnect(&mut self, _cause: Option<&SessionError>) {}
+
+    // a session level Reject of one of our messages was handled.
+    fn on_reject(&mut self, _client: &mut FixClient, _reject: &Reject) {}
+
+    // an incoming message has a higher MsgSeqNum than expected, called before it is handled,
+    // e.g. to send a ResendRequest for `expected` up to `received - 1`.
+    fn on_sequence_gap(&mut self, _client: &mut FixClient, _expected: u64, _received: u64) {}
+
+    // amends an outgoing session level message, e.g. adds a Password (554) to the Logon.
+    fn to_admin(&mut self, _msg: &mut RawMessage) {}
+
+    // amends an outgoing application message.
+    fn to_app(&mut self, _msg: &mut RawMessage) {}
 }
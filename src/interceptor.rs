@@ -0,0 +1,299 @@
+use RawMessage;
+
+/// What an `Interceptor` decided about a message.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Verdict {
+    // hands the message, amended or not, to the next interceptor.
+    Pass,
+    // discards the message quietly, the interceptors after this one do not see it.
+    Drop,
+    // refuses the message: an outbound one fails `FixClient::send` with `SendError::Vetoed`,
+    // an inbound one is answered with a session level Reject.
+    Veto(String),
+}
+
+/// Cross-cutting behaviour on the messages of a `FixClient`, e.g. audit logging, adding an
+/// Account (1) to every order or refusing orders over a limit.
+///
+/// Interceptors run in the order they were added, in both directions, over the messages taken
+/// apart into fields, `RawMessage::typed` gives the typed message. Messages a `Session` holds
+/// back for `FixHandler::to_app` are intercepted before that amendment, resends only reach
+/// `written`.
+pub trait Interceptor {
+    // a message given to `FixClient::send`, before it is stored, throttled and written.
+    fn outbound(&mut self, _msg: &mut RawMessage) -> Verdict {
+        Verdict::Pass
+    }
+
+    // a message received, before the `FixHandler` gets it.
+    fn inbound(&mut self, _msg: &mut RawMessage) -> Verdict {
+        Verdict::Pass
+    }
+
+    // an inbound message as the `FixHandler` gets it next. Unlike `inbound` it is not called for
+    // messages an interceptor dropped or vetoed, nor for those with a MsgSeqNum received before.
+    fn received(&mut self, _msg: &RawMessage) {}
+
+    // a copy of a message of either direction about to be written to the `MessageLog`, changes only
+    // affect the log, e.g. to scrub personal data.
+    fn logged(&mut self, _msg: &mut RawMessage) {}
+
+    // an outbound message once it was written to the stream, or buffered while the stream does not
+    // take more, resends included. Unlike `outbound` it
+    // is not called for messages a later interceptor refused, the throttle rejected or that failed to
+    // be written.
+    fn written(&mut self, _msg: &RawMessage) {}
+}
+
+pub(crate) enum Intercepted {
+    Unchanged,
+    Amended(String),
+    Dropped,
+    Vetoed(String),
+}
+
+// runs `chain` over a serialized message, in the given direction.
+pub(crate) fn intercept(chain: &mut [Box<Interceptor>], fix_msg: &[u8], outbound: bool) -> Intercepted {
+    if chain.is_empty() {
+        return Intercepted::Unchanged;
+    }
+    let mut msg = match RawMessage::parse(fix_msg) {
+        Ok(msg) => msg,
+        Err(err) => {
+            warn!(
+                "not intercepting {:?}, it does not parse: {}",
+                String::from_utf8_lossy(fix_msg),
+                err
+            );
+            return Intercepted::Unchanged;
+        }
+    };
+    let original = msg.clone();
+
+    for interceptor in chain.iter_mut() {
+        let verdict = if outbound {
+            interceptor.outbound(&mut msg)
+        } else {
+            interceptor.inbound(&mut msg)
+        };
+        match verdict {
+            Verdict::Pass => {}
+            Verdict::Drop => return Intercepted::Dropped,
+            Verdict::Veto(reason) => return Intercepted::Vetoed(reason),
+        }
+    }
+
+    if msg == original {
+        Intercepted::Unchanged
+    } else {
+        Intercepted::Amended(msg.to_fix())
+    }
+}
+
+// what the `MessageLog` gets of a message, None when it is to be logged as it is.
+pub(crate) fn logged(chain: &mut [Box<Interceptor>], fix_msg: &[u8]) -> Option<String> {
+    if chain.is_empty() {
+        return None;
+    }
+    let mut msg = RawMessage::parse(fix_msg).ok()?;
+    let original = msg.clone();
+    for interceptor in chain.iter_mut() {
+        interceptor.logged(&mut msg);
+    }
+
+    if msg == original {
+        None
+    } else {
+        Some(msg.to_fix())
+    }
+}
+
+// tells `chain` about a message written.
+pub(crate) fn written(chain: &mut [Box<Interceptor>], fix_msg: &[u8]) {
+    if chain.is_empty() {
+        return;
+    }
+    if let Ok(msg) = RawMessage::parse(fix_msg) {
+        for interceptor in chain.iter_mut() {
+            interceptor.written(&msg);
+        }
+    }
+}
+
+// tells `chain` about a message received and let through.
+pub(crate) fn received(chain: &mut [Box<Interceptor>], fix_msg: &[u8]) {
+    if chain.is_empty() {
+        return;
+    }
+    if let Ok(msg) = RawMessage::parse(fix_msg) {
+        for interceptor in chain.iter_mut() {
+            interceptor.received(&msg);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::sync::mpsc::channel;
+
+    use chrono::NaiveDateTime;
+
+    use super::*;
+    use admin::Logout;
+    use loopback::{loopback, LoopbackStream};
+    use testing::{self, sent, AppMsg, NewOrder, TestHandler};
+    use {serialize, FixClient, HandleErr, MessageLog, SendError, Session, Timestamp};
+
+    // adds the account to orders and keeps it out of the log, refuses big ones, drops test ones.
+    // Refuses incoming orders of restricted symbols and drops incoming test messages.
+    struct Desk;
+
+    impl Interceptor for Desk {
+        fn outbound(&mut self, msg: &mut RawMessage) -> Verdict {
+            if msg.msg_type() != "D" {
+                return Verdict::Pass;
+            }
+            let order: NewOrder = msg.typed().unwrap();
+            if order.qty.unwrap_or(0) > 100 {
+                Verdict::Veto("quantity over 100".to_string())
+            } else if order.symbol.as_deref() == Some("TEST") {
+                Verdict::Drop
+            } else {
+                msg.set(1, "ACC1");
+                Verdict::Pass
+            }
+        }
+
+        fn inbound(&mut self, msg: &mut RawMessage) -> Verdict {
+            match (msg.get(55), msg.get(58)) {
+                (Some("BAD"), _) => Verdict::Veto("restricted symbol".to_string()),
+                (_, Some("TEST")) => Verdict::Drop,
+                _ => Verdict::Pass,
+            }
+        }
+
+        fn logged(&mut self, msg: &mut RawMessage) {
+            if msg.get(1).is_some() {
+                msg.set(1, "***");
+            }
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Recording(Rc<RefCell<Vec<String>>>);
+
+    impl MessageLog for Recording {
+        fn on_incoming(&mut self, _now: NaiveDateTime, msg: &[u8]) {
+            self.0.borrow_mut().push(String::from_utf8_lossy(msg).into_owned());
+        }
+
+        fn on_outgoing(&mut self, _now: NaiveDateTime, msg: &str) {
+            self.0.borrow_mut().push(msg.to_string());
+        }
+
+        fn on_event(&mut self, _now: NaiveDateTime, _text: &str) {}
+    }
+
+    struct Handler {
+        orders: Vec<String>,
+    }
+
+    impl TestHandler for Handler {
+        type Action = ();
+
+        fn handle_app(&mut self, client: &mut FixClient, msg: AppMsg) -> Result<(), HandleErr> {
+            if let AppMsg::NewOrder(order) = msg {
+                client.validate_msg(&order)?;
+                self.orders.push(order.cl_ord_id);
+            }
+            Ok(())
+        }
+    }
+
+    fn client(stream: LoopbackStream) -> FixClient {
+        let mut client = testing::client("us", "them", stream);
+        client.add_interceptor(Box::new(Desk));
+        client
+    }
+
+    fn order(seq: u64, sender: &str, target: &str, symbol: &str, qty: u64) -> NewOrder {
+        NewOrder {
+            seq,
+            sender: sender.to_string(),
+            target: target.to_string(),
+            sending_time: Timestamp::now(),
+            poss_dup: None,
+            orig_sending_time: None,
+            cl_ord_id: format!("order-{}", seq),
+            symbol: Some(symbol.to_string()),
+            side: None,
+            qty: Some(qty),
+            account: None,
+        }
+    }
+
+    #[test]
+    fn test_outbound_amended_vetoed_and_dropped() {
+        let (ours, mut theirs) = loopback();
+        let mut client = client(ours);
+        let log = Recording::default();
+        client.set_message_log(Box::new(log.clone()));
+
+        let seq = client.get_next_send_seq();
+        client.send(&order(seq, "us", "them", "EURUSD", 10)).unwrap();
+        let sent = sent(&mut theirs);
+        assert!(sent.contains("\x0134=1\x01"));
+        assert!(sent.contains("\x0138=10\x011=ACC1\x0110="));
+        assert!(log.0.borrow()[0].contains("\x011=***\x01"));
+
+        let seq = client.get_next_send_seq();
+        match client.send(&order(seq, "us", "them", "EURUSD", 1000)) {
+            Err(SendError::Vetoed(reason)) => assert_eq!(reason, "quantity over 100"),
+            other => panic!("not vetoed: {:?}", other),
+        }
+        let seq = client.get_next_send_seq();
+        client.send(&order(seq, "us", "them", "TEST", 10)).unwrap();
+        assert_eq!(client.get_next_send_seq(), 2);
+        assert_eq!(log.0.borrow().len(), 1);
+    }
+
+    #[test]
+    fn test_inbound_veto_is_rejected() {
+        let (ours, mut theirs) = loopback();
+        let (_actions_tx, actions) = channel();
+        let handler = Handler { orders: Vec::new() };
+        let mut session = Session::new(client(ours), handler, &actions);
+
+        let bad = serialize(&order(1, "them", "us", "BAD", 10));
+        let good = serialize(&order(2, "them", "us", "EURUSD", 10));
+        session.push_received(bad.as_bytes()).unwrap();
+        session.push_received(good.as_bytes()).unwrap();
+
+        assert_eq!(session.handler().orders, vec!["order-2".to_string()]);
+        let sent = sent(&mut theirs);
+        assert!(sent.contains("\x0135=3\x01"));
+        assert!(sent.contains("\x0145=1\x01372=D\x01373=99\x0158=restricted symbol\x01"));
+    }
+
+    #[test]
+    fn test_dropped_logout_is_not_received() {
+        let (ours, _theirs) = loopback();
+        let (_actions_tx, actions) = channel();
+        let handler = Handler { orders: Vec::new() };
+        let mut session = Session::new(client(ours), handler, &actions);
+
+        let logout = |seq, text: &str| Logout {
+            seq,
+            sender: "them".to_string(),
+            target: "us".to_string(),
+            sending_time: Timestamp::now(),
+            text: Some(text.to_string()),
+        };
+        session.push_received(serialize(&logout(1, "TEST")).as_bytes()).unwrap();
+        assert!(!session.logout_received());
+        session.push_received(serialize(&logout(2, "bye")).as_bytes()).unwrap();
+        assert!(session.logout_received());
+    }
+}
@@ -1,10 +1,25 @@
 #[cfg(test)]
 extern crate quickcheck;
 
+#[cfg(feature = "async")]
+extern crate bytes;
 extern crate chrono;
+#[cfg(feature = "async")]
+extern crate futures_core;
+#[cfg(feature = "async")]
+extern crate futures_sink;
 #[macro_use]
 extern crate log;
+extern crate mio;
 extern crate native_tls;
+#[cfg(feature = "rustls")]
+extern crate rustls;
+#[cfg(feature = "rustls")]
+extern crate rustls_pemfile;
+#[cfg(feature = "async")]
+extern crate tokio;
+#[cfg(feature = "async")]
+extern crate tokio_util;
 
 #[macro_use]
 #[allow(unused_imports)]
@@ -12,25 +27,87 @@ extern crate profix_derive;
 #[doc(hidden)]
 pub use self::profix_derive::*;
 
+// lets the derives, which refer to `::profix`, be used for the messages defined in this crate.
+extern crate self as profix;
+
+mod acceptor;
+pub mod admin;
+#[cfg(feature = "async")]
+mod async_session;
+mod book;
 mod client;
+mod clock;
+mod config;
+mod correlation;
+mod dictionary;
+mod event_loop;
 mod factory;
+mod fix44_tags;
 mod fix_loop;
 mod handler;
+mod interceptor;
+mod loopback;
+pub mod market_data;
+mod message_log;
+mod orders;
 mod parsing;
+mod pretty;
+mod raw_message;
+mod reconnect;
+mod replay;
+mod risk;
+mod runner;
 mod serialization;
+mod session;
+mod simulator;
+mod store;
+#[cfg(test)]
+mod testing;
+mod throttle;
+mod timers;
 mod timestamp;
+mod tls;
 
 pub type ParseError = &'static str;
 
+pub use acceptor::{Acceptor, AcceptorFactory};
+#[cfg(feature = "async")]
+pub use async_session::{AsyncFixClient, AsyncSession, FixCodec, Inbound, MessageStream, SendFuture};
+pub use book::{Book, BookOrder, BookSide, L2Book, L3Book, Level, MarketDataBooks};
 pub use client::FixClient;
 pub use client::PlainStreamWrapper;
+pub use client::SendError;
+pub use client::Stream;
 pub use client::TlsStreamWrapper;
+pub use clock::{Clock, SystemClock, TestClock};
+pub use config::SessionConfig;
+pub use correlation::{Correlator, PendingRequest, RequestId, Response};
+pub use dictionary::{Dictionary, FieldDef};
+pub use event_loop::{ActionSender, EventLoop};
 pub use factory::{CompIds, ConnectionFailure, FixFactory};
 pub use fix_loop::fix_loop;
 pub use handler::{FixHandler, HandleErr};
+pub use interceptor::{Interceptor, Verdict};
+pub use loopback::{loopback, LoopbackHarness, LoopbackStream};
+pub use message_log::{FileLog, FileLogConfig, LogMessageLog, MessageLog, Rotation};
+pub use orders::{Fill, OrderError, OrderManager, OrderState, TrackedOrder};
+pub use pretty::{pretty, PrettyPrinter};
+pub use raw_message::RawMessage;
+pub use reconnect::{FailureAction, ReconnectPolicy, ReconnectState};
+pub use replay::{
+    read_log, Direction, FieldDifference, Mismatch, Recorded, Replay, ReplayReport, ReplaySpeed, VOLATILE_TAGS,
+};
+pub use risk::{OpenOrder, RiskCheck, RiskLimits};
+pub use runner::{FatalError, SessionRunner, StopHandle};
 pub use serialization::deserialize;
 pub use serialization::serialize;
-pub use timestamp::Timestamp;
+pub use session::{Drive, Session, SessionError};
+pub use simulator::{Scenario, ScenarioFailure, ScenarioStep, ScriptError, Simulator};
+pub use store::{MemoryStore, MessageStore};
+pub use throttle::{OverflowAction, RateLimit, Throttle, ThrottleUsage};
+pub use timers::SessionTimers;
+pub use timestamp::{Timestamp, TimestampPrecision};
+pub use tls::{Certificate, Identity, TlsBackend, TlsConfig};
 
 pub trait FixParse: Sized {
     fn parse(value: &[u8]) -> Result<Self, ParseError>;
@@ -44,13 +121,28 @@ pub trait FixHeader {
     fn seq(&self) -> u64;
     fn target(&self) -> &str;
     fn sender(&self) -> &str;
+
+    fn sending_time(&self) -> Option<Timestamp> {
+        None
+    }
+    // PossDupFlag (43)
+    fn poss_dup(&self) -> bool {
+        false
+    }
+    // OrigSendingTime (122), only meaningful for PossDup messages.
+    fn orig_sending_time(&self) -> Option<Timestamp> {
+        None
+    }
 }
 
 pub mod detail {
     use super::ParseError;
 
+    pub use super::parsing::begin_string;
+    pub use super::parsing::find_field;
     pub use super::parsing::parse_fix_field;
     pub use super::parsing::parse_fix_message;
+    pub use super::parsing::validate_fix_message;
     pub use super::parsing::FixField;
     pub use super::parsing::FixMessage;
     pub use super::parsing::ParserContinuation;
@@ -0,0 +1,423 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+
+use session::{Drive, SessionError};
+
+#[derive(Default)]
+struct Pipe {
+    data: VecDeque<u8>,
+    closed: bool,
+}
+
+/// One end of an in-memory duplex transport, see `loopback`.
+/// Reads never block: an empty pipe gives `WouldBlock`, a pipe closed by the other end gives `Ok(0)`.
+pub struct LoopbackStream {
+    incoming: Arc<Mutex<Pipe>>,
+    outgoing: Arc<Mutex<Pipe>>,
+}
+
+/// Two connected in-memory streams, whatever is written to one can be read from the other.
+pub fn loopback() -> (LoopbackStream, LoopbackStream) {
+    let a_to_b = Arc::new(Mutex::new(Pipe::default()));
+    let b_to_a = Arc::new(Mutex::new(Pipe::default()));
+
+    let a = LoopbackStream {
+        incoming: b_to_a.clone(),
+        outgoing: a_to_b.clone(),
+    };
+    let b = LoopbackStream {
+        incoming: a_to_b,
+        outgoing: b_to_a,
+    };
+    (a, b)
+}
+
+impl Read for LoopbackStream {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let mut pipe = self.incoming.lock().unwrap();
+        if pipe.data.is_empty() {
+            return if pipe.closed {
+                Ok(0)
+            } else {
+                Err(Error::new(ErrorKind::WouldBlock, "loopback empty"))
+            };
+        }
+
+        let size = buf.len().min(pipe.data.len());
+        for (dst, src) in buf.iter_mut().zip(pipe.data.drain(..size)) {
+            *dst = src;
+        }
+        Ok(size)
+    }
+}
+
+impl Write for LoopbackStream {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        let mut pipe = self.outgoing.lock().unwrap();
+        if pipe.closed {
+            return Err(Error::new(ErrorKind::BrokenPipe, "loopback closed"));
+        }
+        pipe.data.extend(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+impl Drop for LoopbackStream {
+    fn drop(&mut self) {
+        self.incoming.lock().unwrap().closed = true;
+        self.outgoing.lock().unwrap().closed = true;
+    }
+}
+
+/// Drives an initiator and an acceptor session, usually connected by `loopback`, on the calling thread.
+pub struct LoopbackHarness<I, A> {
+    pub initiator: I,
+    pub acceptor: A,
+}
+
+// bounds `run_until_idle`, so two sessions talking to each other forever fail the test instead of hanging it.
+const MAX_ROUNDS: usize = 10000;
+
+impl<I: Drive, A: Drive> LoopbackHarness<I, A> {
+    pub fn new(initiator: I, acceptor: A) -> LoopbackHarness<I, A> {
+        LoopbackHarness {
+            initiator,
+            acceptor,
+        }
+    }
+
+    // steps the initiator and then the acceptor once, returns whether either of them did anything.
+    pub fn step(&mut self) -> Result<bool, SessionError> {
+        let initiator_busy = self.initiator.step()?;
+        let acceptor_busy = self.acceptor.step()?;
+        Ok(initiator_busy || acceptor_busy)
+    }
+
+    // steps both sessions until a whole round passes with nothing to handle.
+    pub fn run_until_idle(&mut self) -> Result<(), SessionError> {
+        for _ in 0..MAX_ROUNDS {
+            if !self.step()? {
+                return Ok(());
+            }
+        }
+        panic!("sessions did not become idle after {} rounds", MAX_ROUNDS);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::{channel, Receiver, Sender};
+    use std::time::Duration;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use admin::{Heartbeat, Reject, SessionRejectReason};
+    use session::Session;
+    use testing::{client, header, AppMsg, ExecReport, Logon, NewOrder, SessionMsg, TestHandler};
+    use {FixClient, HandleErr, RawMessage, SessionError, TestClock};
+
+    #[derive(Debug)]
+    enum Action {
+        SendOrder(String),
+        Logout,
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        LoggedIn,
+        Filled(String),
+        LoggedOut,
+        Gap(u64, u64),
+        Rejected(u64),
+        Heartbeat(Option<String>),
+        Disconnected,
+    }
+
+    // the initiator logs on with `password` added by `to_admin`, the acceptor insists on it.
+    // Orders get an account added by `to_app`, the acceptor rejects those without one and "bad" ones.
+    struct Handler {
+        password: Option<String>,
+        events: Sender<Event>,
+    }
+
+    impl TestHandler for Handler {
+        type Action = Action;
+
+        fn handle_session(&mut self, client: &mut FixClient, msg: SessionMsg) -> Result<(), HandleErr> {
+            match msg {
+                SessionMsg::Logon(logon) => {
+                    client.validate_msg(&logon)?;
+                    if client.comp_ids().sender == "acceptor" {
+                        if logon.password != self.password {
+                            return Err(HandleErr::Fatal("wrong password".to_string()));
+                        }
+                        let logon = Logon::next(client);
+                        client.send(&logon)?;
+                    }
+                }
+                SessionMsg::Logout(logout) => {
+                    client.validate_msg(&logout)?;
+                    if client.is_logged_on() && client.comp_ids().sender == "acceptor" {
+                        client.send_logout(None)?;
+                    }
+                }
+                SessionMsg::Reject(reject) => client.validate_msg(&reject)?,
+                SessionMsg::ResendRequest(request) => client.validate_msg(&request)?,
+                SessionMsg::Heartbeat(heartbeat) => {
+                    client.validate_msg(&heartbeat)?;
+                    self.events.send(Event::Heartbeat(heartbeat.test_req_id)).unwrap();
+                }
+                SessionMsg::TestRequest(request) => {
+                    client.validate_msg(&request)?;
+                    let (seq, sender, target, sending_time) = header(client);
+                    client.send(&Heartbeat {
+                        seq,
+                        sender,
+                        target,
+                        sending_time,
+                        test_req_id: Some(request.test_req_id),
+                    })?;
+                }
+            }
+            Ok(())
+        }
+
+        fn handle_app(&mut self, client: &mut FixClient, msg: AppMsg) -> Result<(), HandleErr> {
+            match msg {
+                AppMsg::NewOrder(order) => {
+                    client.validate_msg(&order)?;
+                    if order.account.is_some() && order.cl_ord_id != "bad" {
+                        let report = ExecReport::next(client, &order.cl_ord_id);
+                        client.send(&report)?;
+                    } else {
+                        let (seq, sender, target, sending_time) = header(client);
+                        client.send(&Reject {
+                            seq,
+                            sender,
+                            target,
+                            sending_time,
+                            ref_seq_num: order.seq,
+                            ref_tag_id: None,
+                            ref_msg_type: Some("D".to_string()),
+                            reason: Some(SessionRejectReason::Other),
+                            text: Some("order refused".to_string()),
+                        })?;
+                    }
+                }
+                AppMsg::ExecReport(report) => {
+                    client.validate_msg(&report)?;
+                    self.events.send(Event::Filled(report.cl_ord_id)).unwrap();
+                }
+            }
+            Ok(())
+        }
+
+        fn handle_action(&mut self, client: &mut FixClient, action: Action) -> Result<(), HandleErr> {
+            match action {
+                Action::SendOrder(cl_ord_id) => {
+                    let order = NewOrder::next(client, &cl_ord_id);
+                    client.send(&order)?;
+                }
+                Action::Logout => client.send_logout(None)?,
+            }
+            Ok(())
+        }
+
+        fn on_connect(&mut self, client: &mut FixClient) {
+            if client.comp_ids().sender == "initiator" {
+                let logon = Logon::next(client);
+                client.send(&logon).unwrap();
+            }
+        }
+
+        fn on_logon(&mut self, _client: &mut FixClient) {
+            self.events.send(Event::LoggedIn).unwrap();
+        }
+
+        fn on_logout(&mut self, _client: &mut FixClient, _reason: Option<&str>) {
+            self.events.send(Event::LoggedOut).unwrap();
+        }
+
+        fn on_disconnect(&mut self, _cause: Option<&SessionError>) {
+            self.events.send(Event::Disconnected).unwrap();
+        }
+
+        fn on_sequence_gap(&mut self, _client: &mut FixClient, expected: u64, received: u64) {
+            self.events.send(Event::Gap(expected, received)).unwrap();
+        }
+
+        fn on_reject(&mut self, _client: &mut FixClient, reject: &Reject) {
+            self.events.send(Event::Rejected(reject.ref_seq_num)).unwrap();
+        }
+
+        fn to_admin(&mut self, msg: &mut RawMessage) {
+            match self.password {
+                Some(ref password) if msg.msg_type() == "A" => msg.set(554, password),
+                _ => {}
+            }
+        }
+
+        fn to_app(&mut self, msg: &mut RawMessage) {
+            if msg.msg_type() == "D" {
+                msg.set(1, "ACC1");
+            }
+        }
+    }
+
+    fn handler(password: Option<&str>) -> (Handler, Receiver<Event>) {
+        let (events, events_rx) = channel();
+        let password = password.map(str::to_string);
+        (Handler { password, events }, events_rx)
+    }
+
+    #[test]
+    fn test_logon_order_exec_report_logout() {
+        let (initiator_stream, acceptor_stream) = loopback();
+        let initiator_client = client("initiator", "acceptor", initiator_stream);
+        let acceptor_client = client("acceptor", "initiator", acceptor_stream);
+
+        let (initiator_handler, initiator_events) = handler(None);
+        let (acceptor_handler, acceptor_events) = handler(None);
+        let (initiator_actions, initiator_action_rx) = channel();
+        let (_acceptor_actions, acceptor_action_rx) = channel();
+
+        let initiator = Session::new(initiator_client, initiator_handler, &initiator_action_rx);
+        let acceptor = Session::new(acceptor_client, acceptor_handler, &acceptor_action_rx);
+        let mut harness = LoopbackHarness::new(initiator, acceptor);
+
+        harness.run_until_idle().unwrap();
+        assert_eq!(acceptor_events.try_recv(), Ok(Event::LoggedIn));
+        assert_eq!(initiator_events.try_recv(), Ok(Event::LoggedIn));
+
+        initiator_actions.send(Action::SendOrder("order1".to_string())).unwrap();
+        harness.run_until_idle().unwrap();
+        assert_eq!(initiator_events.try_recv(), Ok(Event::Filled("order1".to_string())));
+
+        initiator_actions.send(Action::Logout).unwrap();
+        harness.run_until_idle().unwrap();
+        assert_eq!(acceptor_events.try_recv(), Ok(Event::LoggedOut));
+        assert_eq!(initiator_events.try_recv(), Ok(Event::LoggedOut));
+        assert!(!harness.initiator.client().is_logged_on());
+    }
+
+    #[test]
+    fn test_password_added_to_logon_and_gap_reported() {
+        let (initiator_stream, acceptor_stream) = loopback();
+        let initiator_client = client("initiator", "acceptor", initiator_stream);
+        let acceptor_client = client("acceptor", "initiator", acceptor_stream);
+
+        let (initiator_handler, _initiator_events) = handler(Some("secret"));
+        let (acceptor_handler, acceptor_events) = handler(Some("secret"));
+        let (initiator_actions, initiator_action_rx) = channel();
+        let (_acceptor_actions, acceptor_action_rx) = channel();
+
+        let initiator = Session::new(initiator_client, initiator_handler, &initiator_action_rx);
+        let acceptor = Session::new(acceptor_client, acceptor_handler, &acceptor_action_rx);
+        let mut harness = LoopbackHarness::new(initiator, acceptor);
+
+        harness.run_until_idle().unwrap();
+        assert_eq!(acceptor_events.try_recv(), Ok(Event::LoggedIn));
+        assert!(harness.acceptor.client().is_logged_on());
+
+        // skips seq 2.
+        harness.initiator.client().get_next_send_seq();
+        initiator_actions.send(Action::SendOrder("order1".to_string())).unwrap();
+        assert!(harness.run_until_idle().is_err());
+        assert_eq!(acceptor_events.try_recv(), Ok(Event::Gap(2, 3)));
+        assert_eq!(acceptor_events.try_recv(), Ok(Event::Disconnected));
+        assert!(!harness.acceptor.client().is_logged_on());
+    }
+
+    #[test]
+    fn test_account_added_to_orders_and_reject_reported() {
+        let (initiator_stream, acceptor_stream) = loopback();
+        let initiator_client = client("initiator", "acceptor", initiator_stream);
+        let acceptor_client = client("acceptor", "initiator", acceptor_stream);
+
+        let (initiator_handler, initiator_events) = handler(None);
+        let (acceptor_handler, _acceptor_events) = handler(None);
+        let (initiator_actions, initiator_action_rx) = channel();
+        let (_acceptor_actions, acceptor_action_rx) = channel();
+
+        let initiator = Session::new(initiator_client, initiator_handler, &initiator_action_rx);
+        let acceptor = Session::new(acceptor_client, acceptor_handler, &acceptor_action_rx);
+        let mut harness = LoopbackHarness::new(initiator, acceptor);
+
+        harness.run_until_idle().unwrap();
+        assert_eq!(initiator_events.try_recv(), Ok(Event::LoggedIn));
+
+        initiator_actions.send(Action::SendOrder("order1".to_string())).unwrap();
+        initiator_actions.send(Action::SendOrder("bad".to_string())).unwrap();
+        harness.run_until_idle().unwrap();
+        assert_eq!(initiator_events.try_recv(), Ok(Event::Filled("order1".to_string())));
+        assert_eq!(initiator_events.try_recv(), Ok(Event::Rejected(3)));
+        assert!(harness.initiator.client().is_logged_on());
+    }
+
+    #[test]
+    fn test_heartbeats_test_requests_and_timeout() {
+        let (initiator_stream, acceptor_stream) = loopback();
+        let mut initiator_client = client("initiator", "acceptor", initiator_stream);
+        let mut acceptor_client = client("acceptor", "initiator", acceptor_stream);
+        let clock = TestClock::new(
+            NaiveDate::from_ymd_opt(2024, 1, 2)
+                .unwrap()
+                .and_hms_opt(10, 0, 0)
+                .unwrap(),
+        );
+        initiator_client.set_clock(Box::new(clock.clone()));
+        acceptor_client.set_clock(Box::new(clock.clone()));
+
+        let (initiator_handler, initiator_events) = handler(None);
+        let (acceptor_handler, acceptor_events) = handler(None);
+        let (_initiator_actions, initiator_action_rx) = channel();
+        let (_acceptor_actions, acceptor_action_rx) = channel();
+
+        let initiator = Session::new(initiator_client, initiator_handler, &initiator_action_rx);
+        let acceptor = Session::new(acceptor_client, acceptor_handler, &acceptor_action_rx);
+        let mut harness = LoopbackHarness::new(initiator, acceptor);
+        harness.run_until_idle().unwrap();
+        assert_eq!(initiator_events.try_recv(), Ok(Event::LoggedIn));
+        assert_eq!(acceptor_events.try_recv(), Ok(Event::LoggedIn));
+
+        // nothing sent for a heartbeat interval.
+        clock.advance(Duration::from_secs(30));
+        harness.run_until_idle().unwrap();
+        assert_eq!(initiator_events.try_recv(), Ok(Event::Heartbeat(None)));
+        assert_eq!(acceptor_events.try_recv(), Ok(Event::Heartbeat(None)));
+
+        // the initiator steps first and asks, the acceptor's handler answers.
+        clock.advance(Duration::from_secs(36));
+        harness.run_until_idle().unwrap();
+        assert_eq!(
+            initiator_events.try_recv(),
+            Ok(Event::Heartbeat(Some("TEST3".to_string())))
+        );
+        assert!(acceptor_events.try_recv().is_err());
+
+        // the acceptor no longer steps.
+        clock.advance(Duration::from_secs(72));
+        match harness.initiator.step() {
+            Err(SessionError::Io(err)) => assert_eq!(err.kind(), ErrorKind::TimedOut),
+            other => panic!("not timed out: {:?}", other),
+        }
+        assert_eq!(initiator_events.try_recv(), Ok(Event::Disconnected));
+    }
+
+    #[test]
+    fn test_dropped_peer_reads_as_eof() {
+        let (mut a, b) = loopback();
+        let mut buf = [0u8; 8];
+        assert_eq!(a.read(&mut buf).unwrap_err().kind(), ErrorKind::WouldBlock);
+
+        drop(b);
+        assert_eq!(a.read(&mut buf).unwrap(), 0);
+        assert_eq!(a.write(b"x").unwrap_err().kind(), ErrorKind::BrokenPipe);
+    }
+}
@@ -0,0 +1,446 @@
+//! Standard FIX market data messages, for subscribing to prices and keeping `MarketDataBooks`.
+//! Repeating groups are read with `FixDeserializeGroup`: fields an entry does not declare are skipped up to
+//! the first field of the next entry, only after the last entry do they end the group. A group with more or
+//! fewer entries than its count does not parse.
+
+use std::fmt;
+use std::fmt::Write;
+
+use detail::FixSerializable;
+use {FixParse, ParseError, Timestamp};
+
+/// MDEntryType (269), entry types other than those of a book and trades are kept as they are.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MDEntryType {
+    Bid,
+    Offer,
+    Trade,
+    Other(String),
+}
+
+impl FixParse for MDEntryType {
+    fn parse(value: &[u8]) -> Result<MDEntryType, ParseError> {
+        Ok(match value {
+            b"0" => MDEntryType::Bid,
+            b"1" => MDEntryType::Offer,
+            b"2" => MDEntryType::Trade,
+            _ => MDEntryType::Other(String::parse(value)?),
+        })
+    }
+}
+
+impl fmt::Display for MDEntryType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MDEntryType::Bid => write!(f, "0"),
+            MDEntryType::Offer => write!(f, "1"),
+            MDEntryType::Trade => write!(f, "2"),
+            MDEntryType::Other(ref value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum MDUpdateAction {
+    #[fix_value = "0"]
+    New,
+    #[fix_value = "1"]
+    Change,
+    #[fix_value = "2"]
+    Delete,
+}
+
+impl fmt::Display for MDUpdateAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MDUpdateAction::New => write!(f, "0"),
+            MDUpdateAction::Change => write!(f, "1"),
+            MDUpdateAction::Delete => write!(f, "2"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, FixParse)]
+pub enum SubscriptionRequestType {
+    #[fix_value = "0"]
+    Snapshot,
+    #[fix_value = "1"]
+    SnapshotAndUpdates,
+    #[fix_value = "2"]
+    Unsubscribe,
+}
+
+impl fmt::Display for SubscriptionRequestType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SubscriptionRequestType::Snapshot => write!(f, "0"),
+            SubscriptionRequestType::SnapshotAndUpdates => write!(f, "1"),
+            SubscriptionRequestType::Unsubscribe => write!(f, "2"),
+        }
+    }
+}
+
+/// An entry of NoMDEntryTypes (267).
+#[derive(Debug, Clone, PartialEq, FixDeserializeGroup)]
+pub struct RequestedEntryType {
+    #[id = "269"]
+    pub entry_type: MDEntryType,
+}
+
+/// An entry of NoRelatedSym (146).
+#[derive(Debug, Clone, PartialEq, FixDeserializeGroup)]
+pub struct RelatedSym {
+    #[id = "55"]
+    pub symbol: String,
+}
+
+// repeating groups can not be derived for serialization yet, hence the hand written bodies below.
+#[derive(Debug, Clone, PartialEq, FixHeader, FixDeserialize)]
+#[msg_type = "V"]
+pub struct MarketDataRequest {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "262"]
+    pub md_req_id: String,
+    #[id = "263"]
+    pub subscription_request_type: SubscriptionRequestType,
+    // 0 for the full book, 1 for the top of book.
+    #[id = "264"]
+    pub market_depth: u64,
+    // 0 full refresh, 1 incremental refresh.
+    #[id = "265"]
+    pub md_update_type: Option<u64>,
+    #[id = "267"]
+    pub entry_types: Vec<RequestedEntryType>,
+    #[id = "146"]
+    pub related_sym: Vec<RelatedSym>,
+}
+
+impl FixSerializable for MarketDataRequest {
+    fn serialize_body_to_fix(&self) -> String {
+        let mut out = String::from("35=V\x01");
+        header(&mut out, self.seq, &self.sender, &self.target, &self.sending_time);
+        write!(
+            out,
+            "262={}\x01263={}\x01264={}\x01",
+            self.md_req_id, self.subscription_request_type, self.market_depth
+        )
+        .unwrap();
+        optional(&mut out, 265, &self.md_update_type);
+        write!(out, "267={}\x01", self.entry_types.len()).unwrap();
+        for entry in &self.entry_types {
+            write!(out, "269={}\x01", entry.entry_type).unwrap();
+        }
+        write!(out, "146={}\x01", self.related_sym.len()).unwrap();
+        for related in &self.related_sym {
+            write!(out, "55={}\x01", related.symbol).unwrap();
+        }
+        out
+    }
+}
+
+/// An entry of NoMDEntries (268) of a snapshot.
+#[derive(Debug, Clone, PartialEq, FixDeserializeGroup)]
+pub struct SnapshotEntry {
+    #[id = "269"]
+    pub entry_type: MDEntryType,
+    #[id = "270"]
+    pub price: Option<f64>,
+    #[id = "15"]
+    pub currency: Option<String>,
+    #[id = "271"]
+    pub size: Option<f64>,
+    #[id = "272"]
+    pub date: Option<String>,
+    #[id = "273"]
+    pub time: Option<String>,
+    #[id = "275"]
+    pub market: Option<String>,
+    // set for the orders of an order level book.
+    #[id = "278"]
+    pub entry_id: Option<String>,
+    #[id = "290"]
+    pub position_no: Option<u64>,
+    #[id = "346"]
+    pub number_of_orders: Option<u64>,
+    #[id = "1023"]
+    pub price_level: Option<u64>,
+    #[id = "58"]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, FixHeader, FixDeserialize)]
+#[msg_type = "W"]
+pub struct MarketDataSnapshotFullRefresh {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "262"]
+    pub md_req_id: Option<String>,
+    #[id = "55"]
+    pub symbol: String,
+    // RptSeq (83) of the last incremental refresh the snapshot includes.
+    #[id = "83"]
+    pub rpt_seq: Option<u64>,
+    #[id = "268"]
+    pub entries: Vec<SnapshotEntry>,
+}
+
+impl FixSerializable for MarketDataSnapshotFullRefresh {
+    fn serialize_body_to_fix(&self) -> String {
+        let mut out = String::from("35=W\x01");
+        header(&mut out, self.seq, &self.sender, &self.target, &self.sending_time);
+        optional(&mut out, 262, &self.md_req_id);
+        write!(out, "55={}\x01", self.symbol).unwrap();
+        optional(&mut out, 83, &self.rpt_seq);
+        write!(out, "268={}\x01", self.entries.len()).unwrap();
+        for entry in &self.entries {
+            write!(out, "269={}\x01", entry.entry_type).unwrap();
+            optional(&mut out, 270, &entry.price);
+            optional(&mut out, 15, &entry.currency);
+            optional(&mut out, 271, &entry.size);
+            optional(&mut out, 272, &entry.date);
+            optional(&mut out, 273, &entry.time);
+            optional(&mut out, 275, &entry.market);
+            optional(&mut out, 278, &entry.entry_id);
+            optional(&mut out, 290, &entry.position_no);
+            optional(&mut out, 346, &entry.number_of_orders);
+            optional(&mut out, 1023, &entry.price_level);
+            optional(&mut out, 58, &entry.text);
+        }
+        out
+    }
+}
+
+/// An entry of NoMDEntries (268) of an incremental refresh.
+#[derive(Debug, Clone, PartialEq, FixDeserializeGroup)]
+pub struct IncrementalEntry {
+    #[id = "279"]
+    pub action: MDUpdateAction,
+    // may be left out of a Delete by MDEntryID.
+    #[id = "269"]
+    pub entry_type: Option<MDEntryType>,
+    #[id = "278"]
+    pub entry_id: Option<String>,
+    // the MDEntryID a Change gives a new one to.
+    #[id = "280"]
+    pub entry_ref_id: Option<String>,
+    #[id = "55"]
+    pub symbol: Option<String>,
+    #[id = "83"]
+    pub rpt_seq: Option<u64>,
+    #[id = "270"]
+    pub price: Option<f64>,
+    #[id = "15"]
+    pub currency: Option<String>,
+    #[id = "271"]
+    pub size: Option<f64>,
+    #[id = "272"]
+    pub date: Option<String>,
+    #[id = "273"]
+    pub time: Option<String>,
+    #[id = "275"]
+    pub market: Option<String>,
+    #[id = "290"]
+    pub position_no: Option<u64>,
+    #[id = "346"]
+    pub number_of_orders: Option<u64>,
+    #[id = "1023"]
+    pub price_level: Option<u64>,
+    #[id = "58"]
+    pub text: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, FixHeader, FixDeserialize)]
+#[msg_type = "X"]
+pub struct MarketDataIncrementalRefresh {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "262"]
+    pub md_req_id: Option<String>,
+    #[id = "268"]
+    pub entries: Vec<IncrementalEntry>,
+}
+
+impl FixSerializable for MarketDataIncrementalRefresh {
+    fn serialize_body_to_fix(&self) -> String {
+        let mut out = String::from("35=X\x01");
+        header(&mut out, self.seq, &self.sender, &self.target, &self.sending_time);
+        optional(&mut out, 262, &self.md_req_id);
+        write!(out, "268={}\x01", self.entries.len()).unwrap();
+        for entry in &self.entries {
+            write!(out, "279={}\x01", entry.action).unwrap();
+            optional(&mut out, 269, &entry.entry_type);
+            optional(&mut out, 278, &entry.entry_id);
+            optional(&mut out, 280, &entry.entry_ref_id);
+            optional(&mut out, 55, &entry.symbol);
+            optional(&mut out, 83, &entry.rpt_seq);
+            optional(&mut out, 270, &entry.price);
+            optional(&mut out, 15, &entry.currency);
+            optional(&mut out, 271, &entry.size);
+            optional(&mut out, 272, &entry.date);
+            optional(&mut out, 273, &entry.time);
+            optional(&mut out, 275, &entry.market);
+            optional(&mut out, 290, &entry.position_no);
+            optional(&mut out, 346, &entry.number_of_orders);
+            optional(&mut out, 1023, &entry.price_level);
+            optional(&mut out, 58, &entry.text);
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "Y"]
+pub struct MarketDataRequestReject {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+
+    #[id = "262"]
+    pub md_req_id: String,
+    #[id = "281"]
+    pub md_req_rej_reason: Option<String>,
+    #[id = "58"]
+    pub text: Option<String>,
+}
+
+fn header(out: &mut String, seq: u64, sender: &str, target: &str, sending_time: &Timestamp) {
+    write!(
+        out,
+        "34={}\x0149={}\x0156={}\x0152={}\x01",
+        seq, sender, target, sending_time
+    )
+    .unwrap();
+}
+
+fn optional<T: fmt::Display>(out: &mut String, id: u64, value: &Option<T>) {
+    if let Some(ref value) = *value {
+        write!(out, "{}={}\x01", id, value).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use serialization::frame;
+    use {deserialize, serialize};
+
+    fn entry(action: MDUpdateAction, entry_type: MDEntryType, price: f64, size: f64) -> IncrementalEntry {
+        IncrementalEntry {
+            action,
+            entry_type: Some(entry_type),
+            entry_id: None,
+            entry_ref_id: None,
+            symbol: Some("EURUSD".to_string()),
+            rpt_seq: Some(7),
+            price: Some(price),
+            currency: None,
+            size: Some(size),
+            date: None,
+            time: None,
+            market: None,
+            position_no: None,
+            number_of_orders: None,
+            price_level: None,
+            text: None,
+        }
+    }
+
+    #[test]
+    fn test_messages_with_groups_round_trip() {
+        let request = MarketDataRequest {
+            seq: 2,
+            sender: "us".to_string(),
+            target: "them".to_string(),
+            sending_time: Timestamp::now(),
+            md_req_id: "md1".to_string(),
+            subscription_request_type: SubscriptionRequestType::SnapshotAndUpdates,
+            market_depth: 0,
+            md_update_type: Some(1),
+            entry_types: vec![
+                RequestedEntryType {
+                    entry_type: MDEntryType::Bid,
+                },
+                RequestedEntryType {
+                    entry_type: MDEntryType::Offer,
+                },
+            ],
+            related_sym: vec![RelatedSym {
+                symbol: "EURUSD".to_string(),
+            }],
+        };
+        let serialized = serialize(&request);
+        assert!(serialized.contains("\x01267=2\x01269=0\x01269=1\x01146=1\x0155=EURUSD\x0110="));
+        let parsed: MarketDataRequest = deserialize(serialized.as_bytes()).unwrap();
+        assert_eq!(parsed.entry_types, request.entry_types);
+        assert_eq!(parsed.related_sym, request.related_sym);
+
+        let refresh = MarketDataIncrementalRefresh {
+            seq: 3,
+            sender: "them".to_string(),
+            target: "us".to_string(),
+            sending_time: Timestamp::now(),
+            md_req_id: Some("md1".to_string()),
+            entries: vec![
+                entry(MDUpdateAction::New, MDEntryType::Bid, 1.1, 5.0),
+                entry(MDUpdateAction::Delete, MDEntryType::Other("B".to_string()), 1.2, 0.0),
+            ],
+        };
+        let parsed: MarketDataIncrementalRefresh = deserialize(serialize(&refresh).as_bytes()).unwrap();
+        assert_eq!(parsed.entries, refresh.entries);
+    }
+
+    #[test]
+    fn test_fields_an_entry_does_not_declare_are_skipped() {
+        let body = "35=W\x0134=4\x0149=them\x0156=us\x0152=20240102-10:00:00\x0155=EURUSD\x0183=9\x01268=3\x01\
+                    269=0\x01270=1.1\x01271=5\x01\
+                    269=2\x01270=1.15\x01274=0\x0148=EU\x0122=8\x01336=1\x01271=2\x01277=A\x01\
+                    269=1\x01270=1.2\x01271=3\x01";
+        let parsed: MarketDataSnapshotFullRefresh = deserialize(frame(body).as_bytes()).unwrap();
+        let entries: Vec<(MDEntryType, Option<f64>, Option<f64>)> = parsed
+            .entries
+            .into_iter()
+            .map(|entry| (entry.entry_type, entry.price, entry.size))
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                (MDEntryType::Bid, Some(1.1), Some(5.0)),
+                (MDEntryType::Trade, Some(1.15), Some(2.0)),
+                (MDEntryType::Offer, Some(1.2), Some(3.0)),
+            ]
+        );
+
+        for count in &["1", "4"] {
+            let body = body.replace("268=3", &format!("268={}", count));
+            let parsed: Result<MarketDataSnapshotFullRefresh, _> = deserialize(frame(&body).as_bytes());
+            assert_eq!(parsed, Err("SnapshotEntry entries do not match the group count"));
+        }
+    }
+}
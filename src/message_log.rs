@@ -0,0 +1,362 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Error, Write};
+use std::path::{Path, PathBuf};
+use std::str;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+
+use CompIds;
+use PrettyPrinter;
+use Timestamp;
+use TimestampPrecision;
+
+/// Where a session writes the messages it sends and receives, and what happens to it.
+///
+/// Time is passed in by the `FixClient`, so logs follow its `Clock`.
+pub trait MessageLog {
+    fn on_incoming(&mut self, now: NaiveDateTime, msg: &[u8]);
+    fn on_outgoing(&mut self, now: NaiveDateTime, msg: &str);
+    fn on_event(&mut self, now: NaiveDateTime, text: &str);
+}
+
+/// Logs through the `log` crate: messages at info, events at warn.
+#[derive(Debug, Clone, Default)]
+pub struct LogMessageLog {
+    printer: Option<PrettyPrinter>,
+}
+
+impl LogMessageLog {
+    pub fn new() -> LogMessageLog {
+        LogMessageLog::default()
+    }
+
+    // logs messages with tag names instead of as sent.
+    pub fn pretty(printer: PrettyPrinter) -> LogMessageLog {
+        LogMessageLog { printer: Some(printer) }
+    }
+}
+
+impl MessageLog for LogMessageLog {
+    fn on_incoming(&mut self, _now: NaiveDateTime, msg: &[u8]) {
+        if let Some(ref printer) = self.printer {
+            info!("<< {}", printer.format(msg));
+            return;
+        }
+        match str::from_utf8(msg) {
+            Ok(as_str) => info!("<< {}", as_str),
+            Err(_) => error!("couldnt view rcv as utf8?"),
+        }
+    }
+
+    fn on_outgoing(&mut self, _now: NaiveDateTime, msg: &str) {
+        match self.printer {
+            Some(ref printer) => info!(">> {}", printer.format(msg.as_bytes())),
+            None => info!(">> {}", msg),
+        }
+    }
+
+    fn on_event(&mut self, _now: NaiveDateTime, text: &str) {
+        warn!("{}", text);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rotation {
+    Never,
+    // on the first line of a new UTC day.
+    Daily,
+    // before a file would grow beyond this many bytes.
+    MaxBytes(u64),
+}
+
+#[derive(Debug, Clone)]
+pub struct FileLogConfig {
+    pub directory: PathBuf,
+    // written in place of SOH, None keeps SOH like QuickFIX does.
+    pub soh: Option<String>,
+    pub rotation: Rotation,
+    pub timestamp_precision: TimestampPrecision,
+}
+
+impl Default for FileLogConfig {
+    fn default() -> FileLogConfig {
+        FileLogConfig {
+            directory: PathBuf::from("log"),
+            soh: None,
+            rotation: Rotation::Never,
+            timestamp_precision: TimestampPrecision::Millis,
+        }
+    }
+}
+
+/// Writes the QuickFIX file log: `FIX.4.4-SENDER-TARGET.messages.current.log` with both
+/// directions and `FIX.4.4-SENDER-TARGET.event.current.log`, each line being
+/// `YYYYMMDD-HH:MM:SS.sss : ` followed by the message or event.
+///
+/// Rotated files keep the same prefix and get the time of the rotation instead of `current`.
+/// Files are appended to, so a new `FileLog` per connection continues the same files.
+#[derive(Debug)]
+pub struct FileLog {
+    config: FileLogConfig,
+    messages: LogFile,
+    events: LogFile,
+}
+
+impl FileLog {
+    pub fn new(config: FileLogConfig, comp_ids: &CompIds, now: NaiveDateTime) -> Result<FileLog, Error> {
+        fs::create_dir_all(&config.directory)?;
+        let prefix = format!("FIX.4.4-{}-{}", comp_ids.sender, comp_ids.target);
+        let messages = LogFile::open(config.directory.join(format!("{}.messages", prefix)), now)?;
+        let events = LogFile::open(config.directory.join(format!("{}.event", prefix)), now)?;
+        Ok(FileLog {
+            config,
+            messages,
+            events,
+        })
+    }
+
+    pub fn messages_path(&self) -> PathBuf {
+        self.messages.current_path()
+    }
+
+    pub fn events_path(&self) -> PathBuf {
+        self.events.current_path()
+    }
+
+    fn line(&self, now: NaiveDateTime, text: &str) -> String {
+        let timestamp = Timestamp::new(now).with_precision(self.config.timestamp_precision);
+        let text = match self.config.soh {
+            Some(ref soh) => text.replace('\x01', soh),
+            None => text.to_string(),
+        };
+        format!("{} : {}\n", timestamp, text)
+    }
+
+    fn write_message(&mut self, now: NaiveDateTime, msg: &str) {
+        let line = self.line(now, msg);
+        if let Err(err) = self.messages.write(now, &line, self.config.rotation) {
+            error!(
+                "failed to write message log {:?}: {:?}",
+                self.messages.current_path(),
+                err
+            );
+        }
+    }
+}
+
+impl MessageLog for FileLog {
+    fn on_incoming(&mut self, now: NaiveDateTime, msg: &[u8]) {
+        self.write_message(now, &String::from_utf8_lossy(msg));
+    }
+
+    fn on_outgoing(&mut self, now: NaiveDateTime, msg: &str) {
+        self.write_message(now, msg);
+    }
+
+    fn on_event(&mut self, now: NaiveDateTime, text: &str) {
+        let line = self.line(now, text);
+        if let Err(err) = self.events.write(now, &line, self.config.rotation) {
+            error!("failed to write event log {:?}: {:?}", self.events.current_path(), err);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LogFile {
+    // e.g. log/FIX.4.4-A-B.messages, completed with `.current.log`.
+    base: PathBuf,
+    file: File,
+    size: u64,
+    day: NaiveDate,
+}
+
+impl LogFile {
+    fn open(base: PathBuf, now: NaiveDateTime) -> Result<LogFile, Error> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(with_suffix(&base, "current"))?;
+        let metadata = file.metadata()?;
+        // a file kept from before a restart belongs to the day it was last written.
+        let day = match metadata.modified() {
+            Ok(modified) if metadata.len() > 0 => DateTime::<Utc>::from(modified).naive_utc().date(),
+            _ => now.date(),
+        };
+        Ok(LogFile {
+            base,
+            file,
+            size: metadata.len(),
+            day,
+        })
+    }
+
+    fn current_path(&self) -> PathBuf {
+        with_suffix(&self.base, "current")
+    }
+
+    fn write(&mut self, now: NaiveDateTime, line: &str, rotation: Rotation) -> Result<(), Error> {
+        let rotate = match rotation {
+            Rotation::Never => false,
+            Rotation::Daily => now.date() != self.day,
+            Rotation::MaxBytes(max) => self.size > 0 && self.size + line.len() as u64 > max,
+        };
+        if rotate {
+            self.rotate(now)?;
+        }
+
+        self.file.write_all(line.as_bytes())?;
+        self.file.flush()?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    fn rotate(&mut self, now: NaiveDateTime) -> Result<(), Error> {
+        let stamp = now.format("%Y%m%d-%H%M%S").to_string();
+        let mut rotated = with_suffix(&self.base, &stamp);
+        let mut n = 1;
+        while rotated.exists() {
+            rotated = with_suffix(&self.base, &format!("{}.{}", stamp, n));
+            n += 1;
+        }
+        fs::rename(self.current_path(), &rotated)?;
+        *self = LogFile::open(self.base.clone(), now)?;
+        Ok(())
+    }
+}
+
+fn with_suffix(base: &Path, suffix: &str) -> PathBuf {
+    let mut name = base.as_os_str().to_owned();
+    name.push(format!(".{}.log", suffix));
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+    use chrono;
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 1, day)
+            .unwrap()
+            .and_hms_opt(hour, 0, 0)
+            .unwrap()
+    }
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = env::temp_dir().join(format!("profix-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn comp_ids() -> CompIds {
+        CompIds {
+            sender: "A".to_string(),
+            target: "B".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_quickfix_format() {
+        let config = FileLogConfig {
+            directory: directory("format"),
+            soh: Some("|".to_string()),
+            ..FileLogConfig::default()
+        };
+        let mut log = FileLog::new(config.clone(), &comp_ids(), at(1, 0)).unwrap();
+        log.on_outgoing(at(1, 0), "8=FIX.4.4\x019=5\x0135=0\x0110=000\x01");
+        log.on_incoming(at(1, 1), b"8=FIX.4.4\x019=5\x0135=0\x0110=000\x01");
+        log.on_event(at(1, 2), "Logon sent");
+
+        let messages = fs::read_to_string(config.directory.join("FIX.4.4-A-B.messages.current.log")).unwrap();
+        assert_eq!(
+            messages,
+            "20200101-00:00:00.000 : 8=FIX.4.4|9=5|35=0|10=000|\n\
+             20200101-01:00:00.000 : 8=FIX.4.4|9=5|35=0|10=000|\n"
+        );
+        let events = fs::read_to_string(log.events_path()).unwrap();
+        assert_eq!(events, "20200101-02:00:00.000 : Logon sent\n");
+
+        // a new log for the next connection appends.
+        let mut log = FileLog::new(config.clone(), &comp_ids(), at(1, 3)).unwrap();
+        log.on_event(at(1, 3), "Logon sent");
+        assert_eq!(fs::read_to_string(log.events_path()).unwrap().lines().count(), 2);
+        fs::remove_dir_all(&config.directory).unwrap();
+    }
+
+    #[test]
+    fn test_rotation() {
+        let config = FileLogConfig {
+            directory: directory("rotation"),
+            rotation: Rotation::Daily,
+            ..FileLogConfig::default()
+        };
+        let mut log = FileLog::new(config.clone(), &comp_ids(), at(1, 23)).unwrap();
+        log.on_event(at(1, 23), "first day");
+        log.on_event(at(2, 0), "second day");
+        let rotated = config.directory.join("FIX.4.4-A-B.event.20200102-000000.log");
+        assert_eq!(
+            fs::read_to_string(&rotated).unwrap(),
+            "20200101-23:00:00.000 : first day\n"
+        );
+        assert_eq!(
+            fs::read_to_string(log.events_path()).unwrap(),
+            "20200102-00:00:00.000 : second day\n"
+        );
+
+        let config = FileLogConfig {
+            rotation: Rotation::MaxBytes(64),
+            ..config
+        };
+        let mut log = FileLog::new(config.clone(), &comp_ids(), at(3, 0)).unwrap();
+        let mut now = at(3, 0);
+        for _ in 0..4 {
+            log.on_outgoing(now, "8=FIX.4.4\x019=5\x0135=0\x0110=000\x01");
+            now += chrono::Duration::from_std(Duration::from_secs(1)).unwrap();
+        }
+        let files = fs::read_dir(&config.directory)
+            .unwrap()
+            .filter(|entry| {
+                let name = entry.as_ref().unwrap().file_name();
+                name.to_string_lossy().contains(".messages.")
+            })
+            .count();
+        // a line is 51 bytes, so every file holds one.
+        assert_eq!(files, 4);
+        fs::remove_dir_all(&config.directory).unwrap();
+    }
+
+    #[test]
+    fn test_daily_rotation_after_restart() {
+        let config = FileLogConfig {
+            directory: directory("restart"),
+            rotation: Rotation::Daily,
+            ..FileLogConfig::default()
+        };
+        let mut log = FileLog::new(config.clone(), &comp_ids(), at(1, 23)).unwrap();
+        log.on_event(at(1, 23), "before restart");
+        let first_day = UNIX_EPOCH + Duration::from_secs(at(1, 23).and_utc().timestamp() as u64);
+        File::options()
+            .write(true)
+            .open(log.events_path())
+            .unwrap()
+            .set_modified(first_day)
+            .unwrap();
+        drop(log);
+
+        let mut log = FileLog::new(config.clone(), &comp_ids(), at(2, 1)).unwrap();
+        log.on_event(at(2, 1), "after restart");
+        let rotated = config.directory.join("FIX.4.4-A-B.event.20200102-010000.log");
+        assert_eq!(
+            fs::read_to_string(&rotated).unwrap(),
+            "20200101-23:00:00.000 : before restart\n"
+        );
+        assert_eq!(
+            fs::read_to_string(log.events_path()).unwrap(),
+            "20200102-01:00:00.000 : after restart\n"
+        );
+        fs::remove_dir_all(&config.directory).unwrap();
+    }
+}
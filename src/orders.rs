@@ -0,0 +1,782 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use interceptor::Interceptor;
+use {RawMessage, Recorded};
+
+const NEW_ORDER_SINGLE: &str = "D";
+const ORDER_CANCEL_REQUEST: &str = "F";
+const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+const EXECUTION_REPORT: &str = "8";
+const ORDER_CANCEL_REJECT: &str = "9";
+
+const CL_ORD_ID: u64 = 11;
+const CUM_QTY: u64 = 14;
+const EXEC_ID: u64 = 17;
+const LAST_PX: u64 = 31;
+const LAST_QTY: u64 = 32;
+const ORDER_ID: u64 = 37;
+const ORDER_QTY: u64 = 38;
+const ORD_STATUS: u64 = 39;
+const ORIG_CL_ORD_ID: u64 = 41;
+const POSS_DUP_FLAG: u64 = 43;
+const PRICE: u64 = 44;
+const SIDE: u64 = 54;
+const SYMBOL: u64 = 55;
+const TEXT: u64 = 58;
+const EXEC_TYPE: u64 = 150;
+const LEAVES_QTY: u64 = 151;
+
+// quantities closer than this are the same.
+const QTY_EPSILON: f64 = 1e-9;
+
+/// Where an order is in its lifecycle, after OrdStatus (39).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OrderState {
+    PendingNew,
+    New,
+    PartiallyFilled,
+    Filled,
+    DoneForDay,
+    PendingCancel,
+    Cancelled,
+    PendingReplace,
+    Stopped,
+    Suspended,
+    Rejected,
+    Expired,
+}
+
+impl OrderState {
+    fn from_ord_status(ord_status: &str) -> Option<OrderState> {
+        Some(match ord_status {
+            "0" => OrderState::New,
+            "1" => OrderState::PartiallyFilled,
+            "2" => OrderState::Filled,
+            "3" => OrderState::DoneForDay,
+            "4" => OrderState::Cancelled,
+            "6" => OrderState::PendingCancel,
+            "7" => OrderState::Stopped,
+            "8" => OrderState::Rejected,
+            "9" => OrderState::Suspended,
+            "A" => OrderState::PendingNew,
+            "C" => OrderState::Expired,
+            "E" => OrderState::PendingReplace,
+            _ => return None,
+        })
+    }
+
+    // no report may move the order on from here.
+    pub fn is_terminal(self) -> bool {
+        matches!(
+            self,
+            OrderState::Filled | OrderState::Cancelled | OrderState::Rejected | OrderState::Expired
+        )
+    }
+
+    // whether a report may move the order from here to `next`, after the OrdStatus transitions of the
+    // FIX specification. A report restating the state, e.g. an order status, is no transition.
+    fn may_become(self, next: OrderState) -> bool {
+        use self::OrderState::*;
+
+        if self == next {
+            return true;
+        }
+        match self {
+            Filled | Cancelled | Rejected | Expired => false,
+            PendingNew => matches!(next, New | PartiallyFilled | Filled | Rejected | Cancelled | Expired),
+            // an order may still be rejected after it was acknowledged.
+            New => !matches!(next, PendingNew),
+            PartiallyFilled => !matches!(next, PendingNew | New | Rejected),
+            // executed or cancelled meanwhile, or back to where it was on a cancel reject.
+            PendingCancel => matches!(next, New | PartiallyFilled | Filled | Cancelled | Expired),
+            // replaced or back to where it was on a cancel reject, a cancel may follow the replace.
+            PendingReplace => matches!(
+                next,
+                New | PartiallyFilled | Filled | Cancelled | Expired | PendingCancel
+            ),
+            // until the next trading day.
+            DoneForDay => matches!(
+                next,
+                New | PartiallyFilled | Cancelled | Expired | PendingCancel | PendingReplace
+            ),
+            Stopped => matches!(next, PartiallyFilled | Filled | Cancelled | Expired),
+            Suspended => matches!(
+                next,
+                New | PartiallyFilled | Cancelled | Expired | PendingCancel | PendingReplace
+            ),
+        }
+    }
+}
+
+impl fmt::Display for OrderState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match *self {
+            OrderState::PendingNew => "PendingNew",
+            OrderState::New => "New",
+            OrderState::PartiallyFilled => "PartiallyFilled",
+            OrderState::Filled => "Filled",
+            OrderState::DoneForDay => "DoneForDay",
+            OrderState::PendingCancel => "PendingCancel",
+            OrderState::Cancelled => "Cancelled",
+            OrderState::PendingReplace => "PendingReplace",
+            OrderState::Stopped => "Stopped",
+            OrderState::Suspended => "Suspended",
+            OrderState::Rejected => "Rejected",
+            OrderState::Expired => "Expired",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// An execution of an order, from an ExecutionReport with ExecType (150) Trade.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+    pub exec_id: String,
+    pub qty: f64,
+    pub price: f64,
+}
+
+/// An order as an `OrderManager` knows it, by the ClOrdID (11) it was last given.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrackedOrder {
+    pub cl_ord_id: String,
+    // ClOrdIDs the order was known under before it was replaced, oldest first.
+    pub previous_cl_ord_ids: Vec<String>,
+    pub order_id: Option<String>,
+    pub symbol: String,
+    pub side: String,
+    pub qty: f64,
+    pub price: Option<f64>,
+    pub state: OrderState,
+    pub cum_qty: f64,
+    pub leaves_qty: f64,
+    pub fills: Vec<Fill>,
+    // Text (58) of the last report, e.g. why the order was rejected.
+    pub text: Option<String>,
+}
+
+impl TrackedOrder {
+    // volume weighted price of the fills.
+    pub fn avg_price(&self) -> Option<f64> {
+        let qty: f64 = self.fills.iter().map(|fill| fill.qty).sum();
+        if qty <= 0.0 {
+            return None;
+        }
+        Some(self.fills.iter().map(|fill| fill.qty * fill.price).sum::<f64>() / qty)
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.state.is_terminal()
+    }
+}
+
+/// Why an `OrderManager` did not take a message as it is.
+#[derive(Debug, Clone, PartialEq)]
+pub enum OrderError {
+    // a required field is missing or does not parse.
+    Malformed(String),
+    // a NewOrderSingle with the ClOrdID of an order known already.
+    DuplicateClOrdId(String),
+    // neither ClOrdID (11) nor OrigClOrdID (41) of the message are known.
+    UnknownOrder(String),
+    // ExecID (17) seen before, e.g. a report received again as PossDup. It is ignored.
+    DuplicateExecution(String),
+    // the report can not follow the state the order is in. It is ignored.
+    InvalidTransition {
+        cl_ord_id: String,
+        from: OrderState,
+        to: OrderState,
+    },
+    // CumQty (14) of a fill does not add up with the fills seen before, a report was missed or came
+    // out of order. The fill is taken regardless, with the CumQty and LeavesQty of its report.
+    FillOutOfOrder {
+        cl_ord_id: String,
+        expected_cum_qty: f64,
+        cum_qty: f64,
+    },
+}
+
+impl fmt::Display for OrderError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            OrderError::Malformed(ref reason) => write!(f, "malformed: {}", reason),
+            OrderError::DuplicateClOrdId(ref id) => write!(f, "order {} exists already", id),
+            OrderError::UnknownOrder(ref id) => write!(f, "unknown order {}", id),
+            OrderError::DuplicateExecution(ref id) => write!(f, "execution {} seen before", id),
+            OrderError::InvalidTransition {
+                ref cl_ord_id,
+                from,
+                to,
+            } => write!(f, "order {} can not go from {} to {}", cl_ord_id, from, to),
+            OrderError::FillOutOfOrder {
+                ref cl_ord_id,
+                expected_cum_qty,
+                cum_qty,
+            } => write!(
+                f,
+                "order {} filled {} expected {}, a fill is missing or out of order",
+                cl_ord_id, cum_qty, expected_cum_qty
+            ),
+        }
+    }
+}
+
+/// Order states built from the orders and ExecutionReports (8) of a session, keyed by ClOrdID (11).
+///
+/// Tracks NewOrderSingle (D), OrderCancelRequest (F), OrderCancelReplaceRequest (G), ExecutionReport
+/// and OrderCancelReject (9) in whichever direction they go, so it serves the side sending orders as
+/// well as the one executing them. Add a clone to a `FixClient` as an `Interceptor` to see every
+/// message written and received, errors are then logged, or feed messages to `apply` yourself.
+/// Clones share the orders.
+///
+/// After a restart `restore` rebuilds the orders from the messages a `FileLog` recorded. A
+/// `MessageStore` can not do that: it keeps the messages sent only, and as it keeps the next target
+/// seq too the reports received before the restart are not resent.
+#[derive(Clone, Default)]
+pub struct OrderManager {
+    state: Arc<Mutex<Orders>>,
+}
+
+#[derive(Default)]
+struct Orders {
+    orders: HashMap<String, TrackedOrder>,
+    // ClOrdIDs of replaced orders and of cancel requests, to the ClOrdID the order is tracked under.
+    aliases: HashMap<String, String>,
+    // replaces sent and not yet confirmed, by their new ClOrdID: the OrderQty and Price.
+    replacing: HashMap<String, (Option<f64>, Option<f64>)>,
+    exec_ids: HashSet<String>,
+}
+
+impl OrderManager {
+    pub fn new() -> OrderManager {
+        OrderManager::default()
+    }
+
+    // takes a message of either direction into account, messages other than orders and reports are
+    // ignored. Returns the ClOrdID of the order concerned.
+    pub fn apply(&self, msg: &RawMessage) -> Result<Option<String>, OrderError> {
+        let mut orders = self.state.lock().unwrap();
+        match msg.msg_type() {
+            NEW_ORDER_SINGLE => orders.on_new_order(msg).map(Some),
+            ORDER_CANCEL_REQUEST => orders.on_cancel_request(msg).map(Some),
+            ORDER_CANCEL_REPLACE_REQUEST => orders.on_replace_request(msg).map(Some),
+            EXECUTION_REPORT => orders.on_execution_report(msg).map(Some),
+            ORDER_CANCEL_REJECT => orders.on_cancel_reject(msg).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    // applies the messages of a recorded session in both directions, oldest first, e.g. those `read_log`
+    // reads from its `FileLog` on a restart. Resent duplicates are skipped, returns the other errors.
+    pub fn restore(&self, recorded: &[Recorded]) -> Vec<OrderError> {
+        let mut errors = Vec::new();
+        for (index, recorded) in recorded.iter().enumerate() {
+            let msg = match RawMessage::parse(&recorded.msg) {
+                Ok(msg) => msg,
+                Err(err) => {
+                    errors.push(OrderError::Malformed(format!("recorded message {}: {}", index, err)));
+                    continue;
+                }
+            };
+            match self.apply(&msg) {
+                Err(OrderError::DuplicateClOrdId(_)) | Err(OrderError::DuplicateExecution(_))
+                    if msg.get(POSS_DUP_FLAG) == Some("Y") => {}
+                Err(err) => errors.push(err),
+                Ok(_) => {}
+            }
+        }
+        errors
+    }
+
+    // the order by its ClOrdID or any it was known under before.
+    pub fn order(&self, cl_ord_id: &str) -> Option<TrackedOrder> {
+        let orders = self.state.lock().unwrap();
+        orders.key_of(cl_ord_id).map(|key| orders.orders[&key].clone())
+    }
+
+    pub fn orders(&self) -> Vec<TrackedOrder> {
+        let mut orders: Vec<TrackedOrder> = self.state.lock().unwrap().orders.values().cloned().collect();
+        orders.sort_by(|a, b| a.cl_ord_id.cmp(&b.cl_ord_id));
+        orders
+    }
+
+    pub fn open_orders(&self) -> Vec<TrackedOrder> {
+        self.orders().into_iter().filter(TrackedOrder::is_open).collect()
+    }
+
+    // fills of every order, by order and in the order they were reported.
+    pub fn fills(&self) -> Vec<(String, Fill)> {
+        self.orders()
+            .into_iter()
+            .flat_map(|order| {
+                let cl_ord_id = order.cl_ord_id;
+                order.fills.into_iter().map(move |fill| (cl_ord_id.clone(), fill))
+            })
+            .collect()
+    }
+}
+
+// only what was written and what the handler gets counts, not what a later interceptor refused.
+impl Interceptor for OrderManager {
+    fn written(&mut self, msg: &RawMessage) {
+        if let Err(err) = self.apply(msg) {
+            warn!("order tracking, sent {}: {}", msg.msg_type(), err);
+        }
+    }
+
+    fn received(&mut self, msg: &RawMessage) {
+        if let Err(err) = self.apply(msg) {
+            warn!("order tracking, received {}: {}", msg.msg_type(), err);
+        }
+    }
+}
+
+impl Orders {
+    fn key_of(&self, cl_ord_id: &str) -> Option<String> {
+        if self.orders.contains_key(cl_ord_id) {
+            return Some(cl_ord_id.to_string());
+        }
+        self.aliases.get(cl_ord_id).cloned()
+    }
+
+    // the order a message refers to by ClOrdID, or OrigClOrdID as a cancel or replace does.
+    fn find(&self, msg: &RawMessage) -> Result<String, OrderError> {
+        let cl_ord_id = required(msg, CL_ORD_ID)?;
+        self.key_of(cl_ord_id)
+            .or_else(|| msg.get(ORIG_CL_ORD_ID).and_then(|orig| self.key_of(orig)))
+            .ok_or_else(|| OrderError::UnknownOrder(cl_ord_id.to_string()))
+    }
+
+    fn on_new_order(&mut self, msg: &RawMessage) -> Result<String, OrderError> {
+        let cl_ord_id = required(msg, CL_ORD_ID)?.to_string();
+        if self.key_of(&cl_ord_id).is_some() {
+            return Err(OrderError::DuplicateClOrdId(cl_ord_id));
+        }
+        let qty = quantity(msg, ORDER_QTY)?.unwrap_or(0.0);
+        let order = TrackedOrder {
+            cl_ord_id: cl_ord_id.clone(),
+            previous_cl_ord_ids: Vec::new(),
+            order_id: None,
+            symbol: required(msg, SYMBOL)?.to_string(),
+            side: required(msg, SIDE)?.to_string(),
+            qty,
+            price: quantity(msg, PRICE)?,
+            state: OrderState::PendingNew,
+            cum_qty: 0.0,
+            leaves_qty: qty,
+            fills: Vec::new(),
+            text: None,
+        };
+        self.orders.insert(cl_ord_id.clone(), order);
+        Ok(cl_ord_id)
+    }
+
+    fn on_cancel_request(&mut self, msg: &RawMessage) -> Result<String, OrderError> {
+        let key = self.find(msg)?;
+        self.aliases.insert(required(msg, CL_ORD_ID)?.to_string(), key.clone());
+        Ok(key)
+    }
+
+    fn on_replace_request(&mut self, msg: &RawMessage) -> Result<String, OrderError> {
+        let key = self.find(msg)?;
+        let cl_ord_id = required(msg, CL_ORD_ID)?.to_string();
+        let replace = (quantity(msg, ORDER_QTY)?, quantity(msg, PRICE)?);
+        self.aliases.insert(cl_ord_id.clone(), key.clone());
+        self.replacing.insert(cl_ord_id, replace);
+        Ok(key)
+    }
+
+    fn on_cancel_reject(&mut self, msg: &RawMessage) -> Result<String, OrderError> {
+        let key = self.find(msg)?;
+        self.replacing.remove(required(msg, CL_ORD_ID)?);
+        let order = self.orders.get_mut(&key).expect("found above");
+        // the order goes back to the state it was in before the pending cancel or replace.
+        if let Some(state) = msg.get(ORD_STATUS).and_then(OrderState::from_ord_status) {
+            if order.state.may_become(state) {
+                order.state = state;
+            }
+        }
+        order.text = msg.get(TEXT).map(str::to_string);
+        Ok(key)
+    }
+
+    fn on_execution_report(&mut self, msg: &RawMessage) -> Result<String, OrderError> {
+        let exec_id = required(msg, EXEC_ID)?.to_string();
+        if self.exec_ids.contains(&exec_id) {
+            return Err(OrderError::DuplicateExecution(exec_id));
+        }
+        let ord_status = required(msg, ORD_STATUS)?;
+        let state = OrderState::from_ord_status(ord_status)
+            .ok_or_else(|| OrderError::Malformed(format!("unknown OrdStatus {}", ord_status)))?;
+        let exec_type = required(msg, EXEC_TYPE)?;
+        let mut key = self.find(msg)?;
+
+        let cl_ord_id = required(msg, CL_ORD_ID)?.to_string();
+        let replaced = exec_type == "5" && cl_ord_id != key;
+        {
+            let order = &self.orders[&key];
+            if !replaced && !order.state.may_become(state) {
+                return Err(OrderError::InvalidTransition {
+                    cl_ord_id: order.cl_ord_id.clone(),
+                    from: order.state,
+                    to: state,
+                });
+            }
+        }
+        let last_qty = quantity(msg, LAST_QTY)?.unwrap_or(0.0);
+        let fill = if exec_type == "F" || exec_type == "1" || exec_type == "2" {
+            let price = quantity(msg, LAST_PX)?.ok_or_else(|| OrderError::Malformed("no LastPx (31)".to_string()))?;
+            Some(Fill {
+                exec_id: exec_id.clone(),
+                qty: last_qty,
+                price,
+            })
+        } else {
+            None
+        };
+        let cum_qty = quantity(msg, CUM_QTY)?;
+        let leaves_qty = quantity(msg, LEAVES_QTY)?;
+        self.exec_ids.insert(exec_id);
+
+        // a replaced order goes on under the ClOrdID of the replace.
+        if replaced {
+            let mut order = self.orders.remove(&key).expect("found above");
+            if let Some((qty, price)) = self.replacing.remove(&cl_ord_id) {
+                order.qty = qty.unwrap_or(order.qty);
+                order.price = price.or(order.price);
+            }
+            let previous = ::std::mem::replace(&mut order.cl_ord_id, cl_ord_id.clone());
+            for alias in self.aliases.values_mut().filter(|alias| **alias == previous) {
+                *alias = cl_ord_id.clone();
+            }
+            self.aliases.insert(previous.clone(), cl_ord_id.clone());
+            self.aliases.remove(&cl_ord_id);
+            order.previous_cl_ord_ids.push(previous);
+            self.orders.insert(cl_ord_id.clone(), order);
+            key = cl_ord_id;
+        }
+
+        let order = self.orders.get_mut(&key).expect("found above");
+        order.state = state;
+        if let Some(order_id) = msg.get(ORDER_ID) {
+            order.order_id = Some(order_id.to_string());
+        }
+        if let Ok(Some(qty)) = quantity(msg, ORDER_QTY) {
+            order.qty = qty;
+        }
+        if let Ok(Some(price)) = quantity(msg, PRICE) {
+            order.price = Some(price);
+        }
+        order.text = msg.get(TEXT).map(str::to_string);
+
+        let mut out_of_order = None;
+        if let Some(fill) = fill {
+            let expected_cum_qty = order.cum_qty + fill.qty;
+            order.cum_qty = expected_cum_qty;
+            order.fills.push(fill);
+            if let Some(cum_qty) = cum_qty {
+                if (cum_qty - expected_cum_qty).abs() > QTY_EPSILON {
+                    out_of_order = Some(OrderError::FillOutOfOrder {
+                        cl_ord_id: key.clone(),
+                        expected_cum_qty,
+                        cum_qty,
+                    });
+                }
+            }
+        }
+        if let Some(cum_qty) = cum_qty {
+            order.cum_qty = cum_qty;
+        }
+        order.leaves_qty = match leaves_qty {
+            Some(leaves_qty) => leaves_qty,
+            None if order.state.is_terminal() => 0.0,
+            None => (order.qty - order.cum_qty).max(0.0),
+        };
+
+        match out_of_order {
+            Some(err) => Err(err),
+            None => Ok(key),
+        }
+    }
+}
+
+fn required(msg: &RawMessage, id: u64) -> Result<&str, OrderError> {
+    msg.get(id)
+        .ok_or_else(|| OrderError::Malformed(format!("no field {} in {}", id, msg.msg_type())))
+}
+
+fn quantity(msg: &RawMessage, id: u64) -> Result<Option<f64>, OrderError> {
+    msg.parsed(id)
+        .map_err(|err| OrderError::Malformed(format!("field {}: {}", id, err)))
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::BufReader;
+    use std::process;
+    use std::sync::mpsc::channel;
+
+    use super::*;
+    use testing::{self, header, message, sent, AppMsg, TestHandler};
+    use {
+        loopback, read_log, serialize, Direction, FileLog, FileLogConfig, FixClient, HandleErr, MemoryStore,
+        MessageStore, Session, Timestamp,
+    };
+
+    fn new_order(cl_ord_id: &str, qty: &str) -> RawMessage {
+        message(
+            "D",
+            &[(11, cl_ord_id), (55, "EURUSD"), (54, "1"), (38, qty), (44, "1.1")],
+        )
+    }
+
+    fn report(
+        cl_ord_id: &str,
+        exec_id: &str,
+        exec_type: &str,
+        ord_status: &str,
+        fill: Option<(&str, &str, &str)>,
+    ) -> RawMessage {
+        let mut report = message(
+            "8",
+            &[
+                (37, "X1"),
+                (11, cl_ord_id),
+                (17, exec_id),
+                (150, exec_type),
+                (39, ord_status),
+            ],
+        );
+        if let Some((last_qty, last_px, cum_qty)) = fill {
+            report.set(32, last_qty);
+            report.set(31, last_px);
+            report.set(14, cum_qty);
+        }
+        report
+    }
+
+    #[test]
+    fn test_lifecycle_with_replace_and_fills() {
+        let manager = OrderManager::new();
+        manager.apply(&new_order("1", "100")).unwrap();
+        assert_eq!(manager.order("1").unwrap().state, OrderState::PendingNew);
+
+        manager.apply(&report("1", "e1", "0", "0", None)).unwrap();
+        manager
+            .apply(&report("1", "e2", "F", "1", Some(("40", "1.1", "40"))))
+            .unwrap();
+
+        let replace = message(
+            "G",
+            &[(11, "2"), (41, "1"), (55, "EURUSD"), (54, "1"), (38, "80"), (44, "1.2")],
+        );
+        manager.apply(&replace).unwrap();
+        let mut replaced = report("2", "e3", "5", "1", None);
+        replaced.set(41, "1");
+        manager.apply(&replaced).unwrap();
+
+        let order = manager.order("1").unwrap();
+        assert_eq!(order.cl_ord_id, "2");
+        assert_eq!(order.previous_cl_ord_ids, vec!["1".to_string()]);
+        assert_eq!(order.order_id, Some("X1".to_string()));
+        assert_eq!((order.qty, order.price), (80.0, Some(1.2)));
+        assert!((order.leaves_qty - 40.0).abs() < QTY_EPSILON);
+
+        manager
+            .apply(&report("2", "e4", "F", "2", Some(("40", "1.2", "80"))))
+            .unwrap();
+        let order = manager.order("2").unwrap();
+        assert_eq!(order.state, OrderState::Filled);
+        assert_eq!(order.leaves_qty, 0.0);
+        assert!((order.avg_price().unwrap() - 1.15).abs() < 1e-9);
+        assert_eq!(manager.fills().len(), 2);
+        assert!(manager.open_orders().is_empty());
+
+        let cancel = message("F", &[(11, "3"), (41, "2")]);
+        manager.apply(&cancel).unwrap();
+        let mut cancelled = report("3", "e5", "4", "4", None);
+        cancelled.set(41, "2");
+        assert_eq!(
+            manager.apply(&cancelled),
+            Err(OrderError::InvalidTransition {
+                cl_ord_id: "2".to_string(),
+                from: OrderState::Filled,
+                to: OrderState::Cancelled,
+            })
+        );
+    }
+
+    #[test]
+    fn test_duplicate_and_out_of_order_fills() {
+        let manager = OrderManager::new();
+        manager.apply(&new_order("1", "100")).unwrap();
+        assert_eq!(
+            manager.apply(&new_order("1", "100")),
+            Err(OrderError::DuplicateClOrdId("1".to_string()))
+        );
+        manager.apply(&report("1", "e1", "0", "0", None)).unwrap();
+
+        let fill = report("1", "e2", "F", "1", Some(("10", "1.1", "10")));
+        manager.apply(&fill).unwrap();
+        assert_eq!(
+            manager.apply(&fill),
+            Err(OrderError::DuplicateExecution("e2".to_string()))
+        );
+        assert_eq!(manager.order("1").unwrap().cum_qty, 10.0);
+
+        // the fill of e3 never came.
+        let result = manager.apply(&report("1", "e4", "F", "1", Some(("10", "1.1", "30"))));
+        assert_eq!(
+            result,
+            Err(OrderError::FillOutOfOrder {
+                cl_ord_id: "1".to_string(),
+                expected_cum_qty: 20.0,
+                cum_qty: 30.0,
+            })
+        );
+        let order = manager.order("1").unwrap();
+        assert_eq!((order.cum_qty, order.leaves_qty), (30.0, 70.0));
+
+        assert_eq!(
+            manager.apply(&report("9", "e5", "0", "0", None)),
+            Err(OrderError::UnknownOrder("9".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_only_ord_status_transitions_are_taken() {
+        use super::OrderState::*;
+
+        let all = [
+            PendingNew,
+            New,
+            PartiallyFilled,
+            Filled,
+            DoneForDay,
+            PendingCancel,
+            Cancelled,
+            PendingReplace,
+            Stopped,
+            Suspended,
+            Rejected,
+            Expired,
+        ];
+        for &terminal in &[Filled, Cancelled, Rejected, Expired] {
+            for &next in all.iter().filter(|&&next| next != terminal) {
+                assert!(!terminal.may_become(next), "{:?} to {:?}", terminal, next);
+            }
+        }
+        for &state in &all {
+            assert_eq!(state.may_become(PendingNew), state == PendingNew, "{:?}", state);
+        }
+        assert!(PendingCancel.may_become(Cancelled));
+        assert!(PendingCancel.may_become(PartiallyFilled));
+        assert!(!PendingCancel.may_become(PendingReplace));
+        assert!(!PendingCancel.may_become(Rejected));
+        assert!(PendingReplace.may_become(New));
+        assert!(PendingReplace.may_become(PendingCancel));
+        assert!(!PendingReplace.may_become(Rejected));
+        assert!(!PartiallyFilled.may_become(New));
+
+        let manager = OrderManager::new();
+        manager.apply(&new_order("1", "100")).unwrap();
+        manager.apply(&report("1", "e1", "0", "0", None)).unwrap();
+        manager.apply(&message("F", &[(11, "2"), (41, "1")])).unwrap();
+        manager.apply(&report("2", "e2", "6", "6", None)).unwrap();
+        assert_eq!(
+            manager.apply(&report("2", "e3", "8", "8", None)),
+            Err(OrderError::InvalidTransition {
+                cl_ord_id: "1".to_string(),
+                from: PendingCancel,
+                to: Rejected,
+            })
+        );
+
+        // a cancel reject can not bring the order back to pending new.
+        manager
+            .apply(&message("9", &[(37, "X1"), (11, "2"), (41, "1"), (39, "A")]))
+            .unwrap();
+        assert_eq!(manager.order("1").unwrap().state, PendingCancel);
+        manager
+            .apply(&message("9", &[(37, "X1"), (11, "2"), (41, "1"), (39, "0")]))
+            .unwrap();
+        assert_eq!(manager.order("1").unwrap().state, New);
+    }
+
+    struct Handler;
+
+    impl TestHandler for Handler {
+        type Action = ();
+
+        fn handle_app(&mut self, client: &mut FixClient, msg: AppMsg) -> Result<(), HandleErr> {
+            if let AppMsg::ExecReport(report) = msg {
+                client.validate_msg(&report)?;
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_restore_from_message_log_after_restart() {
+        let directory = env::temp_dir().join(format!("profix-orders-{}", process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        let config = FileLogConfig {
+            directory: directory.clone(),
+            ..FileLogConfig::default()
+        };
+        let store = MemoryStore::new();
+        let manager = OrderManager::new();
+        let (ours, mut theirs) = loopback();
+        let mut client = testing::client("us", "them", ours);
+        client.set_store(Box::new(store.clone()));
+        let log = FileLog::new(config.clone(), client.comp_ids(), Timestamp::now().time).unwrap();
+        let log_path = log.messages_path();
+        client.set_message_log(Box::new(log));
+        client.add_interceptor(Box::new(manager.clone()));
+
+        let mut order = new_order("1", "100");
+        let (seq, sender, target, sending_time) = header(&mut client);
+        order.set(34, seq);
+        order.set(49, sender);
+        order.set(56, target);
+        order.set(52, sending_time);
+        client.send(&order).unwrap();
+        assert!(sent(&mut theirs).contains("\x0111=1\x01"));
+
+        let mut ack = report("1", "e1", "0", "0", None);
+        for &(id, value) in &[(34, "1"), (49, "them"), (56, "us")] {
+            ack.set(id, value);
+        }
+        ack.set(52, Timestamp::now().to_string());
+        let (_actions_tx, actions) = channel();
+        let mut session = Session::new(client, Handler, &actions);
+        session.push_received(serialize(&ack).as_bytes()).unwrap();
+        assert_eq!(manager.order("1").unwrap().state, OrderState::New);
+        drop(session);
+
+        // the restarted process continues after the ack, which is therefore not resent.
+        let restarted = testing::client("us", "them", loopback().0);
+        assert_eq!(store.next_target_seq(), 2);
+        let recorded = read_log(BufReader::new(File::open(&log_path).unwrap()), restarted.comp_ids()).unwrap();
+        let manager = OrderManager::new();
+        assert!(manager.restore(&recorded).is_empty());
+        let order = manager.order("1").unwrap();
+        assert_eq!((order.state, order.order_id), (OrderState::New, Some("X1".to_string())));
+        assert_eq!(manager.open_orders().len(), 1);
+
+        // a resend of the ack after all changes nothing.
+        let mut resent = recorded.clone();
+        ack.set(43, "Y");
+        resent.push(Recorded {
+            time: None,
+            direction: Direction::Incoming,
+            msg: serialize(&ack).into_bytes(),
+        });
+        assert!(OrderManager::new().restore(&resent).is_empty());
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
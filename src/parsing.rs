@@ -3,11 +3,16 @@ use std::ops::AddAssign;
 use std::ops::MulAssign;
 use std::result::Result;
 
+use super::serialization::checksum;
 use super::ParseError;
 
 const VERSION_ID: u64 = 8u64;
 const LENGTH_ID: u64 = 9u64;
 const MSG_TYPE_ID: u64 = 35u64;
+const MSG_SEQ_NUM_ID: u64 = 34u64;
+const CHECKSUM_ID: u64 = 10u64;
+// "10=xyz" and SOH.
+const CHECKSUM_LENGTH: usize = 7;
 
 #[derive(Eq, PartialEq, Debug)]
 pub struct FixMessage<'a> {
@@ -81,8 +86,6 @@ pub fn parse_fix_field(input: &[u8]) -> Result<FixField, ParseError> {
 }
 
 pub fn parse_fix_message(input: &[u8]) -> Result<FixMessage, ParseError> {
-    const CHECKSUM_LENGTH: usize = 7;
-
     if input.len() == 0 {
         return Err("FIX received empty message");
     }
@@ -105,6 +108,64 @@ pub fn parse_fix_message(input: &[u8]) -> Result<FixMessage, ParseError> {
     })
 }
 
+// `parse_fix_message` that also checks BodyLength (9) and CheckSum (10) of a complete message.
+pub fn validate_fix_message(input: &[u8]) -> Result<FixMessage, ParseError> {
+    let msg = parse_fix_message(input)?;
+
+    let (_, rest) = parse_header_field(VERSION_ID, input)?;
+    let (length, rest) = parse_header_field(LENGTH_ID, rest)?;
+    let body_length = parse_int::<usize>(length.value)?;
+    if rest.len() < CHECKSUM_LENGTH || body_length != rest.len() - CHECKSUM_LENGTH {
+        return Err("FIX validation: invalid length");
+    }
+
+    let trailer = parse_fix_field(&rest[body_length..])?;
+    if trailer.id != CHECKSUM_ID || trailer.value.len() != 3 {
+        return Err("FIX validation: invalid checksum field");
+    }
+    let expected = parse_int::<u16>(trailer.value)?;
+    if u16::from(checksum(&input[..input.len() - CHECKSUM_LENGTH]).0) != expected {
+        return Err("FIX validation: invalid checksum");
+    }
+    Ok(msg)
+}
+
+// value of the first `id` field of a message body, without deserializing the whole message.
+pub fn find_field(body: &[u8], id: u64) -> Result<Option<&[u8]>, ParseError> {
+    let mut input = body;
+    while !input.is_empty() {
+        let field = parse_fix_field(input)?;
+        if field.id == id {
+            return Ok(Some(field.value));
+        }
+        if field.id == CHECKSUM_ID {
+            break;
+        }
+        input = &input[field.length..];
+    }
+    Ok(None)
+}
+
+// MsgSeqNum (34) of a message, None if it has none or does not parse.
+pub(crate) fn seq_of(msg: &[u8]) -> Option<u64> {
+    let parsed = parse_fix_message(msg).ok()?;
+    let value = find_field(parsed.body, MSG_SEQ_NUM_ID).ok()??;
+    parse_int(value).ok()
+}
+
+// where the BeginString (8) of a message starts in `text`, e.g. a log line, not at a field like `58=FIX`.
+pub fn begin_string(text: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(found) = text[from..].find("8=FIX") {
+        let at = from + found;
+        if at == 0 || !text.as_bytes()[at - 1].is_ascii_digit() {
+            return Some(at);
+        }
+        from = at + 1;
+    }
+    None
+}
+
 fn parse_header_field(id: u64, input: &[u8]) -> Result<(FixField, &[u8]), ParseError> {
     match parse_fix_field(input) {
         Ok(field) => {
@@ -151,7 +212,7 @@ where
 
 #[cfg(test)]
 mod test {
-    use super::super::serialization::checksum;
+    use super::super::serialization::frame;
     use super::*;
     use quickcheck::*;
 
@@ -245,6 +306,26 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_validate() {
+        let msg = frame("35=0\x0134=1\x01");
+        assert_eq!(validate_fix_message(msg.as_bytes()).unwrap().msg_type, b"0");
+
+        let wrong_sum = (checksum(&msg.as_bytes()[..msg.len() - 7]) + Wrapping(1)).0;
+        let bad_checksum = format!("{}{:03}\x01", &msg[..msg.len() - 4], wrong_sum);
+        assert_eq!(
+            validate_fix_message(bad_checksum.as_bytes()),
+            Err("FIX validation: invalid checksum")
+        );
+        let bad_length = msg.replace("9=10", "9=11");
+        assert_eq!(
+            validate_fix_message(bad_length.as_bytes()),
+            Err("FIX validation: invalid length")
+        );
+        let truncated = &msg[..msg.len() - 1];
+        assert!(validate_fix_message(truncated.as_bytes()).is_err());
+    }
+
     #[test]
     fn test_error_empty() {
         assert!(parse_fix_message(&to_fix("")).is_err());
@@ -0,0 +1,228 @@
+use std::str;
+use std::sync::{Arc, OnceLock};
+
+use detail::parse_fix_field;
+use Dictionary;
+
+/// Formats FIX messages with tag names and enumerated values, e.g.
+/// `MsgType(35)=NewOrderSingle(D) | ClOrdID(11)=1 | Side(54)=Buy(1)`.
+///
+/// Entries of repeating groups are put in brackets, or indented when `set_multiline` is on.
+/// Tags missing from the dictionary are shown by number.
+#[derive(Debug, Clone)]
+pub struct PrettyPrinter {
+    dictionary: Arc<Dictionary>,
+    multiline: bool,
+}
+
+impl Default for PrettyPrinter {
+    fn default() -> PrettyPrinter {
+        static FIX44: OnceLock<Arc<Dictionary>> = OnceLock::new();
+        PrettyPrinter::new(FIX44.get_or_init(|| Arc::new(Dictionary::fix44())).clone())
+    }
+}
+
+/// Formats a message on one line with the built-in FIX 4.4 dictionary.
+pub fn pretty(msg: &[u8]) -> String {
+    PrettyPrinter::default().format(msg)
+}
+
+enum Node {
+    Field(String),
+    // the count field and the fields of every entry.
+    Group(String, Vec<Vec<Node>>),
+}
+
+impl PrettyPrinter {
+    pub fn new(dictionary: Arc<Dictionary>) -> PrettyPrinter {
+        PrettyPrinter {
+            dictionary,
+            multiline: false,
+        }
+    }
+
+    // one field per line, group entries indented.
+    pub fn set_multiline(&mut self, multiline: bool) {
+        self.multiline = multiline;
+    }
+
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dictionary
+    }
+
+    // the whole message including BeginString, BodyLength and CheckSum, also when garbled.
+    pub fn format(&self, msg: &[u8]) -> String {
+        let terminated;
+        let mut rest = if msg.last() == Some(&b'\x01') {
+            msg
+        } else {
+            terminated = [msg, b"\x01"].concat();
+            &terminated[..]
+        };
+
+        let mut fields = Vec::new();
+        while !rest.is_empty() {
+            match parse_fix_field(rest) {
+                Ok(field) => {
+                    fields.push((field.id, field.value));
+                    rest = &rest[field.length..];
+                }
+                Err(_) => break,
+            }
+        }
+
+        let mut index = 0;
+        let mut nodes = self.nodes(&fields, &mut index, None);
+        if !rest.is_empty() {
+            let unparsed = String::from_utf8_lossy(&rest[..rest.len() - 1]).replace('\x01', "|");
+            nodes.push(Node::Field(format!("<unparsed {}>", unparsed)));
+        }
+
+        let mut out = String::new();
+        if self.multiline {
+            write_lines(&nodes, "", "", &mut out);
+        } else {
+            write_line(&nodes, &mut out);
+        }
+        out
+    }
+
+    // the fields from `index` on, or those of one group entry when `members` are given.
+    // An entry ends at a tag it may not hold or one it already held.
+    fn nodes(&self, fields: &[(u64, &[u8])], index: &mut usize, members: Option<&[u64]>) -> Vec<Node> {
+        let mut nodes = Vec::new();
+        let mut seen = Vec::new();
+        while let Some(&(tag, value)) = fields.get(*index) {
+            if let Some(members) = members {
+                if !members.contains(&tag) || seen.contains(&tag) {
+                    break;
+                }
+                seen.push(tag);
+            }
+            *index += 1;
+
+            let text = self.field(tag, value);
+            match self.dictionary.group(tag) {
+                Some(group) => {
+                    let count = str::from_utf8(value)
+                        .ok()
+                        .and_then(|count| count.parse().ok())
+                        .unwrap_or(0usize);
+                    let mut entries = Vec::new();
+                    while entries.len() < count {
+                        let entry = self.nodes(fields, index, Some(group));
+                        if entry.is_empty() {
+                            break;
+                        }
+                        entries.push(entry);
+                    }
+                    nodes.push(Node::Group(text, entries));
+                }
+                None => nodes.push(Node::Field(text)),
+            }
+        }
+        nodes
+    }
+
+    fn field(&self, tag: u64, value: &[u8]) -> String {
+        let value = String::from_utf8_lossy(value);
+        let value = match self.dictionary.value_name(tag, &value) {
+            Some(name) => format!("{}({})", name, value),
+            None => value.into_owned(),
+        };
+        match self.dictionary.field_name(tag) {
+            Some(name) => format!("{}({})={}", name, tag, value),
+            None => format!("{}={}", tag, value),
+        }
+    }
+}
+
+fn write_line(nodes: &[Node], out: &mut String) {
+    for (i, node) in nodes.iter().enumerate() {
+        if i > 0 {
+            out.push_str(" | ");
+        }
+        match *node {
+            Node::Field(ref text) => out.push_str(text),
+            Node::Group(ref text, ref entries) => {
+                out.push_str(text);
+                for entry in entries {
+                    out.push_str(" | [");
+                    write_line(entry, out);
+                    out.push(']');
+                }
+            }
+        }
+    }
+}
+
+// the first line of an entry starts with `- `, so entries stand apart.
+fn write_lines(nodes: &[Node], first_indent: &str, indent: &str, out: &mut String) {
+    for (i, node) in nodes.iter().enumerate() {
+        out.push_str(if i == 0 { first_indent } else { indent });
+        match *node {
+            Node::Field(ref text) => {
+                out.push_str(text);
+                out.push('\n');
+            }
+            Node::Group(ref text, ref entries) => {
+                out.push_str(text);
+                out.push('\n');
+                let entry_indent = format!("{}    ", indent);
+                let entry_first_indent = format!("{}  - ", indent);
+                for entry in entries {
+                    write_lines(entry, &entry_first_indent, &entry_indent, out);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_single_line() {
+        let msg = b"8=FIX.4.4\x019=40\x0135=D\x0111=order-1\x0154=1\x0140=2\x019999=x\x0110=123\x01";
+        assert_eq!(
+            pretty(msg),
+            "BeginString(8)=FIX.4.4 | BodyLength(9)=40 | MsgType(35)=NewOrderSingle(D) | ClOrdID(11)=order-1 | \
+             Side(54)=Buy(1) | OrdType(40)=Limit(2) | 9999=x | CheckSum(10)=123"
+        );
+        // a log line cut short.
+        assert_eq!(
+            pretty(b"35=0\x0134=1\x0152"),
+            "MsgType(35)=Heartbeat(0) | MsgSeqNum(34)=1 | <unparsed 52>"
+        );
+    }
+
+    #[test]
+    fn test_groups() {
+        let msg = b"35=V\x01262=req-1\x01146=1\x0155=EURUSD\x01454=1\x01455=EUR/USD\x01456=8\x01\
+                    267=2\x01269=0\x01269=1\x0110=000\x01";
+        assert_eq!(
+            pretty(msg),
+            "MsgType(35)=MarketDataRequest(V) | MDReqID(262)=req-1 | NoRelatedSym(146)=1 | \
+             [Symbol(55)=EURUSD | NoSecurityAltID(454)=1 | \
+             [SecurityAltID(455)=EUR/USD | SecurityAltIDSource(456)=ExchangeSymbol(8)]] | \
+             NoMDEntryTypes(267)=2 | [MDEntryType(269)=Bid(0)] | [MDEntryType(269)=Offer(1)] | CheckSum(10)=000"
+        );
+
+        let mut printer = PrettyPrinter::default();
+        printer.set_multiline(true);
+        // incremental refreshes start their entries with MDUpdateAction.
+        let msg = b"35=X\x01268=2\x01279=0\x01269=0\x01270=1.1\x01279=2\x01269=1\x0110=000\x01";
+        assert_eq!(
+            printer.format(msg),
+            "MsgType(35)=MarketDataIncrementalRefresh(X)\n\
+             NoMDEntries(268)=2\n  \
+             - MDUpdateAction(279)=New(0)\n    \
+             MDEntryType(269)=Bid(0)\n    \
+             MDEntryPx(270)=1.1\n  \
+             - MDUpdateAction(279)=Delete(2)\n    \
+             MDEntryType(269)=Offer(1)\n\
+             CheckSum(10)=000\n"
+        );
+    }
+}
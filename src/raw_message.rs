@@ -0,0 +1,146 @@
+use std::str;
+
+use deserialize;
+use detail::{parse_fix_field, parse_fix_message, FixDeserializable, FixSerializable};
+use serialization::frame;
+use store::is_admin_msg_type;
+use {FixParse, ParseError};
+
+const CHECKSUM_ID: u64 = 10;
+
+/// A serialized message taken apart into its fields, so it can be amended without knowing its type.
+///
+/// BeginString (8), BodyLength (9) and CheckSum (10) are left out and recomputed by `to_fix`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RawMessage {
+    msg_type: String,
+    // in their order in the message, repeating groups included.
+    fields: Vec<(u64, String)>,
+}
+
+impl RawMessage {
+    pub fn new(msg_type: &str) -> RawMessage {
+        RawMessage {
+            msg_type: msg_type.to_string(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn parse(input: &[u8]) -> Result<RawMessage, ParseError> {
+        let msg = parse_fix_message(input)?;
+        let mut raw = RawMessage::new(utf8(msg.msg_type)?);
+        let mut rest = msg.body;
+        while !rest.is_empty() {
+            let field = parse_fix_field(rest)?;
+            if field.id == CHECKSUM_ID {
+                break;
+            }
+            raw.fields.push((field.id, utf8(field.value)?.to_string()));
+            rest = &rest[field.length..];
+        }
+        Ok(raw)
+    }
+
+    pub fn msg_type(&self) -> &str {
+        &self.msg_type
+    }
+
+    // a session level message, see `FixHandler::to_admin`.
+    pub fn is_admin(&self) -> bool {
+        is_admin_msg_type(self.msg_type.as_bytes())
+    }
+
+    pub fn fields(&self) -> &[(u64, String)] {
+        &self.fields
+    }
+
+    // value of the first `id` field.
+    pub fn get(&self, id: u64) -> Option<&str> {
+        self.fields
+            .iter()
+            .find(|field| field.0 == id)
+            .map(|field| field.1.as_str())
+    }
+
+    // value of the first `id` field parsed, e.g. a quantity.
+    pub fn parsed<T: FixParse>(&self, id: u64) -> Result<Option<T>, ParseError> {
+        match self.get(id) {
+            Some(value) => T::parse(value.as_bytes()).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    // replaces the value of the first `id` field, appends the field if there is none.
+    pub fn set<V: ToString>(&mut self, id: u64, value: V) {
+        let value = value.to_string();
+        match self.fields.iter_mut().find(|field| field.0 == id) {
+            Some(field) => field.1 = value,
+            None => self.fields.push((id, value)),
+        }
+    }
+
+    // removes every `id` field, returns the value of the first one.
+    pub fn remove(&mut self, id: u64) -> Option<String> {
+        let removed = self.get(id).map(str::to_string);
+        self.fields.retain(|&(field, _)| field != id);
+        removed
+    }
+
+    // the typed message, e.g. for an `Interceptor` to look at an order.
+    pub fn typed<T: FixDeserializable>(&self) -> Result<T, ParseError> {
+        deserialize(self.to_fix().as_bytes())
+    }
+
+    pub fn to_fix(&self) -> String {
+        frame(&self.serialize_body_to_fix())
+    }
+}
+
+// lets a message put together field by field go through `FixClient::send`.
+impl FixSerializable for RawMessage {
+    fn serialize_body_to_fix(&self) -> String {
+        let mut body = format!("35={}\x01", self.msg_type);
+        for &(id, ref value) in &self.fields {
+            body.push_str(&format!("{}={}\x01", id, value));
+        }
+        body
+    }
+}
+
+fn utf8(value: &[u8]) -> Result<&str, ParseError> {
+    str::from_utf8(value).map_err(|_| "Could not convert to UTF8")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use admin::Logout;
+    use detail::validate_fix_message;
+    use {serialize, Timestamp};
+
+    #[test]
+    fn test_amend_and_serialize_again() {
+        let logout = Logout {
+            seq: 2,
+            sender: "us".to_string(),
+            target: "them".to_string(),
+            sending_time: Timestamp::now(),
+            text: None,
+        };
+        let mut raw = RawMessage::parse(serialize(&logout).as_bytes()).unwrap();
+        assert_eq!(raw.msg_type(), "5");
+        assert!(raw.is_admin());
+        assert_eq!(raw.get(49), Some("us"));
+
+        raw.set(34, 3);
+        raw.set(58, "bye");
+        assert_eq!(raw.remove(56), Some("them".to_string()));
+
+        let amended = raw.to_fix();
+        assert!(validate_fix_message(amended.as_bytes()).is_ok());
+        assert!(amended.contains("\x0134=3\x0149=us\x0152="));
+        assert!(amended.contains("\x0158=bye\x0110="));
+        assert!(!amended.contains("\x0156="));
+        assert_eq!(RawMessage::parse(amended.as_bytes()).unwrap(), raw);
+    }
+}
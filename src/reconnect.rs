@@ -0,0 +1,257 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use ConnectionFailure;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureAction {
+    Retry,
+    // give up, reconnecting will not help.
+    Fatal,
+}
+
+/// How `SessionRunner` reconnects after a failed connection attempt or a dropped session.
+///
+/// Delays grow exponentially from `initial_delay` up to `max_delay`, each one randomly shortened
+/// by up to `jitter` (0.0 - 1.0) of itself so that many clients do not reconnect in lockstep.
+/// `hosts` are handed to `FixFactory::connect_to` in order, moving to the next one on every failure;
+/// when empty `FixFactory::connection_factory` is used.
+#[derive(Debug, Clone)]
+pub struct ReconnectPolicy {
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+    pub multiplier: f64,
+    pub jitter: f64,
+    // consecutive failures after which the runner gives up, None retries forever.
+    pub max_attempts: Option<usize>,
+
+    // primary first, then backups.
+    pub hosts: Vec<String>,
+
+    pub on_tls_error: FailureAction,
+    pub on_tls_handshake_error: FailureAction,
+    pub on_tcp_error: FailureAction,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            multiplier: 2.0,
+            jitter: 0.2,
+            max_attempts: None,
+
+            hosts: Vec::new(),
+
+            on_tls_error: FailureAction::Retry,
+            on_tls_handshake_error: FailureAction::Retry,
+            on_tcp_error: FailureAction::Retry,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    // same delay after every failure, forever.
+    pub fn fixed(delay: Duration) -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: delay,
+            max_delay: delay,
+            multiplier: 1.0,
+            jitter: 0.0,
+            ..ReconnectPolicy::default()
+        }
+    }
+
+    pub fn action_for(&self, failure: &ConnectionFailure) -> FailureAction {
+        match *failure {
+            ConnectionFailure::TlsError(_) => self.on_tls_error,
+            ConnectionFailure::TlsHandshakeError(_) => self.on_tls_handshake_error,
+            ConnectionFailure::TcpStreamError(_) => self.on_tcp_error,
+            ConnectionFailure::TlsVerificationError(_) => self.on_tls_handshake_error,
+            // retrying with the same configuration can not help.
+            ConnectionFailure::TlsConfigError(_) => FailureAction::Fatal,
+        }
+    }
+
+    // delay before the next attempt after `failures` consecutive failures, without jitter.
+    fn base_delay(&self, failures: usize) -> Duration {
+        let factor = self.multiplier.powi(failures.saturating_sub(1) as i32);
+        let delay = duration_to_secs(self.initial_delay) * factor;
+        if delay >= duration_to_secs(self.max_delay) {
+            self.max_delay
+        } else {
+            secs_to_duration(delay)
+        }
+    }
+}
+
+fn duration_to_secs(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9
+}
+
+fn secs_to_duration(secs: f64) -> Duration {
+    Duration::new(secs as u64, ((secs - secs.trunc()) * 1e9) as u32)
+}
+
+/// Progress of reconnecting under a `ReconnectPolicy`.
+#[derive(Debug)]
+pub struct ReconnectState {
+    failures: usize,
+    host: usize,
+    // xorshift state for the jitter.
+    seed: u64,
+}
+
+impl Default for ReconnectState {
+    fn default() -> ReconnectState {
+        ReconnectState::new()
+    }
+}
+
+impl ReconnectState {
+    pub fn new() -> ReconnectState {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() as u64)
+            .unwrap_or(0);
+        ReconnectState::with_seed(nanos)
+    }
+
+    pub fn with_seed(seed: u64) -> ReconnectState {
+        ReconnectState {
+            failures: 0,
+            host: 0,
+            // xorshift gets stuck at 0.
+            seed: seed | 1,
+        }
+    }
+
+    // host to connect to next, None if the policy has no hosts.
+    pub fn host<'a>(&self, policy: &'a ReconnectPolicy) -> Option<&'a str> {
+        policy.hosts.get(self.host).map(|h| h.as_str())
+    }
+
+    pub fn failures(&self) -> usize {
+        self.failures
+    }
+
+    // moves on to the next host and returns how long to wait before trying it,
+    // None when the policy allows no more attempts.
+    pub fn on_failure(&mut self, policy: &ReconnectPolicy) -> Option<Duration> {
+        self.failures += 1;
+        if !policy.hosts.is_empty() {
+            self.host = (self.host + 1) % policy.hosts.len();
+        }
+
+        if let Some(max_attempts) = policy.max_attempts {
+            if self.failures >= max_attempts {
+                return None;
+            }
+        }
+
+        let delay = duration_to_secs(policy.base_delay(self.failures));
+        let jitter = delay * policy.jitter.clamp(0.0, 1.0) * self.next_random();
+        Some(secs_to_duration(delay - jitter))
+    }
+
+    // the session got logged in, the next failure starts the backoff again on the same host.
+    pub fn on_success(&mut self) {
+        self.failures = 0;
+    }
+
+    // uniform in [0, 1).
+    fn next_random(&mut self) -> f64 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 7;
+        self.seed ^= self.seed << 17;
+        (self.seed >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io;
+
+    fn policy() -> ReconnectPolicy {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter: 0.0,
+            ..ReconnectPolicy::default()
+        }
+    }
+
+    #[test]
+    fn test_exponential_backoff_is_capped() {
+        let policy = policy();
+        let mut state = ReconnectState::with_seed(42);
+        let delays: Vec<_> = (0..6).map(|_| state.on_failure(&policy).unwrap()).collect();
+        assert_eq!(
+            delays,
+            vec![
+                Duration::from_millis(100),
+                Duration::from_millis(200),
+                Duration::from_millis(400),
+                Duration::from_millis(800),
+                Duration::from_secs(1),
+                Duration::from_secs(1),
+            ]
+        );
+
+        state.on_success();
+        assert_eq!(state.on_failure(&policy), Some(Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_jitter_only_shortens_delay() {
+        let policy = ReconnectPolicy {
+            jitter: 0.5,
+            ..policy()
+        };
+        let mut state = ReconnectState::with_seed(7);
+        for _ in 0..100 {
+            let delay = state.on_failure(&policy).unwrap();
+            assert!(delay <= Duration::from_secs(1));
+            assert!(delay >= Duration::from_millis(50));
+        }
+    }
+
+    #[test]
+    fn test_max_attempts() {
+        let policy = ReconnectPolicy {
+            max_attempts: Some(2),
+            ..policy()
+        };
+        let mut state = ReconnectState::with_seed(1);
+        assert!(state.on_failure(&policy).is_some());
+        assert!(state.on_failure(&policy).is_none());
+    }
+
+    #[test]
+    fn test_hosts_rotate_on_failure() {
+        let policy = ReconnectPolicy {
+            hosts: vec!["primary:1".to_string(), "backup:2".to_string()],
+            ..policy()
+        };
+        let mut state = ReconnectState::with_seed(1);
+        assert_eq!(state.host(&policy), Some("primary:1"));
+        state.on_failure(&policy);
+        assert_eq!(state.host(&policy), Some("backup:2"));
+        state.on_success();
+        assert_eq!(state.host(&policy), Some("backup:2"));
+        state.on_failure(&policy);
+        assert_eq!(state.host(&policy), Some("primary:1"));
+    }
+
+    #[test]
+    fn test_failure_kinds() {
+        let policy = ReconnectPolicy {
+            on_tcp_error: FailureAction::Fatal,
+            ..policy()
+        };
+        let tcp = ConnectionFailure::TcpStreamError(io::Error::new(io::ErrorKind::ConnectionRefused, "x"));
+        assert_eq!(policy.action_for(&tcp), FailureAction::Fatal);
+        assert_eq!(ReconnectPolicy::default().action_for(&tcp), FailureAction::Retry);
+    }
+}
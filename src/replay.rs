@@ -0,0 +1,577 @@
+use std::fmt::Debug;
+use std::io::{BufRead, Error, Read};
+use std::marker::PhantomData;
+use std::thread;
+use std::time::Duration;
+
+use chrono::NaiveDateTime;
+
+use admin;
+use detail::{begin_string, find_field, parse_fix_field, parse_fix_message, FixDeserializable, FixMessageType};
+use parsing::seq_of;
+use session::{dispatch, frame_length};
+use {loopback, CompIds, FixClient, FixHandler, LoopbackStream, SessionConfig, SessionError, TestClock};
+
+/// Who sent a `Recorded` message, seen from the side being replayed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+}
+
+/// A message of a recorded session, see `read_log`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recorded {
+    // when it was logged, None if the line carries no timestamp.
+    pub time: Option<NaiveDateTime>,
+    pub direction: Direction,
+    pub msg: Vec<u8>,
+}
+
+/// Reads the messages of the session `comp_ids` from a message log, e.g. the one of `FileLog`.
+///
+/// Lines look like `20200101-12:00:00.000 : 8=FIX.4.4|9=...`, with SOH or `|` between fields.
+/// A `<<` or `>>` before the message, as written by `LogMessageLog`, gives the direction,
+/// otherwise SenderCompID does. Lines of other sessions and without a message are skipped.
+pub fn read_log<R: BufRead>(input: R, comp_ids: &CompIds) -> Result<Vec<Recorded>, Error> {
+    let mut recorded = Vec::new();
+    for line in input.split(b'\n') {
+        let line = line?;
+        let line = String::from_utf8_lossy(&line);
+        let start = match begin_string(&line) {
+            Some(start) => start,
+            None => continue,
+        };
+        let (prefix, msg) = line.split_at(start);
+        let msg = message(msg);
+
+        let direction = if prefix.contains("<<") {
+            Direction::Incoming
+        } else if prefix.contains(">>") {
+            Direction::Outgoing
+        } else {
+            match field(&msg, 49) {
+                Some(ref sender) if *sender == comp_ids.sender => Direction::Outgoing,
+                Some(ref sender) if *sender == comp_ids.target => Direction::Incoming,
+                _ => continue,
+            }
+        };
+        let time = prefix
+            .split_whitespace()
+            .filter_map(|word| NaiveDateTime::parse_from_str(word, "%Y%m%d-%H:%M:%S%.f").ok())
+            .next();
+        recorded.push(Recorded { time, direction, msg });
+    }
+    Ok(recorded)
+}
+
+// the message up to and including its CheckSum field, with SOH between fields.
+fn message(text: &str) -> Vec<u8> {
+    let text = text.trim_end();
+    let mut msg = if text.contains('\x01') {
+        text.as_bytes().to_vec()
+    } else {
+        text.replace('|', "\x01").into_bytes()
+    };
+    if msg.last() != Some(&b'\x01') {
+        msg.push(b'\x01');
+    }
+    if let Some(length) = frame_length(&msg) {
+        msg.truncate(length);
+    }
+    msg
+}
+
+fn field(msg: &[u8], id: u64) -> Option<String> {
+    let parsed = parse_fix_message(msg).ok()?;
+    let value = find_field(parsed.body, id).ok()??;
+    Some(String::from_utf8_lossy(value).into_owned())
+}
+
+/// How fast `Replay::run` goes through a recording. The clock of the `FixClient` always shows
+/// the recorded time of the message being handled.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    // no waiting between messages.
+    Instant,
+    // waits for the recorded gaps between messages divided by the factor, 1.0 is real time.
+    // The factor has to be positive, see `Replay::set_speed`.
+    Warped(f64),
+}
+
+/// SendingTime and the fields depending on it, which differ between a recording and its replay.
+pub const VOLATILE_TAGS: &[u64] = &[9, 10, 52, 122];
+
+/// A field differing between an original and a replayed message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDifference {
+    pub tag: u64,
+    pub original: Option<String>,
+    pub replayed: Option<String>,
+}
+
+/// The `index`th outbound message of the recording and of the replay, when they differ.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Mismatch {
+    pub index: usize,
+    pub original: Option<Vec<u8>>,
+    pub replayed: Option<Vec<u8>>,
+    pub differences: Vec<FieldDifference>,
+}
+
+/// What a `Replay::run` sent compared to the recording.
+#[derive(Debug)]
+pub struct ReplayReport {
+    // outbound messages of the recording.
+    pub original: Vec<Vec<u8>>,
+    // outbound messages of the handler during the replay.
+    pub replayed: Vec<Vec<u8>>,
+    // incoming messages which failed to deserialize or to be handled, by index into the recording.
+    pub errors: Vec<(usize, SessionError)>,
+}
+
+impl ReplayReport {
+    /// Compares the outbound messages in order, except for `ignored_tags`, usually `VOLATILE_TAGS`.
+    ///
+    /// Heartbeats and TestRequests are left out, the replay does not run the session timers.
+    pub fn mismatches(&self, ignored_tags: &[u64]) -> Vec<Mismatch> {
+        let original = comparable(&self.original);
+        let replayed = comparable(&self.replayed);
+        let mut mismatches = Vec::new();
+        for index in 0..original.len().max(replayed.len()) {
+            let original = original.get(index).cloned();
+            let replayed = replayed.get(index).cloned();
+            let differences = differences(
+                &fields(original.unwrap_or_default(), ignored_tags),
+                &fields(replayed.unwrap_or_default(), ignored_tags),
+            );
+            if !differences.is_empty() {
+                mismatches.push(Mismatch {
+                    index,
+                    original: original.map(|msg| msg.to_vec()),
+                    replayed: replayed.map(|msg| msg.to_vec()),
+                    differences,
+                });
+            }
+        }
+        mismatches
+    }
+}
+
+fn comparable(msgs: &[Vec<u8>]) -> Vec<&[u8]> {
+    msgs.iter()
+        .map(|msg| msg.as_slice())
+        .filter(|msg| !from_timers(msg))
+        .collect()
+}
+
+// TestRequests and Heartbeats without a TestReqID(112) are sent by the session timers rather than
+// the handler. A Heartbeat answering a TestRequest is the handler's.
+fn from_timers(msg: &[u8]) -> bool {
+    match parse_fix_message(msg) {
+        Ok(parsed) if parsed.msg_type == admin::Heartbeat::MSG_TYPE => {
+            matches!(find_field(parsed.body, 112), Ok(None))
+        }
+        Ok(parsed) => parsed.msg_type == admin::TestRequest::MSG_TYPE,
+        Err(_) => false,
+    }
+}
+
+fn fields(msg: &[u8], ignored_tags: &[u64]) -> Vec<(u64, String)> {
+    let mut fields = Vec::new();
+    let mut rest = msg;
+    while !rest.is_empty() {
+        match parse_fix_field(rest) {
+            Ok(field) => {
+                if !ignored_tags.contains(&field.id) {
+                    fields.push((field.id, String::from_utf8_lossy(field.value).into_owned()));
+                }
+                rest = &rest[field.length..];
+            }
+            Err(_) => {
+                fields.push((0, String::from_utf8_lossy(rest).into_owned()));
+                break;
+            }
+        }
+    }
+    fields
+}
+
+// field by field, a field missing on one side shows as None.
+fn differences(original: &[(u64, String)], replayed: &[(u64, String)]) -> Vec<FieldDifference> {
+    let mut differences = Vec::new();
+    for index in 0..original.len().max(replayed.len()) {
+        let original = original.get(index);
+        let replayed = replayed.get(index);
+        if original == replayed {
+            continue;
+        }
+        match (original, replayed) {
+            (Some(original), Some(replayed)) if original.0 != replayed.0 => {
+                differences.push(FieldDifference {
+                    tag: original.0,
+                    original: Some(original.1.clone()),
+                    replayed: None,
+                });
+                differences.push(FieldDifference {
+                    tag: replayed.0,
+                    original: None,
+                    replayed: Some(replayed.1.clone()),
+                });
+            }
+            (original, replayed) => differences.push(FieldDifference {
+                tag: original.or(replayed).unwrap().0,
+                original: original.map(|field| field.1.clone()),
+                replayed: replayed.map(|field| field.1.clone()),
+            }),
+        }
+    }
+    differences
+}
+
+/// Feeds the incoming messages of a recording to a `FixHandler`, like a `Session` would, and
+/// captures what it sends for `ReplayReport::mismatches`.
+///
+/// The `FixClient` writes to an in-memory stream and runs on a `TestClock` set to the recorded
+/// time of every message, so SendingTime checks and timestamps behave as they did.
+pub struct Replay<Sess, App, H, Action> {
+    client: FixClient,
+    handler: H,
+    clock: TestClock,
+    peer: LoopbackStream,
+    speed: ReplaySpeed,
+
+    _messages: PhantomData<(Sess, App, Action)>,
+}
+
+impl<Sess, App, H, Action> Replay<Sess, App, H, Action>
+where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+    H: FixHandler<Sess, App, Action>,
+{
+    pub fn new(comp_ids: CompIds, config: SessionConfig, handler: H) -> Replay<Sess, App, H, Action> {
+        let (stream, peer) = loopback();
+        let clock = TestClock::new(NaiveDateTime::default());
+        let mut client = FixClient::with_config(comp_ids, config, Box::new(stream));
+        client.set_clock(Box::new(clock.clone()));
+        Replay {
+            client,
+            handler,
+            clock,
+            peer,
+            speed: ReplaySpeed::Instant,
+
+            _messages: PhantomData,
+        }
+    }
+
+    // refuses a `Warped` factor which is not a positive number.
+    pub fn set_speed(&mut self, speed: ReplaySpeed) -> Result<(), &'static str> {
+        if let ReplaySpeed::Warped(factor) = speed {
+            if !(factor > 0.0 && factor.is_finite()) {
+                return Err("replay speed factor must be positive");
+            }
+        }
+        self.speed = speed;
+        Ok(())
+    }
+
+    // e.g. to set a store or a throttle before `run`.
+    pub fn client(&mut self) -> &mut FixClient {
+        &mut self.client
+    }
+
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    pub fn clock(&self) -> &TestClock {
+        &self.clock
+    }
+
+    /// Replays the incoming messages of `recorded`. The sequence numbers continue from the first
+    /// recorded ones, so a recording may start in the middle of a session.
+    pub fn run(&mut self, recorded: &[Recorded]) -> ReplayReport {
+        let first_seq = |direction| {
+            recorded
+                .iter()
+                .filter(|recorded| recorded.direction == direction)
+                .filter_map(|recorded| seq_of(&recorded.msg))
+                .next()
+                .unwrap_or(1)
+        };
+        self.client
+            .set_next_seqs(first_seq(Direction::Outgoing), first_seq(Direction::Incoming));
+
+        let mut report = ReplayReport {
+            original: Vec::new(),
+            replayed: Vec::new(),
+            errors: Vec::new(),
+        };
+        let mut last_time: Option<NaiveDateTime> = None;
+        for (index, recorded) in recorded.iter().enumerate() {
+            if let Some(time) = recorded.time {
+                if let (ReplaySpeed::Warped(factor), Some(last_time)) = (self.speed, last_time) {
+                    let gap = (time - last_time).to_std().unwrap_or_default();
+                    thread::sleep(Duration::from_secs_f64(gap.as_secs_f64() / factor));
+                }
+                self.clock.set(time);
+                last_time = Some(time);
+            }
+
+            match recorded.direction {
+                Direction::Outgoing => {
+                    // takes the seq the timers took, so the handler's messages keep their recorded seqs.
+                    if from_timers(&recorded.msg) && seq_of(&recorded.msg) == Some(self.client.next_send_seq()) {
+                        self.client.get_next_send_seq();
+                    }
+                    report.original.push(recorded.msg.clone());
+                }
+                Direction::Incoming => {
+                    self.client.log_incoming(&recorded.msg);
+                    if let Err(err) = self.handle_message(&recorded.msg) {
+                        report.errors.push((index, err));
+                    }
+                }
+            }
+            self.captured(&mut report.replayed);
+        }
+        report
+    }
+
+    fn handle_message(&mut self, msg: &[u8]) -> Result<(), SessionError> {
+        dispatch::<Sess, App, H, Action>(&mut self.handler, &mut self.client, msg)
+    }
+
+    fn captured(&mut self, replayed: &mut Vec<Vec<u8>>) {
+        let mut written = Vec::new();
+        let mut buf = [0u8; 4096];
+        while let Ok(size) = self.peer.read(&mut buf) {
+            if size == 0 {
+                break;
+            }
+            written.extend_from_slice(&buf[..size]);
+        }
+        let mut rest = &written[..];
+        while let Some(length) = frame_length(rest) {
+            replayed.push(rest[..length].to_vec());
+            rest = &rest[length..];
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::*;
+    use serialization::frame;
+    use testing::{AppMsg, ExecReport, SessionMsg, TestHandler};
+    use {Clock, HandleErr, Timestamp};
+
+    // answers TestRequests, acknowledges orders, and since the incident rejects those of "bad".
+    struct Handler {
+        reject: &'static str,
+    }
+
+    impl TestHandler for Handler {
+        type Action = ();
+
+        fn handle_session(&mut self, client: &mut FixClient, msg: SessionMsg) -> Result<(), HandleErr> {
+            match msg {
+                SessionMsg::Heartbeat(heartbeat) => client.validate_msg(&heartbeat)?,
+                SessionMsg::TestRequest(request) => {
+                    client.validate_msg(&request)?;
+                    let heartbeat = admin::Heartbeat {
+                        seq: client.get_next_send_seq(),
+                        sender: client.comp_ids().sender.clone(),
+                        target: client.comp_ids().target.clone(),
+                        sending_time: client.sending_time(),
+                        test_req_id: Some(request.test_req_id),
+                    };
+                    client.send(&heartbeat)?;
+                }
+                _ => {}
+            }
+            Ok(())
+        }
+
+        fn handle_app(&mut self, client: &mut FixClient, msg: AppMsg) -> Result<(), HandleErr> {
+            if let AppMsg::NewOrder(order) = msg {
+                client.validate_msg(&order)?;
+                let exec_type = if order.cl_ord_id == self.reject { "8" } else { "0" };
+                let report = ExecReport {
+                    exec_type: Some(exec_type.to_string()),
+                    ..ExecReport::next(client, &order.cl_ord_id)
+                };
+                client.send(&report)?;
+            }
+            Ok(())
+        }
+    }
+
+    fn comp_ids() -> CompIds {
+        CompIds {
+            sender: "venue".to_string(),
+            target: "client".to_string(),
+        }
+    }
+
+    fn at(second: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_milli_opt(12, 0, second, 0)
+            .unwrap()
+    }
+
+    // a FileLog with `|` for SOH, starting in the middle of the session.
+    fn recording() -> String {
+        log(&[
+            (0, "35=D|34=40|49=client|56=venue|52=20200101-12:00:00.000|11=good|"),
+            (
+                0,
+                "35=8|34=70|49=venue|56=client|52=20200101-12:00:00.000|11=good|150=0|",
+            ),
+            (1, "35=0|34=41|49=client|56=venue|52=20200101-12:00:01.000|"),
+            (1, "35=0|34=71|49=venue|56=client|52=20200101-12:00:01.000|"),
+            (
+                2,
+                "35=D|34=42|49=other|56=someone|52=20200101-12:00:02.000|11=elsewhere|",
+            ),
+            (3, "35=D|34=42|49=client|56=venue|52=20200101-12:00:03.000|11=bad|"),
+            (
+                3,
+                "35=8|34=72|49=venue|56=client|52=20200101-12:00:03.000|11=bad|150=0|",
+            ),
+        ])
+    }
+
+    fn log(lines: &[(u32, &str)]) -> String {
+        lines
+            .iter()
+            .map(|&(second, body)| {
+                let msg = frame(&body.replace('|', "\x01")).replace('\x01', "|");
+                format!("{} : {}\n", Timestamp::new(at(second)), msg)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_read_log() {
+        let recorded = read_log(recording().as_bytes(), &comp_ids()).unwrap();
+        assert_eq!(recorded.len(), 6);
+        assert_eq!(recorded[0].time, Some(at(0)));
+        assert_eq!(recorded[0].direction, Direction::Incoming);
+        assert_eq!(recorded[1].direction, Direction::Outgoing);
+        assert!(recorded[1].msg.starts_with(b"8=FIX.4.4\x019="));
+        assert!(recorded[1].msg.ends_with(b"\x01"));
+
+        let line = b"INFO profix: >> 8=FIX.4.4\x019=5\x0135=0\x0110=163\x01 trailing\n";
+        let recorded = read_log(&line[..], &comp_ids()).unwrap();
+        assert_eq!(recorded[0].time, None);
+        assert_eq!(recorded[0].direction, Direction::Outgoing);
+        assert_eq!(recorded[0].msg, b"8=FIX.4.4\x019=5\x0135=0\x0110=163\x01".to_vec());
+    }
+
+    #[test]
+    fn test_replay_finds_changed_behaviour() {
+        let recorded = read_log(recording().as_bytes(), &comp_ids()).unwrap();
+        let mut replay = Replay::new(comp_ids(), SessionConfig::default(), Handler { reject: "bad" });
+        let report = replay.run(&recorded);
+
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.original.len(), 3);
+        assert_eq!(report.replayed.len(), 2);
+        // the replayed clock shows the recorded time.
+        assert_eq!(
+            field(&report.replayed[1], 52),
+            Some("20200101-12:00:03.000".to_string())
+        );
+        assert_eq!(replay.clock().now(), at(3));
+
+        // the timers' heartbeat is left out, the seqs continue the recording.
+        assert_eq!(
+            report.mismatches(VOLATILE_TAGS),
+            vec![Mismatch {
+                index: 1,
+                original: Some(report.original[2].clone()),
+                replayed: Some(report.replayed[1].clone()),
+                differences: vec![FieldDifference {
+                    tag: 150,
+                    original: Some("0".to_string()),
+                    replayed: Some("8".to_string()),
+                }],
+            }]
+        );
+
+        let mut replay = Replay::new(comp_ids(), SessionConfig::default(), Handler { reject: "" });
+        replay.set_speed(ReplaySpeed::Warped(1000.0)).unwrap();
+        assert!(replay.run(&recorded).mismatches(VOLATILE_TAGS).is_empty());
+    }
+
+    #[test]
+    fn test_replay_reports_errors() {
+        let mut recorded = read_log(recording().as_bytes(), &comp_ids()).unwrap();
+        recorded.remove(2);
+        let mut replay = Replay::new(comp_ids(), SessionConfig::default(), Handler { reject: "" });
+        let report = replay.run(&recorded);
+        // without the heartbeat of seq 41, the order of seq 42 is out of order.
+        match report.errors[..] {
+            [(3, SessionError::Handle(HandleErr::MessageValidationErr(_)))] => (),
+            ref errors => panic!("unexpected errors {:?}", errors),
+        }
+        let mismatches = report.mismatches(VOLATILE_TAGS);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].replayed, None);
+    }
+
+    #[test]
+    fn test_heartbeat_sent_by_the_handler_keeps_its_seq() {
+        let recording = log(&[
+            (0, "35=1|34=40|49=client|56=venue|52=20200101-12:00:00.000|112=ping|"),
+            (0, "35=0|34=70|49=venue|56=client|52=20200101-12:00:00.000|112=ping|"),
+            (1, "35=0|34=71|49=venue|56=client|52=20200101-12:00:01.000|"),
+            (2, "35=D|34=41|49=client|56=venue|52=20200101-12:00:02.000|11=good|"),
+            (
+                2,
+                "35=8|34=72|49=venue|56=client|52=20200101-12:00:02.000|11=good|150=0|",
+            ),
+        ]);
+        let recorded = read_log(recording.as_bytes(), &comp_ids()).unwrap();
+        let mut replay = Replay::new(comp_ids(), SessionConfig::default(), Handler { reject: "" });
+        let report = replay.run(&recorded);
+
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(field(&report.replayed[0], 34), Some("70".to_string()));
+        assert!(report.mismatches(VOLATILE_TAGS).is_empty());
+    }
+
+    #[test]
+    fn test_heartbeat_answering_a_test_request_is_compared() {
+        // the recorded session never answered the TestRequest, the handler does now.
+        let recording = log(&[
+            (0, "35=1|34=40|49=client|56=venue|52=20200101-12:00:00.000|112=ping|"),
+            (1, "35=0|34=70|49=venue|56=client|52=20200101-12:00:01.000|"),
+        ]);
+        let recorded = read_log(recording.as_bytes(), &comp_ids()).unwrap();
+        let mut replay = Replay::new(comp_ids(), SessionConfig::default(), Handler { reject: "" });
+        let report = replay.run(&recorded);
+
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        let mismatches = report.mismatches(VOLATILE_TAGS);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].original, None);
+        assert_eq!(mismatches[0].replayed, Some(report.replayed[0].clone()));
+        assert_eq!(field(&report.replayed[0], 112), Some("ping".to_string()));
+    }
+
+    #[test]
+    fn test_speed_factor_has_to_be_positive() {
+        let mut replay = Replay::new(comp_ids(), SessionConfig::default(), Handler { reject: "" });
+        for &factor in &[0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(replay.set_speed(ReplaySpeed::Warped(factor)).is_err());
+        }
+        assert!(replay.set_speed(ReplaySpeed::Warped(0.5)).is_ok());
+    }
+}
@@ -0,0 +1,625 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+
+use interceptor::{Interceptor, Verdict};
+use throttle::{OverflowAction, RateLimit, Throttle};
+use {Clock, FixClient, RawMessage, SendError, SystemClock};
+
+const LOGON: &str = "A";
+const NEW_ORDER_SINGLE: &str = "D";
+const ORDER_CANCEL_REQUEST: &str = "F";
+const ORDER_CANCEL_REPLACE_REQUEST: &str = "G";
+const EXECUTION_REPORT: &str = "8";
+const ORDER_CANCEL_REJECT: &str = "9";
+
+const CL_ORD_ID: u64 = 11;
+const CUM_QTY: u64 = 14;
+const EXEC_ID: u64 = 17;
+const LAST_QTY: u64 = 32;
+const ORDER_QTY: u64 = 38;
+const ORD_STATUS: u64 = 39;
+const ORIG_CL_ORD_ID: u64 = 41;
+const POSS_DUP_FLAG: u64 = 43;
+const PRICE: u64 = 44;
+const SIDE: u64 = 54;
+const SYMBOL: u64 = 55;
+const TRANSACT_TIME: u64 = 60;
+const EXEC_TYPE: u64 = 150;
+const RESET_SEQ_NUM_FLAG: u64 = 141;
+const LEAVES_QTY: u64 = 151;
+
+/// Limits `RiskCheck` holds outbound orders to, those left unset are not checked.
+#[derive(Debug, Clone, Default)]
+pub struct RiskLimits {
+    // OrderQty (38) of a single order.
+    pub max_order_qty: Option<f64>,
+    // OrderQty (38) times Price (44), or the reference price for orders without one.
+    pub max_notional: Option<f64>,
+    // largest distance of Price (44) from the reference price of the symbol, as a fraction of the latter.
+    pub price_band: Option<f64>,
+    // orders written and not yet filled, cancelled, rejected or expired.
+    pub max_open_orders: Option<usize>,
+    // NewOrderSingles and replaces within any window.
+    pub order_rate: Option<RateLimit>,
+    pub restricted_symbols: HashSet<String>,
+}
+
+/// An order `RiskCheck` counts as open, by the ClOrdID (11) it is known under now.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OpenOrder {
+    pub cl_ord_id: String,
+    pub symbol: String,
+    pub side: String,
+    pub price: Option<f64>,
+    pub qty: f64,
+    pub leaves_qty: f64,
+}
+
+/// Pre-trade risk checks for the orders of a `FixClient`, added to it as an `Interceptor`.
+///
+/// A NewOrderSingle (D) or OrderCancelReplaceRequest (G) over one of the `RiskLimits` is vetoed, so
+/// `FixClient::send` fails with `SendError::Vetoed` and the reason. Orders count as open and towards the
+/// order rate once they were written, ExecutionReports (8) received keep the open orders, positions and
+/// exposure up to date. Clones share all of it, so one goes to the client while another is kept to look
+/// at the counters, set reference prices or pull the kill switch.
+#[derive(Clone)]
+pub struct RiskCheck {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    limits: RiskLimits,
+    clock: Box<Clock + Send>,
+    rate: Throttle,
+    reference_prices: HashMap<String, f64>,
+    open: HashMap<String, OpenOrder>,
+    // replaces sent and not yet confirmed, by their new ClOrdID.
+    replacing: HashMap<String, (f64, Option<f64>)>,
+    positions: HashMap<String, f64>,
+    // ExecIDs of the fills counted, a report received again is not counted twice. Cleared when the
+    // session is reset, as nothing from before is resent after that.
+    exec_ids: HashSet<String>,
+    killed: bool,
+}
+
+impl RiskCheck {
+    // fails for an `order_rate` the `Throttle` does not take.
+    pub fn new(limits: RiskLimits) -> Result<RiskCheck, &'static str> {
+        let mut rate = Throttle::new(OverflowAction::Reject);
+        if let Some(ref limit) = limits.order_rate {
+            rate.add_limit(limit.clone())?;
+        }
+        Ok(RiskCheck {
+            state: Arc::new(Mutex::new(State {
+                limits,
+                clock: Box::new(SystemClock),
+                rate,
+                reference_prices: HashMap::new(),
+                open: HashMap::new(),
+                replacing: HashMap::new(),
+                positions: HashMap::new(),
+                exec_ids: HashSet::new(),
+                killed: false,
+            })),
+        })
+    }
+
+    // clock the order rate is measured on.
+    pub fn set_clock(&self, clock: Box<Clock + Send>) {
+        self.state.lock().unwrap().clock = clock;
+    }
+
+    // price the band is put around, e.g. the last trade or mid.
+    pub fn set_reference_price(&self, symbol: &str, price: f64) {
+        self.state
+            .lock()
+            .unwrap()
+            .reference_prices
+            .insert(symbol.to_string(), price);
+    }
+
+    // filled quantity, bought minus sold.
+    pub fn position(&self, symbol: &str) -> f64 {
+        self.state.lock().unwrap().positions.get(symbol).cloned().unwrap_or(0.0)
+    }
+
+    // notional of what is left of the open orders, of both sides.
+    pub fn exposure(&self) -> f64 {
+        let state = self.state.lock().unwrap();
+        state
+            .open
+            .values()
+            .map(|order| order.leaves_qty * state.price_of(&order.symbol, order.price).unwrap_or(0.0))
+            .sum()
+    }
+
+    pub fn open_orders(&self) -> Vec<OpenOrder> {
+        let mut orders: Vec<OpenOrder> = self.state.lock().unwrap().open.values().cloned().collect();
+        orders.sort_by(|a, b| a.cl_ord_id.cmp(&b.cl_ord_id));
+        orders
+    }
+
+    // kill switch: vetoes every new order and replace from now on and sends an OrderCancelRequest (F)
+    // for each open order, returns how many went out. A cancel failing does not stop the others, the
+    // orders whose cancel failed are returned with the error.
+    pub fn kill(&self, client: &mut FixClient) -> Result<usize, Vec<(String, SendError)>> {
+        // the lock is let go before sending, the cancels pass through this interceptor as well.
+        let open = {
+            let mut state = self.state.lock().unwrap();
+            state.killed = true;
+            state.open.values().cloned().collect::<Vec<_>>()
+        };
+        warn!("kill switch engaged, cancelling {} open orders", open.len());
+
+        let mut failed = Vec::new();
+
+        for order in &open {
+            let mut cancel = RawMessage::new(ORDER_CANCEL_REQUEST);
+            cancel.set(34, client.get_next_send_seq());
+            cancel.set(49, client.comp_ids().sender.clone());
+            cancel.set(56, client.comp_ids().target.clone());
+            cancel.set(52, client.sending_time());
+            cancel.set(ORIG_CL_ORD_ID, &order.cl_ord_id);
+            cancel.set(CL_ORD_ID, format!("{}-kill", order.cl_ord_id));
+            cancel.set(SYMBOL, &order.symbol);
+            cancel.set(SIDE, &order.side);
+            cancel.set(ORDER_QTY, order.qty);
+            cancel.set(TRANSACT_TIME, client.sending_time());
+            if let Err(err) = client.send(&cancel) {
+                error!("failed to cancel {}: {:?}", order.cl_ord_id, err);
+                failed.push((order.cl_ord_id.clone(), err));
+            }
+        }
+        if failed.is_empty() {
+            Ok(open.len())
+        } else {
+            Err(failed)
+        }
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.state.lock().unwrap().killed
+    }
+
+    // lets orders through again after `kill`.
+    pub fn resume(&self) {
+        self.state.lock().unwrap().killed = false;
+    }
+}
+
+impl Interceptor for RiskCheck {
+    fn outbound(&mut self, msg: &mut RawMessage) -> Verdict {
+        let state = self.state.lock().unwrap();
+        let replace = match msg.msg_type() {
+            NEW_ORDER_SINGLE => false,
+            ORDER_CANCEL_REPLACE_REQUEST => true,
+            _ => return Verdict::Pass,
+        };
+        match state.check(msg, replace) {
+            Ok(_) => Verdict::Pass,
+            Err(reason) => {
+                warn!("risk check failed: {}", reason);
+                Verdict::Veto(reason)
+            }
+        }
+    }
+
+    // only what went out is counted, a later interceptor or the throttle may still refuse an order
+    // this one let pass. Resends were counted the first time.
+    fn written(&mut self, msg: &RawMessage) {
+        let replace = match msg.msg_type() {
+            NEW_ORDER_SINGLE => false,
+            ORDER_CANCEL_REPLACE_REQUEST => true,
+            LOGON => {
+                self.state.lock().unwrap().on_logon(msg);
+                return;
+            }
+            _ => return,
+        };
+        if msg.get(POSS_DUP_FLAG) == Some("Y") {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        if let Ok(order) = open_order(msg) {
+            let now = state.clock.now();
+            state.rate.record(msg.msg_type(), now);
+            if replace {
+                state.replacing.insert(order.cl_ord_id, (order.qty, order.price));
+            } else {
+                state.open.insert(order.cl_ord_id.clone(), order);
+            }
+        }
+    }
+
+    // only what the handler gets counts, not a report a later interceptor dropped.
+    fn received(&mut self, msg: &RawMessage) {
+        let mut state = self.state.lock().unwrap();
+        match msg.msg_type() {
+            EXECUTION_REPORT => state.on_execution_report(msg),
+            ORDER_CANCEL_REJECT => {
+                if let Some(cl_ord_id) = msg.get(CL_ORD_ID) {
+                    state.replacing.remove(cl_ord_id);
+                }
+            }
+            LOGON => state.on_logon(msg),
+            _ => {}
+        }
+    }
+}
+
+impl State {
+    // the order as it would be open, or why it may not be sent.
+    fn check(&self, msg: &RawMessage, replace: bool) -> Result<OpenOrder, String> {
+        if self.killed {
+            return Err("kill switch engaged".to_string());
+        }
+        let order = open_order(msg)?;
+        let (symbol, qty, price) = (order.symbol.as_str(), order.qty, order.price);
+
+        if self.limits.restricted_symbols.contains(symbol) {
+            return Err(format!("restricted symbol {}", symbol));
+        }
+        if let Some(max) = self.limits.max_order_qty {
+            if qty > max {
+                return Err(format!("quantity {} over limit {}", qty, max));
+            }
+        }
+        if let (Some(band), Some(price)) = (self.limits.price_band, price) {
+            let reference = *self
+                .reference_prices
+                .get(symbol)
+                .ok_or_else(|| format!("no reference price for {}", symbol))?;
+            if (price - reference).abs() > band * reference {
+                return Err(format!("price {} outside band around {}", price, reference));
+            }
+        }
+        if let Some(max) = self.limits.max_notional {
+            let price = self
+                .price_of(symbol, price)
+                .ok_or_else(|| format!("no price for the notional of {}", symbol))?;
+            if qty * price > max {
+                return Err(format!("notional {} over limit {}", qty * price, max));
+            }
+        }
+        if let Some(max) = self.limits.max_open_orders {
+            if !replace && self.open.len() >= max {
+                return Err(format!("{} orders open already", self.open.len()));
+            }
+        }
+        if self.rate.delay(msg.msg_type(), self.clock.now()).is_some() {
+            return Err("order rate over limit".to_string());
+        }
+        Ok(order)
+    }
+
+    fn on_logon(&mut self, msg: &RawMessage) {
+        if msg.get(RESET_SEQ_NUM_FLAG) == Some("Y") {
+            self.exec_ids.clear();
+        }
+    }
+
+    fn on_execution_report(&mut self, msg: &RawMessage) {
+        let cl_ord_id = match msg.get(CL_ORD_ID) {
+            Some(cl_ord_id) => cl_ord_id.to_string(),
+            None => return,
+        };
+        let orig_cl_ord_id = msg.get(ORIG_CL_ORD_ID);
+        let exec_type = msg.get(EXEC_TYPE).unwrap_or("");
+
+        // a replaced order is known under the ClOrdID of the replace from now on.
+        if exec_type == "5" {
+            if let Some(mut order) = orig_cl_ord_id.and_then(|orig| self.open.remove(orig)) {
+                if let Some((qty, price)) = self.replacing.remove(&cl_ord_id) {
+                    order.qty = qty;
+                    order.price = price.or(order.price);
+                }
+                order.cl_ord_id = cl_ord_id.clone();
+                self.open.insert(cl_ord_id.clone(), order);
+            }
+        }
+
+        let key = if self.open.contains_key(&cl_ord_id) {
+            Some(cl_ord_id)
+        } else {
+            orig_cl_ord_id
+                .filter(|orig| self.open.contains_key(*orig))
+                .map(str::to_string)
+        };
+
+        let last_qty = number(msg, LAST_QTY).ok().and_then(|qty| qty).unwrap_or(0.0);
+        let fill = last_qty > 0.0 && (exec_type == "F" || exec_type == "1" || exec_type == "2");
+        let counted = fill && msg.get(EXEC_ID).is_some_and(|id| !self.exec_ids.insert(id.to_string()));
+        if fill && !counted {
+            let order = key.as_ref().and_then(|key| self.open.get(key));
+            let symbol = msg.get(SYMBOL).or_else(|| order.map(|order| order.symbol.as_str()));
+            let side = msg.get(SIDE).or_else(|| order.map(|order| order.side.as_str()));
+            if let (Some(symbol), Some(side)) = (symbol, side) {
+                let signed = if side == "1" { last_qty } else { -last_qty };
+                *self.positions.entry(symbol.to_string()).or_insert(0.0) += signed;
+            }
+        }
+
+        if let Some(key) = key {
+            match msg.get(ORD_STATUS) {
+                // filled, done for day, cancelled, rejected, expired.
+                Some("2") | Some("3") | Some("4") | Some("8") | Some("C") => {
+                    self.open.remove(&key);
+                }
+                _ => {
+                    if let Some(order) = self.open.get_mut(&key) {
+                        if let Ok(Some(leaves_qty)) = number(msg, LEAVES_QTY) {
+                            order.leaves_qty = leaves_qty;
+                        } else if let Ok(Some(cum_qty)) = number(msg, CUM_QTY) {
+                            order.leaves_qty = (order.qty - cum_qty).max(0.0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn price_of(&self, symbol: &str, price: Option<f64>) -> Option<f64> {
+        price.or_else(|| self.reference_prices.get(symbol).cloned())
+    }
+}
+
+// an order or replace as it is open once sent.
+fn open_order(msg: &RawMessage) -> Result<OpenOrder, String> {
+    let cl_ord_id = msg.get(CL_ORD_ID).ok_or("no ClOrdID (11)")?;
+    let symbol = msg.get(SYMBOL).ok_or("no Symbol (55)")?;
+    let side = msg.get(SIDE).ok_or("no Side (54)")?;
+    let qty = number(msg, ORDER_QTY)?.ok_or("no OrderQty (38)")?;
+    Ok(OpenOrder {
+        cl_ord_id: cl_ord_id.to_string(),
+        symbol: symbol.to_string(),
+        side: side.to_string(),
+        price: number(msg, PRICE)?,
+        qty,
+        leaves_qty: qty,
+    })
+}
+
+fn number(msg: &RawMessage, id: u64) -> Result<Option<f64>, String> {
+    msg.parsed(id)
+        .map_err(|_| format!("field {} is not a number: {}", id, msg.get(id).unwrap_or("")))
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use chrono::NaiveDate;
+
+    use super::*;
+    use loopback::{loopback, LoopbackStream};
+    use testing::{self, sent};
+    use TestClock;
+
+    fn client(stream: LoopbackStream, risk: &RiskCheck) -> FixClient {
+        let mut client = testing::client("us", "them", stream);
+        client.add_interceptor(Box::new(risk.clone()));
+        client
+    }
+
+    fn order(client: &mut FixClient, cl_ord_id: &str, symbol: &str, qty: f64, price: f64) -> RawMessage {
+        message(client, NEW_ORDER_SINGLE, cl_ord_id, symbol, qty, price)
+    }
+
+    fn message(
+        client: &mut FixClient,
+        msg_type: &str,
+        cl_ord_id: &str,
+        symbol: &str,
+        qty: f64,
+        price: f64,
+    ) -> RawMessage {
+        let mut order = RawMessage::new(msg_type);
+        order.set(34, client.get_next_send_seq());
+        order.set(49, "us");
+        order.set(56, "them");
+        order.set(52, client.sending_time());
+        order.set(CL_ORD_ID, cl_ord_id);
+        order.set(SYMBOL, symbol);
+        order.set(SIDE, "1");
+        order.set(ORDER_QTY, qty);
+        order.set(PRICE, price);
+        order
+    }
+
+    fn report(cl_ord_id: &str, exec_type: &str, ord_status: &str, last_qty: f64, leaves_qty: f64) -> RawMessage {
+        let mut report = RawMessage::new("8");
+        report.set(CL_ORD_ID, cl_ord_id);
+        report.set(EXEC_TYPE, exec_type);
+        report.set(ORD_STATUS, ord_status);
+        report.set(SYMBOL, "EURUSD");
+        report.set(SIDE, "1");
+        report.set(LAST_QTY, last_qty);
+        report.set(LEAVES_QTY, leaves_qty);
+        report
+    }
+
+    fn vetoed(result: Result<(), SendError>) -> String {
+        match result {
+            Err(SendError::Vetoed(reason)) => reason,
+            other => panic!("not vetoed: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_orders_over_limits_are_vetoed() {
+        let mut limits = RiskLimits {
+            max_order_qty: Some(1000.0),
+            max_notional: Some(50_000.0),
+            price_band: Some(0.05),
+            max_open_orders: Some(3),
+            order_rate: Some(RateLimit::new(3, Duration::from_secs(1))),
+            ..Default::default()
+        };
+        limits.restricted_symbols.insert("BAD".to_string());
+        let risk = RiskCheck::new(limits).unwrap();
+        let clock = TestClock::new(
+            NaiveDate::from_ymd_opt(2024, 1, 2)
+                .unwrap()
+                .and_hms_opt(10, 0, 0)
+                .unwrap(),
+        );
+        risk.set_clock(Box::new(clock.clone()));
+        risk.set_reference_price("EURUSD", 50.0);
+
+        let (ours, _theirs) = loopback();
+        let mut client = client(ours, &risk);
+
+        let msg = order(&mut client, "1", "BAD", 10.0, 50.0);
+        assert_eq!(vetoed(client.send(&msg)), "restricted symbol BAD");
+        let msg = order(&mut client, "1", "EURUSD", 2000.0, 50.0);
+        assert_eq!(vetoed(client.send(&msg)), "quantity 2000 over limit 1000");
+        let msg = order(&mut client, "1", "EURUSD", 10.0, 60.0);
+        assert_eq!(vetoed(client.send(&msg)), "price 60 outside band around 50");
+        let msg = order(&mut client, "1", "GBPUSD", 10.0, 60.0);
+        assert_eq!(vetoed(client.send(&msg)), "no reference price for GBPUSD");
+        let msg = order(&mut client, "1", "EURUSD", 1000.0, 51.0);
+        assert_eq!(vetoed(client.send(&msg)), "notional 51000 over limit 50000");
+        assert_eq!(client.get_next_send_seq(), 1);
+
+        for id in &["1", "2", "3"] {
+            let msg = order(&mut client, id, "EURUSD", 10.0, 50.0);
+            client.send(&msg).unwrap();
+        }
+        let msg = order(&mut client, "4", "EURUSD", 10.0, 50.0);
+        assert_eq!(vetoed(client.send(&msg)), "3 orders open already");
+
+        RiskCheck::clone(&risk).received(&report("1", "8", "8", 0.0, 0.0));
+        let msg = order(&mut client, "4", "EURUSD", 10.0, 50.0);
+        assert_eq!(vetoed(client.send(&msg)), "order rate over limit");
+        clock.advance(Duration::from_secs(1));
+        client.send(&msg).unwrap();
+        assert_eq!(risk.open_orders().len(), 3);
+        assert!((risk.exposure() - 1500.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_fills_replaces_and_kill_switch() {
+        let risk = RiskCheck::new(RiskLimits::default()).unwrap();
+        let (ours, mut theirs) = loopback();
+        let mut client = client(ours, &risk);
+        let mut inbound = risk.clone();
+
+        let msg = order(&mut client, "1", "EURUSD", 100.0, 1.1);
+        client.send(&msg).unwrap();
+        let msg = order(&mut client, "2", "EURUSD", 50.0, 1.2);
+        client.send(&msg).unwrap();
+
+        let mut fill = report("1", "F", "1", 30.0, 70.0);
+        fill.set(EXEC_ID, "e1");
+        inbound.received(&fill);
+        // received again, e.g. resent after a gap.
+        inbound.received(&fill);
+        inbound.received(&report("2", "F", "2", 50.0, 0.0));
+        assert!((risk.position("EURUSD") - 80.0).abs() < 1e-9);
+
+        let mut replace = message(&mut client, ORDER_CANCEL_REPLACE_REQUEST, "1b", "EURUSD", 60.0, 1.15);
+        replace.set(ORIG_CL_ORD_ID, "1");
+        client.send(&replace).unwrap();
+        let mut replaced = report("1b", "5", "5", 0.0, 30.0);
+        replaced.set(ORIG_CL_ORD_ID, "1");
+        inbound.received(&replaced);
+
+        let open = risk.open_orders();
+        assert_eq!(open.len(), 1);
+        assert_eq!(open[0].cl_ord_id, "1b");
+        assert_eq!(open[0].price, Some(1.15));
+        assert!((open[0].leaves_qty - 30.0).abs() < 1e-9);
+
+        sent(&mut theirs);
+
+        assert_eq!(risk.kill(&mut client).unwrap(), 1);
+        assert!(risk.is_killed());
+        let cancel = sent(&mut theirs);
+        assert!(cancel.contains("\x0135=F\x01"));
+        assert!(cancel.contains("\x0141=1b\x0111=1b-kill\x0155=EURUSD\x0154=1\x0138=60\x0160="));
+
+        let msg = order(&mut client, "3", "EURUSD", 10.0, 1.1);
+        assert_eq!(vetoed(client.send(&msg)), "kill switch engaged");
+        risk.resume();
+        client.send(&msg).unwrap();
+    }
+
+    #[test]
+    fn test_exec_ids_are_forgotten_on_a_session_reset() {
+        let mut risk = RiskCheck::new(RiskLimits::default()).unwrap();
+        let mut fill = report("1", "F", "1", 10.0, 90.0);
+        fill.set(EXEC_ID, "e1");
+        risk.received(&fill);
+
+        // a Logon without reset continues the session.
+        let mut logon = RawMessage::new(LOGON);
+        risk.received(&logon);
+        risk.received(&fill);
+        assert!((risk.position("EURUSD") - 10.0).abs() < 1e-9);
+
+        // ExecIDs start over in the new session, whichever side asked for the reset.
+        logon.set(RESET_SEQ_NUM_FLAG, "Y");
+        risk.written(&logon);
+        risk.received(&fill);
+        assert!((risk.position("EURUSD") - 20.0).abs() < 1e-9);
+        risk.received(&logon);
+        risk.received(&fill);
+        assert!((risk.position("EURUSD") - 30.0).abs() < 1e-9);
+        assert_eq!(risk.state.lock().unwrap().exec_ids.len(), 1);
+    }
+
+    // refuses orders for GBPUSD and the cancel of order 1, after the risk check.
+    struct Desk;
+
+    impl Interceptor for Desk {
+        fn outbound(&mut self, msg: &mut RawMessage) -> Verdict {
+            if msg.get(SYMBOL) == Some("GBPUSD") || msg.get(ORIG_CL_ORD_ID) == Some("1") {
+                Verdict::Veto("not on this desk".to_string())
+            } else {
+                Verdict::Pass
+            }
+        }
+    }
+
+    #[test]
+    fn test_orders_refused_after_the_check_are_not_counted() {
+        let risk = RiskCheck::new(RiskLimits {
+            max_open_orders: Some(3),
+            ..Default::default()
+        })
+        .unwrap();
+        let (ours, mut theirs) = loopback();
+        let mut client = client(ours, &risk);
+        client.add_interceptor(Box::new(Desk));
+        let mut throttle = Throttle::new(OverflowAction::Reject);
+        throttle
+            .add_limit(RateLimit::for_msg_type(NEW_ORDER_SINGLE, 2, Duration::from_secs(60)))
+            .unwrap();
+        client.set_throttle(throttle);
+
+        let msg = order(&mut client, "0", "GBPUSD", 10.0, 1.3);
+        assert_eq!(vetoed(client.send(&msg)), "not on this desk");
+        for id in &["1", "2"] {
+            let msg = order(&mut client, id, "EURUSD", 10.0, 1.1);
+            client.send(&msg).unwrap();
+        }
+        let msg = order(&mut client, "3", "EURUSD", 10.0, 1.1);
+        match client.send(&msg) {
+            Err(SendError::Throttled { .. }) => {}
+            other => panic!("not throttled: {:?}", other),
+        }
+        let open: Vec<String> = risk.open_orders().into_iter().map(|order| order.cl_ord_id).collect();
+        assert_eq!(open, vec!["1", "2"]);
+
+        // the cancel of order 1 is refused, that of order 2 still goes out.
+        sent(&mut theirs);
+        let mut failed = risk.kill(&mut client).unwrap_err();
+        match failed.pop() {
+            Some((ref cl_ord_id, SendError::Vetoed(ref reason))) if failed.is_empty() => {
+                assert_eq!((cl_ord_id.as_str(), reason.as_str()), ("1", "not on this desk"))
+            }
+            other => panic!("not vetoed: {:?}", other),
+        }
+        assert!(sent(&mut theirs).contains("\x0141=2\x0111=2-kill\x01"));
+    }
+}
@@ -0,0 +1,540 @@
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::io::{self, ErrorKind};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use mio::{Events, Interest, Poll, Token, Waker};
+
+use detail::FixDeserializable;
+use event_loop::{update_interest, waking_channel, ActionSender};
+use reconnect::{FailureAction, ReconnectPolicy, ReconnectState};
+use session::{Drive, Session, SessionError};
+use ConnectionFailure;
+use FixFactory;
+use FixHandler;
+use HandleErr;
+use Interceptor;
+
+/// Asks a running `SessionRunner` to log out and return. Clones share the same flag.
+#[derive(Debug, Clone, Default)]
+pub struct StopHandle {
+    stopped: Arc<AtomicBool>,
+}
+
+impl StopHandle {
+    pub fn new() -> StopHandle {
+        StopHandle::default()
+    }
+
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+}
+
+#[derive(Debug)]
+pub enum FatalError {
+    Handler(String),
+    // either classified as fatal by the reconnect policy or the last of the allowed attempts.
+    Connection(ConnectionFailure),
+    // the session kept failing after connecting until the reconnect policy ran out of attempts.
+    AttemptsExhausted,
+}
+
+/// Keeps a FIX session connected until it is stopped or fails for good.
+///
+/// The runner stops when its `StopHandle` is triggered or every action sender is dropped, logged in
+/// or not. If logged in it then sends a Logout, waits up to `logout_timeout` for the counterparty to
+/// answer it and flushes the transport before returning.
+///
+/// When the client has an event source the idle runner sleeps until the transport is readable,
+/// an action arrives through `action_channel` or a session timer is due, but at most `max_wait`.
+/// Without one it keeps polling the transport.
+pub struct SessionRunner<Factory> {
+    factory: Factory,
+    stop: StopHandle,
+    poll: Poll,
+    waker: Arc<Waker>,
+    // make the interceptors of every connection, see `add_interceptor`.
+    interceptors: Vec<Box<Fn() -> Box<Interceptor> + Send>>,
+
+    pub logout_timeout: Duration,
+    pub reconnect_policy: ReconnectPolicy,
+    pub max_wait: Duration,
+}
+
+// how often sleeping runners and acceptors check whether they were stopped.
+pub(crate) const STOP_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+const TRANSPORT: Token = Token(0);
+const WAKER: Token = Token(1);
+
+impl<Factory> SessionRunner<Factory> {
+    // fails only when out of file descriptors.
+    pub fn new(factory: Factory) -> io::Result<SessionRunner<Factory>> {
+        let poll = Poll::new()?;
+        let waker = Arc::new(Waker::new(poll.registry(), WAKER)?);
+        Ok(SessionRunner {
+            factory,
+            stop: StopHandle::new(),
+            poll,
+            waker,
+            interceptors: Vec::new(),
+
+            logout_timeout: Duration::from_secs(10),
+            reconnect_policy: ReconnectPolicy::default(),
+            max_wait: STOP_CHECK_INTERVAL,
+        })
+    }
+
+    pub fn stop_handle(&self) -> StopHandle {
+        self.stop.clone()
+    }
+
+    // every connection gets an interceptor made by `factory`, after those the `FixFactory` added.
+    // Interceptors sharing state across reconnects keep it behind an `Arc`.
+    pub fn add_interceptor<I, F>(&mut self, factory: F)
+    where
+        I: Interceptor + 'static,
+        F: Fn() -> I + Send + 'static,
+    {
+        self.interceptors.push(Box::new(move || Box::new(factory()) as Box<Interceptor>));
+    }
+
+    // actions sent through the returned sender wake up the idle runner immediately.
+    pub fn action_channel<Action>(&self) -> (ActionSender<Action>, Receiver<Action>) {
+        waking_channel(&self.waker)
+    }
+
+    pub fn run<Sess, App, H, Action>(&mut self, action_rx: Receiver<Action>) -> Result<(), FatalError>
+    where
+        Sess: FixDeserializable + Debug,
+        App: FixDeserializable + Debug,
+        H: FixHandler<Sess, App, Action>,
+        Factory: FixFactory<H>,
+        Action: Debug,
+    {
+        let policy = self.reconnect_policy.clone();
+        let policy = &policy;
+        let mut reconnect = ReconnectState::new();
+        let mut events = Events::with_capacity(16);
+
+        while !self.stop.is_stopped() {
+            let connected = match reconnect.host(policy) {
+                Some(host) => {
+                    info!("initiating connection to {}", host);
+                    self.factory.connect_to(host)
+                }
+                None => {
+                    info!("initiating connection");
+                    self.factory.connection_factory()
+                }
+            };
+            let mut client = match connected {
+                Ok(client) => {
+                    info!("connected!");
+                    client
+                }
+                Err(err) => {
+                    error!("connection failed with {:?}", err);
+                    if policy.action_for(&err) == FailureAction::Fatal {
+                        return Err(FatalError::Connection(err));
+                    }
+                    match reconnect.on_failure(policy) {
+                        Some(delay) => self.wait(delay),
+                        None => return Err(FatalError::Connection(err)),
+                    }
+                    continue;
+                }
+            };
+
+            for interceptor in &self.interceptors {
+                client.add_interceptor(interceptor());
+            }
+            let handler = self.factory.handler_factory();
+            let mut session = Session::new(client, handler, &action_rx);
+            let mut interest = Drive::interest(&session);
+            let registered = match Drive::event_source(&mut session) {
+                Some(source) => match self.poll.registry().register(source, TRANSPORT, interest) {
+                    Ok(()) => true,
+                    Err(err) => {
+                        error!("failed to register transport, falling back to polling. {:?}", err);
+                        false
+                    }
+                },
+                None => false,
+            };
+
+            // Some when the runner is done, None to reconnect.
+            let outcome = loop {
+                if session.client().is_logged_on() {
+                    reconnect.on_success();
+                }
+
+                if self.stop.is_stopped() || session.actions_closed() {
+                    info!("stopping session");
+                    // what was asked before stopping is still done.
+                    if let Err(err) = session.handle_actions() {
+                        error!("failed to handle the remaining actions. {:?}", err);
+                    }
+                    let poll = if registered {
+                        Some((&mut self.poll, &mut events))
+                    } else {
+                        None
+                    };
+                    logout(&mut session, self.logout_timeout, poll);
+                    break Some(Ok(()));
+                }
+
+                match session.step() {
+                    Ok(true) => {}
+                    Ok(false) if registered => self.wait_for_events(&mut session, &mut interest, &mut events),
+                    Ok(false) => sleep(Duration::new(0, 1000)),
+                    Err(SessionError::Handle(HandleErr::Fatal(reason))) => {
+                        error!("fatal session error: {}", reason);
+                        let poll = if registered {
+                            Some((&mut self.poll, &mut events))
+                        } else {
+                            None
+                        };
+                        logout(&mut session, self.logout_timeout, poll);
+                        break Some(Err(FatalError::Handler(reason)));
+                    }
+                    Err(err) => {
+                        error!("session failed, resetting connection. {:?}", err);
+                        break None;
+                    }
+                }
+            };
+
+            if registered {
+                if let Some(source) = Drive::event_source(&mut session) {
+                    let _ = self.poll.registry().deregister(source);
+                }
+            }
+            if let Some(result) = outcome {
+                return result;
+            }
+
+            match reconnect.on_failure(policy) {
+                Some(delay) => self.wait(delay),
+                None => return Err(FatalError::AttemptsExhausted),
+            }
+        }
+
+        Ok(())
+    }
+
+    // sleeps until the transport is readable, or writable while sent bytes are buffered, an action arrives
+    // or a session timer is due.
+    fn wait_for_events<D: Drive>(&mut self, session: &mut D, interest: &mut Interest, events: &mut Events) {
+        let wanted = session.interest();
+        if let Some(source) = session.event_source() {
+            if let Err(err) = update_interest(self.poll.registry(), source, TRANSPORT, interest, wanted) {
+                error!("failed to register transport for {:?}. {:?}", wanted, err);
+            }
+        }
+        let timeout = session
+            .next_deadline()
+            .map_or(self.max_wait, |deadline| deadline.min(self.max_wait));
+        if let Err(err) = self.poll.poll(events, Some(timeout)) {
+            if err.kind() != ErrorKind::Interrupted {
+                error!("runner poll failed: {:?}", err);
+            }
+        }
+    }
+
+    // sleeps, but wakes up early when stopped.
+    fn wait(&self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+        while !self.stop.is_stopped() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            sleep(STOP_CHECK_INTERVAL.min(deadline - now));
+        }
+    }
+}
+
+// sends a Logout unless the counterparty already did, waits up to `timeout` for the answer and flushes.
+// In between reads it sleeps on `poll`, where the transport is registered, or keeps polling the transport.
+pub(crate) fn logout<Sess, App, H, Action>(
+    session: &mut Session<Sess, App, H, Action>,
+    timeout: Duration,
+    mut poll: Option<(&mut Poll, &mut Events)>,
+) where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+    H: FixHandler<Sess, App, Action>,
+    Action: Debug,
+{
+    if session.client().is_logged_on() && !session.logout_received() {
+        // the counterparty can not answer a Logout still buffered.
+        if let Err(err) = session.send_logout(None).and_then(|()| Ok(session.client().flush()?)) {
+            error!("failed to send logout. {:?}", err);
+            return;
+        }
+
+        let deadline = Instant::now() + timeout;
+        while !session.logout_received() {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            match session.receive() {
+                Ok(true) => {}
+                Ok(false) => match poll {
+                    Some((ref mut poll, ref mut events)) => {
+                        if let Err(err) = poll.poll(events, Some(deadline - now)) {
+                            if err.kind() != ErrorKind::Interrupted {
+                                error!("poll failed while waiting for logout. {:?}", err);
+                                break;
+                            }
+                        }
+                    }
+                    None => sleep(Duration::new(0, 1000)),
+                },
+                Err(err) => {
+                    if !session.logout_received() {
+                        error!("session failed while waiting for logout. {:?}", err);
+                    }
+                    break;
+                }
+            }
+        }
+
+        if !session.logout_received() {
+            error!("counterparty did not confirm logout");
+        }
+    }
+
+    if let Err(err) = session.client().flush() {
+        error!("failed to flush on shutdown. {:?}", err);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc::channel;
+    use std::sync::Mutex;
+    use std::thread::spawn;
+
+    use super::*;
+    use admin::Logout;
+    use loopback::{loopback, LoopbackStream};
+    use testing::{client, Logon, TestHandler};
+    use {serialize, ConnectionFailure, FixClient, PlainStreamWrapper, Timestamp};
+
+    // records its actions, "stop" stops the runner and "fail" fails for good.
+    struct Handler {
+        stop: StopHandle,
+        handled: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl TestHandler for Handler {
+        type Action = &'static str;
+
+        fn handle_action(&mut self, _client: &mut FixClient, action: &'static str) -> Result<(), HandleErr> {
+            match action {
+                "fail" => return Err(HandleErr::Fatal("failed".to_string())),
+                "stop" => self.stop.stop(),
+                _ => {}
+            }
+            self.handled.lock().unwrap().push(action);
+            Ok(())
+        }
+    }
+
+    struct Factory {
+        client: Mutex<Option<FixClient>>,
+        stop: StopHandle,
+        handled: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    impl FixFactory<Handler> for Factory {
+        fn connection_factory(&self) -> Result<FixClient, ConnectionFailure> {
+            Ok(self.client.lock().unwrap().take().expect("connected twice"))
+        }
+
+        fn handler_factory(&self) -> Handler {
+            Handler {
+                stop: self.stop.clone(),
+                handled: self.handled.clone(),
+            }
+        }
+    }
+
+    // a runner connecting `client` whose handlers can stop it.
+    fn runner(client: Option<FixClient>) -> SessionRunner<Factory> {
+        let mut runner = SessionRunner::new(Factory {
+            client: Mutex::new(client),
+            stop: StopHandle::new(),
+            handled: Arc::default(),
+        })
+        .unwrap();
+        runner.factory.stop = runner.stop_handle();
+        runner
+    }
+
+    // the counterparty, already logged on.
+    fn logged_on() -> (FixClient, LoopbackStream) {
+        let (ours, mut theirs) = loopback();
+        let logon = Logon::new(1, "them", "us");
+        theirs.write_all(serialize(&logon).as_bytes()).unwrap();
+        (client("us", "them", ours), theirs)
+    }
+
+    #[test]
+    fn test_dropped_action_sender_logs_out_and_returns() {
+        let (ours, mut theirs) = logged_on();
+        let mut runner = runner(Some(ours));
+        runner.logout_timeout = Duration::from_millis(10);
+
+        let (action_tx, action_rx) = channel::<&'static str>();
+        drop(action_tx);
+
+        assert!(runner.run(action_rx).is_ok());
+
+        let mut sent = String::new();
+        theirs.read_to_string(&mut sent).unwrap();
+        assert!(sent.contains("\x0135=5\x01"));
+    }
+
+    #[test]
+    fn test_dropped_action_sender_returns_before_logon() {
+        // nobody logs on.
+        let (ours, mut theirs) = loopback();
+        let mut runner = runner(Some(client("us", "them", ours)));
+
+        let (action_tx, action_rx) = channel::<&'static str>();
+        drop(action_tx);
+
+        let started = Instant::now();
+        assert!(runner.run(action_rx).is_ok());
+        assert!(started.elapsed() < Duration::from_secs(1));
+
+        let mut sent = String::new();
+        theirs.read_to_string(&mut sent).unwrap();
+        assert!(!sent.contains("\x0135=5\x01"));
+    }
+
+    #[test]
+    fn test_stopped_runner_does_not_connect() {
+        let mut runner = runner(None);
+        runner.stop_handle().stop();
+
+        let (_action_tx, action_rx) = channel::<&'static str>();
+        assert!(runner.run(action_rx).is_ok());
+    }
+
+    #[test]
+    fn test_actions_sent_before_stopping_are_handled() {
+        let (ours, mut theirs) = logged_on();
+        let mut runner = runner(Some(ours));
+        runner.logout_timeout = Duration::from_millis(10);
+        let handled = runner.factory.handled.clone();
+
+        let (action_tx, action_rx) = channel();
+        for action in &["stop", "a", "b"] {
+            action_tx.send(*action).unwrap();
+        }
+        assert!(runner.run(action_rx).is_ok());
+        assert_eq!(*handled.lock().unwrap(), vec!["stop", "a", "b"]);
+
+        let mut sent = String::new();
+        theirs.read_to_string(&mut sent).unwrap();
+        assert!(sent.contains("\x0135=5\x01"));
+    }
+
+    #[test]
+    fn test_fatal_handler_error_logs_out_and_fails() {
+        let (ours, mut theirs) = logged_on();
+        let mut runner = runner(Some(ours));
+        runner.logout_timeout = Duration::from_millis(10);
+
+        let (action_tx, action_rx) = channel();
+        action_tx.send("fail").unwrap();
+        match runner.run(action_rx) {
+            Err(FatalError::Handler(reason)) => assert_eq!(reason, "failed"),
+            other => panic!("not failed: {:?}", other),
+        }
+
+        let mut sent = String::new();
+        theirs.read_to_string(&mut sent).unwrap();
+        assert!(sent.contains("\x0135=5\x01"));
+    }
+
+    #[test]
+    fn test_logout_confirmed_by_counterparty_returns_before_the_timeout() {
+        let (ours, mut theirs) = logged_on();
+        let mut runner = runner(Some(ours));
+        runner.logout_timeout = Duration::from_secs(30);
+
+        // answers the Logout of the runner.
+        let counterparty = spawn(move || {
+            let mut sent = String::new();
+            let mut buf = [0u8; 4096];
+            while !sent.contains("\x0135=5\x01") {
+                match theirs.read(&mut buf) {
+                    Ok(size) => sent.push_str(&String::from_utf8_lossy(&buf[..size])),
+                    Err(_) => sleep(Duration::from_millis(1)),
+                }
+            }
+            let logout = Logout {
+                seq: 2,
+                sender: "them".to_string(),
+                target: "us".to_string(),
+                sending_time: Timestamp::now(),
+                text: None,
+            };
+            theirs.write_all(serialize(&logout).as_bytes()).unwrap();
+        });
+
+        let (action_tx, action_rx) = channel::<&'static str>();
+        drop(action_tx);
+        let started = Instant::now();
+        assert!(runner.run(action_rx).is_ok());
+        assert!(started.elapsed() < Duration::from_secs(10));
+        counterparty.join().unwrap();
+    }
+
+    #[test]
+    fn test_action_wakes_the_idle_runner() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let ours = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut theirs, _) = listener.accept().unwrap();
+        let logon = Logon::new(1, "them", "us");
+        theirs.write_all(serialize(&logon).as_bytes()).unwrap();
+
+        let wrapper = PlainStreamWrapper::new(ours);
+        let source = wrapper.event_source().unwrap();
+        let mut ours = client("us", "them", wrapper);
+        ours.set_event_source(source);
+        let mut runner = runner(Some(ours));
+        runner.max_wait = Duration::from_secs(5);
+        runner.logout_timeout = Duration::from_millis(10);
+
+        let (action_tx, action_rx) = runner.action_channel();
+        let started = Instant::now();
+        let stopping = spawn(move || {
+            sleep(Duration::from_millis(100));
+            action_tx.send("stop").unwrap();
+        });
+        assert!(runner.run(action_rx).is_ok());
+        assert!(started.elapsed() < Duration::from_secs(2));
+        stopping.join().unwrap();
+
+        let mut sent = String::new();
+        theirs.read_to_string(&mut sent).unwrap();
+        assert!(sent.contains("\x0135=5\x01"));
+    }
+}
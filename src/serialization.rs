@@ -9,7 +9,11 @@ use super::ParseError;
 
 #[inline]
 pub fn serialize<T: FixSerializable>(t: &T) -> String {
-    let body = t.serialize_body_to_fix();
+    frame(&t.serialize_body_to_fix())
+}
+
+// adds BeginString, BodyLength and CheckSum to a body starting with MsgType.
+pub(crate) fn frame(body: &str) -> String {
     let header = format!("8=FIX.4.4\x019={}\x01", body.len());
     let chksum = checksum(header.as_bytes()) + checksum(body.as_bytes());
     format!("{}{}10={:03}\x01", header, body, chksum)
@@ -0,0 +1,601 @@
+use std;
+use std::fmt::Debug;
+use std::marker::PhantomData;
+use std::str;
+use std::sync::mpsc::{Receiver, TryRecvError};
+use std::time::Duration;
+
+use mio::event::Source;
+use mio::Interest;
+
+use admin;
+use client::{logon_reset, Held};
+use deserialize;
+use interceptor::Intercepted;
+use detail::{find_field, parse_fix_message, FixDeserializable, FixMessageType};
+use parsing::seq_of;
+use ConnectionFailure;
+use FixClient;
+use SendError;
+use FixHandler;
+use HandleErr;
+use ParseError;
+use RawMessage;
+
+const READ_BUFFER_SIZE: usize = 200000;
+// "\x0110=XXX\x01" closes every FIX message.
+const CHECKSUM_FIELD: &[u8] = b"\x0110=";
+const CHECKSUM_FIELD_LENGTH: usize = 8;
+
+pub(crate) const LOGON_MSG_TYPE: &[u8] = b"A";
+const TEXT: u64 = 58;
+
+#[derive(Debug)]
+pub enum SessionError {
+    Io(std::io::Error),
+    Handle(HandleErr),
+    Deserialize(ParseError),
+    // the transport could not be set up, e.g. the TLS handshake of an accepted connection failed.
+    Connection(ConnectionFailure),
+    // the session itself failed to write, e.g. a Logout.
+    Send(SendError),
+}
+
+impl From<std::io::Error> for SessionError {
+    fn from(io_error: std::io::Error) -> SessionError {
+        SessionError::Io(io_error)
+    }
+}
+
+impl From<SendError> for SessionError {
+    fn from(send_error: SendError) -> SessionError {
+        SessionError::Send(send_error)
+    }
+}
+
+impl From<ConnectionFailure> for SessionError {
+    fn from(failure: ConnectionFailure) -> SessionError {
+        SessionError::Connection(failure)
+    }
+}
+
+/// Something that can be driven one step at a time, see `Session::step`.
+pub trait Drive {
+    fn step(&mut self) -> Result<bool, SessionError>;
+
+    // time until `step` has timer work to do.
+    fn next_deadline(&self) -> Option<Duration> {
+        None
+    }
+
+    // readiness source of the transport, without one `step` has to be polled.
+    fn event_source(&mut self) -> Option<&mut Source> {
+        None
+    }
+
+    // readiness of the event source `step` waits for, writable too while sent bytes are buffered.
+    fn interest(&self) -> Interest {
+        Interest::READABLE
+    }
+}
+
+/// One connection of a FIX session: a client, the handler serving it and the actions to execute.
+///
+/// After the handler, a ResendRequest (2) is answered with `FixClient::resend`, and a Logon with
+/// `FixClient::resend_unsent` for what the transport refused before.
+///
+/// Once logged on `step` sends a Heartbeat after a heartbeat interval without sending and a
+/// TestRequest when the counterparty is silent, and fails once it did not even answer that.
+/// Answering TestRequests is up to the handler.
+pub struct Session<'a, Sess, App, H, Action: 'a>
+where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+    H: FixHandler<Sess, App, Action>,
+    Action: Debug,
+{
+    client: FixClient,
+    handler: H,
+    action_rx: &'a Receiver<Action>,
+
+    read_buffer: Vec<u8>,
+    // bytes read but not yet forming a full message.
+    pending: Vec<u8>,
+
+    // `on_connect` and `on_disconnect` were called.
+    connected: bool,
+    disconnected: bool,
+    actions_closed: bool,
+    // taken from `action_rx` before logging on to see whether it was closed, handled once logged on.
+    held_action: Option<Action>,
+    // a TestRequest went out and nothing was received since.
+    test_request_pending: bool,
+
+    _messages: PhantomData<(Sess, App)>,
+}
+
+fn find_subsequence(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+// length of the first complete message in `buf`, None until its checksum field arrived.
+pub(crate) fn frame_length(buf: &[u8]) -> Option<usize> {
+    let end = find_subsequence(buf, CHECKSUM_FIELD)? + CHECKSUM_FIELD_LENGTH;
+    if end > buf.len() {
+        None
+    } else {
+        Some(end)
+    }
+}
+
+impl<'a, Sess, App, H, Action> Session<'a, Sess, App, H, Action>
+where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+    H: FixHandler<Sess, App, Action>,
+    Action: Debug,
+{
+    pub fn new(client: FixClient, handler: H, action_rx: &'a Receiver<Action>) -> Self {
+        Session {
+            client,
+            handler,
+            action_rx,
+
+            read_buffer: vec![0; READ_BUFFER_SIZE],
+            pending: Vec::new(),
+
+            connected: false,
+            disconnected: false,
+            actions_closed: false,
+            held_action: None,
+            test_request_pending: false,
+
+            _messages: PhantomData,
+        }
+    }
+
+    pub fn client(&mut self) -> &mut FixClient {
+        &mut self.client
+    }
+
+    pub fn handler(&mut self) -> &mut H {
+        &mut self.handler
+    }
+
+    // every sender of actions is gone and what they sent was taken.
+    pub fn actions_closed(&self) -> bool {
+        self.actions_closed
+    }
+
+    // counterparty sent a Logout, see `FixClient::logout_received`.
+    pub fn logout_received(&self) -> bool {
+        self.client.logout_received()
+    }
+
+    // writes what the transport did not take before, reads whatever is available, handles complete messages,
+    // sends what the throttle queued and allows, handles one pending action and polls the handler.
+    // Returns whether anything was read, sent or handled.
+    pub fn step(&mut self) -> Result<bool, SessionError> {
+        let result = self.try_step();
+        self.check(result)
+    }
+
+    // reads and handles messages only, returns whether anything was read.
+    pub fn receive(&mut self) -> Result<bool, SessionError> {
+        let result = self.connect().and_then(|()| self.try_receive());
+        self.check(result)
+    }
+
+    // handles bytes read elsewhere, e.g. by the `Acceptor` while identifying the session.
+    pub fn push_received(&mut self, bytes: &[u8]) -> Result<(), SessionError> {
+        self.pending.extend_from_slice(bytes);
+        let result = self.connect().and_then(|()| self.handle_pending());
+        self.check(result)
+    }
+
+    // handles every action sent so far, e.g. before logging out. Those which can no longer be handled
+    // because the session is not logged on are dropped with a warning.
+    pub fn handle_actions(&mut self) -> Result<(), SessionError> {
+        while let Some(action) = self.held_action.take().or_else(|| self.action_rx.try_recv().ok()) {
+            if self.client.is_logged_on() {
+                self.handle_action(action)?;
+            } else {
+                warn!("dropping action, not logged on. {:?}", action);
+            }
+        }
+        Ok(())
+    }
+
+    // sends a Logout through `FixHandler::to_admin`, unlike `FixClient::send_logout`.
+    pub fn send_logout(&mut self, text: Option<String>) -> Result<(), SessionError> {
+        self.call(|_, client| Ok(client.send_logout(text)?))
+    }
+
+    fn try_step(&mut self) -> Result<bool, SessionError> {
+        self.connect()?;
+        let mut busy = self.client.write_buffered()?;
+        busy |= self.try_receive()?;
+        busy |= self.check_timers()?;
+        busy |= self.client.send_queued()? > 0;
+
+        if self.held_action.is_none() {
+            match self.action_rx.try_recv() {
+                Ok(action) => self.held_action = Some(action),
+                Err(TryRecvError::Disconnected) => self.actions_closed = true,
+                Err(TryRecvError::Empty) => {}
+            }
+        }
+        if self.client.is_logged_on() {
+            if let Some(action) = self.held_action.take() {
+                self.handle_action(action)?;
+                busy = true;
+            }
+        }
+
+        self.call(|handler, client| {
+            handler.poll(client);
+            Ok(())
+        })?;
+
+        Ok(busy)
+    }
+
+    fn handle_action(&mut self, action: Action) -> Result<(), SessionError> {
+        info!("got something to do. {:?}", action);
+        self.call(|handler, client| {
+            handler.handle_action(client, action).map_err(|err| {
+                error!("something went wrong while handling action: {:?}", err);
+                SessionError::Handle(err)
+            })
+        })
+    }
+
+    // returns whether a Heartbeat or TestRequest was sent.
+    fn check_timers(&mut self) -> Result<bool, SessionError> {
+        if !self.client.is_logged_on() {
+            return Ok(false);
+        }
+        if self.client.peer_timed_out() {
+            return Err(SessionError::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "counterparty stopped responding",
+            )));
+        }
+
+        let mut sent = false;
+        if self.client.test_request_due() && !self.test_request_pending {
+            self.test_request_pending = true;
+            self.call(|_, client| {
+                let seq = client.get_next_send_seq();
+                let request = admin::TestRequest {
+                    seq,
+                    sender: client.comp_ids().sender.clone(),
+                    target: client.comp_ids().target.clone(),
+                    sending_time: client.sending_time(),
+                    test_req_id: format!("TEST{}", seq),
+                };
+                Ok(client.send(&request)?)
+            })?;
+            sent = true;
+        }
+        if self.client.heartbeat_due() {
+            self.call(|_, client| {
+                let heartbeat = admin::Heartbeat {
+                    seq: client.get_next_send_seq(),
+                    sender: client.comp_ids().sender.clone(),
+                    target: client.comp_ids().target.clone(),
+                    sending_time: client.sending_time(),
+                    test_req_id: None,
+                };
+                Ok(client.send(&heartbeat)?)
+            })?;
+            sent = true;
+        }
+        Ok(sent)
+    }
+
+    fn try_receive(&mut self) -> Result<bool, SessionError> {
+        match self.client.poll(&mut self.read_buffer) {
+            Ok(size) => {
+                self.test_request_pending = false;
+                self.pending.extend_from_slice(&self.read_buffer[..size]);
+                self.handle_pending()?;
+                Ok(true)
+            }
+            Err(err) => match err.kind() {
+                std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut => {
+                    //its okay.
+                    Ok(false)
+                }
+                _ => Err(SessionError::Io(err)),
+            },
+        }
+    }
+
+    fn handle_pending(&mut self) -> Result<(), SessionError> {
+        let mut slice_begin = 0;
+        let mut result = Ok(());
+        while let Some(length) = frame_length(&self.pending[slice_begin..]) {
+            let slice_end = slice_begin + length;
+            let msg = self.pending[slice_begin..slice_end].to_vec();
+            slice_begin = slice_end;
+
+            self.client.log_incoming(&msg);
+            result = self.handle_message(&msg);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.pending.drain(..slice_begin);
+        result
+    }
+
+    fn handle_message(&mut self, msg: &[u8]) -> Result<(), SessionError> {
+        dispatch::<Sess, App, H, Action>(&mut self.handler, &mut self.client, msg)
+    }
+
+    fn call<T, F>(&mut self, callback: F) -> Result<T, SessionError>
+    where
+        F: FnOnce(&mut H, &mut FixClient) -> Result<T, SessionError>,
+    {
+        call_handler::<Sess, App, H, Action, T, F>(&mut self.handler, &mut self.client, callback)
+    }
+
+    // tells the handler about the connection before anything else happens on it.
+    fn connect(&mut self) -> Result<(), SessionError> {
+        if !self.connected {
+            self.connected = true;
+            self.call(|handler, client| {
+                handler.on_connect(client);
+                Ok(())
+            })?;
+        }
+        Ok(())
+    }
+
+    // the runners drop the connection on every error, so the handler learns about it here.
+    // They still log out after a fatal one, which the handler raised itself, see `Drop`.
+    fn check<T>(&mut self, result: Result<T, SessionError>) -> Result<T, SessionError> {
+        match result {
+            Err(SessionError::Handle(HandleErr::Fatal(_))) | Ok(_) => {}
+            Err(ref err) => self.disconnect(Some(err)),
+        }
+        result
+    }
+
+    fn disconnect(&mut self, cause: Option<&SessionError>) {
+        if self.connected && !self.disconnected {
+            self.disconnected = true;
+            self.client.set_logged_on(false);
+            self.handler.on_disconnect(cause);
+        }
+    }
+}
+
+// the connection goes with the session.
+impl<'a, Sess, App, H, Action> Drop for Session<'a, Sess, App, H, Action>
+where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+    H: FixHandler<Sess, App, Action>,
+    Action: Debug,
+{
+    fn drop(&mut self) {
+        self.disconnect(None);
+    }
+}
+
+// hands a complete incoming message to the handler and calls the hooks concerning it.
+pub(crate) fn dispatch<Sess, App, H, Action>(
+    handler: &mut H,
+    client: &mut FixClient,
+    msg: &[u8],
+) -> Result<(), SessionError>
+where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+    H: FixHandler<Sess, App, Action>,
+{
+    let amended;
+    let msg = match client.intercept_inbound(msg) {
+        Intercepted::Unchanged => msg,
+        Intercepted::Amended(fix_msg) => {
+            amended = fix_msg;
+            amended.as_bytes()
+        }
+        Intercepted::Dropped => {
+            debug!("interceptor dropped {:?}", str::from_utf8(msg));
+            client.skip_incoming(msg);
+            return Ok(());
+        }
+        Intercepted::Vetoed(reason) => {
+            client.veto_incoming(msg, &reason)?;
+            return Ok(());
+        }
+    };
+    let parsed = parse_fix_message(msg).map_err(SessionError::Deserialize)?;
+    // even if the handler fails on it.
+    if parsed.msg_type == admin::Logout::MSG_TYPE {
+        client.set_logout_received();
+    }
+
+    // a Logon resetting the sequences is expected with MsgSeqNum 1.
+    if let Some(reset) = logon_reset(msg) {
+        client.on_logon(reset, 1);
+    }
+
+    let expected = client.next_rcv_seq();
+    let seq = seq_of(msg);
+    // a SequenceReset in reset mode does not care about its own MsgSeqNum.
+    if parsed.msg_type != admin::SequenceReset::MSG_TYPE {
+        match seq {
+            Some(received) if received > expected => {
+                client.log_event(&format!("Sequence gap, expected {} got {}", expected, received));
+                call_handler::<Sess, App, H, Action, _, _>(handler, client, |handler, client| {
+                    handler.on_sequence_gap(client, expected, received);
+                    Ok(())
+                })?;
+            }
+            _ => {}
+        }
+    }
+    // one received before, e.g. resent as PossDup, only goes to the handler.
+    if seq.is_none_or(|seq| seq >= expected) {
+        client.received_inbound(msg);
+    }
+
+    if let Ok(resp) = deserialize::<Sess>(msg) {
+        info!("sess << {:?}", resp);
+        call_handler::<Sess, App, H, Action, _, _>(handler, client, |handler, client| {
+            handler.handle_session(client, resp).map_err(|err| {
+                error!("something went wrong while handling sess message: {:?} msg: {:?}", err, str::from_utf8(msg));
+                SessionError::Handle(err)
+            })
+        })?;
+    } else {
+        match deserialize::<App>(msg) {
+            Ok(resp) => {
+                info!("app << {:?}", resp);
+                call_handler::<Sess, App, H, Action, _, _>(handler, client, |handler, client| {
+                    handler.handle_app(client, resp).map_err(|err| {
+                        error!(
+                            "something went wrong while handling app message: {:?} err: {:?}",
+                            str::from_utf8(msg), err
+                        );
+                        SessionError::Handle(err)
+                    })
+                })?;
+            }
+            Err(err) => {
+                error!("failed to derialize :( {}", err);
+                return Err(SessionError::Deserialize(err));
+            }
+        }
+    }
+
+    if parsed.msg_type == LOGON_MSG_TYPE {
+        client.set_logged_on(true);
+        client.resend_unsent()?;
+        call_handler::<Sess, App, H, Action, _, _>(handler, client, |handler, client| {
+            handler.on_logon(client);
+            Ok(())
+        })?;
+    } else if parsed.msg_type == admin::Logout::MSG_TYPE {
+        client.set_logged_on(false);
+        let reason = find_field(parsed.body, TEXT)
+            .ok()
+            .and_then(|text| text)
+            .map(String::from_utf8_lossy);
+        call_handler::<Sess, App, H, Action, _, _>(handler, client, |handler, client| {
+            handler.on_logout(client, reason.as_ref().map(|reason| reason.as_ref()));
+            Ok(())
+        })?;
+    } else if parsed.msg_type == admin::ResendRequest::MSG_TYPE {
+        let request = deserialize::<admin::ResendRequest>(msg).map_err(SessionError::Deserialize)?;
+        client.resend(request.begin_seq_no, request.end_seq_no)?;
+    } else if parsed.msg_type == admin::Reject::MSG_TYPE {
+        if let Ok(reject) = deserialize::<admin::Reject>(msg) {
+            call_handler::<Sess, App, H, Action, _, _>(handler, client, |handler, client| {
+                handler.on_reject(client, &reject);
+                Ok(())
+            })?;
+        }
+    }
+    Ok(())
+}
+
+// runs a handler callback, what it sent goes out once it returned, amended by `to_admin` or `to_app`.
+pub(crate) fn call_handler<Sess, App, H, Action, T, F>(
+    handler: &mut H,
+    client: &mut FixClient,
+    callback: F,
+) -> Result<T, SessionError>
+where
+    Sess: FixDeserializable,
+    App: FixDeserializable,
+    H: FixHandler<Sess, App, Action>,
+    F: FnOnce(&mut H, &mut FixClient) -> Result<T, SessionError>,
+{
+    client.hold();
+    let result = callback(handler, client);
+    // e.g. the Reject and Logout of a failed validation go out as well.
+    let released = release::<Sess, App, H, Action>(handler, client);
+    let value = result?;
+    released?;
+    Ok(value)
+}
+
+fn release<Sess, App, H, Action>(handler: &mut H, client: &mut FixClient) -> Result<(), SessionError>
+where
+    Sess: FixDeserializable,
+    App: FixDeserializable,
+    H: FixHandler<Sess, App, Action>,
+{
+    for held in client.take_held() {
+        let held = match held {
+            Held::Send(fix_msg) => Held::Send(amend::<Sess, App, H, Action>(handler, fix_msg)),
+            Held::Counted(fix_msg) => Held::Counted(amend::<Sess, App, H, Action>(handler, fix_msg)),
+            written => written,
+        };
+        client.send_held(held)?;
+    }
+    Ok(())
+}
+
+fn amend<Sess, App, H, Action>(handler: &mut H, fix_msg: String) -> String
+where
+    Sess: FixDeserializable,
+    App: FixDeserializable,
+    H: FixHandler<Sess, App, Action>,
+{
+    let mut msg = match RawMessage::parse(fix_msg.as_bytes()) {
+        Ok(msg) => msg,
+        Err(err) => {
+            warn!("sending {:?} unamended, it does not parse: {}", fix_msg, err);
+            return fix_msg;
+        }
+    };
+    let original = msg.clone();
+    if msg.is_admin() {
+        handler.to_admin(&mut msg);
+    } else {
+        handler.to_app(&mut msg);
+    }
+
+    if msg == original {
+        fix_msg
+    } else {
+        msg.to_fix()
+    }
+}
+
+impl<'a, Sess, App, H, Action> Drive for Session<'a, Sess, App, H, Action>
+where
+    Sess: FixDeserializable + Debug,
+    App: FixDeserializable + Debug,
+    H: FixHandler<Sess, App, Action>,
+    Action: Debug,
+{
+    fn step(&mut self) -> Result<bool, SessionError> {
+        Session::step(self)
+    }
+
+    fn next_deadline(&self) -> Option<Duration> {
+        self.client.next_timer_deadline()
+    }
+
+    fn event_source(&mut self) -> Option<&mut Source> {
+        self.client.event_source()
+    }
+
+    fn interest(&self) -> Interest {
+        if self.client.has_buffered() {
+            Interest::READABLE | Interest::WRITABLE
+        } else {
+            Interest::READABLE
+        }
+    }
+}
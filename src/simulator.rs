@@ -0,0 +1,610 @@
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use detail::{find_field, validate_fix_message};
+use serialization::frame;
+use session::{frame_length, Drive};
+use {Clock, CompIds, ParseError, SessionError, Stream, SystemClock, Timestamp};
+
+const READ_BUFFER_SIZE: usize = 4096;
+
+/// One step of a `Scenario`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScenarioStep {
+    // waits for a message of the type carrying the fields, Heartbeats in between are ignored.
+    Expect {
+        msg_type: String,
+        fields: Vec<(u64, String)>,
+    },
+    // sends a message with a header of its own, a `$TAG` value copies the field of the last expected message.
+    Send {
+        msg_type: String,
+        fields: Vec<(u64, String)>,
+    },
+    // skips outgoing sequence numbers.
+    Gap(u64),
+    // writes the bytes as they are.
+    Garbled(Vec<u8>),
+    // closes the connection.
+    Disconnect,
+}
+
+/// The script a `Simulator` plays against the session under test.
+///
+/// Built step by step or read from a script with one step per line:
+///
+/// ```text
+/// # comment
+/// expect A
+/// send A 98=0 108=30
+/// expect D 55=EURUSD 54=1
+/// send 8 37=order-1 11=$11 150=0 39=0
+/// gap 2
+/// garbled 8=FIX.4.4|9=5|35=0|10=000|
+/// disconnect
+/// ```
+///
+/// Field values can not contain spaces, the fields of `garbled` are separated by `|`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scenario {
+    steps: Vec<ScenarioStep>,
+}
+
+/// A line of a scenario script which could not be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub reason: ParseError,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.reason)
+    }
+}
+
+fn fields(args: &[&str]) -> Result<Vec<(u64, String)>, ParseError> {
+    args.iter()
+        .map(|arg| {
+            let mut parts = arg.splitn(2, '=');
+            let tag = parts.next().and_then(|tag| tag.parse().ok());
+            match (tag, parts.next()) {
+                (Some(tag), Some(value)) => Ok((tag, value.to_string())),
+                _ => Err("invalid field, expected TAG=VALUE"),
+            }
+        })
+        .collect()
+}
+
+fn owned(fields: &[(u64, &str)]) -> Vec<(u64, String)> {
+    fields.iter().map(|&(tag, value)| (tag, value.to_string())).collect()
+}
+
+impl Scenario {
+    pub fn new() -> Scenario {
+        Scenario::default()
+    }
+
+    pub fn expect(mut self, msg_type: &str, fields: &[(u64, &str)]) -> Scenario {
+        self.steps.push(ScenarioStep::Expect {
+            msg_type: msg_type.to_string(),
+            fields: owned(fields),
+        });
+        self
+    }
+
+    pub fn send(mut self, msg_type: &str, fields: &[(u64, &str)]) -> Scenario {
+        self.steps.push(ScenarioStep::Send {
+            msg_type: msg_type.to_string(),
+            fields: owned(fields),
+        });
+        self
+    }
+
+    pub fn gap(mut self, skipped: u64) -> Scenario {
+        self.steps.push(ScenarioStep::Gap(skipped));
+        self
+    }
+
+    pub fn garbled(mut self, bytes: &[u8]) -> Scenario {
+        self.steps.push(ScenarioStep::Garbled(bytes.to_vec()));
+        self
+    }
+
+    pub fn disconnect(mut self) -> Scenario {
+        self.steps.push(ScenarioStep::Disconnect);
+        self
+    }
+
+    pub fn steps(&self) -> &[ScenarioStep] {
+        &self.steps
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, Error> {
+        let script = fs::read_to_string(path)?;
+        Scenario::parse(&script).map_err(|err| Error::new(ErrorKind::InvalidData, err.to_string()))
+    }
+
+    pub fn parse(script: &str) -> Result<Scenario, ScriptError> {
+        let mut steps = Vec::new();
+        for (index, line) in script.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let step = Scenario::parse_step(line).map_err(|reason| ScriptError {
+                line: index + 1,
+                reason,
+            })?;
+            steps.push(step);
+        }
+        Ok(Scenario { steps })
+    }
+
+    fn parse_step(line: &str) -> Result<ScenarioStep, ParseError> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words[0] {
+            "expect" | "send" => {
+                let msg_type = match words.get(1) {
+                    Some(msg_type) if !msg_type.contains('=') => msg_type.to_string(),
+                    _ => return Err("missing MsgType"),
+                };
+                let fields = fields(&words[2..])?;
+                Ok(if words[0] == "expect" {
+                    ScenarioStep::Expect { msg_type, fields }
+                } else {
+                    ScenarioStep::Send { msg_type, fields }
+                })
+            }
+            "gap" => match words[1..] {
+                [skipped] => skipped.parse().map(ScenarioStep::Gap).map_err(|_| "invalid gap"),
+                _ => Err("invalid gap"),
+            },
+            "garbled" => {
+                let bytes = line["garbled".len()..].trim().replace('|', "\x01");
+                Ok(ScenarioStep::Garbled(bytes.into_bytes()))
+            }
+            "disconnect" if words.len() == 1 => Ok(ScenarioStep::Disconnect),
+            _ => Err("unknown step"),
+        }
+    }
+}
+
+/// Why a `Simulator` stopped before the end of its scenario.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScenarioFailure {
+    // index of the failed step.
+    pub step: usize,
+    pub reason: String,
+    // the message which did not match, if any.
+    pub received: Option<Vec<u8>>,
+}
+
+impl fmt::Display for ScenarioFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "step {}: {}", self.step, self.reason)?;
+        if let Some(ref received) = self.received {
+            write!(
+                f,
+                ", received {}",
+                String::from_utf8_lossy(received).replace('\x01', "|")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// A scripted counterparty, to test how a session copes with a venue without having one.
+///
+/// Whether it acts as acceptor or initiator only depends on whether its scenario starts with
+/// `expect` or `send`. It keeps its own outgoing sequence numbers, answers TestRequests and
+/// ignores Heartbeats unless they are expected.
+///
+/// Drive it with `step`, e.g. in a `LoopbackHarness`, or on its own thread with `run`.
+pub struct Simulator {
+    comp_ids: CompIds,
+    stream: Option<Box<Stream>>,
+    clock: Box<Clock>,
+    scenario: Scenario,
+
+    next_step: usize,
+    send_seq: u64,
+    pending: Vec<u8>,
+    peer_closed: bool,
+    // the message matched by the last `Expect`, for `$TAG` values.
+    last_expected: Option<Vec<u8>>,
+    received: Vec<Vec<u8>>,
+    failure: Option<ScenarioFailure>,
+}
+
+impl Simulator {
+    // the stream has to be non blocking or have a read timeout, see `run`.
+    pub fn new(comp_ids: CompIds, stream: Box<Stream>, scenario: Scenario) -> Simulator {
+        Simulator {
+            comp_ids,
+            stream: Some(stream),
+            clock: Box::new(SystemClock),
+            scenario,
+
+            next_step: 0,
+            send_seq: 1,
+            pending: Vec::new(),
+            peer_closed: false,
+            last_expected: None,
+            received: Vec::new(),
+            failure: None,
+        }
+    }
+
+    pub fn set_clock(&mut self, clock: Box<Clock>) {
+        self.clock = clock;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.failure.is_none() && self.next_step == self.scenario.steps.len()
+    }
+
+    pub fn failure(&self) -> Option<&ScenarioFailure> {
+        self.failure.as_ref()
+    }
+
+    // every message read from the peer, including ignored Heartbeats.
+    pub fn received(&self) -> &[Vec<u8>] {
+        &self.received
+    }
+
+    /// Steps until the scenario is done or failed, waiting at most `timeout` in total.
+    pub fn run(&mut self, timeout: Duration) -> Result<(), ScenarioFailure> {
+        let deadline = Instant::now() + timeout;
+        while !self.is_done() {
+            if let Some(ref failure) = self.failure {
+                return Err(failure.clone());
+            }
+            if !self.advance() {
+                if Instant::now() > deadline {
+                    self.fail("timed out".to_string(), None);
+                } else {
+                    sleep(Duration::from_millis(1));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // runs the steps as far as possible, returns whether anything happened.
+    fn advance(&mut self) -> bool {
+        let mut busy = self.read();
+        while self.failure.is_none() {
+            let step = match self.scenario.steps.get(self.next_step) {
+                Some(step) => step.clone(),
+                None => break,
+            };
+            match step {
+                ScenarioStep::Expect { msg_type, fields } => match self.next_message() {
+                    Some(msg) => self.expect(msg, &msg_type, &fields),
+                    None => {
+                        if self.peer_closed && self.failure.is_none() {
+                            self.fail("disconnected by the peer".to_string(), None);
+                        }
+                        break;
+                    }
+                },
+                ScenarioStep::Send { msg_type, fields } => self.send(&msg_type, &fields),
+                ScenarioStep::Gap(skipped) => self.send_seq += skipped,
+                ScenarioStep::Garbled(bytes) => self.write(&bytes),
+                ScenarioStep::Disconnect => self.stream = None,
+            }
+            if self.failure.is_none() {
+                self.next_step += 1;
+            }
+            busy = true;
+        }
+        busy
+    }
+
+    fn fail(&mut self, reason: String, received: Option<Vec<u8>>) {
+        if self.failure.is_none() {
+            self.failure = Some(ScenarioFailure {
+                step: self.next_step,
+                reason,
+                received,
+            });
+        }
+    }
+
+    fn read(&mut self) -> bool {
+        let mut busy = false;
+        let mut buf = [0u8; READ_BUFFER_SIZE];
+        while let Some(ref mut stream) = self.stream {
+            match stream.read(&mut buf) {
+                Ok(0) => {
+                    self.peer_closed = true;
+                    break;
+                }
+                Ok(size) => {
+                    self.pending.extend_from_slice(&buf[..size]);
+                    busy = true;
+                }
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => break,
+                Err(err) => {
+                    self.fail(format!("read failed: {}", err), None);
+                    break;
+                }
+            }
+        }
+        busy
+    }
+
+    // the next message not handled by the simulator itself.
+    fn next_message(&mut self) -> Option<Vec<u8>> {
+        while let Some(length) = frame_length(&self.pending) {
+            let msg: Vec<u8> = self.pending.drain(..length).collect();
+            self.received.push(msg.clone());
+
+            let expecting_heartbeat = match self.scenario.steps.get(self.next_step) {
+                Some(ScenarioStep::Expect { msg_type, .. }) => msg_type == "0" || msg_type == "1",
+                _ => false,
+            };
+            if !expecting_heartbeat {
+                match validate_fix_message(&msg).map(|parsed| parsed.msg_type) {
+                    Ok(b"0") => continue,
+                    Ok(b"1") => {
+                        let test_req_id = field(&msg, 112).unwrap_or_default();
+                        self.send("0", &[(112, test_req_id)]);
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+            return Some(msg);
+        }
+        None
+    }
+
+    fn expect(&mut self, msg: Vec<u8>, msg_type: &str, fields: &[(u64, String)]) {
+        let mismatch = match validate_fix_message(&msg) {
+            Err(err) => Some(err.to_string()),
+            Ok(ref parsed) if parsed.msg_type != msg_type.as_bytes() => Some(format!("expected MsgType {}", msg_type)),
+            Ok(_) => fields
+                .iter()
+                .find(|&&(tag, ref value)| field(&msg, tag).as_ref() != Some(value))
+                .map(|&(tag, ref value)| format!("expected {}={}", tag, value)),
+        };
+        match mismatch {
+            Some(reason) => self.fail(reason, Some(msg)),
+            None => self.last_expected = Some(msg),
+        }
+    }
+
+    fn send(&mut self, msg_type: &str, fields: &[(u64, String)]) {
+        let mut body = format!(
+            "35={}\x0134={}\x0149={}\x0156={}\x0152={}\x01",
+            msg_type,
+            self.send_seq,
+            self.comp_ids.sender,
+            self.comp_ids.target,
+            Timestamp::from_clock(&*self.clock)
+        );
+        for &(tag, ref value) in fields {
+            let value = if let Some(copied_tag) = value.strip_prefix('$') {
+                let copied = copied_tag
+                    .parse()
+                    .ok()
+                    .and_then(|tag| self.last_expected.as_ref().and_then(|msg| field(msg, tag)));
+                match copied {
+                    Some(copied) => copied,
+                    None => return self.fail(format!("no field to copy for {}={}", tag, value), None),
+                }
+            } else {
+                value.clone()
+            };
+            body.push_str(&format!("{}={}\x01", tag, value));
+        }
+        self.send_seq += 1;
+        self.write(frame(&body).as_bytes());
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        let result = match self.stream {
+            Some(ref mut stream) => stream.write_all(bytes).and_then(|_| stream.flush()),
+            None => Err(Error::new(ErrorKind::NotConnected, "disconnected by the scenario")),
+        };
+        if let Err(err) = result {
+            self.fail(format!("write failed: {}", err), None);
+        }
+    }
+}
+
+fn field(msg: &[u8], tag: u64) -> Option<String> {
+    let parsed = validate_fix_message(msg).ok()?;
+    let value = find_field(parsed.body, tag).ok()??;
+    Some(String::from_utf8_lossy(value).into_owned())
+}
+
+impl Drive for Simulator {
+    // failures are kept for `failure` rather than returned, so a harness can keep stepping the other side.
+    fn step(&mut self) -> Result<bool, SessionError> {
+        Ok(self.advance())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::mpsc::{channel, Receiver};
+
+    use super::*;
+    use admin::ResendRequest;
+    use client::MessageValidationErr;
+    use session::Session;
+    use testing::{AppMsg, Logon, NewOrder, SessionMsg, TestHandler};
+    use {loopback, FixClient, HandleErr};
+
+    // sends an order once logged in and asks for a resend when reports go missing.
+    struct Trader {
+        next_rcv: u64,
+        reports: Vec<(String, Option<String>)>,
+    }
+
+    impl Trader {
+        fn check_seq(&mut self, client: &mut FixClient, seq: u64) -> Result<(), HandleErr> {
+            if seq > self.next_rcv {
+                let seq = client.get_next_send_seq();
+                client.send(&ResendRequest {
+                    seq,
+                    sender: client.comp_ids().sender.clone(),
+                    target: client.comp_ids().target.clone(),
+                    sending_time: client.sending_time(),
+                    begin_seq_no: self.next_rcv,
+                    end_seq_no: 0,
+                })?;
+                return Err(HandleErr::MessageValidationErr(MessageValidationErr::SeqNumOutOfOrder));
+            }
+            self.next_rcv += 1;
+            Ok(())
+        }
+    }
+
+    impl TestHandler for Trader {
+        type Action = ();
+
+        fn handle_session(&mut self, client: &mut FixClient, msg: SessionMsg) -> Result<(), HandleErr> {
+            if let SessionMsg::Logon(logon) = msg {
+                self.check_seq(client, logon.seq)?;
+                let order = NewOrder {
+                    symbol: Some("EURUSD".to_string()),
+                    side: Some("1".to_string()),
+                    ..NewOrder::next(client, "order-1")
+                };
+                client.send(&order)?;
+            }
+            Ok(())
+        }
+
+        fn handle_app(&mut self, client: &mut FixClient, msg: AppMsg) -> Result<(), HandleErr> {
+            if let AppMsg::ExecReport(report) = msg {
+                self.check_seq(client, report.seq)?;
+                self.reports.push((report.cl_ord_id, report.exec_type));
+            }
+            Ok(())
+        }
+    }
+
+    fn comp_ids(sender: &str, target: &str) -> CompIds {
+        CompIds {
+            sender: sender.to_string(),
+            target: target.to_string(),
+        }
+    }
+
+    fn trader_session(stream: Box<Stream>, actions: &Receiver<()>) -> Session<'_, SessionMsg, AppMsg, Trader, ()> {
+        let mut client = FixClient::new(comp_ids("trader", "venue"), stream);
+        let logon = Logon {
+            encrypt_method: Some(0),
+            heart_bt_int: Some(30),
+            ..Logon::next(&mut client)
+        };
+        client.send(&logon).unwrap();
+        let trader = Trader {
+            next_rcv: 1,
+            reports: Vec::new(),
+        };
+        Session::new(client, trader, actions)
+    }
+
+    #[test]
+    fn test_parse_script() {
+        let scenario = Scenario::parse(include_str!("../testdata/scenarios/gap_after_ack.fix")).unwrap();
+        assert_eq!(
+            scenario.steps()[0],
+            ScenarioStep::Expect {
+                msg_type: "A".to_string(),
+                fields: vec![(98, "0".to_string())],
+            }
+        );
+        assert_eq!(scenario.steps()[4], ScenarioStep::Gap(2));
+        assert_eq!(
+            scenario.steps()[7],
+            ScenarioStep::Garbled(b"8=FIX.4.4\x019=9\x0135=8\x0134=\x0110=000\x01".to_vec())
+        );
+        assert_eq!(scenario.steps()[8], ScenarioStep::Disconnect);
+
+        let error = |script| Scenario::parse(script).unwrap_err().to_string();
+        assert_eq!(error("expect A\n\nsend 98=0"), "line 3: missing MsgType");
+        assert_eq!(error("expect D 55"), "line 1: invalid field, expected TAG=VALUE");
+        assert_eq!(error("gap some"), "line 1: invalid gap");
+        assert_eq!(error("reconnect"), "line 1: unknown step");
+    }
+
+    #[test]
+    fn test_scenario_with_gap_and_garbled_message() {
+        let scenario = Scenario::parse(include_str!("../testdata/scenarios/gap_after_ack.fix")).unwrap();
+        let (ours, theirs) = loopback();
+        let (_actions_tx, actions) = channel();
+        let mut trader = trader_session(Box::new(ours), &actions);
+        let mut venue = Simulator::new(comp_ids("venue", "trader"), Box::new(theirs), scenario);
+
+        let mut errors = Vec::new();
+        for _ in 0..20 {
+            venue.step().unwrap();
+            match trader.step() {
+                Err(SessionError::Io(err)) => {
+                    // the disconnect.
+                    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+                    break;
+                }
+                Err(err) => errors.push(err),
+                Ok(_) => {}
+            }
+        }
+
+        assert_eq!(venue.failure(), None);
+        assert!(venue.is_done());
+        assert_eq!(trader.handler().reports, vec![("order-1".to_string(), Some("0".to_string()))]);
+        // the gap, answered by a ResendRequest, and the garbled message.
+        assert_eq!(errors.len(), 2, "{:?}", errors);
+        assert!(matches!(
+            errors[0],
+            SessionError::Handle(HandleErr::MessageValidationErr(MessageValidationErr::SeqNumOutOfOrder))
+        ));
+        assert!(matches!(errors[1], SessionError::Deserialize(_)));
+    }
+
+    #[test]
+    fn test_scenario_failure() {
+        let (ours, theirs) = loopback();
+        let (_actions_tx, actions) = channel();
+        let mut trader = trader_session(Box::new(ours), &actions);
+        let scenario = Scenario::new()
+            .expect("A", &[])
+            .send("A", &[(98, "0"), (108, "30")])
+            .expect("D", &[(55, "EURUSD"), (54, "2")]);
+        let mut venue = Simulator::new(comp_ids("venue", "trader"), Box::new(theirs), scenario);
+
+        for _ in 0..10 {
+            trader.step().unwrap();
+            venue.step().unwrap();
+        }
+        let failure = venue.failure().unwrap();
+        assert_eq!(failure.step, 2);
+        assert_eq!(failure.reason, "expected 54=2");
+        assert_eq!(
+            field(failure.received.as_ref().unwrap(), 11),
+            Some("order-1".to_string())
+        );
+        assert!(!venue.is_done());
+
+        // the timeout of `run` applies while waiting for the peer.
+        let (_ours, theirs) = loopback();
+        let mut venue = Simulator::new(
+            comp_ids("venue", "trader"),
+            Box::new(theirs),
+            Scenario::new().expect("A", &[]),
+        );
+        let failure = venue.run(Duration::from_millis(10)).unwrap_err();
+        assert_eq!(failure.to_string(), "step 0: timed out");
+    }
+}
@@ -0,0 +1,536 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::str;
+use std::sync::{Arc, Mutex};
+
+use detail::{find_field, parse_fix_field, parse_fix_message};
+use serialization::frame;
+use ParseError;
+
+const POSS_DUP_FLAG: u64 = 43;
+const SENDING_TIME: u64 = 52;
+const ORIG_SENDING_TIME: u64 = 122;
+const CHECKSUM_ID: u64 = 10;
+
+// admin messages are gap filled instead of resent, see `FixClient::resend`.
+const ADMIN_MSG_TYPES: &[&[u8]] = &[b"0", b"1", b"2", b"3", b"4", b"5", b"A"];
+
+/// Outbound messages of a session and its sequence numbers, kept across reconnects.
+///
+/// A `FixClient` given a store with `FixClient::set_store` continues from the stored sequence
+/// numbers and stores every message before writing it. Messages which could not be written are
+/// marked unsent until `FixClient::resend_unsent` sends them again.
+pub trait MessageStore {
+    fn store(&mut self, seq: u64, msg: &str);
+    // stored messages with begin <= seq <= end, end 0 meaning up to the last one.
+    fn get(&self, begin: u64, end: u64) -> Vec<(u64, String)>;
+
+    fn mark_unsent(&mut self, seq: u64);
+    // returns the unsent seqs ascending and forgets them.
+    fn take_unsent(&mut self) -> Vec<u64>;
+
+    fn next_sender_seq(&self) -> u64;
+    fn next_target_seq(&self) -> u64;
+    fn set_next_target_seq(&mut self, seq: u64);
+
+    // forgets every message and starts both sequences from 1. The `FixClient` does so when a Logon with
+    // ResetSeqNumFlag (141) goes out or comes in.
+    fn reset(&mut self);
+}
+
+#[derive(Debug)]
+struct MemoryStoreState {
+    messages: BTreeMap<u64, String>,
+    unsent: BTreeSet<u64>,
+    next_sender_seq: u64,
+    next_target_seq: u64,
+}
+
+/// `MessageStore` kept in memory, clones share the same messages.
+///
+/// Hand a clone to every `FixClient` made by the factory to keep the session across reconnects.
+#[derive(Debug, Clone)]
+pub struct MemoryStore {
+    state: Arc<Mutex<MemoryStoreState>>,
+}
+
+impl Default for MemoryStore {
+    fn default() -> MemoryStore {
+        MemoryStore {
+            state: Arc::new(Mutex::new(MemoryStoreState {
+                messages: BTreeMap::new(),
+                unsent: BTreeSet::new(),
+                next_sender_seq: 1,
+                next_target_seq: 1,
+            })),
+        }
+    }
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore::default()
+    }
+}
+
+impl MessageStore for MemoryStore {
+    fn store(&mut self, seq: u64, msg: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.messages.insert(seq, msg.to_string());
+        state.next_sender_seq = state.next_sender_seq.max(seq + 1);
+    }
+
+    fn get(&self, begin: u64, end: u64) -> Vec<(u64, String)> {
+        let state = self.state.lock().unwrap();
+        let end = if end == 0 { u64::MAX } else { end };
+        if begin > end {
+            return Vec::new();
+        }
+        state
+            .messages
+            .range(begin..=end)
+            .map(|(&seq, msg)| (seq, msg.clone()))
+            .collect()
+    }
+
+    fn mark_unsent(&mut self, seq: u64) {
+        self.state.lock().unwrap().unsent.insert(seq);
+    }
+
+    fn take_unsent(&mut self) -> Vec<u64> {
+        let mut state = self.state.lock().unwrap();
+        let unsent = state.unsent.iter().cloned().collect();
+        state.unsent.clear();
+        unsent
+    }
+
+    fn next_sender_seq(&self) -> u64 {
+        self.state.lock().unwrap().next_sender_seq
+    }
+
+    fn next_target_seq(&self) -> u64 {
+        self.state.lock().unwrap().next_target_seq
+    }
+
+    fn set_next_target_seq(&mut self, seq: u64) {
+        self.state.lock().unwrap().next_target_seq = seq;
+    }
+
+    fn reset(&mut self) {
+        let mut state = self.state.lock().unwrap();
+        state.messages.clear();
+        state.unsent.clear();
+        state.next_sender_seq = 1;
+        state.next_target_seq = 1;
+    }
+}
+
+// a message answering a ResendRequest, see `resend_plan`.
+#[derive(Debug, PartialEq)]
+pub(crate) enum Resend {
+    // a stored application message, to be sent again as it is.
+    Message(String),
+    // a gap fill in place of `seq`, continuing at `new_seq_no`.
+    GapFill { seq: u64, new_seq_no: u64 },
+}
+
+// answers a ResendRequest for `begin` to `end` from the messages stored in that range: application
+// messages are resent as PossDup copies, admin messages and those missing are gap filled.
+pub(crate) fn resend_plan(
+    stored: Vec<(u64, String)>,
+    begin: u64,
+    end: u64,
+    sending_time: &str,
+) -> Result<Vec<Resend>, ParseError> {
+    let mut plan = Vec::new();
+    let mut next = begin;
+    // first seq of the gap fill to send before the next resent message.
+    let mut gap_from = None;
+    for (seq, msg) in stored {
+        if seq > next {
+            gap_from = gap_from.or(Some(next));
+        }
+        next = seq + 1;
+        if is_admin(&msg)? {
+            gap_from = gap_from.or(Some(seq));
+            continue;
+        }
+
+        if let Some(from) = gap_from.take() {
+            plan.push(Resend::GapFill {
+                seq: from,
+                new_seq_no: seq,
+            });
+        }
+        plan.push(Resend::Message(poss_dup_copy(&msg, sending_time)?));
+    }
+    if next <= end {
+        gap_from = gap_from.or(Some(next));
+    }
+    if let Some(from) = gap_from {
+        plan.push(Resend::GapFill {
+            seq: from,
+            new_seq_no: end + 1,
+        });
+    }
+    Ok(plan)
+}
+
+pub(crate) fn is_admin(msg: &str) -> Result<bool, ParseError> {
+    let parsed = parse_fix_message(msg.as_bytes())?;
+    Ok(is_admin_msg_type(parsed.msg_type))
+}
+
+pub(crate) fn is_admin_msg_type(msg_type: &[u8]) -> bool {
+    ADMIN_MSG_TYPES.contains(&msg_type)
+}
+
+// copy of a stored message to be resent: PossDupFlag set, original SendingTime moved to
+// OrigSendingTime and the current one put in its place.
+pub(crate) fn poss_dup_copy(msg: &str, sending_time: &str) -> Result<String, ParseError> {
+    let parsed = parse_fix_message(msg.as_bytes())?;
+    let msg_type = str::from_utf8(parsed.msg_type).map_err(|_| "MsgType is not UTF8")?;
+    let mut body = format!("35={}\x01", msg_type);
+    // a message resent before keeps the time of its first transmission.
+    let orig_sending_time = match find_field(parsed.body, ORIG_SENDING_TIME)? {
+        Some(value) => Some(str::from_utf8(value).map_err(|_| "OrigSendingTime is not UTF8")?),
+        None => None,
+    };
+
+    let mut input = parsed.body;
+    while !input.is_empty() {
+        let field = parse_fix_field(input)?;
+        input = &input[field.length..];
+        let value = str::from_utf8(field.value).map_err(|_| "field is not UTF8")?;
+        match field.id {
+            CHECKSUM_ID => break,
+            POSS_DUP_FLAG | ORIG_SENDING_TIME => {}
+            SENDING_TIME => {
+                body.push_str(&format!(
+                    "{}={}\x01{}=Y\x01{}={}\x01",
+                    SENDING_TIME,
+                    sending_time,
+                    POSS_DUP_FLAG,
+                    ORIG_SENDING_TIME,
+                    orig_sending_time.unwrap_or(value)
+                ));
+            }
+            id => body.push_str(&format!("{}={}\x01", id, value)),
+        }
+    }
+
+    Ok(frame(&body))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Error, ErrorKind, Read, Write};
+    use std::sync::mpsc::channel;
+
+    use super::*;
+    use admin::{Flag, Heartbeat, Logout, ResendRequest, SequenceReset};
+    use parsing::seq_of;
+    use session::frame_length;
+    use testing::{self, Logon, NewOrder, SessionMsg, TestHandler};
+    use {deserialize, serialize, FixClient, FixHeader, HandleErr, SendError, Session, Timestamp};
+
+    // collects what is written, or refuses every write once broken.
+    struct Transport {
+        written: Arc<Mutex<Vec<u8>>>,
+        broken: bool,
+    }
+
+    impl Read for Transport {
+        fn read(&mut self, _buf: &mut [u8]) -> Result<usize, Error> {
+            Err(Error::new(ErrorKind::WouldBlock, "nothing to read"))
+        }
+    }
+
+    impl Write for Transport {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+            if self.broken {
+                return Err(Error::new(ErrorKind::BrokenPipe, "broken"));
+            }
+            self.written.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), Error> {
+            Ok(())
+        }
+    }
+
+    fn client(store: &MemoryStore, broken: bool) -> (FixClient, Arc<Mutex<Vec<u8>>>) {
+        let (mut client, written) = unstored(broken);
+        client.set_store(Box::new(store.clone()));
+        (client, written)
+    }
+
+    fn unstored(broken: bool) -> (FixClient, Arc<Mutex<Vec<u8>>>) {
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let transport = Transport {
+            written: written.clone(),
+            broken,
+        };
+        (testing::client("a", "b", transport), written)
+    }
+
+    fn messages(written: &Arc<Mutex<Vec<u8>>>) -> Vec<Vec<u8>> {
+        let written = written.lock().unwrap();
+        let mut messages = Vec::new();
+        let mut rest = &written[..];
+        while let Some(length) = frame_length(rest) {
+            messages.push(rest[..length].to_vec());
+            rest = &rest[length..];
+        }
+        messages
+    }
+
+    fn heartbeat(seq: u64) -> String {
+        serialize(&Heartbeat {
+            seq,
+            sender: "a".to_string(),
+            target: "b".to_string(),
+            sending_time: Timestamp::now(),
+            test_req_id: Some("x".to_string()),
+        })
+    }
+
+    #[test]
+    fn test_memory_store_is_shared_by_clones() {
+        let mut store = MemoryStore::new();
+        let clone = store.clone();
+        store.store(1, &heartbeat(1));
+        store.store(2, &heartbeat(2));
+        store.store(3, &heartbeat(3));
+        store.mark_unsent(3);
+        store.mark_unsent(2);
+
+        assert_eq!(clone.next_sender_seq(), 4);
+        assert_eq!(clone.get(2, 0).len(), 2);
+        assert_eq!(clone.get(2, 2)[0].0, 2);
+        assert_eq!(store.take_unsent(), vec![2, 3]);
+        assert!(store.take_unsent().is_empty());
+    }
+
+    #[test]
+    fn test_poss_dup_copy() {
+        let original = heartbeat(7);
+        let copy = poss_dup_copy(&original, "20300101-00:00:00.000").unwrap();
+        assert_eq!(seq_of(copy.as_bytes()), Some(7));
+        assert_eq!(is_admin(&copy), Ok(true));
+
+        let parsed = parse_fix_message(copy.as_bytes()).unwrap();
+        let orig_sending_time = find_field(parse_fix_message(original.as_bytes()).unwrap().body, 52).unwrap();
+        assert_eq!(find_field(parsed.body, 122).unwrap(), orig_sending_time);
+        assert_eq!(find_field(parsed.body, 112).unwrap(), Some(&b"x"[..]));
+        let resent: Heartbeat = deserialize(copy.as_bytes()).unwrap();
+        assert_eq!(resent.seq(), 7);
+
+        // a resent message keeps its OrigSendingTime and gets a single PossDupFlag.
+        let reset = serialize(&SequenceReset {
+            seq: 3,
+            sender: "a".to_string(),
+            target: "b".to_string(),
+            sending_time: Timestamp::now(),
+            poss_dup: Some(Flag::Yes),
+            orig_sending_time: Some(Timestamp::now()),
+            gap_fill: Some(Flag::Yes),
+            new_seq_no: 5,
+        });
+        let copy = poss_dup_copy(&reset, "20300101-00:00:00.000").unwrap();
+        assert_eq!(copy.matches("\x0143=").count(), 1);
+        assert_eq!(copy.matches("\x01122=").count(), 1);
+        assert_eq!(
+            find_field(parse_fix_message(copy.as_bytes()).unwrap().body, 122).unwrap(),
+            find_field(parse_fix_message(reset.as_bytes()).unwrap().body, 122).unwrap()
+        );
+        let resent: SequenceReset = deserialize(copy.as_bytes()).unwrap();
+        assert_eq!(resent.new_seq_no, 5);
+    }
+
+    #[test]
+    fn test_unsent_message_is_resent_after_reconnect() {
+        let store = MemoryStore::new();
+        let (mut broken, _) = client(&store, true);
+        let first = NewOrder::next(&mut broken, "1");
+        match broken.send(&first) {
+            Err(SendError::Io(ref err)) if err.kind() == ErrorKind::BrokenPipe => {}
+            other => panic!("unexpected {:?}", other),
+        }
+
+        let (mut client, written) = client(&store, false);
+        assert_eq!(client.get_next_send_seq(), 2);
+        assert_eq!(client.resend_unsent().unwrap(), 1);
+        assert_eq!(client.resend_unsent().unwrap(), 0);
+
+        let resent = messages(&written);
+        assert_eq!(resent.len(), 1);
+        let resent: NewOrder = deserialize(&resent[0]).unwrap();
+        assert_eq!(resent.seq, 1);
+        assert_eq!(resent.poss_dup, Some(Flag::Yes));
+        assert_eq!(resent.cl_ord_id, "1");
+    }
+
+    #[test]
+    fn test_resend_gap_fills_admin_messages() {
+        let store = MemoryStore::new();
+        let (mut client, written) = client(&store, false);
+        let first = NewOrder::next(&mut client, "1");
+        client.send(&first).unwrap();
+        let logout = Logout {
+            seq: client.get_next_send_seq(),
+            sender: "a".to_string(),
+            target: "b".to_string(),
+            sending_time: client.sending_time(),
+            text: None,
+        };
+        client.send(&logout).unwrap();
+        let third = NewOrder::next(&mut client, "3");
+        client.send(&third).unwrap();
+        written.lock().unwrap().clear();
+
+        assert_eq!(client.resend(1, 0).unwrap(), 2);
+        let resent = messages(&written);
+        assert_eq!(resent.len(), 3);
+        let gap_fill: SequenceReset = deserialize(&resent[1]).unwrap();
+        assert_eq!((gap_fill.seq, gap_fill.new_seq_no), (2, 3));
+        assert_eq!(gap_fill.gap_fill, Some(Flag::Yes));
+        let third: NewOrder = deserialize(&resent[2]).unwrap();
+        assert_eq!(third.cl_ord_id, "3");
+    }
+
+    #[test]
+    fn test_resend_gap_fills_what_is_not_stored() {
+        let (mut client, written) = unstored(false);
+        for id in &["1", "2"] {
+            let order = NewOrder::next(&mut client, id);
+            client.send(&order).unwrap();
+        }
+        written.lock().unwrap().clear();
+
+        assert_eq!(client.resend(1, 0).unwrap(), 0);
+        assert_eq!(client.resend(2, 10).unwrap(), 0);
+        // nothing was sent from 3 on.
+        assert_eq!(client.resend(3, 0).unwrap(), 0);
+        let gap_fills: Vec<(u64, u64)> = messages(&written)
+            .iter()
+            .map(|msg| deserialize::<SequenceReset>(msg).unwrap())
+            .map(|reset| (reset.seq, reset.new_seq_no))
+            .collect();
+        assert_eq!(gap_fills, vec![(1, 3), (2, 3)]);
+    }
+
+    struct Handler;
+
+    impl TestHandler for Handler {
+        type Action = ();
+
+        fn handle_session(&mut self, client: &mut FixClient, msg: SessionMsg) -> Result<(), HandleErr> {
+            match msg {
+                SessionMsg::Logon(logon) => {
+                    client.validate_msg(&logon)?;
+                    let answer = Logon {
+                        reset_seq_num: logon.reset_seq_num,
+                        ..Logon::next(client)
+                    };
+                    client.send(&answer)?;
+                }
+                SessionMsg::ResendRequest(request) => client.validate_msg(&request)?,
+                _ => {}
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_session_resends_after_logon_and_answers_resend_request() {
+        let store = MemoryStore::new();
+        let (mut broken, _) = client(&store, true);
+        let first = NewOrder::next(&mut broken, "1");
+        assert!(broken.send(&first).is_err());
+
+        let (client, written) = client(&store, false);
+        let (_actions_tx, actions) = channel();
+        let mut session = Session::new(client, Handler, &actions);
+        session
+            .push_received(serialize(&Logon::new(1, "b", "a")).as_bytes())
+            .unwrap();
+        let request = ResendRequest {
+            seq: 2,
+            sender: "b".to_string(),
+            target: "a".to_string(),
+            sending_time: Timestamp::now(),
+            begin_seq_no: 1,
+            end_seq_no: 0,
+        };
+        session.push_received(serialize(&request).as_bytes()).unwrap();
+
+        let sent = messages(&written);
+        assert_eq!(sent.len(), 4);
+        let logon: Logon = deserialize(&sent[0]).unwrap();
+        assert_eq!(logon.seq, 2);
+        for resent in &sent[1..3] {
+            let order: NewOrder = deserialize(resent).unwrap();
+            assert_eq!((order.seq, order.poss_dup), (1, Some(Flag::Yes)));
+        }
+        let gap_fill: SequenceReset = deserialize(&sent[3]).unwrap();
+        assert_eq!((gap_fill.seq, gap_fill.new_seq_no), (2, 3));
+    }
+
+    #[test]
+    fn test_logon_with_reset_seq_num_flag_resets_the_store() {
+        let store = MemoryStore::new();
+        let (mut acceptor, written) = client(&store, false);
+        for id in &["1", "2"] {
+            let order = NewOrder::next(&mut acceptor, id);
+            acceptor.send(&order).unwrap();
+        }
+        acceptor.set_next_seqs(3, 5);
+        written.lock().unwrap().clear();
+
+        // the counterparty starts over, the answer resets nothing again.
+        let (_actions_tx, actions) = channel();
+        let mut session = Session::new(acceptor, Handler, &actions);
+        let reset = Logon {
+            reset_seq_num: Some(Flag::Yes),
+            ..Logon::new(1, "b", "a")
+        };
+        session.push_received(serialize(&reset).as_bytes()).unwrap();
+        let acceptor = session.client();
+        assert_eq!((acceptor.next_send_seq(), acceptor.next_rcv_seq()), (2, 2));
+        assert_eq!((store.next_sender_seq(), store.next_target_seq()), (2, 2));
+        assert_eq!(store.get(1, 0).len(), 1);
+
+        // the orders from before the reset are not resent.
+        let request = ResendRequest {
+            seq: 2,
+            sender: "b".to_string(),
+            target: "a".to_string(),
+            sending_time: Timestamp::now(),
+            begin_seq_no: 1,
+            end_seq_no: 0,
+        };
+        session.push_received(serialize(&request).as_bytes()).unwrap();
+        let sent = messages(&written);
+        assert_eq!(sent.len(), 2);
+        let answer: Logon = deserialize(&sent[0]).unwrap();
+        assert_eq!((answer.seq, answer.reset_seq_num), (1, Some(Flag::Yes)));
+        let gap_fill: SequenceReset = deserialize(&sent[1]).unwrap();
+        assert_eq!((gap_fill.seq, gap_fill.new_seq_no), (1, 2));
+
+        // so does one sent.
+        let store = MemoryStore::new();
+        let (mut initiator, _) = client(&store, false);
+        let order = NewOrder::next(&mut initiator, "1");
+        initiator.send(&order).unwrap();
+        initiator.set_next_seqs(1, 7);
+        let reset = Logon {
+            reset_seq_num: Some(Flag::Yes),
+            ..Logon::next(&mut initiator)
+        };
+        initiator.send(&reset).unwrap();
+        assert_eq!((initiator.next_send_seq(), initiator.next_rcv_seq()), (2, 1));
+        assert_eq!(store.get(1, 0), vec![(1, serialize(&reset))]);
+        assert_eq!(store.next_target_seq(), 1);
+    }
+}
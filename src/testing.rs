@@ -0,0 +1,279 @@
+// messages and helpers shared by the tests of the other modules.
+
+use std::io::Read;
+
+use admin::{Flag, Heartbeat, Logout, Reject, ResendRequest, TestRequest};
+use loopback::LoopbackStream;
+use session::SessionError;
+use {CompIds, FixClient, FixHandler, HandleErr, RawMessage, Stream, Timestamp};
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "A"]
+pub(crate) struct Logon {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+    #[id = "98"]
+    pub encrypt_method: Option<u64>,
+    #[id = "108"]
+    pub heart_bt_int: Option<u64>,
+    #[id = "141"]
+    pub reset_seq_num: Option<Flag>,
+    #[id = "554"]
+    pub password: Option<String>,
+}
+
+impl Logon {
+    // a Logon written by hand, e.g. by the counterparty of a test.
+    pub fn new(seq: u64, sender: &str, target: &str) -> Logon {
+        Logon {
+            seq,
+            sender: sender.to_string(),
+            target: target.to_string(),
+            sending_time: Timestamp::now(),
+            encrypt_method: None,
+            heart_bt_int: None,
+            reset_seq_num: None,
+            password: None,
+        }
+    }
+
+    pub fn next(client: &mut FixClient) -> Logon {
+        let (seq, sender, target, sending_time) = header(client);
+        Logon {
+            sending_time,
+            ..Logon::new(seq, &sender, &target)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "D"]
+pub(crate) struct NewOrder {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+    #[id = "43"]
+    pub poss_dup: Option<Flag>,
+    #[id = "122"]
+    pub orig_sending_time: Option<Timestamp>,
+    #[id = "11"]
+    pub cl_ord_id: String,
+    #[id = "55"]
+    pub symbol: Option<String>,
+    #[id = "54"]
+    pub side: Option<String>,
+    #[id = "38"]
+    pub qty: Option<u64>,
+    #[id = "1"]
+    pub account: Option<String>,
+}
+
+impl NewOrder {
+    pub fn new(seq: u64, sender: &str, target: &str, cl_ord_id: &str) -> NewOrder {
+        NewOrder {
+            seq,
+            sender: sender.to_string(),
+            target: target.to_string(),
+            sending_time: Timestamp::now(),
+            poss_dup: None,
+            orig_sending_time: None,
+            cl_ord_id: cl_ord_id.to_string(),
+            symbol: None,
+            side: None,
+            qty: None,
+            account: None,
+        }
+    }
+
+    pub fn next(client: &mut FixClient, cl_ord_id: &str) -> NewOrder {
+        let (seq, sender, target, sending_time) = header(client);
+        NewOrder {
+            sending_time,
+            ..NewOrder::new(seq, &sender, &target, cl_ord_id)
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, FixHeader, FixDeserialize, FixSerialize)]
+#[msg_type = "8"]
+pub(crate) struct ExecReport {
+    #[id = "34"]
+    pub seq: u64,
+    #[id = "49"]
+    pub sender: String,
+    #[id = "56"]
+    pub target: String,
+    #[id = "52"]
+    pub sending_time: Timestamp,
+    #[id = "11"]
+    pub cl_ord_id: String,
+    #[id = "150"]
+    pub exec_type: Option<String>,
+}
+
+impl ExecReport {
+    pub fn next(client: &mut FixClient, cl_ord_id: &str) -> ExecReport {
+        let (seq, sender, target, sending_time) = header(client);
+        ExecReport {
+            seq,
+            sender,
+            target,
+            sending_time,
+            cl_ord_id: cl_ord_id.to_string(),
+            exec_type: None,
+        }
+    }
+}
+
+// the session messages a test handler gets.
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, FixDeserialize)]
+pub(crate) enum SessionMsg {
+    Logon(Logon),
+    Logout(Logout),
+    Heartbeat(Heartbeat),
+    TestRequest(TestRequest),
+    ResendRequest(ResendRequest),
+    Reject(Reject),
+}
+
+// the application messages a test handler gets.
+#[allow(dead_code)]
+#[derive(Debug, PartialEq, FixDeserialize)]
+pub(crate) enum AppMsg {
+    NewOrder(NewOrder),
+    ExecReport(ExecReport),
+}
+
+// a `FixHandler` of `SessionMsg` and `AppMsg` doing nothing, tests implement what they need.
+pub(crate) trait TestHandler {
+    type Action;
+
+    fn handle_session(&mut self, _client: &mut FixClient, _msg: SessionMsg) -> Result<(), HandleErr> {
+        Ok(())
+    }
+
+    fn handle_app(&mut self, _client: &mut FixClient, _msg: AppMsg) -> Result<(), HandleErr> {
+        Ok(())
+    }
+
+    fn handle_action(&mut self, _client: &mut FixClient, _action: Self::Action) -> Result<(), HandleErr> {
+        Ok(())
+    }
+
+    fn poll(&mut self, _client: &mut FixClient) {}
+
+    fn on_connect(&mut self, _client: &mut FixClient) {}
+
+    fn on_logon(&mut self, _client: &mut FixClient) {}
+
+    fn on_logout(&mut self, _client: &mut FixClient, _reason: Option<&str>) {}
+
+    fn on_disconnect(&mut self, _cause: Option<&SessionError>) {}
+
+    fn on_reject(&mut self, _client: &mut FixClient, _reject: &Reject) {}
+
+    fn on_sequence_gap(&mut self, _client: &mut FixClient, _expected: u64, _received: u64) {}
+
+    fn to_admin(&mut self, _msg: &mut RawMessage) {}
+
+    fn to_app(&mut self, _msg: &mut RawMessage) {}
+}
+
+impl<H: TestHandler> FixHandler<SessionMsg, AppMsg, H::Action> for H {
+    fn handle_session(&mut self, client: &mut FixClient, msg: SessionMsg) -> Result<(), HandleErr> {
+        TestHandler::handle_session(self, client, msg)
+    }
+
+    fn handle_app(&mut self, client: &mut FixClient, msg: AppMsg) -> Result<(), HandleErr> {
+        TestHandler::handle_app(self, client, msg)
+    }
+
+    fn handle_action(&mut self, client: &mut FixClient, action: H::Action) -> Result<(), HandleErr> {
+        TestHandler::handle_action(self, client, action)
+    }
+
+    fn poll(&mut self, client: &mut FixClient) {
+        TestHandler::poll(self, client)
+    }
+
+    fn on_connect(&mut self, client: &mut FixClient) {
+        TestHandler::on_connect(self, client)
+    }
+
+    fn on_logon(&mut self, client: &mut FixClient) {
+        TestHandler::on_logon(self, client)
+    }
+
+    fn on_logout(&mut self, client: &mut FixClient, reason: Option<&str>) {
+        TestHandler::on_logout(self, client, reason)
+    }
+
+    fn on_disconnect(&mut self, cause: Option<&SessionError>) {
+        TestHandler::on_disconnect(self, cause)
+    }
+
+    fn on_reject(&mut self, client: &mut FixClient, reject: &Reject) {
+        TestHandler::on_reject(self, client, reject)
+    }
+
+    fn on_sequence_gap(&mut self, client: &mut FixClient, expected: u64, received: u64) {
+        TestHandler::on_sequence_gap(self, client, expected, received)
+    }
+
+    fn to_admin(&mut self, msg: &mut RawMessage) {
+        TestHandler::to_admin(self, msg)
+    }
+
+    fn to_app(&mut self, msg: &mut RawMessage) {
+        TestHandler::to_app(self, msg)
+    }
+}
+
+// MsgSeqNum, SenderCompID, TargetCompID and SendingTime of the next message of `client`.
+pub(crate) fn header(client: &mut FixClient) -> (u64, String, String, Timestamp) {
+    let seq = client.get_next_send_seq();
+    let comp_ids = client.comp_ids().clone();
+    (seq, comp_ids.sender, comp_ids.target, client.sending_time())
+}
+
+pub(crate) fn client<S: Stream + 'static>(sender: &str, target: &str, stream: S) -> FixClient {
+    let comp_ids = CompIds {
+        sender: sender.to_string(),
+        target: target.to_string(),
+    };
+    FixClient::new(comp_ids, Box::new(stream))
+}
+
+// a message with just the given fields, e.g. a response that is only looked at by an interceptor.
+pub(crate) fn message(msg_type: &str, fields: &[(u64, &str)]) -> RawMessage {
+    let mut msg = RawMessage::new(msg_type);
+    for &(id, value) in fields {
+        msg.set(id, value);
+    }
+    msg
+}
+
+// everything written to the other end of a loopback so far.
+pub(crate) fn sent(theirs: &mut LoopbackStream) -> String {
+    let mut sent = String::new();
+    let mut buf = [0u8; 4096];
+    while let Ok(size) = theirs.read(&mut buf) {
+        if size == 0 {
+            break;
+        }
+        sent.push_str(&String::from_utf8_lossy(&buf[..size]));
+    }
+    sent
+}
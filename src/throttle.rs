@@ -0,0 +1,429 @@
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use chrono;
+use chrono::NaiveDateTime;
+
+/// What `FixClient::send` does with a message over one of the limits of its `Throttle`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OverflowAction {
+    // keeps it, and everything sent after it, until the limits allow. See `FixClient::send_queued`.
+    Queue,
+    // returns `SendError::Throttled`.
+    Reject,
+}
+
+/// At most `max_messages` within any `window`, counting messages of `msg_type` or all of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimit {
+    pub max_messages: usize,
+    pub window: Duration,
+    pub msg_type: Option<String>,
+}
+
+impl RateLimit {
+    pub fn new(max_messages: usize, window: Duration) -> RateLimit {
+        RateLimit {
+            max_messages,
+            window,
+            msg_type: None,
+        }
+    }
+
+    pub fn for_msg_type(msg_type: &str, max_messages: usize, window: Duration) -> RateLimit {
+        RateLimit {
+            max_messages,
+            window,
+            msg_type: Some(msg_type.to_string()),
+        }
+    }
+}
+
+/// How much of a limit is used up, see `Throttle::usage`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThrottleUsage {
+    pub limit: RateLimit,
+    pub used: usize,
+    pub remaining: usize,
+}
+
+#[derive(Debug)]
+struct Budget {
+    limit: RateLimit,
+    window: chrono::Duration,
+    // send times within the window, oldest first.
+    sent: VecDeque<NaiveDateTime>,
+}
+
+impl Budget {
+    fn applies_to(&self, msg_type: &str) -> bool {
+        match self.limit.msg_type {
+            Some(ref limited) => limited == msg_type,
+            None => true,
+        }
+    }
+
+    fn forget_before(&mut self, now: NaiveDateTime) {
+        while let Some(&oldest) = self.sent.front() {
+            if now - oldest < self.window {
+                break;
+            }
+            self.sent.pop_front();
+        }
+    }
+
+    fn used(&self, now: NaiveDateTime) -> usize {
+        self.sent.iter().filter(|&&sent| now - sent < self.window).count()
+    }
+
+    // time until one more message fits.
+    fn wait(&self, now: NaiveDateTime) -> chrono::Duration {
+        let used = self.used(now);
+        if used < self.limit.max_messages {
+            return chrono::Duration::zero();
+        }
+        let first_in_window = self.sent.len() - used;
+        let frees_up = self.sent[first_in_window + used - self.limit.max_messages] + self.window;
+        frees_up - now
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Queued {
+    pub msg_type: String,
+    pub admin: bool,
+    pub seq: Option<u64>,
+    pub msg: String,
+}
+
+pub(crate) enum Admission {
+    Now,
+    Queue,
+    Reject(Duration),
+}
+
+/// Outbound rate limits of a `FixClient`, see `FixClient::set_throttle`.
+///
+/// Admin messages count against the limits but are never held back, so heartbeats and
+/// logouts still go out, unless messages are queued in front of them.
+/// Time is always passed in so any `Clock` can drive it.
+#[derive(Debug)]
+pub struct Throttle {
+    budgets: Vec<Budget>,
+    on_overflow: OverflowAction,
+    queue: VecDeque<Queued>,
+}
+
+impl Throttle {
+    pub fn new(on_overflow: OverflowAction) -> Throttle {
+        Throttle {
+            budgets: Vec::new(),
+            on_overflow,
+            queue: VecDeque::new(),
+        }
+    }
+
+    // fails for a limit allowing no messages or with a window too long for chrono.
+    pub fn add_limit(&mut self, limit: RateLimit) -> Result<(), &'static str> {
+        if limit.max_messages == 0 {
+            return Err("a rate limit has to allow some messages");
+        }
+        let window = chrono::Duration::from_std(limit.window).map_err(|_| "rate limit window out of range")?;
+        self.budgets.push(Budget {
+            limit,
+            window,
+            sent: VecDeque::new(),
+        });
+        Ok(())
+    }
+
+    pub fn on_overflow(&self) -> OverflowAction {
+        self.on_overflow
+    }
+
+    // time until a message of `msg_type` fits every limit, None if it does now.
+    pub fn delay(&self, msg_type: &str, now: NaiveDateTime) -> Option<Duration> {
+        let wait = self
+            .budgets
+            .iter()
+            .filter(|budget| budget.applies_to(msg_type))
+            .map(|budget| budget.wait(now))
+            .max()
+            .unwrap_or_else(chrono::Duration::zero);
+        if wait <= chrono::Duration::zero() {
+            None
+        } else {
+            // rounded up so sleeping for it is enough.
+            Some(wait.to_std().unwrap_or_default() + Duration::from_micros(1))
+        }
+    }
+
+    pub fn usage(&self, now: NaiveDateTime) -> Vec<ThrottleUsage> {
+        self.budgets
+            .iter()
+            .map(|budget| {
+                let used = budget.used(now);
+                ThrottleUsage {
+                    limit: budget.limit.clone(),
+                    used,
+                    remaining: budget.limit.max_messages.saturating_sub(used),
+                }
+            })
+            .collect()
+    }
+
+    // messages waiting for `FixClient::send_queued`.
+    pub fn queued(&self) -> usize {
+        self.queue.len()
+    }
+
+    // time until the first queued message may go out.
+    pub fn next_deadline(&self, now: NaiveDateTime) -> Option<Duration> {
+        self.queue.front().map(|queued| {
+            if queued.admin {
+                Duration::from_secs(0)
+            } else {
+                self.delay(&queued.msg_type, now).unwrap_or_default()
+            }
+        })
+    }
+
+    pub(crate) fn admit(&self, msg_type: &str, admin: bool, now: NaiveDateTime) -> Admission {
+        // nothing may overtake a queued message, the counterparty would see a sequence gap.
+        if !self.queue.is_empty() {
+            return Admission::Queue;
+        }
+        if admin {
+            return Admission::Now;
+        }
+        match (self.delay(msg_type, now), self.on_overflow) {
+            (None, _) => Admission::Now,
+            (Some(_), OverflowAction::Queue) => Admission::Queue,
+            (Some(delay), OverflowAction::Reject) => Admission::Reject(delay),
+        }
+    }
+
+    pub(crate) fn record(&mut self, msg_type: &str, now: NaiveDateTime) {
+        for budget in self.budgets.iter_mut().filter(|budget| budget.applies_to(msg_type)) {
+            budget.forget_before(now);
+            budget.sent.push_back(now);
+        }
+    }
+
+    pub(crate) fn enqueue(&mut self, queued: Queued) {
+        self.queue.push_back(queued);
+    }
+
+    // moves the messages queued by `other` in front of those queued here.
+    pub(crate) fn take_queue_of(&mut self, other: &mut Throttle) {
+        let mut queue = std::mem::take(&mut other.queue);
+        queue.append(&mut self.queue);
+        self.queue = queue;
+    }
+
+    // the first queued message if it may go out now.
+    pub(crate) fn pop_ready(&mut self, now: NaiveDateTime) -> Option<Queued> {
+        let ready = match self.queue.front() {
+            Some(queued) => queued.admin || self.delay(&queued.msg_type, now).is_none(),
+            None => false,
+        };
+        if ready {
+            self.queue.pop_front()
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+    use std::sync::mpsc::channel;
+
+    use super::*;
+    use admin::Heartbeat;
+    use chrono::NaiveDate;
+    use detail::{find_field, parse_fix_message};
+    use session::{frame_length, Session};
+    use testing::{self, NewOrder, TestHandler};
+    use {loopback, FixClient, FixParse, LoopbackStream, SendError, TestClock};
+
+    fn at(millis: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2020, 1, 1)
+            .unwrap()
+            .and_hms_milli_opt(0, 0, 0, millis)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_limits_per_window_and_msg_type() {
+        let mut throttle = Throttle::new(OverflowAction::Reject);
+        throttle
+            .add_limit(RateLimit::new(3, Duration::from_millis(100)))
+            .unwrap();
+        throttle
+            .add_limit(RateLimit::for_msg_type("D", 1, Duration::from_millis(50)))
+            .unwrap();
+
+        throttle.record("D", at(0));
+        assert_eq!(throttle.delay("D", at(10)), Some(Duration::from_millis(40) + Duration::from_micros(1)));
+        assert_eq!(throttle.delay("i", at(10)), None);
+        throttle.record("i", at(10));
+        throttle.record("i", at(20));
+        assert_eq!(throttle.delay("i", at(30)), Some(Duration::from_millis(70) + Duration::from_micros(1)));
+        assert_eq!(throttle.delay("D", at(100)), None);
+
+        let usage = throttle.usage(at(30));
+        assert_eq!((usage[0].used, usage[0].remaining), (3, 0));
+        assert_eq!((usage[1].used, usage[1].remaining), (1, 0));
+        let usage = throttle.usage(at(60));
+        assert_eq!((usage[0].used, usage[1].used), (3, 0));
+    }
+
+    fn throttled_client(on_overflow: OverflowAction) -> (FixClient, LoopbackStream, TestClock) {
+        let (stream, peer) = loopback();
+        let clock = TestClock::new(at(0));
+        let mut client = testing::client("a", "b", stream);
+        client.set_clock(Box::new(clock.clone()));
+        let mut throttle = Throttle::new(on_overflow);
+        throttle.add_limit(RateLimit::new(1, Duration::from_millis(100))).unwrap();
+        client.set_throttle(throttle);
+        (client, peer, clock)
+    }
+
+    fn heartbeat(client: &mut FixClient) -> Heartbeat {
+        Heartbeat {
+            seq: client.get_next_send_seq(),
+            sender: "a".to_string(),
+            target: "b".to_string(),
+            sending_time: client.sending_time(),
+            test_req_id: None,
+        }
+    }
+
+    // seqs of the messages the peer received so far.
+    fn received(peer: &mut LoopbackStream) -> Vec<u64> {
+        let mut buf = [0u8; 4096];
+        let size = match peer.read(&mut buf) {
+            Ok(size) => size,
+            Err(_) => return Vec::new(),
+        };
+        let mut seqs = Vec::new();
+        let mut rest = &buf[..size];
+        while let Some(length) = frame_length(rest) {
+            let parsed = parse_fix_message(&rest[..length]).unwrap();
+            seqs.push(FixParse::parse(find_field(parsed.body, 34).unwrap().unwrap()).unwrap());
+            rest = &rest[length..];
+        }
+        seqs
+    }
+
+    #[test]
+    fn test_client_rejects_over_the_limit() {
+        let (mut client, mut peer, clock) = throttled_client(OverflowAction::Reject);
+        let first = NewOrder::next(&mut client, "1");
+        client.send(&first).unwrap();
+        assert_eq!(client.throttle_usage()[0].remaining, 0);
+
+        clock.advance(Duration::from_millis(10));
+        let second = NewOrder::next(&mut client, "1");
+        match client.send(&second) {
+            Err(SendError::Throttled { ref msg_type, retry_after }) => {
+                assert_eq!(msg_type, "D");
+                assert!(retry_after > Duration::from_millis(89) && retry_after < Duration::from_millis(91));
+            }
+            other => panic!("not rejected: {:?}", other),
+        }
+        // admin messages are counted but never held back, it gets the seq given back by the order.
+        let heartbeat = heartbeat(&mut client);
+        assert_eq!(heartbeat.seq, 2);
+        client.send(&heartbeat).unwrap();
+        assert_eq!(client.throttle_usage()[0].used, 2);
+        assert_eq!(received(&mut peer), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_client_queues_over_the_limit() {
+        let (mut client, mut peer, clock) = throttled_client(OverflowAction::Queue);
+        for _ in 0..2 {
+            let order = NewOrder::next(&mut client, "1");
+            client.send(&order).unwrap();
+        }
+        // queued behind the order, so the peer sees no gap.
+        let heartbeat = heartbeat(&mut client);
+        client.send(&heartbeat).unwrap();
+        assert_eq!(received(&mut peer), vec![1]);
+        assert_eq!(client.throttle().unwrap().queued(), 2);
+        assert_eq!(client.send_queued().unwrap(), 0);
+        assert!(client.next_timer_deadline().unwrap() <= Duration::from_millis(101));
+
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(client.send_queued().unwrap(), 2);
+        assert_eq!(received(&mut peer), vec![2, 3]);
+    }
+
+    #[test]
+    fn test_replaced_throttle_keeps_the_queued_messages() {
+        let (mut client, mut peer, clock) = throttled_client(OverflowAction::Queue);
+        for _ in 0..3 {
+            let order = NewOrder::next(&mut client, "1");
+            client.send(&order).unwrap();
+        }
+        assert_eq!(received(&mut peer), vec![1]);
+
+        let mut throttle = Throttle::new(OverflowAction::Queue);
+        throttle.add_limit(RateLimit::new(1, Duration::from_millis(50))).unwrap();
+        client.set_throttle(throttle);
+        assert_eq!(client.throttle().unwrap().queued(), 2);
+        assert_eq!(client.send_queued().unwrap(), 1);
+        assert_eq!(received(&mut peer), vec![2]);
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(client.send_queued().unwrap(), 1);
+        assert_eq!(received(&mut peer), vec![3]);
+    }
+
+    #[test]
+    fn test_limits_allowing_nothing_or_too_long_are_refused() {
+        let mut throttle = Throttle::new(OverflowAction::Reject);
+        assert!(throttle.add_limit(RateLimit::new(0, Duration::from_secs(1))).is_err());
+        assert!(throttle.add_limit(RateLimit::new(1, Duration::from_secs(u64::MAX))).is_err());
+        assert!(throttle.usage(at(0)).is_empty());
+    }
+
+    // sends a burst of orders once connected.
+    struct Burst {
+        results: Vec<Result<(), SendError>>,
+    }
+
+    impl TestHandler for Burst {
+        type Action = ();
+
+        fn on_connect(&mut self, client: &mut FixClient) {
+            for cl_ord_id in &["1", "2", "3"] {
+                let order = NewOrder::next(client, cl_ord_id);
+                self.results.push(client.send(&order));
+            }
+        }
+    }
+
+    #[test]
+    fn test_burst_sent_from_a_callback_is_rejected_by_send() {
+        let (client, mut peer, clock) = throttled_client(OverflowAction::Reject);
+        let (_actions_tx, actions) = channel();
+        let mut session = Session::new(client, Burst { results: Vec::new() }, &actions);
+        session.step().unwrap();
+
+        let results = &session.handler().results;
+        assert!(results[0].is_ok());
+        assert!(matches!(results[1], Err(SendError::Throttled { .. })));
+        assert!(matches!(results[2], Err(SendError::Throttled { .. })));
+        assert_eq!(received(&mut peer), vec![1]);
+
+        // the seqs of the rejected orders were given back, so there is no gap.
+        clock.advance(Duration::from_millis(100));
+        let order = NewOrder::next(session.client(), "4");
+        session.client().send(&order).unwrap();
+        assert_eq!(received(&mut peer), vec![2]);
+    }
+}
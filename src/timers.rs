@@ -0,0 +1,121 @@
+use std::time::Duration;
+
+use chrono;
+use chrono::NaiveDateTime;
+
+// Same thresholds as QuickFIX: a TestRequest goes out after 1.2 heartbeat intervals of silence,
+// the counterparty is considered gone after 2.4.
+const TEST_REQUEST_FACTOR: f64 = 1.2;
+const TIMEOUT_FACTOR: f64 = 2.4;
+
+/// Heartbeat bookkeeping of a session. Time is always passed in so any `Clock` can drive it.
+#[derive(Debug, Clone)]
+pub struct SessionTimers {
+    heartbeat_interval: chrono::Duration,
+    last_sent: NaiveDateTime,
+    last_received: NaiveDateTime,
+}
+
+impl SessionTimers {
+    pub fn new(heartbeat_interval: Duration, now: NaiveDateTime) -> SessionTimers {
+        SessionTimers {
+            heartbeat_interval: chrono::Duration::from_std(heartbeat_interval)
+                .expect("heartbeat interval out of range"),
+            last_sent: now,
+            last_received: now,
+        }
+    }
+
+    pub fn on_send(&mut self, now: NaiveDateTime) {
+        self.last_sent = now;
+    }
+
+    pub fn on_receive(&mut self, now: NaiveDateTime) {
+        self.last_received = now;
+    }
+
+    pub fn heartbeat_due(&self, now: NaiveDateTime) -> bool {
+        now - self.last_sent >= self.heartbeat_interval
+    }
+
+    pub fn test_request_due(&self, now: NaiveDateTime) -> bool {
+        now - self.last_received >= self.scaled_interval(TEST_REQUEST_FACTOR)
+    }
+
+    pub fn timed_out(&self, now: NaiveDateTime) -> bool {
+        now - self.last_received >= self.scaled_interval(TIMEOUT_FACTOR)
+    }
+
+    // time until the nearest of the above becomes due, None if all of them already are.
+    pub fn next_deadline(&self, now: NaiveDateTime) -> Option<chrono::Duration> {
+        let deadlines = [
+            self.last_sent + self.heartbeat_interval,
+            self.last_received + self.scaled_interval(TEST_REQUEST_FACTOR),
+            self.last_received + self.scaled_interval(TIMEOUT_FACTOR),
+        ];
+        deadlines.iter().filter(|d| **d > now).min().map(|d| *d - now)
+    }
+
+    fn scaled_interval(&self, factor: f64) -> chrono::Duration {
+        let millis = self.heartbeat_interval.num_milliseconds() as f64 * factor;
+        chrono::Duration::milliseconds(millis as i64)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::DateTime;
+
+    use super::*;
+    use clock::{Clock, TestClock};
+
+    fn setup() -> (TestClock, SessionTimers) {
+        let clock = TestClock::new(DateTime::from_timestamp(1519590632, 0).unwrap().naive_utc());
+        let timers = SessionTimers::new(Duration::from_secs(30), clock.now());
+        (clock, timers)
+    }
+
+    #[test]
+    fn test_heartbeat_due_after_interval_without_sending() {
+        let (clock, mut timers) = setup();
+        clock.advance(Duration::from_secs(29));
+        assert!(!timers.heartbeat_due(clock.now()));
+        clock.advance(Duration::from_secs(1));
+        assert!(timers.heartbeat_due(clock.now()));
+
+        timers.on_send(clock.now());
+        assert!(!timers.heartbeat_due(clock.now()));
+    }
+
+    #[test]
+    fn test_test_request_and_timeout() {
+        let (clock, mut timers) = setup();
+        clock.advance(Duration::from_secs(35));
+        assert!(!timers.test_request_due(clock.now()));
+        clock.advance(Duration::from_secs(1));
+        assert!(timers.test_request_due(clock.now()));
+        assert!(!timers.timed_out(clock.now()));
+
+        clock.advance(Duration::from_secs(36));
+        assert!(timers.timed_out(clock.now()));
+
+        timers.on_receive(clock.now());
+        assert!(!timers.test_request_due(clock.now()));
+        assert!(!timers.timed_out(clock.now()));
+    }
+
+    #[test]
+    fn test_next_deadline_skips_overdue_timers() {
+        let (clock, mut timers) = setup();
+        assert_eq!(timers.next_deadline(clock.now()), Some(chrono::Duration::seconds(30)));
+
+        clock.advance(Duration::from_secs(31));
+        assert_eq!(timers.next_deadline(clock.now()), Some(chrono::Duration::seconds(5)));
+
+        timers.on_send(clock.now());
+        assert_eq!(timers.next_deadline(clock.now()), Some(chrono::Duration::seconds(5)));
+
+        clock.advance(Duration::from_secs(60));
+        assert_eq!(timers.next_deadline(clock.now()), None);
+    }
+}
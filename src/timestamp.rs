@@ -1,18 +1,81 @@
 use std::fmt;
 use std::str;
 
-use chrono::prelude::Local;
 use chrono::NaiveDateTime;
 
+use clock::{Clock, SystemClock};
 use FixParse;
 use ParseError;
 
-#[derive(Debug, PartialEq, Copy, Clone)]
-pub struct Timestamp(pub NaiveDateTime);
+/// Number of fractional second digits written for a `Timestamp`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Default)]
+pub enum TimestampPrecision {
+    Seconds,
+    #[default]
+    Millis,
+    Micros,
+    Nanos,
+}
+
+impl TimestampPrecision {
+    // chooses the smallest precision able to represent `digits` fractional digits.
+    fn from_fraction_digits(digits: usize) -> TimestampPrecision {
+        match digits {
+            0 => TimestampPrecision::Seconds,
+            1..=3 => TimestampPrecision::Millis,
+            4..=6 => TimestampPrecision::Micros,
+            _ => TimestampPrecision::Nanos,
+        }
+    }
+
+    fn format(&self) -> &'static str {
+        match *self {
+            TimestampPrecision::Seconds => "%Y%m%d-%H:%M:%S",
+            TimestampPrecision::Millis => "%Y%m%d-%H:%M:%S%.3f",
+            TimestampPrecision::Micros => "%Y%m%d-%H:%M:%S%.6f",
+            TimestampPrecision::Nanos => "%Y%m%d-%H:%M:%S%.9f",
+        }
+    }
+}
+
+/// UTC timestamp with nanosecond resolution.
+/// `precision` only affects how the timestamp is written out, timestamps of the same `time` are equal.
+///
+/// Migrating from the former tuple struct `Timestamp(NaiveDateTime)`: build it with
+/// `Timestamp::new(time)` instead of `Timestamp(time)` and read `timestamp.time` instead of `timestamp.0`.
+#[derive(Debug, Copy, Clone)]
+pub struct Timestamp {
+    pub time: NaiveDateTime,
+    pub precision: TimestampPrecision,
+}
 
 impl Timestamp {
+    pub fn new(time: NaiveDateTime) -> Timestamp {
+        Timestamp {
+            time,
+            precision: TimestampPrecision::default(),
+        }
+    }
+
     pub fn now() -> Timestamp {
-        Timestamp(Local::now().naive_utc())
+        Timestamp::from_clock(&SystemClock)
+    }
+
+    pub fn from_clock(clock: &Clock) -> Timestamp {
+        Timestamp::new(clock.now())
+    }
+
+    pub fn with_precision(self, precision: TimestampPrecision) -> Timestamp {
+        Timestamp {
+            time: self.time,
+            precision,
+        }
+    }
+}
+
+impl PartialEq for Timestamp {
+    fn eq(&self, other: &Timestamp) -> bool {
+        self.time == other.time
     }
 }
 
@@ -23,15 +86,97 @@ impl FixParse for Timestamp {
             Err(_) => return Err("Could not parse timestamp because of UTF8"),
         };
 
-        match NaiveDateTime::parse_from_str(value, "%Y%m%d-%H:%M:%S%.f") {
-            Ok(t) => Ok(Timestamp(t)),
-            Err(_) => Err("Could not parse timestamp (format: %Y%m%d-%H:%M:%S%.f)"),
-        }
+        let time = match NaiveDateTime::parse_from_str(value, "%Y%m%d-%H:%M:%S%.f") {
+            Ok(t) => t,
+            Err(_) => return Err("Could not parse timestamp (format: %Y%m%d-%H:%M:%S%.f)"),
+        };
+
+        let digits = match value.find('.') {
+            Some(dot) => value.len() - dot - 1,
+            None => 0,
+        };
+
+        Ok(Timestamp {
+            time,
+            precision: TimestampPrecision::from_fraction_digits(digits),
+        })
     }
 }
 
 impl fmt::Display for Timestamp {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0.format("%Y%m%d-%H:%M:%S%.3f"))
+        write!(f, "{}", self.time.format(self.precision.format()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use {deserialize, serialize};
+
+    #[derive(Debug, PartialEq, FixDeserialize, FixSerialize)]
+    #[msg_type = "0"]
+    struct Stamped {
+        #[id = "52"]
+        #[precision = "micros"]
+        sending_time: Timestamp,
+        #[id = "60"]
+        #[precision = "seconds"]
+        transact_time: Option<Timestamp>,
+    }
+
+    fn parse(s: &str) -> Timestamp {
+        FixParse::parse(s.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip_keeps_precision() {
+        for s in &[
+            "20180225-21:30:32",
+            "20180225-21:30:32.123",
+            "20180225-21:30:32.123456",
+            "20180225-21:30:32.123456789",
+        ] {
+            assert_eq!(&parse(s).to_string(), s);
+        }
+    }
+
+    #[test]
+    fn test_nanos_survive_lower_precision() {
+        let ts = parse("20180225-21:30:32.123456789");
+        let millis = ts.with_precision(TimestampPrecision::Millis);
+        assert_eq!(millis.to_string(), "20180225-21:30:32.123");
+        assert_eq!(
+            millis.with_precision(TimestampPrecision::Nanos).to_string(),
+            "20180225-21:30:32.123456789"
+        );
+    }
+
+    #[test]
+    fn test_seconds_precision() {
+        let ts = parse("20180225-21:30:32.999").with_precision(TimestampPrecision::Seconds);
+        assert_eq!(ts.to_string(), "20180225-21:30:32");
+    }
+
+    #[test]
+    fn test_equal_regardless_of_precision() {
+        let ts = parse("20180225-21:30:32.123");
+        assert_eq!(ts, ts.with_precision(TimestampPrecision::Nanos));
+        assert_ne!(ts, parse("20180225-21:30:32.124"));
+    }
+
+    #[test]
+    fn test_precision_attribute() {
+        let ts = parse("20180225-21:30:32.123456789");
+        let stamped = Stamped {
+            sending_time: ts,
+            transact_time: Some(ts),
+        };
+        let serialized = serialize(&stamped);
+        assert!(serialized.contains("\x0152=20180225-21:30:32.123456\x0160=20180225-21:30:32\x01"));
+
+        let parsed: Stamped = deserialize(serialized.as_bytes()).unwrap();
+        assert_eq!(parsed.sending_time.precision, TimestampPrecision::Micros);
+        assert_eq!(parsed.sending_time.to_string(), "20180225-21:30:32.123456");
     }
 }
@@ -0,0 +1,506 @@
+use std::net::TcpStream;
+
+use native_tls;
+
+use ConnectionFailure;
+use TlsStreamWrapper;
+
+/// X509 certificate, PEM or DER encoded.
+#[derive(Clone, Debug)]
+pub struct Certificate {
+    data: Vec<u8>,
+    pem: bool,
+}
+
+impl Certificate {
+    pub fn from_pem(pem: &[u8]) -> Certificate {
+        Certificate {
+            data: pem.to_vec(),
+            pem: true,
+        }
+    }
+
+    pub fn from_der(der: &[u8]) -> Certificate {
+        Certificate {
+            data: der.to_vec(),
+            pem: false,
+        }
+    }
+
+    fn to_native(&self) -> Result<native_tls::Certificate, ConnectionFailure> {
+        let certificate = if self.pem {
+            native_tls::Certificate::from_pem(&self.data)
+        } else {
+            native_tls::Certificate::from_der(&self.data)
+        };
+        certificate.map_err(|err| ConnectionFailure::TlsConfigError(format!("bad certificate: {}", err)))
+    }
+
+    fn to_der(&self) -> Result<Vec<u8>, ConnectionFailure> {
+        if !self.pem {
+            return Ok(self.data.clone());
+        }
+        self.to_native()?
+            .to_der()
+            .map_err(|err| ConnectionFailure::TlsConfigError(format!("bad certificate: {}", err)))
+    }
+}
+
+/// Our own certificate chain and its private key, presented to the counterparty.
+#[derive(Clone, Debug)]
+pub struct Identity {
+    cert_chain_pem: Vec<u8>,
+    key_pem: Vec<u8>,
+}
+
+impl Identity {
+    // `cert_chain` holds our certificate first, `key` an unencrypted PKCS#8 private key, both PEM.
+    pub fn from_pkcs8_pem(cert_chain: &[u8], key: &[u8]) -> Identity {
+        Identity {
+            cert_chain_pem: cert_chain.to_vec(),
+            key_pem: key.to_vec(),
+        }
+    }
+
+    fn to_native(&self) -> Result<native_tls::Identity, ConnectionFailure> {
+        native_tls::Identity::from_pkcs8(&self.cert_chain_pem, &self.key_pem)
+            .map_err(|err| ConnectionFailure::TlsConfigError(format!("bad identity: {}", err)))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TlsBackend {
+    // the platform's TLS library: openssl, schannel or security framework.
+    NativeTls,
+    // pure Rust, needs no system library. Has no built in roots and always needs `root_certificates`
+    // or a `pinned_certificate`.
+    #[cfg(feature = "rustls")]
+    Rustls,
+}
+
+/// How to set up TLS for initiators (`connect`) and acceptors (`accept`).
+///
+/// By default the counterparty's certificate has to chain up to the system roots and match
+/// the domain. `root_certificates` adds a custom CA, `pinned_certificate` instead accepts exactly
+/// one certificate whatever signed it. Verification can only be turned off through
+/// `danger_disable_verification`, which is logged on every connection.
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub backend: TlsBackend,
+    // presented to the counterparty: the server certificate of an acceptor, the client certificate of an initiator.
+    pub identity: Option<Identity>,
+    pub root_certificates: Vec<Certificate>,
+    pub use_built_in_roots: bool,
+    pub pinned_certificate: Option<Certificate>,
+    // acceptors only, clients have to present a certificate signed by one of `root_certificates`.
+    pub require_client_certificate: bool,
+
+    verify: bool,
+}
+
+impl Default for TlsConfig {
+    fn default() -> TlsConfig {
+        TlsConfig {
+            backend: TlsBackend::NativeTls,
+            identity: None,
+            root_certificates: Vec::new(),
+            use_built_in_roots: true,
+            pinned_certificate: None,
+            require_client_certificate: false,
+
+            verify: true,
+        }
+    }
+}
+
+impl TlsConfig {
+    pub fn new() -> TlsConfig {
+        TlsConfig::default()
+    }
+
+    // accepts any certificate for any domain, meant for test venues only.
+    pub fn danger_disable_verification(&mut self) {
+        warn!("TLS certificate verification disabled");
+        self.verify = false;
+    }
+
+    pub fn verifies(&self) -> bool {
+        self.verify
+    }
+
+    pub fn connect(&self, domain: &str, stream: TcpStream) -> Result<TlsStreamWrapper, ConnectionFailure> {
+        if !self.verify {
+            warn!("connecting to {} without verifying its TLS certificate", domain);
+        }
+        match self.backend {
+            TlsBackend::NativeTls => self.connect_native(domain, stream),
+            #[cfg(feature = "rustls")]
+            TlsBackend::Rustls => rustls_backend::connect(self, domain, stream),
+        }
+    }
+
+    pub fn accept(&self, stream: TcpStream) -> Result<TlsStreamWrapper, ConnectionFailure> {
+        match self.backend {
+            TlsBackend::NativeTls => self.accept_native(stream),
+            #[cfg(feature = "rustls")]
+            TlsBackend::Rustls => rustls_backend::accept(self, stream),
+        }
+    }
+
+    fn identity(&self) -> Result<&Identity, ConnectionFailure> {
+        self.identity
+            .as_ref()
+            .ok_or_else(|| ConnectionFailure::TlsConfigError("accepting TLS needs an identity".to_string()))
+    }
+
+    fn connect_native(&self, domain: &str, stream: TcpStream) -> Result<TlsStreamWrapper, ConnectionFailure> {
+        let mut builder = native_tls::TlsConnector::builder();
+        for certificate in &self.root_certificates {
+            builder.add_root_certificate(certificate.to_native()?);
+        }
+        builder.disable_built_in_roots(!self.use_built_in_roots);
+        if let Some(ref identity) = self.identity {
+            builder.identity(identity.to_native()?);
+        }
+        // the pin replaces the chain and domain checks, it is compared once the handshake is done.
+        let pinned = match self.pinned_certificate {
+            Some(ref certificate) => Some(certificate.to_der()?),
+            None => None,
+        };
+        if pinned.is_some() || !self.verify {
+            builder.danger_accept_invalid_certs(true);
+            builder.danger_accept_invalid_hostnames(true);
+        }
+        let connector = builder.build()?;
+        let stream = connector.connect(domain, stream)?;
+
+        if let Some(pinned) = pinned {
+            if self.verify {
+                let presented = match stream.peer_certificate()? {
+                    Some(certificate) => certificate.to_der()?,
+                    None => Vec::new(),
+                };
+                if presented != pinned {
+                    return Err(ConnectionFailure::TlsVerificationError(format!(
+                        "{} did not present the pinned certificate",
+                        domain
+                    )));
+                }
+            }
+        }
+        Ok(TlsStreamWrapper::new(stream))
+    }
+
+    fn accept_native(&self, stream: TcpStream) -> Result<TlsStreamWrapper, ConnectionFailure> {
+        if self.require_client_certificate {
+            return Err(ConnectionFailure::TlsConfigError(
+                "client certificates need the rustls backend".to_string(),
+            ));
+        }
+        let acceptor = native_tls::TlsAcceptor::new(self.identity()?.to_native()?)?;
+        Ok(TlsStreamWrapper::new(acceptor.accept(stream)?))
+    }
+}
+
+#[cfg(feature = "rustls")]
+mod rustls_backend {
+    use std::convert::TryFrom;
+    use std::io::{BufReader, Error, ErrorKind};
+    use std::net::TcpStream;
+    use std::sync::Arc;
+
+    use rustls;
+    use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+    use rustls::crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+    use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+    use rustls::server::WebPkiClientVerifier;
+    use rustls::{ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, ServerConfig};
+    use rustls::{ServerConnection, SignatureScheme, StreamOwned};
+    use rustls_pemfile;
+
+    use super::{Certificate, Identity, TlsConfig};
+    use ConnectionFailure;
+    use TlsStreamWrapper;
+
+    pub fn connect(config: &TlsConfig, domain: &str, mut stream: TcpStream) -> Result<TlsStreamWrapper, ConnectionFailure> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(config_error)?;
+
+        let pinned = match config.pinned_certificate {
+            Some(ref certificate) => Some(certificate.to_der()?),
+            None => None,
+        };
+        let builder = if pinned.is_some() || !config.verify {
+            let check = ServerCertCheck {
+                provider,
+                pinned: if config.verify { pinned } else { None },
+            };
+            builder.dangerous().with_custom_certificate_verifier(Arc::new(check))
+        } else {
+            if config.root_certificates.is_empty() {
+                return Err(ConnectionFailure::TlsConfigError(
+                    "rustls has no built in roots, set root_certificates or pinned_certificate".to_string(),
+                ));
+            }
+            builder.with_root_certificates(root_store(&config.root_certificates)?)
+        };
+        let client_config = match config.identity {
+            Some(ref identity) => {
+                let (chain, key) = parse_identity(identity)?;
+                builder.with_client_auth_cert(chain, key).map_err(config_error)?
+            }
+            None => builder.with_no_client_auth(),
+        };
+
+        let server_name = ServerName::try_from(domain.to_string())
+            .map_err(|_| ConnectionFailure::TlsConfigError(format!("invalid domain {}", domain)))?;
+        let mut connection = ClientConnection::new(Arc::new(client_config), server_name).map_err(config_error)?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream).map_err(handshake_error)?;
+        }
+        Ok(TlsStreamWrapper::rustls_client(StreamOwned::new(connection, stream)))
+    }
+
+    pub fn accept(config: &TlsConfig, mut stream: TcpStream) -> Result<TlsStreamWrapper, ConnectionFailure> {
+        let provider = Arc::new(ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(config_error)?;
+
+        let builder = if config.require_client_certificate {
+            let roots = Arc::new(root_store(&config.root_certificates)?);
+            let verifier = WebPkiClientVerifier::builder_with_provider(roots, provider)
+                .build()
+                .map_err(|err| ConnectionFailure::TlsConfigError(err.to_string()))?;
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+        let (chain, key) = parse_identity(config.identity()?)?;
+        let server_config = builder.with_single_cert(chain, key).map_err(config_error)?;
+
+        let mut connection = ServerConnection::new(Arc::new(server_config)).map_err(config_error)?;
+        while connection.is_handshaking() {
+            connection.complete_io(&mut stream).map_err(handshake_error)?;
+        }
+        Ok(TlsStreamWrapper::rustls_server(StreamOwned::new(connection, stream)))
+    }
+
+    fn root_store(certificates: &[Certificate]) -> Result<RootCertStore, ConnectionFailure> {
+        let mut store = RootCertStore::empty();
+        for certificate in certificates {
+            store.add(CertificateDer::from(certificate.to_der()?)).map_err(config_error)?;
+        }
+        Ok(store)
+    }
+
+    fn parse_identity(
+        identity: &Identity,
+    ) -> Result<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>), ConnectionFailure> {
+        let chain = rustls_pemfile::certs(&mut BufReader::new(&identity.cert_chain_pem[..]))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| ConnectionFailure::TlsConfigError(format!("bad identity: {}", err)))?;
+        let key = rustls_pemfile::private_key(&mut BufReader::new(&identity.key_pem[..]))
+            .map_err(|err| ConnectionFailure::TlsConfigError(format!("bad identity: {}", err)))?
+            .ok_or_else(|| ConnectionFailure::TlsConfigError("identity without private key".to_string()))?;
+        Ok((chain, key))
+    }
+
+    fn config_error(err: rustls::Error) -> ConnectionFailure {
+        ConnectionFailure::TlsConfigError(err.to_string())
+    }
+
+    // rustls reports a refused certificate as an InvalidData io error wrapping its own error.
+    fn handshake_error(err: Error) -> ConnectionFailure {
+        let refused = matches!(
+            err.get_ref().and_then(|inner| inner.downcast_ref::<rustls::Error>()),
+            Some(rustls::Error::InvalidCertificate(_)) | Some(rustls::Error::NoCertificatesPresented)
+        );
+        if err.kind() == ErrorKind::InvalidData && refused {
+            return ConnectionFailure::TlsVerificationError(err.to_string());
+        }
+        ConnectionFailure::TcpStreamError(err)
+    }
+
+    // accepts the pinned certificate only, or anything when there is no pin.
+    #[derive(Debug)]
+    struct ServerCertCheck {
+        provider: Arc<CryptoProvider>,
+        pinned: Option<Vec<u8>>,
+    }
+
+    impl ServerCertVerifier for ServerCertCheck {
+        fn verify_server_cert(
+            &self,
+            end_entity: &CertificateDer,
+            _intermediates: &[CertificateDer],
+            _server_name: &ServerName,
+            _ocsp_response: &[u8],
+            _now: UnixTime,
+        ) -> Result<ServerCertVerified, rustls::Error> {
+            match self.pinned {
+                Some(ref pinned) if pinned[..] != end_entity[..] => Err(rustls::Error::InvalidCertificate(
+                    rustls::CertificateError::ApplicationVerificationFailure,
+                )),
+                _ => Ok(ServerCertVerified::assertion()),
+            }
+        }
+
+        fn verify_tls12_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+        }
+
+        fn verify_tls13_signature(
+            &self,
+            message: &[u8],
+            cert: &CertificateDer,
+            dss: &DigitallySignedStruct,
+        ) -> Result<HandshakeSignatureValid, rustls::Error> {
+            verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+        }
+
+        fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+            self.provider.signature_verification_algorithms.supported_schemes()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread::{spawn, JoinHandle};
+
+    use super::*;
+
+    const CA: &[u8] = include_bytes!("../testdata/tls/ca.pem");
+    const SERVER_CERT: &[u8] = include_bytes!("../testdata/tls/server.pem");
+    const SERVER_KEY: &[u8] = include_bytes!("../testdata/tls/server.key");
+    const CLIENT_CERT: &[u8] = include_bytes!("../testdata/tls/client.pem");
+    const CLIENT_KEY: &[u8] = include_bytes!("../testdata/tls/client.key");
+
+    fn server_config(backend: TlsBackend) -> TlsConfig {
+        let mut config = TlsConfig::new();
+        config.backend = backend;
+        config.identity = Some(Identity::from_pkcs8_pem(SERVER_CERT, SERVER_KEY));
+        config
+    }
+
+    // accepts one connection and echoes a single read back, returns whether the handshake succeeded.
+    fn echo_server(config: TlsConfig) -> (u16, JoinHandle<bool>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            match config.accept(stream) {
+                Ok(mut tls) => {
+                    let mut buf = [0u8; 64];
+                    let size = tls.read(&mut buf).unwrap();
+                    tls.write_all(&buf[..size]).unwrap();
+                    tls.flush().unwrap();
+                    true
+                }
+                Err(_) => false,
+            }
+        });
+        (port, handle)
+    }
+
+    fn connect(config: &TlsConfig, port: u16) -> Result<TlsStreamWrapper, ConnectionFailure> {
+        let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        config.connect("localhost", stream)
+    }
+
+    fn echo(mut tls: TlsStreamWrapper) {
+        tls.write_all(b"8=FIX.4.4").unwrap();
+        let mut buf = [0u8; 64];
+        let size = tls.read(&mut buf).unwrap();
+        assert_eq!(&buf[..size], b"8=FIX.4.4");
+    }
+
+    #[test]
+    fn test_custom_ca() {
+        let (port, server) = echo_server(server_config(TlsBackend::NativeTls));
+        let mut config = TlsConfig::new();
+        config.root_certificates.push(Certificate::from_pem(CA));
+        config.use_built_in_roots = false;
+        echo(connect(&config, port).unwrap());
+        assert!(server.join().unwrap());
+
+        let (port, server) = echo_server(server_config(TlsBackend::NativeTls));
+        match connect(&TlsConfig::new(), port) {
+            Err(ConnectionFailure::TlsHandshakeError(_)) => {}
+            other => panic!("untrusted CA accepted: {:?}", other.is_ok()),
+        }
+        assert!(!server.join().unwrap());
+    }
+
+    #[test]
+    fn test_pinned_certificate() {
+        let (port, server) = echo_server(server_config(TlsBackend::NativeTls));
+        let mut config = TlsConfig::new();
+        config.pinned_certificate = Some(Certificate::from_pem(SERVER_CERT));
+        echo(connect(&config, port).unwrap());
+        assert!(server.join().unwrap());
+
+        let (port, _server) = echo_server(server_config(TlsBackend::NativeTls));
+        config.pinned_certificate = Some(Certificate::from_pem(CLIENT_CERT));
+        match connect(&config, port) {
+            Err(ConnectionFailure::TlsVerificationError(_)) => {}
+            other => panic!("wrong certificate accepted: {:?}", other.is_ok()),
+        }
+    }
+
+    #[test]
+    fn test_disabled_verification() {
+        let (port, server) = echo_server(server_config(TlsBackend::NativeTls));
+        let mut config = TlsConfig::new();
+        config.danger_disable_verification();
+        assert!(!config.verifies());
+        echo(connect(&config, port).unwrap());
+        assert!(server.join().unwrap());
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn test_rustls_mutual_tls() {
+        let mut server = server_config(TlsBackend::Rustls);
+        server.root_certificates.push(Certificate::from_pem(CA));
+        server.require_client_certificate = true;
+
+        let mut config = TlsConfig::new();
+        config.backend = TlsBackend::Rustls;
+        config.root_certificates.push(Certificate::from_pem(CA));
+        config.identity = Some(Identity::from_pkcs8_pem(CLIENT_CERT, CLIENT_KEY));
+        let (port, handle) = echo_server(server.clone());
+        echo(connect(&config, port).unwrap());
+        assert!(handle.join().unwrap());
+
+        // a certificate from a CA the server does not trust.
+        let mut untrusted = server.clone();
+        untrusted.root_certificates = vec![Certificate::from_pem(SERVER_CERT)];
+        refused(untrusted, &config);
+
+        config.identity = None;
+        refused(server, &config);
+    }
+
+    #[cfg(feature = "rustls")]
+    fn refused(server: TlsConfig, config: &TlsConfig) {
+        let (port, handle) = echo_server(server);
+        // with TLS 1.3 the client finishes its handshake before the server checks the client certificate.
+        if let Ok(mut tls) = connect(config, port) {
+            let _ = tls.write_all(b"8=FIX.4.4");
+            let mut buf = [0u8; 64];
+            assert!(!matches!(tls.read(&mut buf), Ok(size) if size > 0));
+        }
+        assert!(!handle.join().unwrap());
+    }
+}